@@ -3,23 +3,35 @@
 # ensure that the x11 directory exists so the server doesn't print any errors
 mkdir -p "/tmp/.X11-unix"
 
-# Clean up any existing X server lock files
-for i in {1..10}; do
-    rm -f "/tmp/.X$i-lock"
-    rm -f "/tmp/.X11-unix/X$i"
-done
+# With ANSICRAFT_SPAWN_X the terminal viewer starts an X server for each session itself,
+# and with ANSICRAFT_PROVISIONER each session's container or pod starts its own
+if [ "$ANSICRAFT_SPAWN_X" != "true" ] && [ -z "$ANSICRAFT_PROVISIONER" ]; then
+    # Clean up any existing X server lock files
+    for i in {1..10}; do
+        rm -f "/tmp/.X$i-lock"
+        rm -f "/tmp/.X11-unix/X$i"
+    done
 
-# Start multiple Xorg instances with dummy drivers for different displays
-# We'll start X servers on :1 through :10 to support multiple seats
-for i in {1..10}; do
-    Xorg ":$i" -noreset -logfile "/tmp/xorg$i.log" -config /etc/X11/xorg.conf.dummy &
-done
-sleep 2
+    # Start multiple Xorg instances with dummy drivers for different displays
+    # We'll start X servers on :1 through :10 to support multiple seats
+    for i in {1..10}; do
+        Xorg ":$i" -noreset -logfile "/tmp/xorg$i.log" -config /etc/X11/xorg.conf.dummy &
+    done
+    sleep 2
 
-# Set invisible cursor for each display
-for i in {1..10}; do
-    DISPLAY=:$i xsetroot -cursor /root/blank_cursor.xbm /root/blank_cursor.xbm || true
-done
+    # Set invisible cursor for each display
+    for i in {1..10}; do
+        DISPLAY=:$i xsetroot -cursor /root/blank_cursor.xbm /root/blank_cursor.xbm || true
+    done
+fi
+
+# Game sound goes through PulseAudio, with a sink per display
+if [ -n "$ANSICRAFT_AUDIO" ]; then
+    pulseaudio --start --exit-idle-time=-1 || echo "Couldn't start PulseAudio, games will be silent"
+fi
+
+# Say what's missing or broken now, rather than when the first player turns up
+/root/termcast doctor || echo "Some checks failed, sessions may not work until that's fixed"
 
 # Start terminal viewer
-RUST_BACKTRACE=full /root/termcast
+RUST_BACKTRACE=full /root/termcast serve
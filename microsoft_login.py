@@ -0,0 +1,113 @@
+import json
+import os
+import sys
+import time
+import urllib.error
+import urllib.parse
+import urllib.request
+import argparse
+
+import minecraft_launcher_lib
+
+# Microsoft's OAuth endpoints for personal accounts
+DEVICE_CODE_URL = "https://login.microsoftonline.com/consumers/oauth2/v2.0/devicecode"
+TOKEN_URL = "https://login.microsoftonline.com/consumers/oauth2/v2.0/token"
+SCOPE = "XboxLive.signin offline_access"
+
+def post_form(url: str, fields: dict) -> dict:
+    """POST a form and return the JSON reply, errors included"""
+    data = urllib.parse.urlencode(fields).encode()
+    try:
+        with urllib.request.urlopen(urllib.request.Request(url, data=data)) as response:
+            return json.load(response)
+    except urllib.error.HTTPError as e:
+        # The token endpoint says "still waiting" with a 400
+        return json.load(e)
+
+def minecraft_login(microsoft_token: str) -> dict:
+    """Trade a Microsoft access token for a Minecraft one, through Xbox Live"""
+    xbl = minecraft_launcher_lib.microsoft_account.authenticate_with_xbl(microsoft_token)
+    userhash = xbl["DisplayClaims"]["xui"][0]["uhs"]
+    xsts = minecraft_launcher_lib.microsoft_account.authenticate_with_xsts(xbl["Token"])
+    minecraft = minecraft_launcher_lib.microsoft_account.authenticate_with_minecraft(userhash, xsts["Token"])
+    if "access_token" not in minecraft:
+        raise RuntimeError("Minecraft didn't accept the Xbox Live login")
+    profile = minecraft_launcher_lib.microsoft_account.get_profile(minecraft["access_token"])
+    if "error" in profile:
+        raise RuntimeError("This Microsoft account doesn't own Minecraft")
+    return {"name": profile["name"], "id": profile["id"], "token": minecraft["access_token"]}
+
+def save_account(path: str, refresh_token: str, profile: dict):
+    """Keep the refresh token (not the short-lived access token) for next time"""
+    os.makedirs(os.path.dirname(path) or ".", exist_ok=True)
+    fd = os.open(path, os.O_WRONLY | os.O_CREAT | os.O_TRUNC, 0o600)
+    with os.fdopen(fd, "w") as f:
+        json.dump({"refresh_token": refresh_token, "name": profile["name"], "id": profile["id"]}, f)
+
+def refresh(path: str, client_id: str) -> dict:
+    """Log in again with a saved account, for launching the game"""
+    with open(path) as f:
+        account = json.load(f)
+    reply = post_form(TOKEN_URL, {
+        "grant_type": "refresh_token",
+        "client_id": client_id,
+        "refresh_token": account["refresh_token"],
+        "scope": SCOPE,
+    })
+    if "access_token" not in reply:
+        raise RuntimeError(reply.get("error_description", "The saved login has expired"))
+    profile = minecraft_login(reply["access_token"])
+    # Refresh tokens are replaced each time they're used
+    save_account(path, reply.get("refresh_token", account["refresh_token"]), profile)
+    return profile
+
+def device_login(path: str, client_id: str):
+    """Log in with a code typed in on another device, printing progress for ansicraft"""
+    reply = post_form(DEVICE_CODE_URL, {"client_id": client_id, "scope": SCOPE})
+    if "device_code" not in reply:
+        raise RuntimeError(reply.get("error_description", "Couldn't start logging in"))
+    print(f"CODE {reply['verification_uri']} {reply['user_code']}", flush=True)
+
+    interval = reply.get("interval", 5)
+    deadline = time.monotonic() + reply.get("expires_in", 900)
+    while time.monotonic() < deadline:
+        time.sleep(interval)
+        token = post_form(TOKEN_URL, {
+            "grant_type": "urn:ietf:params:oauth:grant-type:device_code",
+            "client_id": client_id,
+            "device_code": reply["device_code"],
+        })
+        error = token.get("error")
+        if error == "authorization_pending":
+            continue
+        if error == "slow_down":
+            interval += 5
+            continue
+        if error:
+            raise RuntimeError(token.get("error_description", error))
+        profile = minecraft_login(token["access_token"])
+        save_account(path, token["refresh_token"], profile)
+        print(f"LOGGED_IN {profile['name']}", flush=True)
+        return
+    raise RuntimeError("The code expired before it was used")
+
+if __name__ == "__main__":
+    parser = argparse.ArgumentParser(description='Microsoft account login for ansicraft')
+    parser.add_argument('account', help='File to save the login in')
+    parser.add_argument('--refresh', action='store_true',
+                        help='Log in again with the saved login, printing the profile to play as')
+    parser.add_argument('--client-id', default=os.environ.get("ANSICRAFT_MS_CLIENT_ID"),
+                        help='Azure application ID (default: $ANSICRAFT_MS_CLIENT_ID)')
+    args = parser.parse_args()
+    if not args.client_id:
+        print("ERROR No Microsoft client ID is set", flush=True)
+        sys.exit(1)
+    try:
+        if args.refresh:
+            profile = refresh(args.account, args.client_id)
+            print(f"PROFILE {profile['name']} {profile['id']} {profile['token']}", flush=True)
+        else:
+            device_login(args.account, args.client_id)
+    except Exception as e:
+        print(f"ERROR {e}", flush=True)
+        sys.exit(1)
@@ -0,0 +1,147 @@
+// Microsoft accounts, so players can join online-mode servers as themselves.  They
+// log in by typing a code on their phone, and the login is saved under their SSH
+// key for the launcher to use from then on.  Needs ANSICRAFT_MS_CLIENT_ID, the ID
+// of an Azure app allowed to sign in to Xbox Live.
+
+#[cfg(feature = "server")]
+use std::fs;
+#[cfg(feature = "server")]
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+#[cfg(feature = "server")]
+use std::path::PathBuf;
+#[cfg(feature = "server")]
+use std::process::Child;
+use std::process::{Command, Stdio};
+#[cfg(feature = "server")]
+use std::thread;
+
+#[cfg(feature = "server")]
+use tokio::sync::mpsc;
+
+use crate::config::AppConfig;
+
+#[cfg(feature = "server")]
+pub fn enabled(settings: &AppConfig) -> bool {
+    !settings.ms_client_id.is_empty()
+}
+
+#[cfg(feature = "server")]
+fn account_path(username: &str, settings: &AppConfig) -> Option<PathBuf> {
+    // Usernames are hex digests, but never let one escape the directory
+    if username.is_empty() || !username.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    Some(settings.accounts_dir.join(format!("{}.json", username)))
+}
+
+// The player's saved login, if they have one
+#[cfg(feature = "server")]
+pub fn saved(username: &str, settings: &AppConfig) -> Option<PathBuf> {
+    if !enabled(settings) {
+        return None;
+    }
+    account_path(username, settings).filter(|path| path.exists())
+}
+
+#[cfg(feature = "server")]
+pub fn forget(username: &str, settings: &AppConfig) -> io::Result<()> {
+    let Some(path) = account_path(username, settings) else {
+        return Ok(());
+    };
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+// Who a saved login is, and a token for playing as them
+pub struct Profile {
+    pub name: String,
+    pub id: String,
+    pub token: String,
+}
+
+// Logs in again with a saved login, which keeps it fresh for next time
+pub fn refresh(path: &Path, settings: &AppConfig) -> Result<Profile, String> {
+    let output = Command::new("python3")
+        .arg(&settings.ms_login_script)
+        .arg("--refresh")
+        .arg(path)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("Couldn't start the login helper: {}", e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for line in stdout.lines() {
+        match line.split_once(' ') {
+            Some(("PROFILE", rest)) => {
+                let mut fields = rest.splitn(3, ' ').map(str::to_owned);
+                if let (Some(name), Some(id), Some(token)) = (fields.next(), fields.next(), fields.next()) {
+                    return Ok(Profile { name, id, token });
+                }
+            }
+            Some(("ERROR", error)) => return Err(error.to_owned()),
+            _ => {}
+        }
+    }
+    Err("The login helper stopped".to_owned())
+}
+
+#[cfg(feature = "server")]
+pub enum LoginEvent {
+    // Where to go and what to type there
+    Code { url: String, code: String },
+    LoggedIn(String),
+    Failed(String),
+}
+
+// A device code login in progress, which is given up on when dropped
+#[cfg(feature = "server")]
+pub struct Login {
+    helper: Child,
+    pub events: mpsc::UnboundedReceiver<LoginEvent>,
+}
+
+#[cfg(feature = "server")]
+impl Login {
+    pub fn start(username: &str, settings: &AppConfig) -> Result<Self, String> {
+        let path = account_path(username, settings).ok_or("Can't save a login for this key")?;
+        let mut helper = Command::new("python3")
+            .arg(&settings.ms_login_script)
+            .arg(&path)
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Couldn't start the login helper: {}", e))?;
+        let stdout = helper.stdout.take().expect("stdout is piped");
+
+        let (events_tx, events) = mpsc::unbounded_channel();
+        thread::spawn(move || {
+            let mut finished = false;
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                let event = match line.split_once(' ') {
+                    Some(("CODE", rest)) => match rest.split_once(' ') {
+                        Some((url, code)) => LoginEvent::Code { url: url.to_owned(), code: code.to_owned() },
+                        None => continue,
+                    },
+                    Some(("LOGGED_IN", name)) => LoginEvent::LoggedIn(name.to_owned()),
+                    Some(("ERROR", error)) => LoginEvent::Failed(error.to_owned()),
+                    _ => continue,
+                };
+                finished |= !matches!(event, LoginEvent::Code { .. });
+                let _ = events_tx.send(event);
+            }
+            if !finished {
+                let _ = events_tx.send(LoginEvent::Failed("The login helper stopped".to_owned()));
+            }
+        });
+        Ok(Self { helper, events })
+    }
+}
+
+#[cfg(feature = "server")]
+impl Drop for Login {
+    fn drop(&mut self) {
+        let _ = self.helper.kill();
+        let _ = self.helper.wait();
+    }
+}
@@ -0,0 +1,276 @@
+// Operator commands, run over SSH by keys listed in the admin key file:
+//
+//   ssh -p 2222 host admin queue
+//   ssh -p 2222 host admin metrics
+//   ssh -p 2222 host admin drain
+//   ssh -p 2222 host admin reload
+//   ssh -t -p 2222 host admin dashboard
+//
+// or on the server itself, through the admin socket, with `termcast admin queue`
+// and so on (the dashboard if there's no command).  The socket's only for the
+// user the server runs as.
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use std::{fs, io};
+
+use crossterm::terminal;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{info, warn};
+
+use crate::queueing::{QueueSnapshot, Resource, ResourceAllocator};
+use crate::render::Screen;
+use crate::{dashboard, local, metrics};
+
+pub const USAGE: &str = "Usage: admin <command>\n\nCommands:\n  queue      Show sessions, reservations and everyone waiting\n  metrics    Print counters and gauges in Prometheus text format\n  drain      Stop starting new sessions so the server can be updated\n  resume     Start handing out sessions again after a drain\n  reload     Read the config file and key lists again\n  dashboard  Watch sessions, the queue and displays live, and kick, message or drain (needs ssh -t)\n";
+
+// Runs an admin command, returning the text to send back
+pub async fn run_command<T: Resource>(
+    args: &[&str],
+    allocator: &ResourceAllocator<T>,
+    reload: impl FnOnce() -> Result<String, String>,
+) -> Result<String, String> {
+    match args {
+        ["queue"] => {
+            let snapshot = allocator
+                .snapshot()
+                .await
+                .ok_or("The queue manager isn't running")?;
+            Ok(format_snapshot(&snapshot))
+        }
+        ["metrics"] => Ok(metrics::render()),
+        ["drain"] => {
+            allocator.set_draining(true);
+            let snapshot = allocator
+                .snapshot()
+                .await
+                .ok_or("The queue manager isn't running")?;
+            Ok(if snapshot.allocated.is_empty() {
+                "Draining. No sessions are running, it's safe to restart.\n".to_owned()
+            } else {
+                format!(
+                    "Draining. Waiting on {} sessions, run `admin queue` to check on them.\n",
+                    snapshot.allocated.len()
+                )
+            })
+        }
+        ["resume"] => {
+            allocator.set_draining(false);
+            Ok("Handing out sessions again.\n".to_owned())
+        }
+        ["reload"] => reload().map_err(|e| format!("Couldn't reload the config, so it's as it was: {}\n", e)),
+        _ => Err(USAGE.to_owned()),
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+fn format_snapshot(snapshot: &QueueSnapshot) -> String {
+    let mut output = String::new();
+
+    if snapshot.draining {
+        output.push_str(&format!(
+            "DRAINING: {}\n\n",
+            if snapshot.allocated.is_empty() { "done, safe to restart" } else { "waiting for sessions to end" }
+        ));
+    }
+    for pool in &snapshot.pools {
+        output.push_str(&format!(
+            "Pool {}: {} of {} free, {} waiting\n",
+            pool.name, pool.available, pool.capacity, pool.waiting
+        ));
+    }
+    if let Some(capacity) = snapshot.capacity {
+        output.push_str(&format!("Scheduled capacity: {} sessions\n", capacity));
+    }
+    output.push_str(&format!("\nAvailable: {:?}\n", snapshot.available));
+    if !snapshot.unhealthy.is_empty() {
+        output.push_str(&format!("Unhealthy: {:?}\n", snapshot.unhealthy));
+    }
+
+    output.push_str(&format!("\nReserved ({}):\n", snapshot.reserved.len()));
+    for (code, res_id) in &snapshot.reserved {
+        output.push_str(&format!("  {:>3}  code {}\n", res_id, code));
+    }
+
+    if !snapshot.held_for_reconnect.is_empty() {
+        output.push_str(&format!("\nHeld for reconnecting players ({}):\n", snapshot.held_for_reconnect.len()));
+        for (owner, res_id) in &snapshot.held_for_reconnect {
+            output.push_str(&format!("  {:>3}  {}\n", res_id, owner));
+        }
+    }
+
+    output.push_str(&format!("\nIn use ({}):\n", snapshot.allocated.len()));
+    for (res_id, requester, elapsed) in &snapshot.allocated {
+        output.push_str(&format!(
+            "  {:>3}  {}  {:<10} {}  {}\n",
+            res_id,
+            format_duration(*elapsed),
+            requester.pool,
+            requester.owner,
+            requester.fingerprint
+        ));
+    }
+
+    output.push_str(&format!("\nWaiting ({}):\n", snapshot.pending.len()));
+    for (position, (req_id, requester, waited)) in snapshot.pending.iter().enumerate() {
+        let source = requester
+            .source
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| "-".to_owned());
+        output.push_str(&format!(
+            "  {:>3}  #{:<5} {}  {:<10} prio {:<3} {}  {}  {}\n",
+            position + 1,
+            req_id,
+            format_duration(*waited),
+            requester.pool,
+            requester.priority,
+            source,
+            requester.owner,
+            requester.fingerprint
+        ));
+    }
+
+    output
+}
+
+// The dashboard goes straight out over the socket
+impl Screen for OwnedWriteHalf {
+    async fn show(&mut self, bytes: Vec<u8>) -> io::Result<()> {
+        self.write_all(&bytes).await
+    }
+}
+
+// Takes admin commands on the socket, for as long as the server runs
+pub async fn listen<T: Resource>(
+    path: PathBuf,
+    allocator: ResourceAllocator<T>,
+    reload: impl Fn() -> Result<String, String> + Clone + Send + Sync + 'static,
+) -> io::Result<()> {
+    if UnixStream::connect(&path).await.is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("another server's using {}", path.display())));
+    }
+    // Left behind by a server that didn't get to clean up
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    info!("Taking admin commands on {}", path.display());
+    loop {
+        let (stream, _) = listener.accept().await?;
+        // In case it was opened before its permissions were set
+        let uid = stream.peer_cred().ok().map(|cred| cred.uid());
+        if uid != Some(nix::unistd::getuid().as_raw()) {
+            warn!("Turned away another user on the admin socket ({:?})", uid);
+            continue;
+        }
+        let (allocator, reload) = (allocator.clone(), reload.clone());
+        tokio::spawn(async move {
+            if let Err(e) = serve_connection(stream, &allocator, reload).await {
+                warn!("Admin socket error: {}", e);
+            }
+        });
+    }
+}
+
+// One line with the command, then either the dashboard (with everything after that
+// line being what's typed), or the exit status on a line and the command's output
+async fn serve_connection<T: Resource>(
+    stream: UnixStream,
+    allocator: &ResourceAllocator<T>,
+    reload: impl FnOnce() -> Result<String, String>,
+) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    if words == ["dashboard"] {
+        let (input_tx, input_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 256];
+            while let Ok(n) = reader.read(&mut buf).await {
+                if n == 0 || input_tx.send(buf[..n].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+        // Until `termcast admin` says how big its terminal is
+        let input = Arc::new(tokio::sync::Mutex::new(input_rx));
+        return dashboard::run(allocator, writer, input, || (80, 24)).await;
+    }
+    let (output, exit_status) = match run_command(&words, allocator, reload).await {
+        Ok(output) => (output, 0),
+        Err(output) => (output, 1),
+    };
+    writer.write_all(format!("{}\n{}", exit_status, output).as_bytes()).await
+}
+
+// `termcast admin`: runs the command through the socket, returning its exit status,
+// or shows the dashboard in this terminal
+pub async fn attach(socket: &Path, command: &[String]) -> anyhow::Result<i32> {
+    let stream = UnixStream::connect(socket).await.map_err(|e| {
+        anyhow::anyhow!("Couldn't connect to {} ({}), is the server running from here?", socket.display(), e)
+    })?;
+    let (mut reader, mut writer) = stream.into_split();
+    let command = if command.is_empty() { "dashboard".to_owned() } else { command.join(" ") };
+    writer.write_all(format!("{}\n", command).as_bytes()).await?;
+
+    if command.trim() != "dashboard" {
+        let mut reply = String::new();
+        reader.read_to_string(&mut reply).await?;
+        let (exit_status, output) = reply.split_once('\n').unwrap_or(("1", &reply));
+        print!("{}", output);
+        return Ok(exit_status.parse().unwrap_or(1));
+    }
+    terminal::enable_raw_mode()?;
+    let shown = show_dashboard(reader, writer).await;
+    terminal::disable_raw_mode()?;
+    shown.map(|()| 0)
+}
+
+// Passes what's typed (and the terminal's size) to the dashboard, and shows it
+async fn show_dashboard(mut reader: OwnedReadHalf, mut writer: OwnedWriteHalf) -> anyhow::Result<()> {
+    let size = || terminal::size().map(|(cols, rows)| format!("\x1b[8;{};{}t", rows, cols).into_bytes());
+    writer.write_all(&size()?).await?;
+    let input = local::read_stdin();
+    let mut resized = signal(SignalKind::window_change())?;
+    let forwarding = tokio::spawn(async move {
+        loop {
+            let data = tokio::select! {
+                data = async { input.lock().await.recv().await } => match data {
+                    Some(data) => data,
+                    None => break,
+                },
+                Some(()) = resized.recv() => match size() {
+                    Ok(data) => data,
+                    Err(_) => continue,
+                },
+            };
+            if writer.write_all(&data).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut stdout = tokio::io::stdout();
+    let mut buf = [0u8; 16 * 1024];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        stdout.write_all(&buf[..n]).await?;
+        stdout.flush().await?;
+    }
+    forwarding.abort();
+    Ok(())
+}
@@ -0,0 +1,218 @@
+// What runs on the display and gets streamed.  Usually Minecraft, but any X
+// program works, set up with ANSICRAFT_APP_COMMAND and friends, e.g. to stream
+// SuperTuxKart or DOOM instead.
+
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use nix::sys::signal::Signal;
+use tracing::{error, info, warn};
+
+use crate::config::AppSettings;
+use crate::launcher::{LaunchError, LaunchOptions};
+use crate::minecraft::{self, MinecraftConfig};
+use crate::sandbox;
+
+pub trait AppLauncher: Send + Sync {
+    // What players are told is starting
+    fn name(&self) -> &str;
+
+    // The command that starts it for this session.  DISPLAY is already set.
+    fn command(&self, config: &MinecraftConfig) -> Result<Command, LaunchError>;
+
+    // The name (a regex) of its window, which it's ready once it shows
+    fn window_name(&self) -> Option<&str> {
+        None
+    }
+
+    // Whether it's up and showing something on the display yet
+    fn ready(&self, display: &str) -> bool {
+        self.window_name().is_none_or(|name| has_window(display, name))
+    }
+
+    // Pauses it while the player's dropped out, returning whether it could be
+    fn pause(&self, _display: &str) -> bool {
+        false
+    }
+
+    // Picks up where it was paused, once the player's back
+    fn resume(&self, _display: &str) {}
+
+    // Quits the way a player would, so it saves first, returning whether the
+    // keys could be pressed.  Signals come after, in case it didn't work.
+    fn save_and_quit(&self, _display: &str) -> bool {
+        false
+    }
+
+    // How to ask it to exit, and how long to give it before it's killed
+    fn stop_signal(&self) -> (Signal, Duration) {
+        (Signal::SIGTERM, Duration::from_secs(5))
+    }
+
+    // Asks it to exit, then makes it
+    fn stop(&self, process: &mut Child) {
+        let pid = process.id();
+        // Check if process is still running before sending signals
+        match process.try_wait() {
+            Ok(Some(status)) => {
+                info!("{} already exited with status: {}", self.name(), status);
+            }
+            Ok(None) => {
+                let (stop_signal, grace) = self.stop_signal();
+                info!("Sending {} to {} (PID: {})...", stop_signal, self.name(), pid);
+                if let Err(e) = sandbox::signal_game(pid, stop_signal) {
+                    warn!("Could not send {} to process: {}", stop_signal, e);
+                } else {
+                    // Wait for the process to exit gracefully
+                    let mut terminated = false;
+                    for _ in 0..(grace.as_millis() / 500).max(1) {
+                        thread::sleep(Duration::from_millis(500));
+                        match process.try_wait() {
+                            Ok(Some(status)) => {
+                                info!("{} exited gracefully with status: {}", self.name(), status);
+                                terminated = true;
+                                break;
+                            }
+                            Ok(None) => continue, // Still running
+                            Err(e) => {
+                                error!("Error checking process status: {}", e);
+                                break;
+                            }
+                        }
+                    }
+
+                    // If process is still alive, force kill it
+                    if !terminated {
+                        info!("Process didn't exit after {}, attempting to kill...", stop_signal);
+                        match process.kill() {
+                            Ok(_) => info!("Successfully terminated {}.", self.name()),
+                            Err(e) => error!("Failed to terminate {}: {}", self.name(), e),
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Error checking {} process status: {}", self.name(), e);
+            }
+        }
+    }
+}
+
+// Whether a window with this name (a regex) is on the display
+fn has_window(display: &str, name: &str) -> bool {
+    Command::new("xdotool")
+        .args(["search", "--onlyvisible", "--name", name])
+        .env("DISPLAY", display)
+        .output()
+        .is_ok_and(|output| output.status.success() && !output.stdout.is_empty())
+}
+
+// Presses a key on the display, e.g. Escape
+fn press_key(display: &str, key: &str) -> bool {
+    Command::new("xdotool")
+        .args(["key", key])
+        .env("DISPLAY", display)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+pub struct Minecraft;
+
+impl AppLauncher for Minecraft {
+    fn name(&self) -> &str {
+        "Minecraft"
+    }
+
+    fn command(&self, config: &MinecraftConfig) -> Result<Command, LaunchError> {
+        minecraft::launch_command(&LaunchOptions {
+            username: &config.username,
+            server: &config.server_address,
+            display: &config.xorg_display,
+            version: &config.version,
+            game_dir: config.game_dir.as_deref(),
+            world: config.world.as_deref(),
+            jvm_args: &config.jvm_args,
+            account: config.account.as_deref(),
+            env: &config.env,
+            settings: &config.settings,
+        })
+    }
+
+    fn window_name(&self) -> Option<&str> {
+        Some("^Minecraft")
+    }
+
+    // Escape brings up the pause menu, which stops singleplayer worlds, and closes it again
+    fn pause(&self, display: &str) -> bool {
+        press_key(display, "Escape")
+    }
+
+    fn resume(&self, display: &str) {
+        press_key(display, "Escape");
+    }
+
+    // Escape opens the pause menu, where the last button is Save and Quit to Title
+    fn save_and_quit(&self, display: &str) -> bool {
+        press_key(display, "Escape") && press_key(display, "shift+Tab") && press_key(display, "Return")
+    }
+}
+
+// Any other program, from the environment
+pub struct CustomApp {
+    name: String,
+    // Split on whitespace, with {username}, {display} and {server} filled in
+    command: String,
+    // A window name (regex) that shows it's started
+    ready_window: Option<String>,
+    stop_signal: Signal,
+    stop_timeout: Duration,
+}
+
+impl AppLauncher for CustomApp {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn command(&self, config: &MinecraftConfig) -> Result<Command, LaunchError> {
+        let mut args = self.command.split_whitespace().map(|arg| {
+            arg.replace("{username}", &config.username)
+                .replace("{display}", &config.xorg_display)
+                .replace("{server}", &config.server_address)
+        });
+        let mut cmd = Command::new(args.next().unwrap_or_default());
+        cmd.args(args).env("DISPLAY", &config.xorg_display);
+        Ok(cmd)
+    }
+
+    fn window_name(&self) -> Option<&str> {
+        self.ready_window.as_deref()
+    }
+
+    fn stop_signal(&self) -> (Signal, Duration) {
+        (self.stop_signal, self.stop_timeout)
+    }
+}
+
+// Minecraft, unless the config says otherwise
+pub fn from_config(settings: &AppSettings) -> Arc<dyn AppLauncher> {
+    let Some(command) = settings.command.clone() else {
+        return Arc::new(Minecraft);
+    };
+    let name = settings.stop_signal.to_uppercase();
+    let name = if name.starts_with("SIG") { name } else { format!("SIG{}", name) };
+    let stop_signal = name
+        .parse()
+        .map_err(|_| warn!("Ignoring unknown ANSICRAFT_APP_STOP_SIGNAL {}", name))
+        .unwrap_or(Signal::SIGTERM);
+    Arc::new(CustomApp {
+        name: settings.name.clone(),
+        command,
+        ready_window: settings.ready_window.clone(),
+        stop_signal,
+        stop_timeout: settings.stop_timeout,
+    })
+}
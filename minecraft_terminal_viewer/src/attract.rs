@@ -0,0 +1,115 @@
+// Something to watch while waiting: a recording (ANSICRAFT_ATTRACT_VIDEO) played
+// on a loop, or else the title screen of a warm instance on an idle display.
+// People in the queue can switch to it, and anyone can `ssh ... watch`.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::error;
+
+use crate::config::{AppConfig, LiveConfig, RenderSettings, TerminalSize};
+use crate::render::{self, VideoSource};
+use crate::session_log::SessionLogs;
+use crate::supervisor::Processes;
+use crate::warm;
+
+pub fn source(settings: &AppConfig) -> Option<VideoSource> {
+    if let Some(path) = &settings.attract_video {
+        return Some(VideoSource::File(path.clone()));
+    }
+    warm::showcase().map(|(display, size)| VideoSource::Display(display, size))
+}
+
+// Whether a source is still fine to show, i.e. nobody's started playing on it
+fn still_idle(source: &VideoSource) -> bool {
+    match source {
+        VideoSource::Display(display, _) => warm::is_warm(display),
+        VideoSource::File(_) | VideoSource::Window(_) => true,
+    }
+}
+
+// Frames of whatever there is to watch, until dropped
+pub struct AttractFeed {
+    cancel: CancellationToken,
+    frames: mpsc::Receiver<String>,
+}
+
+impl AttractFeed {
+    // None if there's nothing to watch right now
+    pub fn start(terminal_size: Arc<Mutex<TerminalSize>>, config: LiveConfig) -> Option<Self> {
+        let first_source = source(&config.get())?;
+        let cancel = CancellationToken::new();
+        let (frames_tx, frames) = mpsc::channel(1);
+
+        let feed_cancel = cancel.clone();
+        tokio::spawn(async move {
+            let render_settings = Arc::new(Mutex::new(RenderSettings::default()));
+            let mut next_source = Some(first_source);
+            while !feed_cancel.is_cancelled() {
+                let Some(source) = next_source.take().or_else(|| source(&config.get())) else {
+                    tokio::select! {
+                        _ = feed_cancel.cancelled() => {}
+                        _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+                    }
+                    continue;
+                };
+
+                let source_cancel = feed_cancel.child_token();
+                let mut renderer = tokio::spawn(render::render_video(
+                    frames_tx.clone(),
+                    // Viewers only get the latest frame, so each one's drawn whole
+                    None,
+                    Default::default(),
+                    terminal_size.clone(),
+                    render_settings.clone(),
+                    config.clone(),
+                    source.clone(),
+                    source_cancel.clone(),
+                    Processes::default(),
+                    SessionLogs::default(),
+                ));
+                // Move on as soon as someone starts playing on the display
+                let mut check = tokio::time::interval(Duration::from_millis(250));
+                let finished = loop {
+                    tokio::select! {
+                        _ = feed_cancel.cancelled() => break None,
+                        result = &mut renderer => break Some(result),
+                        _ = check.tick() => {
+                            if !still_idle(&source) {
+                                break None;
+                            }
+                        }
+                    }
+                };
+                source_cancel.cancel();
+                let result = match finished {
+                    Some(result) => result,
+                    None => renderer.await,
+                };
+                if let Ok(Err(e)) = result {
+                    error!("Error rendering the attract feed: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        });
+
+        Some(Self { cancel, frames })
+    }
+
+    // The newest frame since the last call, if there is one
+    pub fn latest_frame(&mut self) -> Option<String> {
+        let mut latest = None;
+        while let Ok(frame) = self.frames.try_recv() {
+            latest = Some(frame);
+        }
+        latest
+    }
+}
+
+impl Drop for AttractFeed {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
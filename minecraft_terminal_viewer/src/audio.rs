@@ -0,0 +1,161 @@
+// Game sound, which a terminal can't play.  Each display gets a PulseAudio sink,
+// and the game on it plays into that.  The sound is then either streamed as Opus
+// on a port set aside for the session, for the player to open with ffplay or mpv,
+// or just listened to, ringing the terminal bell when something loud happens.
+
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing::{error, info};
+
+use crate::config::{AppConfig, AudioMode, AUDIO_BELL_INTERVAL, AUDIO_BELL_THRESHOLD};
+use crate::overlay::Overlay;
+use crate::session_log::SessionLogs;
+use crate::supervisor::Processes;
+
+// The port a resource's sound is streamed on
+#[cfg(feature = "server")]
+pub fn port(resource_id: u32, settings: &AppConfig) -> Option<u16> {
+    if settings.audio != AudioMode::Stream {
+        return None;
+    }
+    settings.audio_base_port.checked_add(resource_id as u16)
+}
+
+fn sink_name(display: &str) -> String {
+    format!("ansicraft_{}", display.trim_start_matches(':').replace('.', "_"))
+}
+
+// Points a game at its display's sink, creating the sink if this is the first
+fn ensure_sink(display: &str) {
+    let name = sink_name(display);
+    let exists = Command::new("pactl")
+        .args(["list", "short", "sinks"])
+        .output()
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .any(|line| line.split('\t').nth(1) == Some(name.as_str()))
+        })
+        .unwrap_or(false);
+    if exists {
+        return;
+    }
+    let status = Command::new("pactl")
+        .args(["load-module", "module-null-sink", &format!("sink_name={}", name)])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    match status {
+        Ok(status) if status.success() => info!("Created audio sink {}", name),
+        Ok(status) => error!("Couldn't create audio sink {}: pactl exited with {}", name, status),
+        Err(e) => error!("Error running pactl: {}", e),
+    }
+}
+
+// Sends the game's sound to its display's sink
+pub fn apply(cmd: &mut Command, display: &str, settings: &AppConfig) {
+    if settings.audio == AudioMode::Off {
+        return;
+    }
+    ensure_sink(display);
+    cmd.env("PULSE_SINK", sink_name(display));
+}
+
+// Streams or listens to a session's sound until it ends
+pub struct AudioCapture {
+    ffmpeg: Arc<Mutex<Option<Child>>>,
+    running: Arc<AtomicBool>,
+}
+
+impl AudioCapture {
+    pub fn start(
+        display: &str,
+        port: Option<u16>,
+        overlay: Arc<Mutex<Overlay>>,
+        processes: Processes,
+        logs: SessionLogs,
+        settings: &AppConfig,
+    ) -> Option<Self> {
+        let mode = settings.audio;
+        if mode == AudioMode::Off || (mode == AudioMode::Stream && port.is_none()) {
+            return None;
+        }
+        let capture = Self {
+            ffmpeg: Arc::new(Mutex::new(None)),
+            running: Arc::new(AtomicBool::new(true)),
+        };
+        let source = format!("{}.monitor", sink_name(display));
+        let (ffmpeg, running) = (capture.ffmpeg.clone(), capture.running.clone());
+        let binary = settings.ffmpeg.clone();
+        thread::spawn(move || {
+            // With a stream, ffmpeg exits when the player's audio client disconnects,
+            // so it's started again for them to come back
+            while running.load(Ordering::SeqCst) {
+                let mut cmd = Command::new(&binary);
+                cmd.args(["-hide_banner", "-loglevel", "info", "-f", "pulse", "-i", &source]);
+                match (mode, port) {
+                    (AudioMode::Stream, Some(port)) => {
+                        cmd.args(["-c:a", "libopus", "-b:a", "64k", "-f", "ogg"])
+                            .arg(format!("tcp://0.0.0.0:{}?listen=1", port));
+                    }
+                    _ => {
+                        cmd.args(["-af", &format!("silencedetect=noise={}dB:d=0.3", AUDIO_BELL_THRESHOLD)])
+                            .args(["-f", "null", "-"]);
+                    }
+                }
+                let mut child = match cmd.stdout(Stdio::null()).stderr(Stdio::piped()).spawn() {
+                    Ok(child) => child,
+                    Err(e) => {
+                        error!("Error starting ffmpeg for audio: {}", e);
+                        return;
+                    }
+                };
+                let stderr = child.stderr.take().expect("stderr is piped");
+                let _tracked = processes.track("audio ffmpeg", child.id());
+                {
+                    let mut ffmpeg = ffmpeg.lock().unwrap();
+                    // The session might have ended while it was starting
+                    if !running.load(Ordering::SeqCst) {
+                        let _ = child.kill();
+                        let _ = child.wait();
+                        return;
+                    }
+                    *ffmpeg = Some(child);
+                }
+
+                // silencedetect logs the end of each quiet stretch, which is when
+                // something made a noise
+                let mut last_bell: Option<Instant> = None;
+                for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                    logs.line("audio", &line);
+                    if line.contains("silence_end")
+                        && last_bell.is_none_or(|rung| rung.elapsed() >= AUDIO_BELL_INTERVAL)
+                    {
+                        overlay.lock().unwrap().ring_bell();
+                        last_bell = Some(Instant::now());
+                    }
+                }
+                if let Some(mut child) = ffmpeg.lock().unwrap().take() {
+                    let _ = child.wait();
+                }
+                thread::sleep(Duration::from_secs(1));
+            }
+        });
+        Some(capture)
+    }
+}
+
+impl Drop for AudioCapture {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(mut child) = self.ffmpeg.lock().unwrap().take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
@@ -0,0 +1,236 @@
+// Growing and shrinking the pools with demand.  When too many players are waiting
+// for a pool (ANSICRAFT_SCALE_UP_QUEUE) or the first of them has waited too long
+// (ANSICRAFT_SCALE_UP_WAIT_SECS), and it's no free slots, the autoscaler's asked
+// for more, and the workers it started join the pool.  Once one of those has had
+// nothing to do for ANSICRAFT_SCALE_DOWN_IDLE_MINS it's taken out of the pool, and
+// the autoscaler's asked to stop it.  Workers from ANSICRAFT_WORKERS stay put.
+//
+// The autoscaler's the operator's commands from the config, unless a project using
+// this as a library registers one of its own (to call a cloud's API, say) before
+// `run`.
+
+use std::collections::HashMap;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, LazyLock, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc;
+use tracing::{error, info};
+
+use crate::cluster;
+use crate::config::{self, AppConfig, LiveConfig};
+use crate::queueing::{ResourceAllocator, ResourceDescriptor};
+
+// How backed up a pool is, when it's asked for more
+#[derive(Clone, Debug)]
+pub struct Pressure {
+    pub pool: String,
+    pub waiting: usize,
+    // How long the first in line has waited
+    pub longest_wait: Duration,
+    pub running: usize,
+}
+
+pub trait Autoscaler: Send + Sync {
+    // Starts more capacity for the pool, returning the workers to add to it, each
+    // as `host:port/slots` like in ANSICRAFT_WORKERS (or none, if it only told
+    // something else about it).  It's called on a thread of its own, so it can wait
+    // for them to come up.
+    fn scale_up(&self, pressure: &Pressure) -> Result<Vec<String>, String>;
+
+    // Stops a worker it started, which has already been taken out of the pool
+    fn scale_down(&self, pool: &str, worker: &str) -> Result<(), String>;
+}
+
+static REGISTERED: LazyLock<RwLock<Option<Arc<dyn Autoscaler>>>> = LazyLock::new(Default::default);
+
+// Scales the pools with this instead of the commands in the config
+pub fn register(autoscaler: impl Autoscaler + 'static) {
+    *REGISTERED.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(autoscaler));
+}
+
+// The operator's commands, split on whitespace with {pool}, {waiting}, {wait_secs}
+// and {worker} filled in
+struct Commands {
+    up: String,
+    down: String,
+}
+
+fn run_command(command: &str, fill: impl Fn(&str) -> String) -> Result<String, String> {
+    let mut args = command.split_whitespace().map(fill);
+    let program = args.next().unwrap_or_default();
+    let output = Command::new(&program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Couldn't run {}: {}", program, e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("{} exited with {}: {}", program, output.status, stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+impl Autoscaler for Commands {
+    fn scale_up(&self, pressure: &Pressure) -> Result<Vec<String>, String> {
+        let output = run_command(&self.up, |arg| {
+            arg.replace("{pool}", &pressure.pool)
+                .replace("{waiting}", &pressure.waiting.to_string())
+                .replace("{wait_secs}", &pressure.longest_wait.as_secs().to_string())
+        })?;
+        Ok(output.lines().map(str::trim).filter(|line| !line.is_empty()).map(str::to_owned).collect())
+    }
+
+    fn scale_down(&self, pool: &str, worker: &str) -> Result<(), String> {
+        if self.down.is_empty() {
+            return Ok(());
+        }
+        run_command(&self.down, |arg| arg.replace("{pool}", pool).replace("{worker}", worker)).map(|_| ())
+    }
+}
+
+// The registered autoscaler, or else the commands, if there are any
+fn autoscaler(config: &AppConfig) -> Option<Arc<dyn Autoscaler>> {
+    let registered = REGISTERED.read().unwrap_or_else(PoisonError::into_inner).clone();
+    registered.or_else(|| {
+        (!config.scale_up_command.is_empty()).then(|| {
+            Arc::new(Commands {
+                up: config.scale_up_command.clone(),
+                down: config.scale_down_command.clone(),
+            }) as Arc<dyn Autoscaler>
+        })
+    })
+}
+
+// A worker the autoscaler started
+struct Scaled {
+    pool: String,
+    address: String,
+    ids: Vec<u32>,
+    // Since when none of its slots have been in use, with nobody waiting for them
+    idle_since: Instant,
+}
+
+// Keeps an eye on the queues for as long as the server's up.  Resources for the
+// workers it starts are numbered from next_id, after the pool's own.
+pub(crate) async fn run(allocator: ResourceAllocator<ResourceDescriptor>, settings: LiveConfig, mut next_id: u32) {
+    let mut scaled: Vec<Scaled> = vec![];
+    // Pools being scaled up right now (None), or when they last were
+    let mut scaling: HashMap<String, Option<Instant>> = HashMap::new();
+    // What each scale-up started, once the autoscaler's done
+    let (started_tx, mut started_rx) = mpsc::unbounded_channel::<(String, Result<Vec<String>, String>)>();
+    let mut checks = tokio::time::interval(config::AUTOSCALE_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = checks.tick() => {}
+            Some((pool, started)) = started_rx.recv() => {
+                scaling.insert(pool.clone(), Some(Instant::now()));
+                let workers = match started {
+                    Ok(workers) => workers,
+                    Err(e) => {
+                        error!("Error scaling up pool \"{}\": {}", pool, e);
+                        continue;
+                    }
+                };
+                let resources = match cluster::worker_resources(&workers.join(","), &pool, next_id) {
+                    Ok(resources) => resources,
+                    Err(e) => {
+                        error!("Ignoring the workers started for pool \"{}\": {}", pool, e);
+                        continue;
+                    }
+                };
+                next_id += resources.len() as u32;
+                for resource in &resources {
+                    match scaled.iter_mut().find(|worker| worker.address == resource.display) {
+                        Some(worker) => worker.ids.push(resource.id),
+                        None => {
+                            info!("Worker {} started for pool \"{}\"", resource.display, resource.pool);
+                            scaled.push(Scaled {
+                                pool: resource.pool.clone(),
+                                address: resource.display.clone(),
+                                ids: vec![resource.id],
+                                idle_since: Instant::now(),
+                            });
+                        }
+                    }
+                }
+                cluster::watch(resources.iter().map(|resource| resource.display.clone()));
+                allocator.grow(resources);
+                continue;
+            }
+        }
+        let config = settings.get();
+        let Some(autoscaler) = autoscaler(&config) else {
+            continue;
+        };
+        let Some(snapshot) = allocator.snapshot().await else {
+            return;
+        };
+
+        for pool in &snapshot.pools {
+            let longest_wait = snapshot
+                .pending
+                .iter()
+                .filter(|(_, requester, _)| requester.pool == pool.name)
+                .map(|(_, _, waited)| *waited)
+                .max()
+                .unwrap_or_default();
+            let backed_up = pool.waiting >= config.scale_up_queue
+                || (pool.waiting > 0 && config.scale_up_wait.is_some_and(|wait| longest_wait >= wait));
+            let cooling_down = match scaling.get(&pool.name) {
+                Some(None) => true,
+                Some(Some(at)) => at.elapsed() < config::SCALE_UP_COOLDOWN,
+                None => false,
+            };
+            let in_progress = scaling.values().filter(|at| at.is_none()).count();
+            let at_limit = scaled.len() + in_progress >= config.max_scaled_workers;
+            if !backed_up || pool.available > 0 || cooling_down || at_limit {
+                continue;
+            }
+            let pressure = Pressure {
+                pool: pool.name.clone(),
+                waiting: pool.waiting,
+                longest_wait,
+                running: snapshot.allocated.iter().filter(|(_, requester, _)| requester.pool == pool.name).count(),
+            };
+            info!(
+                "{} waiting for pool \"{}\", the first for {}s, so scaling it up",
+                pressure.waiting,
+                pressure.pool,
+                pressure.longest_wait.as_secs()
+            );
+            scaling.insert(pool.name.clone(), None);
+            let (autoscaler, started_tx) = (autoscaler.clone(), started_tx.clone());
+            tokio::task::spawn_blocking(move || {
+                let _ = started_tx.send((pressure.pool.clone(), autoscaler.scale_up(&pressure)));
+            });
+        }
+
+        // Free slots are available, or set aside if the worker's stopped answering
+        for worker in &mut scaled {
+            let free = |id: &u32| snapshot.available.contains(id) || snapshot.unhealthy.contains(id);
+            let waiting = snapshot.pools.iter().any(|pool| pool.name == worker.pool && pool.waiting > 0);
+            if waiting || !worker.ids.iter().all(free) {
+                worker.idle_since = Instant::now();
+            }
+        }
+        let mut index = 0;
+        while index < scaled.len() {
+            let worker = &scaled[index];
+            if worker.idle_since.elapsed() < config.scale_down_idle || !allocator.shrink(worker.ids.clone()).await {
+                index += 1;
+                continue;
+            }
+            let worker = scaled.remove(index);
+            info!("Worker {} has been idle for a while, so stopping it", worker.address);
+            cluster::forget(&worker.address);
+            let autoscaler = autoscaler.clone();
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = autoscaler.scale_down(&worker.pool, &worker.address) {
+                    error!("Error stopping worker {}: {}", worker.address, e);
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,79 @@
+// Backups of players' singleplayer worlds, taken when a session in their own game
+// directory ends cleanly.  The last few of each world are kept, under
+// ANSICRAFT_BACKUPS_DIR/<username>, where players can download them over SFTP.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use tracing::info;
+
+#[cfg(feature = "server")]
+use crate::config::AppConfig;
+use crate::session_log;
+
+// Where the player's backups go, if they're being taken
+#[cfg(feature = "server")]
+pub fn player_dir(username: &str, settings: &AppConfig) -> Option<PathBuf> {
+    // Usernames are hex digests, but never let one escape the directory
+    if settings.world_backups == 0 || username.is_empty() || !username.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    Some(settings.backups_dir.join(username))
+}
+
+// The world that was played last, going by what the game saved most recently
+fn last_played(game_dir: &Path) -> Option<String> {
+    fs::read_dir(game_dir.join("saves"))
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().join("level.dat").exists())
+        .max_by_key(|entry| entry.metadata().and_then(|metadata| metadata.modified()).ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+}
+
+// Snapshots the world that was just played into a .tar.zst, then drops that
+// world's oldest backups, keeping `kept`
+pub fn snapshot(game_dir: &Path, backup_dir: &Path, kept: usize) -> io::Result<()> {
+    let Some(world) = last_played(game_dir) else {
+        return Ok(());
+    };
+    fs::create_dir_all(backup_dir)?;
+    // Named so they sort oldest first, and can't clash with another world's
+    let prefix = format!("{}-", world.replace(|c: char| !c.is_ascii_alphanumeric(), "_"));
+    let path = backup_dir.join(format!("{}{}.tar.zst", prefix, session_log::timestamp()));
+    let output = Command::new("tar")
+        .arg("--zstd")
+        .arg("-cf")
+        .arg(&path)
+        .arg("-C")
+        .arg(game_dir.join("saves"))
+        // Players name their worlds, so one can't be taken for an option
+        .arg("--")
+        .arg(&world)
+        .stderr(Stdio::piped())
+        .output()?;
+    if !output.status.success() {
+        let _ = fs::remove_file(&path);
+        let reason = String::from_utf8_lossy(&output.stderr).lines().next().unwrap_or_default().to_owned();
+        return Err(io::Error::other(format!("tar exited with {}: {}", output.status, reason)));
+    }
+    info!("Backed up {} to {}", world, path.display());
+
+    let mut backups: Vec<PathBuf> = fs::read_dir(backup_dir)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|backup| {
+            backup.file_name().and_then(|name| name.to_str()).is_some_and(|name| {
+                name.strip_prefix(&prefix).is_some_and(|rest| rest.len() == "20240131-235959.tar.zst".len())
+            })
+        })
+        .collect();
+    backups.sort();
+    let excess = backups.len().saturating_sub(kept);
+    for old in &backups[..excess] {
+        fs::remove_file(old)?;
+    }
+    Ok(())
+}
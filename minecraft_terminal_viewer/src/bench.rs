@@ -0,0 +1,42 @@
+// `termcast bench` encodes frames of a moving test pattern in each color mode as
+// fast as it can, to show what a machine can keep up with and how much each mode
+// sends per frame.
+
+use std::time::{Duration, Instant};
+
+use crate::config::{RenderMode, DEFAULT_FPS};
+use crate::render;
+use crate::synthetic::test_frame;
+
+pub fn run(width: usize, seconds: u64) {
+    let width = width.max(2);
+    let height = render::get_height_from_width(width);
+    // A handful of frames to cycle through, made up front so only encoding is timed
+    let frames: Vec<Vec<u8>> = (0..16).map(|frame| test_frame(width, height, frame)).collect();
+    println!("Encoding {}x{} frames for {}s in each mode", width, height / 2, seconds);
+
+    let mut mode = RenderMode::default();
+    loop {
+        let started = Instant::now();
+        let mut encoded = 0;
+        let mut bytes = 0;
+        while started.elapsed() < Duration::from_secs(seconds) {
+            bytes += render::encode_frame(mode, &frames[encoded % frames.len()], height, width, 0, 0).len();
+            encoded += 1;
+        }
+        let fps = encoded as f64 / started.elapsed().as_secs_f64();
+        let per_frame = bytes / encoded.max(1);
+        println!(
+            "{:>10}: {:>8.1} frames/s, {:>7.1} KB a frame, {:>6.2} MB/s at {} FPS",
+            format!("{:?}", mode),
+            fps,
+            per_frame as f64 / 1024.0,
+            (per_frame * DEFAULT_FPS as usize) as f64 / 1_000_000.0,
+            DEFAULT_FPS
+        );
+        mode = mode.next();
+        if mode == RenderMode::default() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,191 @@
+// Client-side helper for playing with a gamepad over SSH.
+//
+// Usage: ansicraft-gamepad [--device /dev/input/js0] -- <ssh arguments>
+//
+// Runs ssh with its stdin connected to us, forwards the local keyboard/mouse input
+// unchanged and injects the gamepad state using the protocol described in
+// gamepad.rs.  The gamepad is read through the Linux joystick API, with the
+// axis/button numbering the xpad driver uses for Xbox style controllers.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crossterm::terminal;
+use minecraft_terminal_viewer::gamepad::{self, ControlMessage, GamepadState};
+
+const DEFAULT_DEVICE: &str = "/dev/input/js0";
+const SEND_INTERVAL: Duration = Duration::from_millis(33);
+
+// struct js_event from linux/joystick.h
+const JS_EVENT_BUTTON: u8 = 0x01;
+const JS_EVENT_AXIS: u8 = 0x02;
+const JS_EVENT_INIT: u8 = 0x80;
+
+// Triggers are reported as axes; past this they count as a button press
+const TRIGGER_THRESHOLD: i16 = 0;
+
+fn apply_joystick_event(state: &mut GamepadState, kind: u8, number: u8, value: i16) {
+    match kind & !JS_EVENT_INIT {
+        JS_EVENT_AXIS => match number {
+            0 => state.left_x = value,
+            1 => state.left_y = value,
+            3 => state.right_x = value,
+            4 => state.right_y = value,
+            2 | 5 => {
+                let button = if number == 2 { gamepad::BUTTON_LT } else { gamepad::BUTTON_RT };
+                set_button(state, button, value > TRIGGER_THRESHOLD);
+            }
+            _ => {}
+        },
+        JS_EVENT_BUTTON => {
+            let button = match number {
+                0 => gamepad::BUTTON_A,
+                1 => gamepad::BUTTON_B,
+                2 => gamepad::BUTTON_X,
+                3 => gamepad::BUTTON_Y,
+                4 => gamepad::BUTTON_LB,
+                5 => gamepad::BUTTON_RB,
+                6 => gamepad::BUTTON_BACK,
+                7 => gamepad::BUTTON_START,
+                9 => gamepad::BUTTON_LSTICK,
+                10 => gamepad::BUTTON_RSTICK,
+                _ => return,
+            };
+            set_button(state, button, value != 0);
+        }
+        _ => {}
+    }
+}
+
+fn set_button(state: &mut GamepadState, button: u16, pressed: bool) {
+    if pressed {
+        state.buttons |= button;
+    } else {
+        state.buttons &= !button;
+    }
+}
+
+fn read_joystick(mut device: File, state: Arc<Mutex<GamepadState>>) -> io::Result<()> {
+    let mut event = [0u8; 8];
+    loop {
+        device.read_exact(&mut event)?;
+        let value = i16::from_ne_bytes([event[4], event[5]]);
+        let mut state = state.lock().unwrap();
+        apply_joystick_event(&mut state, event[6], event[7], value);
+    }
+}
+
+fn sticks_deflected(state: &GamepadState) -> bool {
+    [state.left_x, state.left_y, state.right_x, state.right_y]
+        .iter()
+        .any(|axis| *axis != 0)
+}
+
+// Keeps the terminal raw until it's dropped, however main returns
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn main() -> io::Result<()> {
+    let mut args = std::env::args().skip(1).peekable();
+    let mut device_path = DEFAULT_DEVICE.to_owned();
+    let mut ssh_args = vec![];
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--device" => device_path = args.next().unwrap_or(device_path),
+            "--" => ssh_args.extend(args.by_ref()),
+            _ => ssh_args.push(arg),
+        }
+    }
+    if ssh_args.is_empty() {
+        eprintln!("Usage: ansicraft-gamepad [--device {}] -- <ssh arguments>", DEFAULT_DEVICE);
+        std::process::exit(2);
+    }
+
+    let device = File::open(&device_path).map_err(|e| {
+        io::Error::new(e.kind(), format!("Could not open gamepad {}: {}", device_path, e))
+    })?;
+
+    // -tt forces a pty on the server even though our stdin isn't a terminal
+    let mut ssh = Command::new("ssh")
+        .arg("-tt")
+        .args(&ssh_args)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    let ssh_stdin = Arc::new(Mutex::new(ssh.stdin.take().unwrap()));
+
+    let raw_mode = RawMode::enable()?;
+
+    let pad_state = Arc::new(Mutex::new(GamepadState::default()));
+    let joystick_state = pad_state.clone();
+    thread::spawn(move || {
+        if let Err(e) = read_joystick(device, joystick_state) {
+            eprintln!("Gamepad disconnected: {}\r", e);
+        }
+    });
+
+    // Local keyboard and mouse input goes through untouched
+    let keyboard_stdin = ssh_stdin.clone();
+    thread::spawn(move || {
+        let mut buf = [0u8; 1024];
+        let mut stdin = io::stdin();
+        while let Ok(n) = stdin.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            let mut ssh_stdin = keyboard_stdin.lock().unwrap();
+            if ssh_stdin.write_all(&buf[..n]).and_then(|_| ssh_stdin.flush()).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut last_sent = GamepadState::default();
+    let mut last_size = None;
+    let status = loop {
+        if let Some(status) = ssh.try_wait()? {
+            break status;
+        }
+
+        let mut messages = vec![];
+        if let Ok((cols, rows)) = terminal::size()
+            && last_size != Some((cols, rows))
+        {
+            last_size = Some((cols, rows));
+            messages.push(ControlMessage::Resize { cols, rows });
+        }
+        let state = *pad_state.lock().unwrap();
+        if state != last_sent || sticks_deflected(&state) {
+            last_sent = state;
+            messages.push(ControlMessage::Pad(state));
+        }
+
+        if !messages.is_empty() {
+            let mut ssh_stdin = ssh_stdin.lock().unwrap();
+            for message in messages {
+                let _ = ssh_stdin.write_all(message.encode().as_bytes());
+            }
+            let _ = ssh_stdin.flush();
+        }
+        thread::sleep(SEND_INTERVAL);
+    };
+
+    // exit doesn't run destructors
+    drop(raw_mode);
+    std::process::exit(status.code().unwrap_or(1));
+}
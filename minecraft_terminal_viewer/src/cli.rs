@@ -0,0 +1,318 @@
+// The command line.  Without a subcommand, --local or --serve say which of those
+// to do; with neither it plays in this terminal if stdin is one and serves over SSH
+// otherwise, like it always has, saying so when it serves.  Flags override the
+// server's config, which their ANSICRAFT_* environment variables set too (handier
+// in a container).  Builds without the server feature only have `local`.
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::config::{self, AppConfig, LogFormat, LogRotation, RenderMode};
+
+#[derive(Parser)]
+#[command(name = "termcast", about = "Plays Minecraft (or any X program) in a terminal, here or over SSH")]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[arg(long, conflicts_with = "serve", help = "Play in this terminal, like `local`")]
+    pub local: bool,
+    #[arg(long, help = "Let players connect over SSH, like `serve`")]
+    pub serve: bool,
+    #[arg(
+        long,
+        global = true,
+        help = "Config file, of NAME=value lines or TOML if it ends in .toml [env: ANSICRAFT_CONFIG]"
+    )]
+    pub config: Option<std::path::PathBuf>,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum Command {
+    #[cfg(feature = "server")]
+    #[command(about = "Lets players connect over SSH and play")]
+    Serve(ServeArgs),
+    #[command(about = "Plays in this terminal, on a display that's already running")]
+    Local(LocalArgs),
+    #[cfg(feature = "server")]
+    #[command(about = "Stops a server started with `serve --daemon`, and its games")]
+    Stop(StopArgs),
+    #[cfg(feature = "server")]
+    #[command(about = "Runs admin commands on the server running here, or shows its dashboard")]
+    Admin(AdminArgs),
+    #[cfg(feature = "server")]
+    #[command(about = "Plays games for a gateway, which players connect to instead")]
+    Worker(WorkerArgs),
+    #[cfg(unix)]
+    #[command(about = "Checks that everything sessions need is installed and working")]
+    Doctor,
+    #[command(about = "Measures how fast frames are encoded in each color mode")]
+    Bench(BenchArgs),
+}
+
+// How the game's shown, which players can still change while they play
+#[derive(Args, Clone)]
+pub struct RenderArgs {
+    #[arg(long, help = "Frame rate to capture at [env: ANSICRAFT_FPS] [default: 30]")]
+    pub fps: Option<u32>,
+    #[arg(
+        long,
+        value_enum,
+        help = "How frames are encoded [env: ANSICRAFT_COLOR] [default: the best the terminal can show]"
+    )]
+    pub color: Option<RenderMode>,
+    #[arg(long, help = "ffmpeg to capture the game with [env: ANSICRAFT_FFMPEG] [default: ffmpeg]")]
+    pub ffmpeg: Option<String>,
+}
+
+impl RenderArgs {
+    pub fn apply(&self, config: &mut AppConfig) {
+        if let Some(fps) = self.fps {
+            config.render.fps = fps.max(1);
+        }
+        if let Some(color) = self.color {
+            config.render.mode = color;
+            config.detect_color = false;
+        }
+        if let Some(ffmpeg) = &self.ffmpeg {
+            config.ffmpeg = ffmpeg.clone();
+        }
+    }
+}
+
+// Which displays sessions play on, and how many at once
+#[cfg(feature = "server")]
+#[derive(Args, Clone)]
+pub struct DisplayArgs {
+    #[arg(
+        long,
+        help = "Sessions at once, when displays are started for them [env: ANSICRAFT_MAX_SESSIONS] [default: 10]"
+    )]
+    pub max_sessions: Option<u32>,
+    #[arg(
+        long,
+        help = "Displays in each pool, like survival=1-6,creative=7-9 [env: ANSICRAFT_POOLS] [default: every display]"
+    )]
+    pub pools: Option<String>,
+}
+
+#[cfg(feature = "server")]
+impl DisplayArgs {
+    pub fn apply(&self, config: &mut AppConfig) {
+        if let Some(sessions) = self.max_sessions {
+            config.max_sessions = sessions;
+        }
+        if let Some(pools) = &self.pools {
+            config.pools = Some(pools.clone());
+        }
+    }
+}
+
+// Which log lines are kept, and where they go
+#[derive(Args, Clone)]
+pub struct LogArgs {
+    #[arg(long, help = "Log level, with levels for modules after it, like info,russh=warn [env: ANSICRAFT_LOG]")]
+    pub log: Option<String>,
+    #[arg(long, value_enum, help = "How log lines are written [env: ANSICRAFT_LOG_FORMAT] [default: pretty]")]
+    pub log_format: Option<LogFormat>,
+    #[arg(long, help = "File to log to instead of stderr [env: ANSICRAFT_LOG_FILE]")]
+    pub log_file: Option<std::path::PathBuf>,
+    #[arg(long, value_enum, help = "How often to start a new log file [env: ANSICRAFT_LOG_ROTATION] [default: daily]")]
+    pub log_rotation: Option<LogRotation>,
+}
+
+impl LogArgs {
+    pub fn apply(&self, config: &mut AppConfig) {
+        if let Some(filter) = &self.log {
+            config.log_filter = filter.clone();
+        }
+        if let Some(format) = self.log_format {
+            config.log_format = format;
+        }
+        if let Some(file) = &self.log_file {
+            config.log_file = Some(file.clone());
+        }
+        if let Some(rotation) = self.log_rotation {
+            config.log_rotation = rotation;
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+#[derive(Parser, Clone)]
+pub struct ServeArgs {
+    #[arg(long, help = "Port to listen for SSH on [env: ANSICRAFT_PORT] [default: 2222]")]
+    pub port: Option<u16>,
+    #[arg(
+        long,
+        help = "The server's SSH key, made if it isn't there [env: ANSICRAFT_HOST_KEY] [default: ssh_server_key]"
+    )]
+    pub host_key: Option<std::path::PathBuf>,
+    #[arg(long, help = "Serve in the background, logging to termcast.log unless --log-file says otherwise")]
+    pub daemon: bool,
+    #[arg(
+        long,
+        env = "ANSICRAFT_PIDFILE",
+        default_value = "termcast.pid",
+        help = "Where --daemon writes its process ID"
+    )]
+    pub pidfile: std::path::PathBuf,
+    #[command(flatten)]
+    pub displays: DisplayArgs,
+    #[command(flatten)]
+    pub render: RenderArgs,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[cfg(feature = "server")]
+impl ServeArgs {
+    pub fn apply(&self, config: &mut AppConfig) {
+        if let Some(port) = self.port {
+            config.port = port;
+        }
+        if let Some(host_key) = &self.host_key {
+            config.host_key = host_key.clone();
+        }
+        self.displays.apply(config);
+        self.render.apply(config);
+        self.log.apply(config);
+    }
+}
+
+#[derive(Parser, Clone)]
+pub struct LocalArgs {
+    #[cfg(unix)]
+    #[arg(long, env = "ANSICRAFT_DISPLAY", default_value = ":1", help = "X display the game runs on")]
+    pub display: String,
+    #[cfg(unix)]
+    #[arg(long, env = "ANSICRAFT_USERNAME", default_value = "docker", help = "Name to play as")]
+    pub username: String,
+    #[cfg(unix)]
+    #[arg(long, help = "Server to join, or none for singleplayer [env: MINECRAFT_SERVER_ADDRESS]")]
+    pub server: Option<String>,
+    // Windows has no displays to run the game on, so it plays a window that's open already
+    #[cfg(windows)]
+    #[arg(long, env = "ANSICRAFT_WINDOW", help = "Title of the window to play, instead of the whole screen")]
+    pub window: Option<String>,
+    #[arg(long, help = "Columns wide to show the game, instead of following the terminal's size")]
+    pub width: Option<usize>,
+    #[command(flatten)]
+    pub render: RenderArgs,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[cfg(feature = "server")]
+#[derive(Args, Clone)]
+pub struct StopArgs {
+    #[arg(long, env = "ANSICRAFT_PIDFILE", default_value = "termcast.pid", help = "The server's pidfile")]
+    pub pidfile: std::path::PathBuf,
+}
+
+#[cfg(feature = "server")]
+#[derive(Args, Clone)]
+pub struct AdminArgs {
+    #[arg(long, help = "The server's admin socket [env: ANSICRAFT_ADMIN_SOCKET] [default: admin.sock]")]
+    pub socket: Option<std::path::PathBuf>,
+    #[arg(help = "What `admin` over SSH takes, like queue or drain [default: dashboard]")]
+    pub command: Vec<String>,
+}
+
+#[cfg(feature = "server")]
+#[derive(Args, Clone)]
+pub struct WorkerArgs {
+    #[arg(long, help = "Port to take games from the gateway on [env: ANSICRAFT_WORKER_PORT] [default: 2223]")]
+    pub port: Option<u16>,
+    #[command(flatten)]
+    pub displays: DisplayArgs,
+    #[command(flatten)]
+    pub render: RenderArgs,
+    #[command(flatten)]
+    pub log: LogArgs,
+}
+
+#[derive(Args, Clone)]
+pub struct BenchArgs {
+    #[arg(long, default_value_t = 160, help = "Width in columns to encode at")]
+    pub width: usize,
+    #[arg(long, default_value_t = 3, help = "Seconds to spend on each color mode")]
+    pub seconds: u64,
+}
+
+impl Command {
+    // Overrides the config with the flags given
+    pub fn apply(&self, config: &mut AppConfig) {
+        match self {
+            #[cfg(feature = "server")]
+            Command::Serve(args) => args.apply(config),
+            Command::Local(args) => {
+                args.render.apply(config);
+                args.log.apply(config);
+            }
+            #[cfg(feature = "server")]
+            Command::Stop(_) => {}
+            #[cfg(feature = "server")]
+            Command::Admin(args) => {
+                if let Some(socket) = &args.socket {
+                    config.admin_socket = socket.clone();
+                }
+            }
+            #[cfg(feature = "server")]
+            Command::Worker(args) => {
+                if let Some(port) = args.port {
+                    config.worker_port = port;
+                }
+                // Only a gateway has workers, even if it shares the config file
+                config.workers.clear();
+                args.displays.apply(config);
+                args.render.apply(config);
+                args.log.apply(config);
+            }
+            #[cfg(unix)]
+            Command::Doctor => {}
+            Command::Bench(_) => {}
+        }
+    }
+}
+
+// What to do, which without a subcommand or mode flag goes by whether this is a terminal
+pub fn command(terminal: bool) -> Command {
+    let cli = Cli::parse();
+    if let Some(path) = cli.config {
+        config::use_file(path);
+    }
+    if let Some(command) = cli.command {
+        return command;
+    }
+    // Only the environment's left to fill in the flags
+    let program = std::env::args().take(1);
+    let local = match (cli.local, cli.serve) {
+        (true, _) => true,
+        (_, true) => false,
+        // A build without the server can only play here
+        _ if !cfg!(feature = "server") => true,
+        _ => {
+            if !terminal {
+                // Piping something in shouldn't quietly start a server
+                eprintln!("stdin isn't a terminal, so serving over SSH (run with --local to play here instead)");
+            }
+            terminal
+        }
+    };
+    if local {
+        Command::Local(LocalArgs::parse_from(program))
+    } else {
+        serve(program)
+    }
+}
+
+#[cfg(feature = "server")]
+fn serve(program: impl Iterator<Item = String>) -> Command {
+    Command::Serve(ServeArgs::parse_from(program))
+}
+
+#[cfg(not(feature = "server"))]
+fn serve(_program: impl Iterator<Item = String>) -> Command {
+    eprintln!("This termcast was built without the server feature, so it can't serve over SSH");
+    std::process::exit(2);
+}
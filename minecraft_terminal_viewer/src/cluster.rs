@@ -0,0 +1,616 @@
+// Playing games on other machines.  A gateway takes every player's SSH connection,
+// and plays their games on workers (`termcast worker`), so there can be more games
+// than one machine can run while players still connect to one address.  Each of a
+// worker's slots is a resource in the gateway's pools, queued for like a display.
+//
+// A game gets a TCP connection to its worker of its own, carrying messages that are
+// a kind byte, a big-endian u32 length and the payload: the game and the player's
+// settings to start with (as JSON), their input, resizes, whether others are
+// waiting and when the player needs a whole frame one way, and frames and how it
+// went the other.  The worker opens each connection with a random challenge, and
+// only takes a game from a gateway that answers it with an HMAC of it keyed with
+// the token they share, so the token itself never goes over the wire.  The rest
+// is plaintext, so it still belongs on a private network.
+
+use std::collections::{BTreeSet, HashSet};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, Once, PoisonError};
+use std::time::{Duration, Instant};
+
+use hmac::{Hmac, Mac};
+use rand_core::{OsRng, RngCore};
+use serde_json::json;
+use sha2::Sha256;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::config::{self, AppConfig, InputSettings, RenderSettings, TerminalSize};
+use crate::minecraft::{ReclaimPolicy, ReclaimReason, SessionSummary};
+use crate::plugins::SessionInfo;
+use crate::queueing::{Provision, ResourceDescriptor};
+use crate::render::{Screen, Streamed};
+use crate::session::{self, SessionState};
+use crate::session_log::SessionLogs;
+use crate::session_settings::{self, SessionSettings};
+use crate::xdo::InputSource;
+
+// From the gateway
+const PROOF: u8 = b'A';
+const START: u8 = b'S';
+const INPUT: u8 = b'I';
+const RESIZE: u8 = b'R';
+const WAITING: u8 = b'W';
+const NOTICE: u8 = b'N';
+const STOP: u8 = b'Q';
+const REPAINT: u8 = b'D';
+const PING: u8 = b'P';
+// From a worker
+const CHALLENGE: u8 = b'C';
+const SHOW: u8 = b'F';
+const ENDED: u8 = b'E';
+const REFUSED: u8 = b'X';
+const PONG: u8 = b'O';
+
+// Far more than a frame ever is, so a bad length can't have us allocate gigabytes
+const MAX_MESSAGE: usize = 16 * 1024 * 1024;
+// All a worker reads before the gateway's proved itself, which is an HMAC-SHA256
+const PROOF_LENGTH: usize = 32;
+
+// A game as the player's session asks for it, wherever it's played
+#[derive(Clone)]
+pub(crate) struct Game {
+    pub session_info: SessionInfo,
+    pub server_address: String,
+    pub version: String,
+    pub world: Option<String>,
+    // The screen size the player asked for, if they did
+    pub size: Option<(u16, u16)>,
+    pub render_settings: Arc<Mutex<RenderSettings>>,
+    pub input_settings: Arc<Mutex<InputSettings>>,
+    pub logs: SessionLogs,
+    pub reclaim_policy: ReclaimPolicy,
+}
+
+impl Game {
+    fn settings(&self) -> SessionSettings {
+        SessionSettings {
+            size: self.size,
+            render: self.render_settings.lock().unwrap_or_else(PoisonError::into_inner).clone(),
+            input: self.input_settings.lock().unwrap_or_else(PoisonError::into_inner).clone(),
+        }
+    }
+}
+
+async fn send(writer: &mut (impl AsyncWrite + Unpin), kind: u8, payload: &[u8]) -> io::Result<()> {
+    // All at once, so a frame's never split up by anything sent alongside it
+    let mut message = Vec::with_capacity(payload.len() + 5);
+    message.push(kind);
+    message.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    message.extend_from_slice(payload);
+    writer.write_all(&message).await
+}
+
+async fn receive(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<(u8, Vec<u8>)> {
+    receive_at_most(reader, MAX_MESSAGE).await
+}
+
+// Like receive, refusing anything longer before making room for it
+async fn receive_at_most(reader: &mut (impl AsyncRead + Unpin), limit: usize) -> io::Result<(u8, Vec<u8>)> {
+    let kind = reader.read_u8().await?;
+    let length = reader.read_u32().await? as usize;
+    if length > limit {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} byte message", length)));
+    }
+    let mut payload = vec![0; length];
+    reader.read_exact(&mut payload).await?;
+    Ok((kind, payload))
+}
+
+// The HMAC of a worker's challenge, keyed with the cluster token
+fn prove(token: &str, challenge: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(token.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(challenge);
+    mac
+}
+
+// The challenge a worker opens every connection with
+async fn challenge(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Vec<u8>> {
+    let receiving = tokio::time::timeout(config::WORKER_TIMEOUT, receive(reader));
+    match receiving.await.unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into()))? {
+        (CHALLENGE, challenge) => Ok(challenge),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "not a worker")),
+    }
+}
+
+// Messages as they arrive, until the connection's closed.  Reading one isn't
+// cancel safe, so it's done on a task of its own rather than in a select.
+fn read_messages(mut reader: OwnedReadHalf) -> mpsc::UnboundedReceiver<(u8, Vec<u8>)> {
+    let (messages_tx, messages_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok(message) = receive(&mut reader).await {
+            if messages_tx.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    messages_rx
+}
+
+// The slots in ANSICRAFT_WORKERS, like `10.0.0.2:2223/4,creative=10.0.0.3:2223`:
+// each worker's address, with how many games it plays at once (one unless it's
+// given) and the pool they're in (the given one unless it says), numbered from
+// first_id
+pub(crate) fn worker_resources(
+    spec: &str,
+    default_pool: &str,
+    first_id: u32,
+) -> Result<Vec<ResourceDescriptor>, String> {
+    let mut resources = vec![];
+    for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (pool, worker) = entry.split_once('=').unwrap_or((default_pool, entry));
+        let (address, slots) = match worker.split_once('/') {
+            Some((address, slots)) => match slots.trim().parse::<u32>() {
+                Ok(slots) => (address.trim(), slots),
+                Err(_) => return Err(format!("{:?} isn't a number of slots", slots)),
+            },
+            None => (worker.trim(), 1),
+        };
+        if !address.contains(':') {
+            return Err(format!("{:?} needs a port", address));
+        }
+        for _ in 0..slots {
+            resources.push(ResourceDescriptor {
+                id: first_id + resources.len() as u32,
+                pool: pool.trim().to_owned(),
+                display: address.to_owned(),
+                ports: vec![],
+                gpu: false,
+                resolution: (config::GAME_WIDTH, config::GAME_HEIGHT),
+                weight: 1,
+                provision: Provision::Worker,
+                data_dir: None,
+            });
+        }
+    }
+    Ok(resources)
+}
+
+// Workers that didn't answer last time, whose slots aren't handed out until they do
+static UNREACHABLE: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(Default::default);
+// The workers to ping, which the autoscaler adds to and takes away from
+static WATCHED: LazyLock<Mutex<BTreeSet<String>>> = LazyLock::new(Default::default);
+static WATCHING: Once = Once::new();
+
+pub(crate) fn reachable(address: &str) -> bool {
+    !UNREACHABLE.lock().unwrap_or_else(PoisonError::into_inner).contains(address)
+}
+
+fn mark(address: &str, reachable: bool) {
+    let mut unreachable = UNREACHABLE.lock().unwrap_or_else(PoisonError::into_inner);
+    if reachable {
+        if unreachable.remove(address) {
+            info!("Worker {} is answering again", address);
+        }
+    } else if unreachable.insert(address.to_owned()) {
+        warn!("Worker {} isn't answering, so it gets no games until it does", address);
+    }
+}
+
+// Pings the workers every so often from now on, along with any already watched
+pub(crate) fn watch(addresses: impl IntoIterator<Item = String>) {
+    WATCHED.lock().unwrap_or_else(PoisonError::into_inner).extend(addresses);
+    WATCHING.call_once(|| {
+        tokio::spawn(async {
+            let mut checks = tokio::time::interval(config::WORKER_CHECK_INTERVAL);
+            loop {
+                checks.tick().await;
+                let addresses = WATCHED.lock().unwrap_or_else(PoisonError::into_inner).clone();
+                for address in &addresses {
+                    mark(address, ping(address).await.is_ok());
+                }
+            }
+        });
+    });
+}
+
+// Stops watching a worker that's gone for good
+pub(crate) fn forget(address: &str) {
+    WATCHED.lock().unwrap_or_else(PoisonError::into_inner).remove(address);
+    UNREACHABLE.lock().unwrap_or_else(PoisonError::into_inner).remove(address);
+}
+
+async fn ping(address: &str) -> io::Result<()> {
+    let exchange = async {
+        let mut stream = TcpStream::connect(address).await?;
+        challenge(&mut stream).await?;
+        send(&mut stream, PING, &[]).await?;
+        match receive(&mut stream).await? {
+            (PONG, _) => Ok(()),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "not a worker")),
+        }
+    };
+    tokio::time::timeout(config::WORKER_TIMEOUT, exchange)
+        .await
+        .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into()))
+}
+
+// Plays the game on the worker the resource's a slot on, showing it on the screen
+// and passing the player's input and resizes along, like `minecraft::run` would
+// here.  A worker that can't be reached, turns the game down or goes away partway
+// through counts as a lost display, so the session moves to another slot.
+pub(crate) async fn play<S: Screen>(
+    resource: &ResourceDescriptor,
+    token: &str,
+    game: Game,
+    running: Arc<AtomicBool>,
+    mut screen: S,
+    input: InputSource,
+    terminal_size: Arc<Mutex<TerminalSize>>,
+) -> Option<SessionSummary> {
+    let address = &resource.display;
+    let username = &game.session_info.username;
+    let started = Instant::now();
+    let lost = |frames| SessionSummary {
+        played: started.elapsed(),
+        frames,
+        display_lost: true,
+    };
+
+    let connecting = tokio::time::timeout(config::WORKER_TIMEOUT, TcpStream::connect(address));
+    let stream = match connecting.await.unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into())) {
+        Ok(stream) => stream,
+        Err(e) => {
+            error!("Error reaching worker {} for {}'s game: {}", address, username, e);
+            mark(address, false);
+            return Some(lost(0));
+        }
+    };
+    let _ = stream.set_nodelay(true);
+    let (mut reader, mut writer) = stream.into_split();
+    let challenge = match challenge(&mut reader).await {
+        Ok(challenge) => challenge,
+        Err(e) => {
+            error!("Error reaching worker {} for {}'s game: {}", address, username, e);
+            mark(address, false);
+            return Some(lost(0));
+        }
+    };
+    let mut sent_size = {
+        let size = terminal_size.lock().unwrap_or_else(PoisonError::into_inner);
+        (size.target_width, size.target_height)
+    };
+    let start = json!({
+        "pool": resource.pool,
+        "username": username,
+        "fingerprint": game.session_info.fingerprint,
+        "source_ip": game.session_info.source_ip.map(|ip| ip.to_string()),
+        "server_address": game.server_address,
+        "version": game.version,
+        "world": game.world,
+        "settings": session_settings::lines(&game.settings()),
+        "afk_timeout": game.reclaim_policy.afk_timeout.as_secs(),
+        "max_duration": game.reclaim_policy.max_duration.map(|max| max.as_secs()),
+        "width": sent_size.0,
+        "height": sent_size.1,
+    });
+    let proof = prove(token, &challenge).finalize().into_bytes();
+    let sent = match send(&mut writer, PROOF, &proof).await {
+        Ok(()) => send(&mut writer, START, start.to_string().as_bytes()).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = sent {
+        error!("Error sending {}'s game to worker {}: {}", username, address, e);
+        return Some(lost(0));
+    }
+    let mut messages = read_messages(reader);
+    info!("Playing {}'s game on worker {}", username, address);
+
+    // Stands in for the game's own state on the admin dashboard, so it can stop
+    // the game or show the player a message like it would one played here
+    let state = SessionState {
+        running: running.clone(),
+        terminal_size: terminal_size.clone(),
+        input_settings: game.input_settings.clone(),
+        render_settings: game.render_settings.clone(),
+        overlay: Default::default(),
+        last_input: Arc::new(Mutex::new(Instant::now())),
+        game_log: Default::default(),
+        dead: Default::default(),
+        logs: game.logs.clone(),
+        session_info: Arc::new(game.session_info.clone()),
+    };
+    let streamed = Arc::new(Streamed::default());
+    let _listed = session::list(state.clone(), streamed.clone());
+
+    let mut syncs = tokio::time::interval(config::WORKER_SYNC_INTERVAL);
+    let mut stopping = false;
+    let mut reading_input = true;
+    let mut sent_waiting = false;
+    let mut sent_notice = None;
+    let ended = loop {
+        tokio::select! {
+            message = messages.recv() => match message {
+                Some((SHOW, bytes)) => {
+                    streamed.frames.fetch_add(1, Ordering::Relaxed);
+                    streamed.bytes.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+                    let _ = screen.show(bytes).await;
+                }
+                Some((ENDED, payload)) => break Some(payload),
+                Some((REFUSED, reason)) => {
+                    warn!("Worker {} turned down {}'s game: {}", address, username, String::from_utf8_lossy(&reason));
+                    break None;
+                }
+                Some(_) => {}
+                None => {
+                    if !stopping {
+                        error!("Lost worker {} during {}'s game", address, username);
+                        mark(address, false);
+                    }
+                    break None;
+                }
+            },
+            data = async { input.lock().await.recv().await }, if reading_input => match data {
+                Some(data) => {
+                    let _ = send(&mut writer, INPUT, &data).await;
+                }
+                None => reading_input = false,
+            },
+            _ = syncs.tick() => {
+                let mut changes = vec![];
+                if screen.take_repaint() {
+                    changes.push((REPAINT, vec![]));
+                }
+                if !stopping && !running.load(Ordering::SeqCst) {
+                    stopping = true;
+                    changes.push((STOP, vec![]));
+                }
+                let size = {
+                    let size = terminal_size.lock().unwrap_or_else(PoisonError::into_inner);
+                    (size.target_width, size.target_height)
+                };
+                if size != sent_size {
+                    sent_size = size;
+                    changes.push((RESIZE, format!("{} {}", size.0, size.1).into_bytes()));
+                }
+                let waiting = (game.reclaim_policy.others_waiting)();
+                if waiting != sent_waiting {
+                    sent_waiting = waiting;
+                    changes.push((WAITING, vec![waiting as u8]));
+                }
+                let notice = state.overlay.lock().unwrap_or_else(PoisonError::into_inner).notice().map(str::to_owned);
+                if notice != sent_notice {
+                    changes.push((NOTICE, notice.clone().unwrap_or_default().into_bytes()));
+                    sent_notice = notice;
+                }
+                for (kind, payload) in changes {
+                    let _ = send(&mut writer, kind, &payload).await;
+                }
+            }
+        }
+    };
+
+    let frames = streamed.frames.load(Ordering::Relaxed);
+    let Some(ended) = ended else {
+        // Going away once it was told to stop is just how it ends
+        return Some(SessionSummary {
+            display_lost: !stopping,
+            ..lost(frames)
+        });
+    };
+    let ended: serde_json::Value = serde_json::from_slice(&ended).unwrap_or_default();
+    // Whatever the player changed while it was going, for their next game
+    let mut settings = game.settings();
+    session_settings::apply(&mut settings, ended["settings"].as_str().unwrap_or_default());
+    *game.render_settings.lock().unwrap_or_else(PoisonError::into_inner) = settings.render;
+    *game.input_settings.lock().unwrap_or_else(PoisonError::into_inner) = settings.input;
+    *game.reclaim_policy.reclaimed.lock().unwrap_or_else(PoisonError::into_inner) = match ended["reclaimed"].as_str() {
+        Some("idle") => Some(ReclaimReason::Idle),
+        Some("time_up") => Some(ReclaimReason::TimeUp),
+        _ => None,
+    };
+    if ended["failed"].as_bool().unwrap_or(false) {
+        return None;
+    }
+    Some(SessionSummary {
+        played: Duration::from_millis(ended["played"].as_u64().unwrap_or_default()),
+        frames: ended["frames"].as_u64().unwrap_or(frames),
+        display_lost: ended["display_lost"].as_bool().unwrap_or(false),
+    })
+}
+
+// A game the gateway's sent a worker, while it's played there
+pub(crate) struct Dispatch {
+    pub pool: String,
+    pub game: Game,
+    // Cleared when the gateway stops the game, or goes away
+    pub running: Arc<AtomicBool>,
+    pub input: InputSource,
+    pub terminal_size: Arc<Mutex<TerminalSize>>,
+    writer: Arc<tokio::sync::Mutex<OwnedWriteHalf>>,
+    // Set when the gateway wants a whole frame
+    repaint: Arc<AtomicBool>,
+}
+
+// Takes a connection from the gateway on a worker, answering its pings, and
+// returning the game it sent if it proved it had the token
+pub(crate) async fn accept(stream: TcpStream, settings: &AppConfig) -> Option<Dispatch> {
+    let peer = stream.peer_addr().ok()?;
+    let _ = stream.set_nodelay(true);
+    let (mut reader, mut writer) = stream.into_split();
+    let mut challenge = [0; 32];
+    OsRng.fill_bytes(&mut challenge);
+    send(&mut writer, CHALLENGE, &challenge).await.ok()?;
+    // Whoever this is hasn't proved anything yet, so they get little time and room
+    let receiving = tokio::time::timeout(config::WORKER_TIMEOUT, receive_at_most(&mut reader, PROOF_LENGTH));
+    let (kind, payload) = receiving.await.ok()?.ok()?;
+    if kind == PING {
+        let _ = send(&mut writer, PONG, &[]).await;
+        return None;
+    }
+    if kind != PROOF || prove(&settings.cluster_token, &challenge).verify_slice(&payload).is_err() {
+        warn!("Turning away a game from {}, which didn't have the cluster token", peer);
+        let _ = send(&mut writer, REFUSED, b"wrong cluster token").await;
+        return None;
+    }
+
+    let (kind, payload) = receive(&mut reader).await.ok()?;
+    let start: serde_json::Value = match serde_json::from_slice(&payload) {
+        Ok(start) if kind == START => start,
+        _ => {
+            warn!("Ignoring a connection from {} that didn't start with a game", peer);
+            return None;
+        }
+    };
+    let text = |key: &str| start[key].as_str().unwrap_or_default().to_owned();
+    let mut session = SessionSettings {
+        size: None,
+        render: RenderSettings::default(),
+        input: settings.input.clone(),
+    };
+    session_settings::apply(&mut session, start["settings"].as_str().unwrap_or_default());
+    let waiting = Arc::new(AtomicBool::new(false));
+    let session_info = SessionInfo {
+        username: text("username"),
+        fingerprint: text("fingerprint"),
+        source_ip: start["source_ip"].as_str().and_then(|ip| ip.parse().ok()),
+        display: None,
+    };
+    let game = Game {
+        logs: SessionLogs::create(settings.session_logs_dir.as_deref(), &session_info.fingerprint),
+        session_info,
+        server_address: text("server_address"),
+        version: text("version"),
+        world: start["world"].as_str().map(str::to_owned),
+        size: session.size,
+        render_settings: Arc::new(Mutex::new(session.render)),
+        input_settings: Arc::new(Mutex::new(session.input)),
+        reclaim_policy: ReclaimPolicy {
+            afk_timeout: Duration::from_secs(start["afk_timeout"].as_u64().unwrap_or_default()),
+            afk_warning: config::AFK_WARNING,
+            max_duration: start["max_duration"].as_u64().map(Duration::from_secs),
+            time_up_warning: config::TIME_UP_WARNING,
+            others_waiting: Arc::new({
+                let waiting = waiting.clone();
+                move || waiting.load(Ordering::SeqCst)
+            }),
+            reclaimed: Default::default(),
+        },
+    };
+    let dimension = |key: &str| start[key].as_u64().unwrap_or(10) as usize;
+    let terminal_size = Arc::new(Mutex::new(TerminalSize {
+        target_width: dimension("width"),
+        target_height: dimension("height"),
+    }));
+    let running = Arc::new(AtomicBool::new(true));
+    let repaint = Arc::new(AtomicBool::new(false));
+    let (input_tx, input_rx) = mpsc::unbounded_channel();
+
+    let (following, session_info, resized) = (running.clone(), game.session_info.clone(), terminal_size.clone());
+    let repainting = repaint.clone();
+    tokio::spawn(async move {
+        let mut shown = String::new();
+        while let Ok((kind, payload)) = receive(&mut reader).await {
+            match kind {
+                INPUT => {
+                    let _ = input_tx.send(payload);
+                }
+                RESIZE => {
+                    let size = String::from_utf8_lossy(&payload).into_owned();
+                    if let Some((Ok(width), Ok(height))) =
+                        size.split_once(' ').map(|(width, height)| (width.parse(), height.parse()))
+                    {
+                        *resized.lock().unwrap_or_else(PoisonError::into_inner) = TerminalSize {
+                            target_width: width,
+                            target_height: height,
+                        };
+                    }
+                }
+                WAITING => waiting.store(payload.first() == Some(&1), Ordering::SeqCst),
+                NOTICE => {
+                    let notice = String::from_utf8_lossy(&payload).into_owned();
+                    show_notice(&session_info, &shown, &notice);
+                    shown = notice;
+                }
+                REPAINT => repainting.store(true, Ordering::SeqCst),
+                STOP => break,
+                _ => {}
+            }
+        }
+        following.store(false, Ordering::SeqCst);
+    });
+
+    Some(Dispatch {
+        pool: text("pool"),
+        game,
+        running,
+        input: Arc::new(tokio::sync::Mutex::new(input_rx)),
+        terminal_size,
+        writer: Arc::new(tokio::sync::Mutex::new(writer)),
+        repaint,
+    })
+}
+
+// Shows a message from the gateway's admin dashboard on the game's own overlay, or
+// hides the last one
+fn show_notice(player: &SessionInfo, shown: &str, notice: &str) {
+    let games = session::live().into_iter().filter(|game| {
+        game.state.session_info.username == player.username && game.state.session_info.fingerprint == player.fingerprint
+    });
+    for game in games {
+        let mut overlay = game.state.overlay.lock().unwrap_or_else(PoisonError::into_inner);
+        if notice.is_empty() {
+            overlay.hide_notice_if(shown);
+        } else {
+            overlay.show_notice(notice.to_owned());
+        }
+    }
+}
+
+impl Dispatch {
+    // Where the game's frames go: back to the gateway, for it to pass on
+    pub(crate) fn screen(&self) -> Upstream {
+        Upstream(self.writer.clone(), self.repaint.clone())
+    }
+
+    // Tells the gateway the game can't be played here, so it tries somewhere else
+    pub(crate) async fn refuse(&self, reason: &str) {
+        let _ = send(&mut *self.writer.lock().await, REFUSED, reason.as_bytes()).await;
+    }
+
+    // Tells the gateway how the game went, and what the player changed
+    pub(crate) async fn finish(&self, summary: Option<SessionSummary>) {
+        let reclaimed = match *self.game.reclaim_policy.reclaimed.lock().unwrap_or_else(PoisonError::into_inner) {
+            Some(ReclaimReason::Idle) => Some("idle"),
+            Some(ReclaimReason::TimeUp) => Some("time_up"),
+            None => None,
+        };
+        let ended = json!({
+            "failed": summary.is_none(),
+            "played": summary.as_ref().map(|summary| summary.played.as_millis() as u64),
+            "frames": summary.as_ref().map(|summary| summary.frames),
+            "display_lost": summary.as_ref().is_some_and(|summary| summary.display_lost),
+            "reclaimed": reclaimed,
+            "settings": session_settings::lines(&self.game.settings()),
+        });
+        let _ = send(&mut *self.writer.lock().await, ENDED, ended.to_string().as_bytes()).await;
+    }
+}
+
+// A game's frames on their way back to the gateway
+pub(crate) struct Upstream(Arc<tokio::sync::Mutex<OwnedWriteHalf>>, Arc<AtomicBool>);
+
+impl Screen for Upstream {
+    async fn show(&mut self, bytes: Vec<u8>) -> io::Result<()> {
+        // A gateway that's gone stops the game, so the frame's just lost
+        let _ = send(&mut *self.0.lock().await, SHOW, &bytes).await;
+        Ok(())
+    }
+
+    fn take_repaint(&mut self) -> bool {
+        self.1.swap(false, Ordering::SeqCst)
+    }
+}
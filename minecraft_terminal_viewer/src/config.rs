@@ -1,16 +1,1437 @@
 // Game's native resolution
+// The screen size of the X servers, unless a pool says otherwise
 pub const GAME_WIDTH: u16 = 320;
 pub const GAME_HEIGHT: u16 = 200;
 
-// Platform-specific ffmpeg binary
+// Where players connect with SSH, unless --port says otherwise
+pub const SSH_PORT: u16 = 2222;
+
+// Platform-specific ffmpeg binary, unless ANSICRAFT_FFMPEG says otherwise
 #[cfg(target_os = "windows")]
 pub const FFMPEG_BINARY: &str = "ffmpeg.exe";
 #[cfg(not(target_os = "windows"))]
 pub const FFMPEG_BINARY: &str = "ffmpeg";
 
+// Game sound is streamed on AUDIO_BASE_PORT plus the session number.  In bell mode,
+// anything louder than AUDIO_BELL_THRESHOLD (in dB) rings the terminal bell, at
+// most once every AUDIO_BELL_INTERVAL.
+pub const AUDIO_BASE_PORT: u16 = 20000;
+pub const AUDIO_BELL_THRESHOLD: i32 = -20;
+pub const AUDIO_BELL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+// How long the port to listen on is shown when a game starts
+pub const AUDIO_NOTICE_DURATION: std::time::Duration = std::time::Duration::from_secs(20);
+
+// What's done with a game's sound (ANSICRAFT_AUDIO)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum AudioMode {
+    #[default]
+    Off,
+    Stream,
+    Bell,
+}
+
+// Where sessions' cgroups go, when their resources are limited
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+// What each game gets of the host (ANSICRAFT_SESSION_MEMORY_MAX and friends),
+// with nothing limited by default
+#[derive(Clone, Debug, Default)]
+pub struct ResourceLimits {
+    // Anything memory.max takes, like "3G"
+    pub memory_max: Option<String>,
+    // 1-10000, relative to the default of 100 every other cgroup has
+    pub cpu_weight: Option<u32>,
+    pub nice: Option<i32>,
+}
+
+impl ResourceLimits {
+    pub fn uses_cgroup(&self) -> bool {
+        self.memory_max.is_some() || self.cpu_weight.is_some()
+    }
+}
+
+// How many sessions a game rendering on a GPU counts as against the capacity
+pub const GPU_WEIGHT: usize = 2;
+
+// How games on displays tagged "gpu" render (ANSICRAFT_GPU_RENDER)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum GpuMode {
+    #[default]
+    Off,
+    // Through an X server running on the GPU
+    #[value(name = "virtualgl")]
+    VirtualGl,
+    // Straight on the GPU with EGL, without an X server for it
+    Egl,
+}
+
+// How long to wait before restarting a window manager that exited right away
+pub const WM_RESTART_DELAY: std::time::Duration = std::time::Duration::from_secs(5);
+
+// How many sessions can run at once when their displays are started for them
+pub const MAX_SESSIONS: u32 = 10;
+
+// For X servers started per session
+pub const XVFB_BINARY: &str = "Xvfb";
+pub const XVFB_START_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+// What each session's container runs (ANSICRAFT_SESSION_IMAGE), and how long it
+// gets to bring its display up
+pub const DOCKER_IMAGE: &str = "ansicraft";
+pub const DOCKER_START_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+// Pods can take a while longer, with an image to pull on a new node
+pub const KUBERNETES_START_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(180);
+
+// What starts each session's game somewhere of its own (ANSICRAFT_PROVISIONER),
+// instead of on a display here
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Provisioner {
+    Docker,
+    Kubernetes,
+}
+
 // Terminal size information
 #[derive(Clone, Default)]
 pub struct TerminalSize {
     pub target_width: usize,
     pub target_height: usize,
 }
+
+// How frames are encoded for the terminal, from most to least demanding
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum RenderMode {
+    #[default]
+    #[value(name = "truecolor")]
+    TrueColor,
+    #[value(name = "256")]
+    Ansi256,
+    #[value(name = "16")]
+    Ansi16,
+    Braille,
+    Ascii,
+}
+
+impl RenderMode {
+    pub fn next(self) -> Self {
+        match self {
+            RenderMode::TrueColor => RenderMode::Ansi256,
+            RenderMode::Ansi256 => RenderMode::Ansi16,
+            RenderMode::Ansi16 => RenderMode::Braille,
+            RenderMode::Braille => RenderMode::Ascii,
+            RenderMode::Ascii => RenderMode::TrueColor,
+        }
+    }
+
+    // The best a terminal can show, going by its TERM and COLORTERM, or None if
+    // they don't say
+    pub fn detect(term: &str, colorterm: &str) -> Option<Self> {
+        let term = term.to_ascii_lowercase();
+        if matches!(colorterm.to_ascii_lowercase().as_str(), "truecolor" | "24bit")
+            || term.ends_with("-direct")
+            || TRUECOLOR_TERMS.iter().any(|name| term.contains(name))
+        {
+            Some(RenderMode::TrueColor)
+        } else if term.contains("256color") {
+            Some(RenderMode::Ansi256)
+        } else if term == "dumb" {
+            Some(RenderMode::Ascii)
+        } else if !term.is_empty() {
+            Some(RenderMode::Ansi16)
+        } else {
+            None
+        }
+    }
+}
+
+// Terminals that always take 24-bit color, though their TERM doesn't say so and
+// COLORTERM doesn't make it over SSH unless it's set up to
+const TRUECOLOR_TERMS: [&str; 6] = ["truecolor", "24bit", "kitty", "alacritty", "wezterm", "ghostty"];
+
+// How log lines are written: as text, or a JSON object a line for log collectors
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Json,
+}
+
+// How often a log file is started afresh, with the time on the end of its name
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogRotation {
+    #[default]
+    Daily,
+    Hourly,
+    Never,
+}
+
+// Render settings that can be changed while a session is running
+#[derive(Clone, Debug)]
+pub struct RenderSettings {
+    pub mode: RenderMode,
+    pub fps: u32,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            mode: RenderMode::default(),
+            fps: DEFAULT_FPS,
+        }
+    }
+}
+
+impl RenderSettings {
+    // Steps the frame rate cap up or down the FPS_STEPS ladder
+    pub fn step_fps(&mut self, up: bool) {
+        self.fps = if up {
+            FPS_STEPS.iter().copied().find(|&fps| fps > self.fps).unwrap_or(self.fps)
+        } else {
+            FPS_STEPS.iter().rev().copied().find(|&fps| fps < self.fps).unwrap_or(self.fps)
+        };
+    }
+}
+
+// Capture frame rate, and the caps players can step through at runtime
+pub const DEFAULT_FPS: u32 = 30;
+pub const FPS_STEPS: [u32; 7] = [1, 2, 5, 10, 15, 20, 30];
+pub const FPS_DOWN_KEY: char = '[';
+pub const FPS_UP_KEY: char = ']';
+
+// Frames are captured no faster than the player's link takes them.  Once sending
+// them has taken most of the time between them for THROTTLE_AFTER, the frame rate
+// goes down a step, and back up one when they've gone easily for UNTHROTTLE_AFTER.
+pub const THROTTLE_AFTER: std::time::Duration = std::time::Duration::from_secs(2);
+pub const UNTHROTTLE_AFTER: std::time::Duration = std::time::Duration::from_secs(30);
+
+// Frames only redraw the cells that changed, but every so often the whole frame's
+// sent anyway, in case the terminal's lost track of something.  Up to DELTA_GAP
+// unchanged cells between changed ones are redrawn rather than moved past.
+pub const FULL_FRAME_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+pub const DELTA_GAP: usize = 2;
+
+// A second Escape within this window opens the local menu instead of reaching
+// the game, so a single Escape is delayed by this long
+pub const DOUBLE_ESCAPE_WINDOW: std::time::Duration = std::time::Duration::from_millis(300);
+
+// Toggles holding the left mouse button down, since terminals can't report a
+// held click reliably.  Z isn't bound to anything in vanilla Minecraft.
+pub const MINING_LATCH_KEY: char = 'z';
+
+// Key that cycles through the render modes (F8)
+pub const CYCLE_RENDER_MODE_KEY: u8 = 8;
+// Key that shows the game's output, to see why it's stuck (F9)
+pub const LOG_VIEWER_KEY: u8 = 9;
+
+// Relative mouse look tuning.  The dead zone is measured in terminal cells so
+// that single-cell jitter from the terminal's mouse reporting is ignored.
+pub const LOOK_DEAD_ZONE: u16 = 1;
+pub const LOOK_SCALE_X: f32 = 10.0;
+pub const LOOK_SCALE_Y: f32 = 10.0;
+
+// Terminals never report key releases.  Held keys are released once no repeat
+// has arrived for KEY_HOLD_TIMEOUT, and a second press of the same key within
+// KEY_REPEAT_WINDOW (long enough to cover the initial auto-repeat delay) is
+// treated as the start of auto-repeat.
+pub const KEY_HOLD_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(100);
+pub const KEY_REPEAT_WINDOW: std::time::Duration = std::time::Duration::from_millis(600);
+
+// Idle players get warned after AFK_TIMEOUT when someone is waiting for a slot,
+// then disconnected if they don't touch anything for AFK_WARNING
+pub const AFK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+pub const AFK_WARNING: std::time::Duration = std::time::Duration::from_secs(30);
+
+// How long before the end of a time-limited session the player is told about it,
+// and how long they get to decide whether to rejoin the queue afterwards
+pub const TIME_UP_WARNING: std::time::Duration = std::time::Duration::from_secs(60);
+pub const REJOIN_PROMPT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+// How long a player who drops out of a game has to reconnect and get straight back
+// in.  Games that can be paused are kept going (paused) for them until then.
+pub const RECONNECT_GRACE: std::time::Duration = std::time::Duration::from_secs(120);
+// How long after a session a player's next game still starts with the settings
+// they had (their color mode, frame rate and keys), e.g. after reconnecting
+pub const SESSION_SETTINGS_KEPT: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+// How often the admin dashboard looks at the sessions and queue again
+pub const DASHBOARD_REFRESH: std::time::Duration = std::time::Duration::from_secs(1);
+// How long a message from the admin dashboard stays on the player's screen
+pub const ADMIN_MESSAGE_DURATION: std::time::Duration = std::time::Duration::from_secs(15);
+
+// The port workers take games from the gateway on, how long the gateway gives one
+// to answer, how often it checks they're still there, and how often a game's
+// resizes and notices are passed on
+pub const WORKER_PORT: u16 = 2223;
+pub const WORKER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+pub const WORKER_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+pub const WORKER_SYNC_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+// How often the autoscaler looks at the queues, how long it leaves a pool after
+// starting a worker for it before starting another, how long a worker it started
+// has to be idle before it's stopped, and how many it starts at most
+pub const AUTOSCALE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+pub const SCALE_UP_COOLDOWN: std::time::Duration = std::time::Duration::from_secs(60);
+pub const SCALE_DOWN_IDLE: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+pub const MAX_SCALED_WORKERS: usize = 4;
+
+// Where `serve --daemon` logs when it's not told to log to a file, how long it's
+// watched after starting in case it stops straight away, and how long `stop` waits
+// for it to go
+pub const DAEMON_LOG_FILE: &str = "termcast.log";
+pub const DAEMON_START_CHECK: std::time::Duration = std::time::Duration::from_secs(1);
+pub const DAEMON_STOP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+// Each place that logs is kept to LOG_BURST lines every LOG_BURST_WINDOW, with a
+// line after saying how many more there were, so nothing in a loop floods the log
+pub const LOG_BURST: u32 = 20;
+pub const LOG_BURST_WINDOW: std::time::Duration = std::time::Duration::from_secs(10);
+// Log lines kept back while `local` is showing the game in the terminal they'd go to
+pub const LOG_LINES_HELD: usize = 500;
+
+// When a game loses its connection to the server (going by these lines in its
+// output), it's relaunched to reconnect, after RECONNECT_DELAY and up to
+// MAX_RECONNECTS times a session
+pub const DISCONNECT_PATTERNS: &[&str] = &["Client disconnected with reason", "Couldn't connect to server"];
+pub const MAX_RECONNECTS: u32 = 3;
+pub const RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(5);
+// How long a line of chat stays below the video
+pub const CHAT_LINE_DURATION: std::time::Duration = std::time::Duration::from_secs(30);
+// How the player's told about dying or being mentioned (ANSICRAFT_NOTIFY)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum NotifyMode {
+    Off,
+    #[default]
+    Bell,
+    Osc9,
+}
+// How the game's death messages carry on after the player's name
+pub const DEATH_MESSAGES: &[&str] = &[
+    "was ", "drowned", "died", "fell ", "burned", "blew up", "hit the ground", "tried to swim in lava",
+    "went up in flames", "went off with a bang", "starved", "suffocated", "walked into", "withered away",
+    "froze to death", "experienced kinetic energy", "discovered the floor was lava", "didn't want to live",
+    "left the confines of this world", "walked on danger zone",
+];
+// How long a game gets to show up on its display before the player is told
+// something might be wrong
+pub const APP_READY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(180);
+
+// A session's threads that can be restarted (like the one running ffmpeg) are, up
+// to MAX_TASK_RESTARTS times.  Once the session ends, its threads get
+// SUPERVISOR_STOP_TIMEOUT to finish before its processes are killed.
+pub const MAX_TASK_RESTARTS: u32 = 3;
+pub const TASK_RESTART_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+pub const SUPERVISOR_STOP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+// A game that crashes is restarted, up to MAX_RESTARTS times a session
+pub const MAX_RESTARTS: u32 = 2;
+// When a singleplayer session ends, the game is told to save and quit from its
+// menu first.  It should start within QUIT_START_TIMEOUT (the keys are pressed
+// again once if not) and say it's saved within SAVE_TIMEOUT, going by these lines.
+pub const QUIT_START_PATTERNS: &[&str] = &["Stopping singleplayer server", "Stopping server"];
+pub const SAVED_PATTERNS: &[&str] = &["All dimensions are saved", "All chunks are saved"];
+pub const QUIT_START_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+pub const SAVE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+// A display is checked on every DISPLAY_CHECK_INTERVAL, and once it's failed
+// DISPLAY_CHECK_FAILURES checks in a row (or capturing it keeps failing) the
+// session moves to another, up to MAX_MIGRATIONS times
+pub const DISPLAY_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+pub const DISPLAY_CHECK_FAILURES: u32 = 3;
+pub const MAX_MIGRATIONS: u32 = 2;
+// Lines of game output kept for the log viewer, and to print if it crashes
+pub const GAME_OUTPUT_LINES: usize = 500;
+// How much an operator's script can do in one call before it's stopped, since
+// it's called for every input and frame and mustn't hold them up
+pub const SCRIPT_MAX_OPERATIONS: u64 = 100_000;
+
+// How long players who connect with the `short` option can play once others are
+// waiting.  They move up the queue in return.
+pub const SHORT_SESSION: std::time::Duration = std::time::Duration::from_secs(15 * 60);
+
+// How often the waiting room redraws, which is also the speed of its snake game
+pub const LOBBY_FRAME_INTERVAL: std::time::Duration = std::time::Duration::from_millis(150);
+
+// Warm instances are launched before anyone has asked for them, so they all share
+// this name.  After a session ends, the game on that display gets WARM_LAUNCH_DELAY
+// to exit before another is started there.
+pub const WARM_USERNAME: &str = "ansicraft";
+pub const WARM_LAUNCH_DELAY: std::time::Duration = std::time::Duration::from_secs(10);
+// With ANSICRAFT_WARM_INSTANCES=auto, how many are kept going is looked at again
+// every WARM_ADJUST_INTERVAL, going by the sessions started in the last
+// WARM_DEMAND_WINDOW
+pub const WARM_ADJUST_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+pub const WARM_DEMAND_WINDOW: std::time::Duration = std::time::Duration::from_secs(15 * 60);
+
+// How many warm instances to keep going
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WarmTarget {
+    Fixed(usize),
+    // Enough for recent demand, within these
+    Demand { min: usize, max: usize },
+}
+
+impl Default for WarmTarget {
+    fn default() -> Self {
+        WarmTarget::Fixed(0)
+    }
+}
+
+// Where the built-in launcher installs versions, and where it gets them from
+pub const MINECRAFT_DIR: &str = "/root/.minecraft";
+pub const DEFAULT_GAME_VERSION: &str = "1.21.4";
+pub const VERSION_MANIFEST_URL: &str = "https://piston-meta.mojang.com/mc/game/version_manifest_v2.json";
+pub const LIBRARIES_URL: &str = "https://libraries.minecraft.net";
+pub const ASSETS_URL: &str = "https://resources.download.minecraft.net";
+
+// The helper that logs players in to Microsoft accounts, and where their logins
+// are kept (overridden by ANSICRAFT_MS_LOGIN_SCRIPT and ANSICRAFT_ACCOUNTS_DIR)
+pub const MS_LOGIN_SCRIPT: &str = "/root/microsoft_login.py";
+pub const ACCOUNTS_DIR: &str = "accounts";
+
+// Where operators can put environment variables for a player's games
+// (ANSICRAFT_USER_ENV_DIR), in a file named after them
+pub const USER_ENV_DIR: &str = "user_env";
+
+// Where players' world backups go, and how many of each world are kept
+// (ANSICRAFT_BACKUPS_DIR, ANSICRAFT_WORLD_BACKUPS; 0 turns them off)
+pub const BACKUPS_DIR: &str = "backups";
+pub const WORLD_BACKUPS: usize = 5;
+
+// Gamepad tuning.  Axes range over -32767..=32767.
+pub const GAMEPAD_MOVE_THRESHOLD: i16 = 12000;
+pub const GAMEPAD_LOOK_DEAD_ZONE: u16 = 4000;
+pub const GAMEPAD_LOOK_SPEED: f32 = 30.0;
+
+// Which keys walk: just WASD, or the arrow keys as well, for keyboards and
+// terminals where those come easier
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Keymap {
+    #[default]
+    Wasd,
+    Arrows,
+}
+
+impl Keymap {
+    pub fn next(self) -> Self {
+        match self {
+            Keymap::Wasd => Keymap::Arrows,
+            Keymap::Arrows => Keymap::Wasd,
+        }
+    }
+}
+
+impl std::str::FromStr for Keymap {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        <Self as clap::ValueEnum>::from_str(value, true)
+    }
+}
+
+impl std::fmt::Display for Keymap {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Keymap::Wasd => "wasd",
+            Keymap::Arrows => "arrows",
+        })
+    }
+}
+
+// Per-session input tuning
+#[derive(Clone, Debug)]
+pub struct InputSettings {
+    pub look_dead_zone: u16,
+    pub look_scale_x: f32,
+    pub look_scale_y: f32,
+    pub gamepad_look_speed: f32,
+    // Mouse mode to start in; None guesses from whether we're joining a server
+    pub absolute_mouse: Option<bool>,
+    pub fps_down_key: char,
+    pub fps_up_key: char,
+    // Hold space down like the movement keys, so holding jump swims and climbs
+    pub jump_latch: bool,
+    pub mining_latch_key: char,
+    pub keymap: Keymap,
+}
+
+impl Default for InputSettings {
+    fn default() -> Self {
+        Self {
+            look_dead_zone: LOOK_DEAD_ZONE,
+            look_scale_x: LOOK_SCALE_X,
+            look_scale_y: LOOK_SCALE_Y,
+            gamepad_look_speed: GAMEPAD_LOOK_SPEED,
+            absolute_mouse: None,
+            fps_down_key: FPS_DOWN_KEY,
+            fps_up_key: FPS_UP_KEY,
+            jump_latch: true,
+            mining_latch_key: MINING_LATCH_KEY,
+            keymap: Keymap::default(),
+        }
+    }
+}
+
+// How to start the game.  The argument templates are split on whitespace, and
+// {username}, {server} and {display} in them are filled in.  The server arguments
+// are only added when there's a server to join.
+#[derive(Clone, Debug)]
+pub struct LauncherSettings {
+    // Runs the launcher, or "" to run it directly
+    pub interpreter: String,
+    // "" for the built-in one, which ignores the rest of these
+    pub launcher: String,
+    pub args: String,
+    pub server_args: String,
+    // Only added when a version was picked
+    pub version_args: String,
+    // Only added when the player has a game directory of their own
+    pub game_dir_args: String,
+    // Only added when loading straight into a saved world
+    pub world_args: String,
+    // Added once for each JVM argument, with {arg} filled in
+    pub jvm_arg: String,
+    // Only added when the player has logged in to a Microsoft account, with
+    // {account} being the file their login is saved in
+    pub account_args: String,
+}
+
+impl Default for LauncherSettings {
+    fn default() -> Self {
+        Self {
+            interpreter: "python3".to_owned(),
+            launcher: String::new(),
+            args: "--username {username}".to_owned(),
+            server_args: "--server {server}".to_owned(),
+            version_args: "--version {version}".to_owned(),
+            game_dir_args: "--game-dir {game_dir}".to_owned(),
+            world_args: "--world {world}".to_owned(),
+            jvm_arg: "--jvm-arg={arg}".to_owned(),
+            account_args: "--account {account}".to_owned(),
+        }
+    }
+}
+
+// What runs on the display instead of Minecraft, if anything (ANSICRAFT_APP_*)
+#[derive(Clone, Debug)]
+pub struct AppSettings {
+    // None for Minecraft.  Split on whitespace, with {username}, {display} and
+    // {server} filled in.
+    pub command: Option<String>,
+    pub name: String,
+    // A window name (regex) that shows it's started
+    pub ready_window: Option<String>,
+    // Parsed when it's started, e.g. SIGINT or INT
+    pub stop_signal: String,
+    pub stop_timeout: std::time::Duration,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            command: None,
+            name: "the game".to_owned(),
+            ready_window: None,
+            stop_signal: "SIGTERM".to_owned(),
+            stop_timeout: std::time::Duration::from_secs(5),
+        }
+    }
+}
+
+impl AppSettings {
+    // Whether the app is Minecraft, which some features (warm instances, versions) need
+    pub fn is_minecraft(&self) -> bool {
+        self.command.is_none()
+    }
+}
+
+// Launching through PrismLauncher instead (ANSICRAFT_PRISM*), with the same kind
+// of argument templates as the launcher's, and {instance} filled in too
+#[derive(Clone, Debug)]
+pub struct PrismSettings {
+    // The launcher's binary, or "" to not use it
+    pub binary: String,
+    // Its data directory, or None for the one in the home directory
+    pub dir: Option<std::path::PathBuf>,
+    pub args: String,
+    pub server_args: String,
+    pub world_args: String,
+}
+
+impl Default for PrismSettings {
+    fn default() -> Self {
+        Self {
+            binary: String::new(),
+            dir: None,
+            args: "--launch {instance} --offline {username}".to_owned(),
+            server_args: "--server {server}".to_owned(),
+            world_args: "--world {world}".to_owned(),
+        }
+    }
+}
+
+impl PrismSettings {
+    pub fn enabled(&self) -> bool {
+        !self.binary.is_empty()
+    }
+}
+
+// The server's settings, read once at startup and handed to whatever needs them.
+// Each has a default below and a variable to override it with, set in the
+// environment or in the config file:
+//
+//   ANSICRAFT_PORT                   port players connect to with SSH *
+//   ANSICRAFT_HOST_KEY               the server's SSH key, made if it isn't there *
+//   ANSICRAFT_FPS, ANSICRAFT_COLOR   how sessions are shown to begin with, colors going
+//                                    by the player's terminal unless set (or if `auto`)
+//   ANSICRAFT_MAX_FPS                frame rate no session goes over, even running ones
+//   ANSICRAFT_BANNER                 shown to SSH clients before they log in
+//   MINECRAFT_SERVER_ADDRESS         server games join, unset for singleplayer, with
+//                                    MINECRAFT_SERVER_ADDRESS_<POOL> for one pool's
+//   ANSICRAFT_SERVER_ALLOW           servers players can type in, when they can
+//   ANSICRAFT_SERVER_DENY            servers players can't join, even listed ones
+//   ANSICRAFT_MAX_SESSIONS           sessions at once, when displays are started for them *
+//   ANSICRAFT_POOLS                  displays in each pool, like survival=1-6,creative=7-9 *
+//   ANSICRAFT_SPAWN_X                start an X server for each session instead *
+//   ANSICRAFT_PROVISIONER            docker or kubernetes, to start a container for each instead *
+//   ANSICRAFT_SESSION_IMAGE          the image they run, with ANSICRAFT_DOCKER_ARGS,
+//                                    ANSICRAFT_K8S_NAMESPACE and ANSICRAFT_KUBECTL_ARGS
+//   ANSICRAFT_CAPACITY               sessions at once, if fewer than there are displays
+//   ANSICRAFT_VIP_KEYS               keys that skip ahead in the queue
+//   ANSICRAFT_ADMIN_KEYS             keys allowed to run `admin` commands
+//   ANSICRAFT_ADMIN_SOCKET           socket `termcast admin` runs them through, here *
+//   ANSICRAFT_WORKERS                machines to play games on instead of here, each as
+//                                    [pool=]host:port[/slots], separated by commas *
+//   ANSICRAFT_WORKER_PORT            port `termcast worker` takes games on *
+//   ANSICRAFT_CLUSTER_TOKEN          shared by a gateway and its workers, so only it can
+//                                    send them games *
+//   ANSICRAFT_SCALE_UP_COMMAND       starts another worker when a pool's queue backs up,
+//                                    printing the ones it started like ANSICRAFT_WORKERS
+//   ANSICRAFT_SCALE_DOWN_COMMAND     stops a worker it started once it's been idle
+//   ANSICRAFT_SCALE_UP_QUEUE         players waiting for a pool that start another worker
+//   ANSICRAFT_SCALE_UP_WAIT_SECS     or how long the first of them has to have waited
+//   ANSICRAFT_SCALE_DOWN_IDLE_MINS   how long one that was started is idle before it's stopped
+//   ANSICRAFT_MAX_SCALED_WORKERS     how many can be started at once
+//   ANSICRAFT_RESERVATIONS           slots held for players at set times *
+//   ANSICRAFT_MAX_QUEUE_LENGTH       players who can wait before more are turned away
+//   ANSICRAFT_QUEUE_TIMEOUT_SECS     how long they wait before they're given up on
+//   ANSICRAFT_FAIR_QUEUE             take turns between addresses instead of first come
+//   ANSICRAFT_CAPACITY_SCHEDULE      sessions at once at different times of day
+//   ANSICRAFT_RECONNECT_GRACE_SECS   how long players who drop out have to come back
+//   ANSICRAFT_KEEP_GAME_SECS         how long their game's kept going for them, if not that
+//   ANSICRAFT_MAX_SESSION_MINS       how long anyone can play while others are waiting
+//   ANSICRAFT_SHORT_SESSION_MINS     the same for players who asked for a short one
+//   ANSICRAFT_AFK_TIMEOUT_SECS       how long players can idle while others are waiting
+//   ANSICRAFT_MAX_RECONNECTS         times a game's relaunched to rejoin its server
+//   ANSICRAFT_MAX_RESTARTS           times a game's restarted after crashing
+//   ANSICRAFT_GOODBYE_MESSAGE        shown when a session's over
+//   ANSICRAFT_SERVERS                servers players can pick, like Friends=10.0.0.5:25566
+//   ANSICRAFT_CUSTOM_SERVERS         whether they can type in others
+//   ANSICRAFT_LOOK_DEAD_ZONE, ANSICRAFT_LOOK_SCALE_X, ANSICRAFT_LOOK_SCALE_Y,
+//   ANSICRAFT_GAMEPAD_LOOK_SPEED, ANSICRAFT_FPS_DOWN_KEY, ANSICRAFT_FPS_UP_KEY,
+//   ANSICRAFT_JUMP_LATCH, ANSICRAFT_MINING_LATCH_KEY, ANSICRAFT_KEYMAP
+//                                    players' input settings, until they change them
+//   ANSICRAFT_VERSIONS               versions (or PrismLauncher instances) players pick from
+//   ANSICRAFT_LAUNCHER               a launcher to run instead of the built-in one, with
+//                                    ANSICRAFT_LAUNCHER_INTERPRETER and ANSICRAFT_LAUNCHER_*ARGS
+//   ANSICRAFT_MINECRAFT_DIR          where the built-in one installs versions
+//   ANSICRAFT_JAVA                   the java it runs them with
+//   ANSICRAFT_JVM_XMX, ANSICRAFT_JVM_XMS, ANSICRAFT_JVM_ARGS
+//                                    what the game's JVM gets, the heap going by the
+//                                    machine's memory unless set *
+//   ANSICRAFT_APP_COMMAND            what to run instead of Minecraft, with
+//                                    ANSICRAFT_APP_NAME, ANSICRAFT_APP_READY_WINDOW,
+//                                    ANSICRAFT_APP_STOP_SIGNAL and ANSICRAFT_APP_STOP_TIMEOUT_SECS
+//   ANSICRAFT_PRISM                  PrismLauncher's binary, to launch its instances, with
+//                                    ANSICRAFT_PRISM_DIR and ANSICRAFT_PRISM_*ARGS
+//   ANSICRAFT_WINDOW_MANAGER         kept running on each display
+//   ANSICRAFT_WARM_INSTANCES         games started ahead of time, or auto to go by demand
+//                                    between ANSICRAFT_WARM_MIN and ANSICRAFT_WARM_MAX
+//   ANSICRAFT_GAME_ENV               KEY=VALUE,... for every game, with
+//                                    ANSICRAFT_GAME_ENV_<POOL> for one pool's
+//   ANSICRAFT_USER_ENV_DIR           files of KEY=VALUE lines for each player's games
+//   ANSICRAFT_INSTANCES_DIR          where players get game directories of their own
+//   ANSICRAFT_SESSION_LOGS_DIR       where each session's logs are kept, if they are
+//   ANSICRAFT_MS_CLIENT_ID           Azure app players log in to Microsoft accounts with
+//   ANSICRAFT_MS_LOGIN_SCRIPT        the helper that logs them in
+//   ANSICRAFT_ACCOUNTS_DIR           where their logins are kept
+//   ANSICRAFT_BACKUPS_DIR            where their world backups go
+//   ANSICRAFT_WORLD_BACKUPS          backups kept of each world, 0 for none
+//   ANSICRAFT_SANDBOX                bwrap to sandbox games, with ANSICRAFT_SANDBOX_<POOL>
+//                                    for one pool's
+//   ANSICRAFT_SANDBOX_BIND           more paths sandboxed games can read, separated by commas
+//   ANSICRAFT_SESSION_UIDS           users to run games as, like 10000-10099
+//   ANSICRAFT_SESSION_MEMORY_MAX, ANSICRAFT_SESSION_CPU_WEIGHT, ANSICRAFT_SESSION_NICE
+//                                    what each game gets of the host
+//   ANSICRAFT_GPU_RENDER             virtualgl or egl, for displays tagged gpu
+//   ANSICRAFT_GPU_WEIGHT             sessions a game on a GPU counts as
+//   ANSICRAFT_VGL_DISPLAY            the GPU's X server, for virtualgl
+//   ANSICRAFT_EGL_DEVICE             egl for the first GPU, or a DRI device
+//   ANSICRAFT_AUDIO                  stream or bell, for the game's sound
+//   ANSICRAFT_AUDIO_BASE_PORT        where sound's streamed from, plus the session number
+//   ANSICRAFT_NOTIFY                 bell, osc9 or off, for deaths and mentions
+//   ANSICRAFT_ATTRACT_VIDEO          a recording to watch while waiting
+//   ANSICRAFT_FFMPEG                 the ffmpeg to capture with
+//   ANSICRAFT_SCRIPT                 a Rhai script for players' input and overlays *
+//   ANSICRAFT_WEBHOOKS               URLs told about the queue, separated by commas
+//   ANSICRAFT_LOG                    log lines kept, as a level with levels for modules
+//                                    after it, e.g. info,minecraft_terminal_viewer::render=warn *
+//   ANSICRAFT_LOG_FORMAT             pretty, or json for log collectors *
+//   ANSICRAFT_LOG_FILE               file to log to instead of stderr *
+//   ANSICRAFT_LOG_ROTATION           daily, hourly or never, for the log file *
+//
+// The command line can override some of these again.  When the config's reloaded
+// (on SIGHUP, or `admin reload`) everything but the ones marked * takes effect for
+// sessions from then on, and the frame rate cap for running ones too.
+#[derive(Clone, Debug)]
+pub struct AppConfig {
+    pub port: u16,
+    pub host_key: std::path::PathBuf,
+    pub render: RenderSettings,
+    // Whether sessions start in the best mode the player's terminal can show,
+    // instead of render.mode
+    pub detect_color: bool,
+    pub max_fps: Option<u32>,
+    pub banner: String,
+    pub server_address: String,
+    // By pool name, upper cased
+    pub pool_server_addresses: std::collections::HashMap<String, String>,
+    pub server_allow: Option<String>,
+    pub server_deny: Option<String>,
+    pub max_sessions: u32,
+    // Parsed by whatever sets up the queues, None for one pool of every display
+    pub pools: Option<String>,
+    pub spawn_x: bool,
+    pub provisioner: Option<Provisioner>,
+    pub session_image: String,
+    pub docker_args: String,
+    pub k8s_namespace: Option<String>,
+    pub kubectl_args: String,
+    pub capacity: Option<usize>,
+    pub vip_keys: std::path::PathBuf,
+    pub admin_keys: std::path::PathBuf,
+    pub admin_socket: std::path::PathBuf,
+    // Parsed by whatever sets up the queues, "" meaning games are played here
+    pub workers: String,
+    pub worker_port: u16,
+    pub cluster_token: String,
+    // Empty for no autoscaling, unless a library's registered an autoscaler
+    pub scale_up_command: String,
+    pub scale_down_command: String,
+    pub scale_up_queue: usize,
+    pub scale_up_wait: Option<std::time::Duration>,
+    pub scale_down_idle: std::time::Duration,
+    pub max_scaled_workers: usize,
+    pub reservations: std::path::PathBuf,
+    pub max_queue_length: Option<usize>,
+    pub queue_timeout: Option<std::time::Duration>,
+    pub fair_queue: bool,
+    // Parsed by whatever sets up the queues, "" meaning no limits
+    pub capacity_schedule: String,
+    pub reconnect_grace: Option<std::time::Duration>,
+    pub keep_game: Option<std::time::Duration>,
+    pub max_session: Option<std::time::Duration>,
+    pub short_session: std::time::Duration,
+    pub afk_timeout: std::time::Duration,
+    pub max_reconnects: u32,
+    pub max_restarts: u32,
+    pub goodbye_message: String,
+    // Parsed by whatever offers them, "" for none
+    pub servers: String,
+    pub custom_servers: bool,
+    // What players' input settings are until they change them
+    pub input: InputSettings,
+    // Empty leaves it up to the launcher, or PrismLauncher's instances
+    pub versions: Vec<String>,
+    pub launcher: LauncherSettings,
+    pub minecraft_dir: std::path::PathBuf,
+    pub java: String,
+    // None sizes the heap to fit every session in memory
+    pub jvm_xmx: Option<String>,
+    pub jvm_xms: Option<String>,
+    // Added after those
+    pub extra_jvm_args: Vec<String>,
+    pub app: AppSettings,
+    pub prism: PrismSettings,
+    pub window_manager: Option<String>,
+    pub warm: WarmTarget,
+    // Parsed by game_env, "" for nothing extra
+    pub game_env: String,
+    // By pool name, upper cased
+    pub pool_game_envs: std::collections::HashMap<String, String>,
+    pub user_env_dir: std::path::PathBuf,
+    pub instances_dir: Option<std::path::PathBuf>,
+    pub session_logs_dir: Option<std::path::PathBuf>,
+    // Empty when Microsoft logins aren't set up
+    pub ms_client_id: String,
+    pub ms_login_script: std::path::PathBuf,
+    pub accounts_dir: std::path::PathBuf,
+    pub backups_dir: std::path::PathBuf,
+    pub world_backups: usize,
+    pub sandbox: bool,
+    // By pool name, upper cased
+    pub pool_sandboxes: std::collections::HashMap<String, bool>,
+    pub sandbox_bind: Vec<String>,
+    pub session_uids: Option<std::ops::RangeInclusive<u32>>,
+    pub limits: ResourceLimits,
+    pub gpu_render: GpuMode,
+    pub gpu_weight: usize,
+    pub vgl_display: String,
+    pub egl_device: String,
+    pub audio: AudioMode,
+    pub audio_base_port: u16,
+    pub notify: NotifyMode,
+    pub attract_video: Option<String>,
+    pub webhooks: Vec<String>,
+    pub ffmpeg: String,
+    pub script: Option<std::path::PathBuf>,
+    pub log_filter: String,
+    pub log_format: LogFormat,
+    pub log_file: Option<std::path::PathBuf>,
+    pub log_rotation: LogRotation,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            port: SSH_PORT,
+            host_key: std::path::PathBuf::from("ssh_server_key"),
+            render: RenderSettings::default(),
+            detect_color: true,
+            max_fps: None,
+            banner: "If you are unable to log in, please be sure to generate a public key first.\n".to_owned(),
+            server_address: String::new(),
+            pool_server_addresses: std::collections::HashMap::new(),
+            server_allow: None,
+            server_deny: None,
+            max_sessions: MAX_SESSIONS,
+            pools: None,
+            spawn_x: false,
+            provisioner: None,
+            session_image: DOCKER_IMAGE.to_owned(),
+            docker_args: String::new(),
+            k8s_namespace: None,
+            kubectl_args: String::new(),
+            capacity: None,
+            vip_keys: std::path::PathBuf::from("vip_keys"),
+            admin_keys: std::path::PathBuf::from("admin_keys"),
+            admin_socket: std::path::PathBuf::from("admin.sock"),
+            workers: String::new(),
+            worker_port: WORKER_PORT,
+            cluster_token: String::new(),
+            scale_up_command: String::new(),
+            scale_down_command: String::new(),
+            scale_up_queue: 1,
+            scale_up_wait: None,
+            scale_down_idle: SCALE_DOWN_IDLE,
+            max_scaled_workers: MAX_SCALED_WORKERS,
+            reservations: std::path::PathBuf::from("reservations"),
+            max_queue_length: None,
+            queue_timeout: None,
+            fair_queue: false,
+            capacity_schedule: String::new(),
+            reconnect_grace: Some(RECONNECT_GRACE),
+            keep_game: Some(RECONNECT_GRACE),
+            max_session: None,
+            short_session: SHORT_SESSION,
+            afk_timeout: AFK_TIMEOUT,
+            max_reconnects: MAX_RECONNECTS,
+            max_restarts: MAX_RESTARTS,
+            goodbye_message: "Thanks for playing!".to_owned(),
+            servers: String::new(),
+            custom_servers: false,
+            input: InputSettings::default(),
+            versions: vec![],
+            launcher: LauncherSettings::default(),
+            minecraft_dir: std::path::PathBuf::from(MINECRAFT_DIR),
+            java: "java".to_owned(),
+            jvm_xmx: None,
+            jvm_xms: None,
+            extra_jvm_args: vec![],
+            app: AppSettings::default(),
+            prism: PrismSettings::default(),
+            window_manager: None,
+            warm: WarmTarget::default(),
+            game_env: String::new(),
+            pool_game_envs: std::collections::HashMap::new(),
+            user_env_dir: std::path::PathBuf::from(USER_ENV_DIR),
+            instances_dir: None,
+            session_logs_dir: None,
+            ms_client_id: String::new(),
+            ms_login_script: std::path::PathBuf::from(MS_LOGIN_SCRIPT),
+            accounts_dir: std::path::PathBuf::from(ACCOUNTS_DIR),
+            backups_dir: std::path::PathBuf::from(BACKUPS_DIR),
+            world_backups: WORLD_BACKUPS,
+            sandbox: false,
+            pool_sandboxes: std::collections::HashMap::new(),
+            sandbox_bind: vec![],
+            session_uids: None,
+            limits: ResourceLimits::default(),
+            gpu_render: GpuMode::default(),
+            gpu_weight: GPU_WEIGHT,
+            vgl_display: ":0".to_owned(),
+            egl_device: "egl".to_owned(),
+            audio: AudioMode::default(),
+            audio_base_port: AUDIO_BASE_PORT,
+            notify: NotifyMode::default(),
+            attract_video: None,
+            webhooks: vec![],
+            ffmpeg: FFMPEG_BINARY.to_owned(),
+            script: None,
+            log_filter: "info".to_owned(),
+            log_format: LogFormat::default(),
+            log_file: None,
+            log_rotation: LogRotation::default(),
+        }
+    }
+}
+
+// Where the settings above are read from: the config file, and then the
+// environment.  The file wins, since it's the one that can be changed without a
+// restart.  It's a NAME=value per line, or TOML if its name ends in .toml.
+struct Sources {
+    file: std::collections::HashMap<String, String>,
+}
+
+// The config file, from --config or else ANSICRAFT_CONFIG
+static CONFIG_FILE: std::sync::OnceLock<std::path::PathBuf> = std::sync::OnceLock::new();
+
+// Reads the config from this file instead of ANSICRAFT_CONFIG's, if it's the first
+// time it's set
+pub fn use_file(path: impl Into<std::path::PathBuf>) {
+    let _ = CONFIG_FILE.set(path.into());
+}
+
+impl Sources {
+    fn read() -> Result<Self, String> {
+        let path = match CONFIG_FILE.get() {
+            Some(path) => path.display().to_string(),
+            None => match std::env::var("ANSICRAFT_CONFIG") {
+                Ok(path) => path,
+                Err(_) => return Ok(Self { file: Default::default() }),
+            },
+        };
+        let contents = std::fs::read_to_string(&path).map_err(|e| format!("Couldn't read {}: {}", path, e))?;
+        let file = if path.to_lowercase().ends_with(".toml") {
+            let table: toml::Table = contents.parse().map_err(|e| format!("Couldn't parse {}: {}", path, e))?;
+            let mut file = std::collections::HashMap::new();
+            toml_vars("", table, &mut file);
+            file
+        } else {
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .filter_map(|line| match line.split_once('=') {
+                    Some((name, value)) => Some((name.trim().to_owned(), value.trim().to_owned())),
+                    None => {
+                        let message = format!("Ignoring \"{}\" in {}, which should look like NAME=value", line, path);
+                        crate::logging::report(&message);
+                        None
+                    }
+                })
+                .collect()
+        };
+        Ok(Self { file })
+    }
+
+    fn var(&self, name: &str) -> Option<String> {
+        self.file.get(name).cloned().or_else(|| std::env::var(name).ok())
+    }
+
+    fn parse<T: std::str::FromStr>(&self, name: &str) -> Option<T> {
+        parse_value(name, self.var(name)?)
+    }
+
+    // A comma-separated list, leaving out blanks
+    fn list(&self, name: &str) -> Vec<String> {
+        self.var(name)
+            .map(|list| list.split(',').map(str::trim).filter(|item| !item.is_empty()).map(str::to_owned).collect())
+            .unwrap_or_default()
+    }
+
+    // One of the values a command line flag takes
+    fn choice<T: clap::ValueEnum>(&self, name: &str) -> Option<T> {
+        let value = self.var(name)?;
+        T::from_str(&value, true)
+            .map_err(|_| crate::logging::report(&format!("Ignoring invalid value for {}: {:?}", name, value)))
+            .ok()
+    }
+
+    // Every variable starting with the prefix, by the rest of its name
+    fn with_prefix(&self, prefix: &str) -> Vec<(String, String)> {
+        let mut vars: Vec<(String, String)> = std::env::vars().collect();
+        vars.extend(self.file.iter().map(|(name, value)| (name.clone(), value.clone())));
+        vars.into_iter()
+            .filter_map(|(name, value)| Some((name.strip_prefix(prefix)?.to_owned(), value)))
+            .collect()
+    }
+}
+
+// A TOML file's settings by the variable they set.  A key's the name without
+// ANSICRAFT_, in lower case (`port = 2222`), unless it's in capitals already, and a
+// table's name goes in front of its keys', so `[launcher] args = ...` sets
+// ANSICRAFT_LAUNCHER_ARGS.  Lists are joined with commas.
+fn toml_vars(prefix: &str, table: toml::Table, vars: &mut std::collections::HashMap<String, String>) {
+    for (key, value) in table {
+        let name = if !prefix.is_empty() {
+            format!("{}_{}", prefix, key.to_uppercase())
+        } else if key == key.to_uppercase() {
+            key
+        } else {
+            format!("ANSICRAFT_{}", key.to_uppercase())
+        };
+        let value = match value {
+            toml::Value::Table(table) => {
+                toml_vars(&name, table, vars);
+                continue;
+            }
+            toml::Value::String(value) => value,
+            toml::Value::Array(values) => values
+                .into_iter()
+                .map(|value| match value {
+                    toml::Value::String(value) => value,
+                    value => value.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(","),
+            value => value.to_string(),
+        };
+        vars.insert(name, value);
+    }
+}
+
+impl AppConfig {
+    // Defaults, overridden by the variables above if set.  Only fails if there's a
+    // config file that can't be read.
+    pub fn load() -> Result<Self, String> {
+        let sources = Sources::read()?;
+        let mut config = Self::default();
+        if let Some(port) = sources.parse("ANSICRAFT_PORT") {
+            config.port = port;
+        }
+        if let Some(path) = sources.var("ANSICRAFT_HOST_KEY") {
+            config.host_key = std::path::PathBuf::from(path);
+        }
+        if let Some(fps) = sources.parse::<u32>("ANSICRAFT_FPS") {
+            config.render.fps = fps.max(1);
+        }
+        if sources.var("ANSICRAFT_COLOR").is_some_and(|mode| mode.eq_ignore_ascii_case("auto")) {
+            config.detect_color = true;
+        } else if let Some(mode) = sources.choice("ANSICRAFT_COLOR") {
+            config.render.mode = mode;
+            config.detect_color = false;
+        }
+        config.max_fps = sources.parse::<u32>("ANSICRAFT_MAX_FPS").map(|fps| fps.max(1));
+        if let Some(banner) = sources.var("ANSICRAFT_BANNER") {
+            // Lines in a file or variable can't have line breaks of their own
+            config.banner = banner.replace("\\n", "\n");
+        }
+        if let Some(address) = sources.var("MINECRAFT_SERVER_ADDRESS") {
+            config.server_address = address;
+        }
+        config.pool_server_addresses = sources
+            .with_prefix("MINECRAFT_SERVER_ADDRESS_")
+            .into_iter()
+            .map(|(pool, address)| (pool.to_uppercase(), address))
+            .collect();
+        config.server_allow = sources.var("ANSICRAFT_SERVER_ALLOW");
+        config.server_deny = sources.var("ANSICRAFT_SERVER_DENY");
+        if let Some(sessions) = sources.parse("ANSICRAFT_MAX_SESSIONS") {
+            config.max_sessions = sessions;
+        }
+        config.pools = sources.var("ANSICRAFT_POOLS");
+        if let Some(spawn_x) = sources.parse("ANSICRAFT_SPAWN_X") {
+            config.spawn_x = spawn_x;
+        }
+        config.provisioner = sources.choice("ANSICRAFT_PROVISIONER");
+        if let Some(image) = sources.var("ANSICRAFT_SESSION_IMAGE") {
+            config.session_image = image;
+        }
+        if let Some(args) = sources.var("ANSICRAFT_DOCKER_ARGS") {
+            config.docker_args = args;
+        }
+        config.k8s_namespace = sources.var("ANSICRAFT_K8S_NAMESPACE");
+        if let Some(args) = sources.var("ANSICRAFT_KUBECTL_ARGS") {
+            config.kubectl_args = args;
+        }
+        config.capacity = sources.parse("ANSICRAFT_CAPACITY");
+        if let Some(path) = sources.var("ANSICRAFT_VIP_KEYS") {
+            config.vip_keys = std::path::PathBuf::from(path);
+        }
+        if let Some(path) = sources.var("ANSICRAFT_ADMIN_KEYS") {
+            config.admin_keys = std::path::PathBuf::from(path);
+        }
+        if let Some(path) = sources.var("ANSICRAFT_ADMIN_SOCKET") {
+            config.admin_socket = std::path::PathBuf::from(path);
+        }
+        if let Some(workers) = sources.var("ANSICRAFT_WORKERS") {
+            config.workers = workers;
+        }
+        if let Some(port) = sources.parse("ANSICRAFT_WORKER_PORT") {
+            config.worker_port = port;
+        }
+        if let Some(token) = sources.var("ANSICRAFT_CLUSTER_TOKEN") {
+            config.cluster_token = token;
+        }
+        if let Some(command) = sources.var("ANSICRAFT_SCALE_UP_COMMAND") {
+            config.scale_up_command = command;
+        }
+        if let Some(command) = sources.var("ANSICRAFT_SCALE_DOWN_COMMAND") {
+            config.scale_down_command = command;
+        }
+        if let Some(waiting) = sources.parse::<usize>("ANSICRAFT_SCALE_UP_QUEUE") {
+            config.scale_up_queue = waiting.max(1);
+        }
+        config.scale_up_wait = sources.parse("ANSICRAFT_SCALE_UP_WAIT_SECS").map(std::time::Duration::from_secs);
+        if let Some(minutes) = sources.parse::<u64>("ANSICRAFT_SCALE_DOWN_IDLE_MINS") {
+            config.scale_down_idle = std::time::Duration::from_secs(minutes * 60);
+        }
+        if let Some(workers) = sources.parse("ANSICRAFT_MAX_SCALED_WORKERS") {
+            config.max_scaled_workers = workers;
+        }
+        if let Some(path) = sources.var("ANSICRAFT_RESERVATIONS") {
+            config.reservations = std::path::PathBuf::from(path);
+        }
+        config.max_queue_length = sources.parse("ANSICRAFT_MAX_QUEUE_LENGTH");
+        config.queue_timeout = sources.parse("ANSICRAFT_QUEUE_TIMEOUT_SECS").map(std::time::Duration::from_secs);
+        if let Some(fair) = sources.parse("ANSICRAFT_FAIR_QUEUE") {
+            config.fair_queue = fair;
+        }
+        if let Some(spec) = sources.var("ANSICRAFT_CAPACITY_SCHEDULE") {
+            config.capacity_schedule = spec;
+        }
+        if let Some(secs) = sources.parse("ANSICRAFT_RECONNECT_GRACE_SECS") {
+            config.reconnect_grace = Some(std::time::Duration::from_secs(secs)).filter(|grace| !grace.is_zero());
+        }
+        config.keep_game = match sources.parse("ANSICRAFT_KEEP_GAME_SECS") {
+            Some(secs) => Some(std::time::Duration::from_secs(secs)).filter(|grace| !grace.is_zero()),
+            None => config.reconnect_grace,
+        };
+        config.max_session =
+            sources.parse("ANSICRAFT_MAX_SESSION_MINS").map(|minutes: u64| std::time::Duration::from_secs(minutes * 60));
+        if let Some(minutes) = sources.parse::<u64>("ANSICRAFT_SHORT_SESSION_MINS") {
+            config.short_session = std::time::Duration::from_secs(minutes * 60);
+        }
+        if let Some(secs) = sources.parse("ANSICRAFT_AFK_TIMEOUT_SECS") {
+            config.afk_timeout = std::time::Duration::from_secs(secs);
+        }
+        if let Some(reconnects) = sources.parse("ANSICRAFT_MAX_RECONNECTS") {
+            config.max_reconnects = reconnects;
+        }
+        if let Some(restarts) = sources.parse("ANSICRAFT_MAX_RESTARTS") {
+            config.max_restarts = restarts;
+        }
+        if let Some(message) = sources.var("ANSICRAFT_GOODBYE_MESSAGE") {
+            config.goodbye_message = message;
+        }
+        if let Some(servers) = sources.var("ANSICRAFT_SERVERS") {
+            config.servers = servers;
+        }
+        if let Some(custom) = sources.parse("ANSICRAFT_CUSTOM_SERVERS") {
+            config.custom_servers = custom;
+        }
+        let input = &mut config.input;
+        if let Some(dead_zone) = sources.parse("ANSICRAFT_LOOK_DEAD_ZONE") {
+            input.look_dead_zone = dead_zone;
+        }
+        if let Some(scale) = sources.parse("ANSICRAFT_LOOK_SCALE_X") {
+            input.look_scale_x = scale;
+        }
+        if let Some(scale) = sources.parse("ANSICRAFT_LOOK_SCALE_Y") {
+            input.look_scale_y = scale;
+        }
+        if let Some(speed) = sources.parse("ANSICRAFT_GAMEPAD_LOOK_SPEED") {
+            input.gamepad_look_speed = speed;
+        }
+        if let Some(key) = sources.parse("ANSICRAFT_FPS_DOWN_KEY") {
+            input.fps_down_key = key;
+        }
+        if let Some(key) = sources.parse("ANSICRAFT_FPS_UP_KEY") {
+            input.fps_up_key = key;
+        }
+        if let Some(latch) = sources.parse("ANSICRAFT_JUMP_LATCH") {
+            input.jump_latch = latch;
+        }
+        if let Some(key) = sources.parse("ANSICRAFT_MINING_LATCH_KEY") {
+            input.mining_latch_key = key;
+        }
+        if let Some(keymap) = sources.parse("ANSICRAFT_KEYMAP") {
+            input.keymap = keymap;
+        }
+        config.versions = sources.list("ANSICRAFT_VERSIONS");
+        let launcher = &mut config.launcher;
+        if let Some(interpreter) = sources.var("ANSICRAFT_LAUNCHER_INTERPRETER") {
+            launcher.interpreter = interpreter;
+        }
+        if let Some(binary) = sources.var("ANSICRAFT_LAUNCHER") {
+            launcher.launcher = binary;
+        }
+        if let Some(args) = sources.var("ANSICRAFT_LAUNCHER_ARGS") {
+            launcher.args = args;
+        }
+        if let Some(args) = sources.var("ANSICRAFT_LAUNCHER_SERVER_ARGS") {
+            launcher.server_args = args;
+        }
+        if let Some(args) = sources.var("ANSICRAFT_LAUNCHER_VERSION_ARGS") {
+            launcher.version_args = args;
+        }
+        if let Some(args) = sources.var("ANSICRAFT_LAUNCHER_GAME_DIR_ARGS") {
+            launcher.game_dir_args = args;
+        }
+        if let Some(args) = sources.var("ANSICRAFT_LAUNCHER_WORLD_ARGS") {
+            launcher.world_args = args;
+        }
+        if let Some(arg) = sources.var("ANSICRAFT_LAUNCHER_JVM_ARG") {
+            launcher.jvm_arg = arg;
+        }
+        if let Some(args) = sources.var("ANSICRAFT_LAUNCHER_ACCOUNT_ARGS") {
+            launcher.account_args = args;
+        }
+        if let Some(dir) = sources.var("ANSICRAFT_MINECRAFT_DIR") {
+            config.minecraft_dir = std::path::PathBuf::from(dir);
+        }
+        if let Some(java) = sources.var("ANSICRAFT_JAVA") {
+            config.java = java;
+        }
+        config.jvm_xmx = sources.var("ANSICRAFT_JVM_XMX");
+        config.jvm_xms = sources.var("ANSICRAFT_JVM_XMS");
+        if let Some(args) = sources.var("ANSICRAFT_JVM_ARGS") {
+            config.extra_jvm_args = args.split_whitespace().map(str::to_owned).collect();
+        }
+        config.app.command = sources.var("ANSICRAFT_APP_COMMAND");
+        if let Some(name) = sources.var("ANSICRAFT_APP_NAME") {
+            config.app.name = name;
+        }
+        config.app.ready_window = sources.var("ANSICRAFT_APP_READY_WINDOW");
+        if let Some(signal) = sources.var("ANSICRAFT_APP_STOP_SIGNAL") {
+            config.app.stop_signal = signal;
+        }
+        if let Some(secs) = sources.parse("ANSICRAFT_APP_STOP_TIMEOUT_SECS") {
+            config.app.stop_timeout = std::time::Duration::from_secs(secs);
+        }
+        if let Some(prism) = sources.var("ANSICRAFT_PRISM") {
+            config.prism.binary = prism;
+        }
+        config.prism.dir = sources.var("ANSICRAFT_PRISM_DIR").map(std::path::PathBuf::from);
+        if let Some(args) = sources.var("ANSICRAFT_PRISM_ARGS") {
+            config.prism.args = args;
+        }
+        if let Some(args) = sources.var("ANSICRAFT_PRISM_SERVER_ARGS") {
+            config.prism.server_args = args;
+        }
+        if let Some(args) = sources.var("ANSICRAFT_PRISM_WORLD_ARGS") {
+            config.prism.world_args = args;
+        }
+        config.window_manager = sources.var("ANSICRAFT_WINDOW_MANAGER");
+        config.warm = match sources.var("ANSICRAFT_WARM_INSTANCES").as_deref() {
+            Some("auto") => WarmTarget::Demand {
+                min: sources.parse("ANSICRAFT_WARM_MIN").unwrap_or(0),
+                max: sources.parse("ANSICRAFT_WARM_MAX").unwrap_or(usize::MAX),
+            },
+            _ => WarmTarget::Fixed(sources.parse("ANSICRAFT_WARM_INSTANCES").unwrap_or(0)),
+        };
+        if let Some(env) = sources.var("ANSICRAFT_GAME_ENV") {
+            config.game_env = env;
+        }
+        config.pool_game_envs = sources
+            .with_prefix("ANSICRAFT_GAME_ENV_")
+            .into_iter()
+            .map(|(pool, env)| (pool.to_uppercase(), env))
+            .collect();
+        if let Some(dir) = sources.var("ANSICRAFT_USER_ENV_DIR") {
+            config.user_env_dir = std::path::PathBuf::from(dir);
+        }
+        config.instances_dir = sources.var("ANSICRAFT_INSTANCES_DIR").map(std::path::PathBuf::from);
+        config.session_logs_dir = sources.var("ANSICRAFT_SESSION_LOGS_DIR").map(std::path::PathBuf::from);
+        if let Some(id) = sources.var("ANSICRAFT_MS_CLIENT_ID") {
+            config.ms_client_id = id;
+        }
+        if let Some(path) = sources.var("ANSICRAFT_MS_LOGIN_SCRIPT") {
+            config.ms_login_script = std::path::PathBuf::from(path);
+        }
+        if let Some(dir) = sources.var("ANSICRAFT_ACCOUNTS_DIR") {
+            config.accounts_dir = std::path::PathBuf::from(dir);
+        }
+        if let Some(dir) = sources.var("ANSICRAFT_BACKUPS_DIR") {
+            config.backups_dir = std::path::PathBuf::from(dir);
+        }
+        if let Some(kept) = sources.parse("ANSICRAFT_WORLD_BACKUPS") {
+            config.world_backups = kept;
+        }
+        config.sandbox = sources.var("ANSICRAFT_SANDBOX").as_deref() == Some("bwrap");
+        config.pool_sandboxes = sources
+            .with_prefix("ANSICRAFT_SANDBOX_")
+            .into_iter()
+            .filter(|(pool, _)| pool != "BIND")
+            .map(|(pool, sandbox)| (pool.to_uppercase(), sandbox == "bwrap"))
+            .collect();
+        config.sandbox_bind = sources.list("ANSICRAFT_SANDBOX_BIND");
+        if let Some(spec) = sources.var("ANSICRAFT_SESSION_UIDS") {
+            let (first, last) = spec.split_once('-').unwrap_or((&spec, &spec));
+            config.session_uids = match (first.trim().parse::<u32>(), last.trim().parse::<u32>()) {
+                // Never root, or a system user
+                (Ok(first), Ok(last)) if first >= 1000 && first <= last => Some(first..=last),
+                _ => {
+                    crate::logging::report("Ignoring ANSICRAFT_SESSION_UIDS, which should look like 10000-10099");
+                    None
+                }
+            };
+        }
+        config.limits = ResourceLimits {
+            memory_max: sources.var("ANSICRAFT_SESSION_MEMORY_MAX"),
+            cpu_weight: sources.parse("ANSICRAFT_SESSION_CPU_WEIGHT"),
+            nice: sources.parse("ANSICRAFT_SESSION_NICE"),
+        };
+        if let Some(mode) = sources.choice("ANSICRAFT_GPU_RENDER") {
+            config.gpu_render = mode;
+        }
+        if let Some(weight) = sources.parse::<usize>("ANSICRAFT_GPU_WEIGHT") {
+            config.gpu_weight = weight.max(1);
+        }
+        if let Some(display) = sources.var("ANSICRAFT_VGL_DISPLAY") {
+            config.vgl_display = display;
+        }
+        if let Some(device) = sources.var("ANSICRAFT_EGL_DEVICE") {
+            config.egl_device = device;
+        }
+        if let Some(mode) = sources.choice("ANSICRAFT_AUDIO") {
+            config.audio = mode;
+        }
+        if let Some(port) = sources.parse("ANSICRAFT_AUDIO_BASE_PORT") {
+            config.audio_base_port = port;
+        }
+        if let Some(mode) = sources.choice("ANSICRAFT_NOTIFY") {
+            config.notify = mode;
+        }
+        config.attract_video = sources.var("ANSICRAFT_ATTRACT_VIDEO");
+        config.webhooks = sources.list("ANSICRAFT_WEBHOOKS");
+        if let Some(ffmpeg) = sources.var("ANSICRAFT_FFMPEG") {
+            config.ffmpeg = ffmpeg;
+        }
+        config.script = sources.var("ANSICRAFT_SCRIPT").filter(|path| !path.is_empty()).map(std::path::PathBuf::from);
+        if let Some(filter) = sources.var("ANSICRAFT_LOG") {
+            config.log_filter = filter;
+        }
+        if let Some(format) = sources.choice("ANSICRAFT_LOG_FORMAT") {
+            config.log_format = format;
+        }
+        config.log_file = sources.var("ANSICRAFT_LOG_FILE").map(std::path::PathBuf::from);
+        if let Some(rotation) = sources.choice("ANSICRAFT_LOG_ROTATION") {
+            config.log_rotation = rotation;
+        }
+        Ok(config)
+    }
+
+    // The Minecraft server a pool's sessions join, or "" for singleplayer
+    pub fn server_address(&self, pool: &str) -> &str {
+        self.pool_server_addresses.get(&pool.to_uppercase()).unwrap_or(&self.server_address)
+    }
+
+    // Whether a pool's games are sandboxed
+    pub fn sandboxed(&self, pool: &str) -> bool {
+        self.pool_sandboxes.get(&pool.to_uppercase()).copied().unwrap_or(self.sandbox)
+    }
+
+    // Game versions (or modpack profiles) players can pick from, the first being
+    // the default.  Empty leaves it up to the launcher.  PrismLauncher's are its
+    // instances, unless some are listed.
+    #[cfg(unix)]
+    pub fn game_versions(&self) -> Vec<String> {
+        if self.versions.is_empty() && self.prism.enabled() {
+            return crate::prism::instances(self);
+        }
+        self.versions.clone()
+    }
+
+    // The frame rate a session asking for `fps` gets
+    pub fn capped_fps(&self, fps: u32) -> u32 {
+        self.max_fps.map_or(fps, |max| fps.min(max))
+    }
+
+    // The JVM arguments for each game when up to `sessions` run at once.  Without
+    // ANSICRAFT_JVM_XMX, the heap is sized to share three quarters of the machine's
+    // memory between them, so a full server doesn't run out.
+    pub fn jvm_args(&self, sessions: usize) -> Vec<String> {
+        let xmx = self.jvm_xmx.clone().or_else(|| {
+            let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+            let total_kb: u64 = meminfo
+                .lines()
+                .find_map(|line| line.strip_prefix("MemTotal:"))?
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse()
+                .ok()?;
+            let share_mb = total_kb / 1024 * 3 / 4 / sessions.max(1) as u64;
+            Some(format!("{}M", share_mb.clamp(512, 4096)))
+        });
+
+        let mut args = vec![];
+        if let Some(xmx) = xmx {
+            args.push(format!("-Xmx{}", xmx));
+        }
+        if let Some(xms) = &self.jvm_xms {
+            args.push(format!("-Xms{}", xms));
+        }
+        args.extend(self.extra_jvm_args.iter().cloned());
+        args
+    }
+}
+
+// The config as it is right now, shared by everything that keeps running across a
+// reload.  Whatever's only read at startup just takes a copy.
+#[derive(Clone)]
+pub struct LiveConfig {
+    current: std::sync::Arc<std::sync::RwLock<std::sync::Arc<AppConfig>>>,
+    // The command line's overrides, applied again on every reload
+    overrides: std::sync::Arc<dyn Fn(&mut AppConfig) + Send + Sync>,
+}
+
+impl LiveConfig {
+    pub fn load(overrides: impl Fn(&mut AppConfig) + Send + Sync + 'static) -> Result<Self, String> {
+        let mut config = AppConfig::load()?;
+        overrides(&mut config);
+        Ok(Self {
+            current: std::sync::Arc::new(std::sync::RwLock::new(std::sync::Arc::new(config))),
+            overrides: std::sync::Arc::new(overrides),
+        })
+    }
+
+    pub fn get(&self) -> std::sync::Arc<AppConfig> {
+        self.current.read().unwrap().clone()
+    }
+
+    // Reads the config again, leaving it as it was if that fails.  Settings that
+    // are only used at startup keep their old values, so what's running matches.
+    pub fn reload(&self) -> Result<std::sync::Arc<AppConfig>, String> {
+        let mut config = AppConfig::load()?;
+        (self.overrides)(&mut config);
+        let old = self.get();
+        let displays =
+            (&config.pools, config.spawn_x, config.provisioner) != (&old.pools, old.spawn_x, old.provisioner);
+        if config.port != old.port
+            || config.max_sessions != old.max_sessions
+            || displays
+            || config.reservations != old.reservations
+        {
+            crate::logging::report("The port, displays, session limit and reservations only change on a restart");
+        }
+        config.port = old.port;
+        config.max_sessions = old.max_sessions;
+        config.pools = old.pools.clone();
+        config.spawn_x = old.spawn_x;
+        config.provisioner = old.provisioner;
+        config.reservations = old.reservations.clone();
+        let config = std::sync::Arc::new(config);
+        *self.current.write().unwrap() = config.clone();
+        Ok(config)
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: String) -> Option<T> {
+    match value.trim().parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            crate::logging::report(&format!("Ignoring invalid value for {}: {:?}", name, value));
+            None
+        }
+    }
+}
@@ -0,0 +1,87 @@
+// Serving in the background, for operators without Docker or systemd to look
+// after it.  `serve --daemon` starts the server again as a process of its own, in
+// a session of its own with nothing for stdin, stdout or stderr, writes its ID to
+// the pidfile and returns.  It logs wherever it's configured to, or DAEMON_LOG_FILE
+// if that's stderr.  `termcast stop` then stops it, and the games it started along
+// with it, since they're in its process group.  It stays in the working directory,
+// which is where it keeps its files.
+
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::{fs, io};
+
+use nix::errno::Errno;
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+
+use crate::config::{AppConfig, DAEMON_LOG_FILE, DAEMON_START_CHECK, DAEMON_STOP_TIMEOUT};
+
+// The process the pidfile names, if it's still running
+fn running(pidfile: &Path) -> Option<Pid> {
+    let pid = fs::read_to_string(pidfile).ok()?.trim().parse().ok().map(Pid::from_raw)?;
+    // Signal 0 just checks it's there
+    (signal::kill(pid, None) != Err(Errno::ESRCH)).then_some(pid)
+}
+
+// Starts the server in the background with the same arguments, apart from --daemon
+pub async fn start(pidfile: &Path, config: &AppConfig) -> anyhow::Result<()> {
+    if let Some(pid) = running(pidfile) {
+        anyhow::bail!("Already running as process {} (going by {})", pid, pidfile.display());
+    }
+    let mut cmd = Command::new(std::env::current_exe()?);
+    cmd.args(std::env::args_os().skip(1).filter(|arg| arg != "--daemon"))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    if config.log_file.is_none() {
+        cmd.env("ANSICRAFT_LOG_FILE", DAEMON_LOG_FILE);
+    }
+    // SAFETY: setsid is async-signal-safe, and nothing else happens between fork and exec
+    unsafe {
+        cmd.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = cmd.spawn()?;
+    fs::write(pidfile, format!("{}\n", child.id()))?;
+
+    // Mistakes in the config, or the port being taken, stop it straight away
+    tokio::time::sleep(DAEMON_START_CHECK).await;
+    if let Some(status) = child.try_wait()? {
+        let _ = fs::remove_file(pidfile);
+        let log = config.log_file.as_deref().unwrap_or(Path::new(DAEMON_LOG_FILE));
+        anyhow::bail!("The server stopped as it started ({}), see {} for why", status, log.display());
+    }
+    println!("Serving in the background as process {} (stop it with `termcast stop`)", child.id());
+    Ok(())
+}
+
+// Stops the server the pidfile names, and everything it started, returning whether it did
+pub fn stop(pidfile: &Path) -> bool {
+    let Some(pid) = running(pidfile) else {
+        eprintln!("Nothing's running, going by {}", pidfile.display());
+        // Left behind by one that didn't stop this way
+        let _ = fs::remove_file(pidfile);
+        return false;
+    };
+    // It's the leader of its process group, which its games are in too
+    if let Err(e) = signal::killpg(pid, Signal::SIGTERM) {
+        eprintln!("Couldn't stop process {}: {}", pid, e);
+        return false;
+    }
+    let started = std::time::Instant::now();
+    while signal::kill(pid, None) != Err(Errno::ESRCH) {
+        if started.elapsed() > DAEMON_STOP_TIMEOUT {
+            eprintln!("Process {} is still running after {:?}", pid, DAEMON_STOP_TIMEOUT);
+            return false;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    let _ = fs::remove_file(pidfile);
+    println!("Stopped process {}", pid);
+    true
+}
@@ -0,0 +1,456 @@
+// `admin dashboard`: the sessions running, their frame rates and bandwidth, the
+// queue and the displays, kept up to date for operators to keep an eye on.  Up and
+// down pick a session, k kicks it, m sends it a message, d drains (or resumes)
+// and q leaves.  ratatui draws it into a buffer, which goes out over the admin's
+// SSH channel or the admin socket `termcast admin` attaches to, so it's the same
+// either way.  The socket has no window-change requests, so `termcast admin` sends
+// resizes in the input, as xterm's CSI 8 ; rows ; cols t.
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, Row, Table, TableState};
+use ratatui::{Frame, Terminal, TerminalOptions, Viewport};
+use tracing::info;
+
+use crate::config::{ADMIN_MESSAGE_DURATION, DASHBOARD_REFRESH, LOBBY_FRAME_INTERVAL};
+use crate::queueing::{QueueSnapshot, Resource, ResourceAllocator};
+use crate::render::Screen;
+use crate::session::{self, LiveSession};
+use crate::xdo::InputSource;
+
+enum Key {
+    Up,
+    Down,
+    Enter,
+    Backspace,
+    Escape,
+    Quit,
+    Char(char),
+    Resize(u16, u16),
+}
+
+// Turns terminal input into keys, keeping any escape sequence split across reads
+// in `pending` for next time
+fn parse_keys(pending: &mut Vec<u8>, data: &[u8]) -> Vec<Key> {
+    let mut input = std::mem::take(pending);
+    input.extend_from_slice(data);
+
+    let mut keys = vec![];
+    let mut i = 0;
+    while i < input.len() {
+        let key = match input[i] {
+            0x03 => Some(Key::Quit),
+            b'\r' | b'\n' => Some(Key::Enter),
+            0x7f | 0x08 => Some(Key::Backspace),
+            // Esc on its own, rather than the start of a sequence
+            0x1b if i + 1 == input.len() => Some(Key::Escape),
+            0x1b if input[i + 1] == b'[' || input[i + 1] == b'O' => {
+                // Parameters, then a letter (or ~) to finish
+                let Some(end) = input[i + 2..].iter().position(|byte| (0x40..=0x7e).contains(byte)) else {
+                    *pending = input[i..].to_vec();
+                    break;
+                };
+                let params = String::from_utf8_lossy(&input[i + 2..i + 2 + end]).into_owned();
+                let last = input[i + 2 + end];
+                i += 2 + end;
+                match (last, params.split(';').collect::<Vec<_>>().as_slice()) {
+                    (b'A', _) => Some(Key::Up),
+                    (b'B', _) => Some(Key::Down),
+                    (b't', ["8", rows, cols]) => match (cols.parse(), rows.parse()) {
+                        (Ok(cols), Ok(rows)) => Some(Key::Resize(cols, rows)),
+                        _ => None,
+                    },
+                    _ => None,
+                }
+            }
+            0x1b => Some(Key::Escape),
+            byte @ 0x20..=0x7e => Some(Key::Char(byte as char)),
+            _ => None,
+        };
+        keys.extend(key);
+        i += 1;
+    }
+    keys
+}
+
+// Frames and bytes a second, going by the last two looks
+#[derive(Clone, Copy, Default)]
+struct Rate {
+    fps: f64,
+    bytes: f64,
+}
+
+struct Dashboard {
+    sessions: Vec<LiveSession>,
+    snapshot: Option<QueueSnapshot>,
+    // Each session's counts when they were last looked at, to work out rates from
+    counted: HashMap<u64, (u64, u64)>,
+    rates: HashMap<u64, Rate>,
+    sampled: Instant,
+    table: TableState,
+    // The message being typed, while one is
+    typing: Option<String>,
+    // What the last action did
+    status: String,
+    pending_input: Vec<u8>,
+    resized: Option<(u16, u16)>,
+}
+
+impl Dashboard {
+    fn new() -> Self {
+        Self {
+            sessions: vec![],
+            snapshot: None,
+            counted: HashMap::new(),
+            rates: HashMap::new(),
+            sampled: Instant::now(),
+            table: TableState::default().with_selected(0),
+            typing: None,
+            status: String::new(),
+            pending_input: vec![],
+            resized: None,
+        }
+    }
+
+    async fn refresh<T: Resource>(&mut self, allocator: &ResourceAllocator<T>) {
+        let elapsed = self.sampled.elapsed().as_secs_f64().max(0.001);
+        self.sampled = Instant::now();
+        self.sessions = session::live();
+        let mut counted = HashMap::new();
+        for live in &self.sessions {
+            let counts = (
+                live.streamed.frames.load(Ordering::Relaxed),
+                live.streamed.bytes.load(Ordering::Relaxed),
+            );
+            if let Some((frames, bytes)) = self.counted.get(&live.id) {
+                let rate = Rate {
+                    fps: counts.0.saturating_sub(*frames) as f64 / elapsed,
+                    bytes: counts.1.saturating_sub(*bytes) as f64 / elapsed,
+                };
+                self.rates.insert(live.id, rate);
+            }
+            counted.insert(live.id, counts);
+        }
+        self.rates.retain(|id, _| counted.contains_key(id));
+        self.counted = counted;
+        self.snapshot = allocator.snapshot().await;
+
+        let last = self.sessions.len().saturating_sub(1);
+        self.table.select(Some(self.table.selected().unwrap_or(0).min(last)));
+    }
+
+    fn selected(&self) -> Option<&LiveSession> {
+        self.sessions.get(self.table.selected()?)
+    }
+
+    // Returns false once the operator's done
+    fn handle_input<T: Resource>(&mut self, data: &[u8], allocator: &ResourceAllocator<T>) -> bool {
+        for key in parse_keys(&mut self.pending_input, data) {
+            if let Key::Resize(cols, rows) = key {
+                self.resized = Some((cols, rows));
+                continue;
+            }
+            if let Some(text) = &mut self.typing {
+                match key {
+                    Key::Char(c) if text.len() < 200 => text.push(c),
+                    Key::Backspace => {
+                        text.pop();
+                    }
+                    Key::Enter => {
+                        let text = self.typing.take().unwrap_or_default();
+                        self.send_message(text);
+                    }
+                    Key::Escape | Key::Quit => self.typing = None,
+                    _ => {}
+                }
+                continue;
+            }
+            match key {
+                Key::Quit | Key::Char('q' | 'Q') => return false,
+                Key::Up => self.table.select_previous(),
+                Key::Down => {
+                    let last = self.sessions.len().saturating_sub(1);
+                    self.table.select(Some((self.table.selected().unwrap_or(0) + 1).min(last)));
+                }
+                Key::Char('k' | 'K') => self.kick(),
+                Key::Char('m' | 'M') if self.selected().is_some() => self.typing = Some(String::new()),
+                Key::Char('d' | 'D') => {
+                    let draining = !self.snapshot.as_ref().is_some_and(|snapshot| snapshot.draining);
+                    allocator.set_draining(draining);
+                    if let Some(snapshot) = &mut self.snapshot {
+                        snapshot.draining = draining;
+                    }
+                    self.status = if draining {
+                        "Draining: no new sessions until it's resumed".to_owned()
+                    } else {
+                        "Handing out sessions again".to_owned()
+                    };
+                    info!("{} from the admin dashboard", if draining { "Draining" } else { "Resumed" });
+                }
+                _ => {}
+            }
+        }
+        true
+    }
+
+    fn kick(&mut self) {
+        let Some(live) = self.selected() else {
+            return;
+        };
+        let info = &live.state.session_info;
+        let screen = info.display.clone().unwrap_or_default();
+        live.state.running.store(false, Ordering::SeqCst);
+        info!("Kicked {} off display {} from the admin dashboard", info.username, screen);
+        self.status = format!("Kicked {} off display {}", info.username, screen);
+    }
+
+    fn send_message(&mut self, text: String) {
+        let text = text.trim().to_owned();
+        let Some(live) = self.selected() else {
+            return;
+        };
+        if text.is_empty() {
+            return;
+        }
+        let username = live.state.session_info.username.clone();
+        let notice = format!("📣 {}", text);
+        {
+            let mut overlay = live.state.overlay.lock().unwrap();
+            overlay.show_notice(notice.clone());
+            overlay.notify(text);
+        }
+        let overlay = live.state.overlay.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(ADMIN_MESSAGE_DURATION).await;
+            overlay.lock().unwrap().hide_notice_if(&notice);
+        });
+        self.status = format!("Sent {} the message", username);
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, sessions, bottom, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(4),
+            Constraint::Length(8),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [queue, displays] =
+            Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(bottom);
+
+        frame.render_widget(Paragraph::new(self.header()), header);
+        self.draw_sessions(frame, sessions);
+        self.draw_queue(frame, queue);
+        self.draw_displays(frame, displays);
+        frame.render_widget(Paragraph::new(self.footer()), footer);
+    }
+
+    fn header(&self) -> Line<'static> {
+        let mut spans = vec![" ansicraft ".bold().reversed(), format!("  {} playing", self.sessions.len()).into()];
+        if let Some(snapshot) = &self.snapshot {
+            spans.push(format!("  ·  {} waiting", snapshot.pending.len()).into());
+            if snapshot.draining {
+                let state = if snapshot.allocated.is_empty() { "done, safe to restart" } else { "waiting on sessions" };
+                spans.push(format!("  ·  DRAINING, {}", state).bold().fg(Color::Yellow));
+            }
+        } else {
+            spans.push("  ·  the queue manager isn't running".fg(Color::Red));
+        }
+        Line::from(spans)
+    }
+
+    fn draw_sessions(&mut self, frame: &mut Frame, area: Rect) {
+        let rows: Vec<Row> = self
+            .sessions
+            .iter()
+            .map(|live| {
+                let info = &live.state.session_info;
+                let render = live.state.render_settings.lock().unwrap().clone();
+                let rate = self.rates.get(&live.id).copied().unwrap_or_default();
+                // Marked when it's been turned down for a slow link
+                let cap = match live.streamed.throttle.limit() {
+                    Some(limit) if limit < render.fps => format!("{}↓", limit),
+                    _ => render.fps.to_string(),
+                };
+                Row::new(vec![
+                    info.username.chars().take(16).collect(),
+                    info.display.clone().unwrap_or_default(),
+                    info.source_ip.map(|ip| ip.to_string()).unwrap_or_else(|| "-".to_owned()),
+                    format_duration(live.started.elapsed()),
+                    format!("{:.1} / {}", rate.fps, cap),
+                    format!("{:?}", render.mode),
+                    format_rate(rate.bytes),
+                ])
+            })
+            .collect();
+        let widths = [
+            Constraint::Length(16),
+            Constraint::Length(8),
+            Constraint::Length(16),
+            Constraint::Length(9),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(11),
+        ];
+        let header = Row::new(["Player", "Display", "From", "Playing", "FPS / cap", "Color", "Bandwidth"])
+            .style(Style::new().add_modifier(Modifier::BOLD));
+        let table = Table::new(rows, widths)
+            .header(header)
+            .block(Block::bordered().title(" Sessions "))
+            .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, area, &mut self.table);
+    }
+
+    fn draw_queue(&self, frame: &mut Frame, area: Rect) {
+        let pending = self.snapshot.iter().flat_map(|snapshot| &snapshot.pending);
+        let rows: Vec<Row> = pending
+            .enumerate()
+            .map(|(position, (_, requester, waited))| {
+                Row::new(vec![
+                    format!("{}", position + 1),
+                    requester.owner.chars().take(16).collect(),
+                    requester.pool.clone(),
+                    format!("{}", requester.priority),
+                    format_duration(*waited),
+                ])
+            })
+            .collect();
+        let widths = [
+            Constraint::Length(3),
+            Constraint::Length(16),
+            Constraint::Length(10),
+            Constraint::Length(4),
+            Constraint::Length(9),
+        ];
+        let header =
+            Row::new(["#", "Player", "Pool", "Prio", "Waited"]).style(Style::new().add_modifier(Modifier::BOLD));
+        let table = Table::new(rows, widths).header(header).block(Block::bordered().title(" Queue "));
+        frame.render_widget(table, area);
+    }
+
+    fn draw_displays(&self, frame: &mut Frame, area: Rect) {
+        let mut lines = vec![];
+        if let Some(snapshot) = &self.snapshot {
+            for pool in &snapshot.pools {
+                let free = format!("{} of {} free, {} waiting", pool.available, pool.capacity, pool.waiting);
+                // Unnamed when there's only the one
+                lines.push(Line::from(if pool.name.is_empty() { free } else { format!("{}: {}", pool.name, free) }));
+            }
+            if let Some(capacity) = snapshot.capacity {
+                lines.push(Line::from(format!("Scheduled capacity: {}", capacity)));
+            }
+            if !snapshot.held_for_reconnect.is_empty() {
+                lines.push(Line::from(format!("Held for reconnects: {}", snapshot.held_for_reconnect.len())));
+            }
+            lines.push(if snapshot.unhealthy.is_empty() {
+                Line::from("All displays healthy".fg(Color::Green))
+            } else {
+                Line::from(format!("Unhealthy: {:?}", snapshot.unhealthy).fg(Color::Red))
+            });
+        }
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Displays ")), area);
+    }
+
+    fn footer(&self) -> Line<'static> {
+        if let Some(text) = &self.typing {
+            let username = self.selected().map(|live| live.state.session_info.username.clone()).unwrap_or_default();
+            return Line::from(format!(" Message for {} (Enter sends, Esc doesn't): {}▏", username, text));
+        }
+        let keys = " ↑↓ pick  k kick  m message  d drain/resume  q leave";
+        if self.status.is_empty() {
+            Line::from(keys.dim())
+        } else {
+            Line::from(vec![keys.dim(), format!("   {}", self.status).into()])
+        }
+    }
+}
+
+// What ratatui draws, to be sent on all at once
+#[derive(Clone, Default)]
+struct Drawn(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Drawn {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+fn format_rate(bytes: f64) -> String {
+    if bytes >= 1024.0 * 1024.0 {
+        format!("{:.1} MB/s", bytes / (1024.0 * 1024.0))
+    } else {
+        format!("{:.1} KB/s", bytes / 1024.0)
+    }
+}
+
+// Shows the dashboard on the screen until the operator leaves, or it's gone.
+// `size` is the terminal's, in columns and rows.
+pub async fn run<T: Resource, S: Screen>(
+    allocator: &ResourceAllocator<T>,
+    mut screen: S,
+    input: InputSource,
+    size: impl Fn() -> (u16, u16),
+) -> io::Result<()> {
+    let mut dashboard = Dashboard::new();
+    dashboard.refresh(allocator).await;
+    let area = |(cols, rows): (u16, u16)| Rect::new(0, 0, cols.max(1), rows.max(1));
+    let mut shown = size();
+    let drawn = Drawn::default();
+    let mut terminal = Terminal::with_options(
+        CrosstermBackend::new(drawn.clone()),
+        TerminalOptions {
+            viewport: Viewport::Fixed(area(shown)),
+        },
+    )?;
+    let mut frames = tokio::time::interval(LOBBY_FRAME_INTERVAL);
+    let mut refreshed = Instant::now();
+
+    // Alternate screen, hidden cursor
+    screen.show(b"\x1b[?1049h\x1b[?25l\x1b[2J".to_vec()).await?;
+    'shown: loop {
+        frames.tick().await;
+        loop {
+            let received = input.lock().await.try_recv();
+            match received {
+                Ok(data) if dashboard.handle_input(&data, allocator) => {}
+                Ok(_) => break 'shown,
+                Err(tokio::sync::mpsc::error::TryRecvError::Empty) => break,
+                Err(tokio::sync::mpsc::error::TryRecvError::Disconnected) => break 'shown,
+            }
+        }
+        if refreshed.elapsed() >= DASHBOARD_REFRESH {
+            dashboard.refresh(allocator).await;
+            refreshed = Instant::now();
+        }
+
+        let size = dashboard.resized.unwrap_or_else(&size);
+        if size != shown {
+            shown = size;
+            terminal.resize(area(shown))?;
+        }
+        terminal.draw(|frame| dashboard.draw(frame))?;
+        let bytes = std::mem::take(&mut *drawn.0.lock().unwrap());
+        if !bytes.is_empty() {
+            screen.show(bytes).await?;
+        }
+    }
+    screen.show(b"\x1b[?25h\x1b[?1049l".to_vec()).await
+}
@@ -0,0 +1,118 @@
+// Each game in a container of its own, started when the session gets its slot and
+// removed when it ends, so a misbehaving game can't get at the host or the other
+// players.  The container runs its own Xvfb on the display number the pool handed
+// out, with its socket in a directory of its own under /tmp/.X11-unix and linked
+// in next to the others, so the capture and input here can still reach it without
+// the container reaching anyone else's.  Set ANSICRAFT_PROVISIONER=docker to use this.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::{AppConfig, Provisioner, DOCKER_START_TIMEOUT};
+use crate::limits;
+
+// Starts the X server, waits for it, then runs the game in its place
+const CONTAINER_SCRIPT: &str = r#"Xvfb "$1" -screen 0 "$2" -nolisten tcp -noreset &
+until [ -e "/tmp/.X11-unix/X${1#:}" ]; do sleep 0.1; done
+DISPLAY="$1" xsetroot -cursor /root/blank_cursor.xbm /root/blank_cursor.xbm
+shift 2
+exec "$@""#;
+
+pub fn enabled(settings: &AppConfig) -> bool {
+    settings.provisioner == Some(Provisioner::Docker)
+}
+
+fn display_number(display: &str) -> &str {
+    display.trim_start_matches(':').split('.').next().unwrap_or("")
+}
+
+fn container_name(display: &str) -> String {
+    format!("ansicraft-session-{}", display_number(display))
+}
+
+fn socket_path(display: &str) -> PathBuf {
+    PathBuf::from(format!("/tmp/.X11-unix/X{}", display_number(display)))
+}
+
+// Where the container's X server makes its socket, relative to /tmp/.X11-unix,
+// which is mounted as the container's
+fn socket_dir_name(display: &str) -> String {
+    format!("session-{}", display_number(display))
+}
+
+fn socket_dir(display: &str) -> PathBuf {
+    Path::new("/tmp/.X11-unix").join(socket_dir_name(display))
+}
+
+// Runs the command in a new container instead, with its own display.  Its
+// environment comes along, and the game directory is mounted at the same path.
+// The game's install is read-only, so one container can't change what the others
+// launch; without a game directory of its own, the rest of it is the container's.
+pub fn wrap(
+    cmd: Command,
+    display: &str,
+    (width, height): (u16, u16),
+    game_dir: Option<&Path>,
+    settings: &AppConfig,
+) -> Command {
+    // Left over from a session that didn't get cleaned up
+    remove(display);
+    let _ = std::fs::create_dir_all(socket_dir(display));
+
+    let mut run = Command::new("docker");
+    run.args(["run", "--rm", "--init", "-i", "--name", &container_name(display)])
+        .arg("-v")
+        .arg(format!("{}:/tmp/.X11-unix", socket_dir(display).display()))
+        .args(limits::docker_args(&settings.limits));
+    for dir in ["versions", "libraries", "assets"] {
+        run.arg("-v").arg(format!("{0}:{0}:ro", settings.minecraft_dir.join(dir).display()));
+    }
+    if let Some(game_dir) = game_dir {
+        run.arg("-v").arg(format!("{0}:{0}", game_dir.display()));
+    }
+    for (key, value) in cmd.get_envs() {
+        if let Some(value) = value {
+            run.arg("-e").arg(format!("{}={}", key.to_string_lossy(), value.to_string_lossy()));
+        }
+    }
+    // Anything else, like more volumes or a network
+    run.args(settings.docker_args.split_whitespace());
+    run.args(["--entrypoint", "sh", &settings.session_image, "-c", CONTAINER_SCRIPT, "sh", display])
+        .arg(format!("{}x{}x24", width, height))
+        .arg(cmd.get_program())
+        .args(cmd.get_args());
+    run
+}
+
+// Waits for the container's X server to come up, then links its socket in with
+// the others, so there's something to capture
+pub fn wait_for_display(display: &str, container: &mut Child) -> io::Result<()> {
+    let started = Instant::now();
+    let name = format!("X{}", display_number(display));
+    while !socket_dir(display).join(&name).exists() {
+        if let Some(status) = container.try_wait()? {
+            return Err(io::Error::other(format!("Container for display {} exited with {}", display, status)));
+        }
+        if started.elapsed() > DOCKER_START_TIMEOUT {
+            return Err(io::Error::other(format!("Container for display {} didn't start in time", display)));
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    // Relative, so it works wherever /tmp/.X11-unix is mounted
+    std::os::unix::fs::symlink(Path::new(&socket_dir_name(display)).join(name), socket_path(display))
+}
+
+// Removes the display's container, whether or not the game in it has exited, and
+// its socket
+pub fn remove(display: &str) {
+    let _ = Command::new("docker")
+        .args(["rm", "-f", &container_name(display)])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    let _ = std::fs::remove_file(socket_path(display));
+    let _ = std::fs::remove_dir_all(socket_dir(display));
+}
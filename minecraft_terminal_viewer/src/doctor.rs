@@ -0,0 +1,248 @@
+// `termcast doctor` checks that everything sessions need is installed and working,
+// so problems turn up before players do: ffmpeg, xdotool, Java and the launcher,
+// the X displays, and a test capture from an X server started just for it.
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::config::{self, AppConfig, GAME_HEIGHT, GAME_WIDTH};
+use crate::queueing::Provision;
+#[cfg(feature = "server")]
+use crate::sshng;
+use crate::{prism, xserver};
+
+enum Outcome {
+    Ok(String),
+    // Only matters for some setups, so it doesn't fail the checks
+    Warning(String),
+    Failed(String),
+}
+
+type Check = fn(&AppConfig) -> Outcome;
+
+// Runs a program, giving the first line it prints if it works
+fn runs(program: &str, args: &[&str], display: Option<&str>) -> Result<String, String> {
+    let mut cmd = Command::new(program);
+    cmd.args(args).stdin(Stdio::null());
+    if let Some(display) = display {
+        cmd.env("DISPLAY", display);
+    }
+    match cmd.output() {
+        Ok(output) if output.status.success() => {
+            // Java says its version on stderr
+            let text = if output.stdout.is_empty() { output.stderr } else { output.stdout };
+            Ok(String::from_utf8_lossy(&text).lines().next().unwrap_or_default().trim().to_owned())
+        }
+        Ok(output) => Err(format!(
+            "{} exited with {}: {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).lines().last().unwrap_or_default().trim()
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(format!("{} isn't installed (or isn't on PATH)", program)),
+        Err(e) => Err(format!("Couldn't run {}: {}", program, e)),
+    }
+}
+
+fn on_path(program: &str) -> bool {
+    if program.contains('/') {
+        return Path::new(program).exists();
+    }
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).exists()))
+}
+
+fn check_ffmpeg(settings: &AppConfig) -> Outcome {
+    let ffmpeg = &settings.ffmpeg;
+    let version = match runs(ffmpeg, &["-version"], None) {
+        Ok(version) => version,
+        Err(e) => return Outcome::Failed(format!("{}. Install ffmpeg to capture the game.", e)),
+    };
+    let devices = Command::new(ffmpeg).args(["-hide_banner", "-devices"]).output();
+    match devices {
+        Ok(output) if String::from_utf8_lossy(&output.stdout).contains("x11grab") => Outcome::Ok(version),
+        _ => Outcome::Failed(format!("{} can't capture X displays (no x11grab). Install a build with xcb support.", version)),
+    }
+}
+
+fn check_xdotool(_: &AppConfig) -> Outcome {
+    match runs("xdotool", &["version"], None) {
+        Ok(version) => Outcome::Ok(version),
+        Err(e) => Outcome::Failed(format!("{}. Install xdotool to send players' input to the game.", e)),
+    }
+}
+
+fn check_launcher(settings: &AppConfig) -> Outcome {
+    if let Some(command) = &settings.app.command {
+        let program = command.split_whitespace().next().unwrap_or_default();
+        if !on_path(program) {
+            return Outcome::Failed(format!("ANSICRAFT_APP_COMMAND runs {}, which isn't there", program));
+        }
+        return Outcome::Ok(format!("runs {}", command));
+    }
+    if settings.prism.enabled() {
+        let prism = &settings.prism.binary;
+        if !on_path(prism) {
+            return Outcome::Failed(format!("ANSICRAFT_PRISM runs {}, which isn't there", prism));
+        }
+        return match prism::instances(settings).len() {
+            0 => Outcome::Failed(format!("{} hasn't got any instances to launch", prism)),
+            count => Outcome::Ok(format!("{}, with {} instances", prism, count)),
+        };
+    }
+    let launcher = &settings.launcher;
+    if launcher.launcher.is_empty() {
+        return match runs(&settings.java, &["-version"], None) {
+            Ok(version) => Outcome::Ok(format!("built in, with {}", version)),
+            Err(e) => Outcome::Failed(format!("{}. Install Java 21, or point ANSICRAFT_JAVA at it.", e)),
+        };
+    }
+    for program in [&launcher.interpreter, &launcher.launcher] {
+        if !program.is_empty() && !on_path(program) {
+            return Outcome::Failed(format!("ANSICRAFT_LAUNCHER needs {}, which isn't there", program));
+        }
+    }
+    Outcome::Ok(launcher.launcher.clone())
+}
+
+fn check_login_script(settings: &AppConfig) -> Outcome {
+    let script = settings.ms_login_script.display().to_string();
+    if !settings.ms_login_script.exists() {
+        return Outcome::Warning(format!("{} isn't there, so players can't log in to Microsoft accounts", script));
+    }
+    match runs("python3", &["-c", "import minecraft_launcher_lib"], None) {
+        Ok(_) => Outcome::Ok(script),
+        Err(_) => Outcome::Warning(format!(
+            "{} needs minecraft-launcher-lib (pip3 install minecraft-launcher-lib) for Microsoft logins",
+            script
+        )),
+    }
+}
+
+// The displays entry-point.sh started (or ANSICRAFT_POOLS lists) should all answer
+#[cfg(feature = "server")]
+fn check_displays(settings: &AppConfig) -> Outcome {
+    let displays: Vec<u32> = match &settings.pools {
+        Some(spec) => match sshng::parse_pools(spec) {
+            Ok(ranges) => ranges.into_iter().flat_map(|range| range.displays).collect(),
+            Err(e) => return Outcome::Failed(format!("ANSICRAFT_POOLS is wrong: {}", e)),
+        },
+        None => sshng::discover_displays(),
+    };
+    if displays.is_empty() {
+        return Outcome::Failed(
+            "No X displays are running. Start some (entry-point.sh does), or set ANSICRAFT_SPAWN_X=true.".to_owned(),
+        );
+    }
+    let broken: Vec<String> = displays
+        .iter()
+        .map(|display| format!(":{}", display))
+        .filter(|display| runs("xdotool", &["getdisplaygeometry"], Some(display)).is_err())
+        .collect();
+    if !broken.is_empty() {
+        return Outcome::Failed(format!("Nothing answering on {}. Is their X server running?", broken.join(", ")));
+    }
+    Outcome::Ok(format!("{} displays answering", displays.len()))
+}
+
+fn check_sandbox(_: &AppConfig) -> Outcome {
+    // Trying it out is the only way to know it's allowed to make namespaces here
+    match runs("bwrap", &["--ro-bind", "/", "/", "--unshare-all", "--cap-drop", "ALL", "true"], None) {
+        Ok(_) => Outcome::Ok(runs("bwrap", &["--version"], None).unwrap_or_default()),
+        Err(e) => Outcome::Failed(format!(
+            "{}. Install bubblewrap, and run the container with --security-opt seccomp=unconfined --cap-add SYS_ADMIN.",
+            e
+        )),
+    }
+}
+
+fn check_provisioner(program: &str, args: &[&str]) -> Outcome {
+    match runs(program, args, None) {
+        Ok(version) => Outcome::Ok(version),
+        Err(e) => Outcome::Failed(format!("{}, which ANSICRAFT_PROVISIONER needs", e)),
+    }
+}
+
+// Starts an X server on a spare display and captures a frame from it, the way a
+// session would
+fn check_capture(settings: &AppConfig) -> Outcome {
+    let Some(number) = (90..100).find(|number| !Path::new(&format!("/tmp/.X11-unix/X{}", number)).exists()) else {
+        return Outcome::Warning("No spare display to test on".to_owned());
+    };
+    let display = format!(":{}", number);
+    let window_manager = settings.window_manager.as_deref();
+    let _x_server = match xserver::XServer::start(&display, (GAME_WIDTH, GAME_HEIGHT), window_manager) {
+        Ok(x_server) => x_server,
+        Err(e) => {
+            let outcome = format!("Couldn't start {} to test with: {}", config::XVFB_BINARY, e);
+            return match provision(settings) {
+                Provision::SpawnX => Outcome::Failed(outcome),
+                _ => Outcome::Warning(outcome),
+            };
+        }
+    };
+    let size = format!("{}x{}", GAME_WIDTH, GAME_HEIGHT);
+    let capture = runs(
+        &settings.ffmpeg,
+        &["-hide_banner", "-f", "x11grab", "-video_size", &size, "-i", &display, "-frames:v", "1", "-f", "null", "-"],
+        None,
+    );
+    if let Err(e) = capture {
+        return Outcome::Failed(format!("Couldn't capture {}: {}", display, e));
+    }
+    match runs("xdotool", &["mousemove", "10", "10"], Some(&display)) {
+        Ok(_) => Outcome::Ok(format!("captured a frame from {} and moved its mouse", display)),
+        Err(e) => Outcome::Failed(format!("Couldn't send input to {}: {}", display, e)),
+    }
+}
+
+// How the server gets its displays.  Without it, `local` plays on one that's running.
+#[cfg(feature = "server")]
+fn provision(settings: &AppConfig) -> Provision {
+    sshng::provision(settings)
+}
+
+#[cfg(not(feature = "server"))]
+fn provision(_: &AppConfig) -> Provision {
+    Provision::Running
+}
+
+// Runs every check, printing how each went, and says whether they all passed
+pub fn run(settings: &AppConfig) -> bool {
+    let mut checks: Vec<(&str, Check)> = vec![
+        ("ffmpeg", check_ffmpeg),
+        ("xdotool", check_xdotool),
+        ("Launcher", check_launcher),
+        ("Microsoft logins", check_login_script),
+    ];
+    match provision(settings) {
+        #[cfg(feature = "server")]
+        Provision::Running => checks.push(("Displays", check_displays)),
+        #[cfg(not(feature = "server"))]
+        Provision::Running => {}
+        Provision::SpawnX => {}
+        Provision::Docker => {
+            checks.push(("Docker", |_| check_provisioner("docker", &["version", "-f", "{{.Server.Version}}"])))
+        }
+        Provision::Kubernetes => checks.push(("kubectl", |_| check_provisioner("kubectl", &["version", "--client"]))),
+        // Only a gateway's slots are on workers, which are checked on their own machines
+        Provision::Worker => {}
+    }
+    if settings.sandboxed("") {
+        checks.push(("Sandbox", check_sandbox));
+    }
+    checks.push(("Test capture", check_capture));
+
+    let mut passed = true;
+    for (name, check) in checks {
+        match check(settings) {
+            Outcome::Ok(detail) => println!("✅ {}: {}", name, detail),
+            Outcome::Warning(detail) => println!("⚠️  {}: {}", name, detail),
+            Outcome::Failed(detail) => {
+                println!("❌ {}: {}", name, detail);
+                passed = false;
+            }
+        }
+    }
+    passed
+}
@@ -0,0 +1,73 @@
+// Extra environment variables for the game, e.g. a JAVA_HOME, MESA overrides or a
+// texture pack path.  ANSICRAFT_GAME_ENV applies to every game and
+// ANSICRAFT_GAME_ENV_<POOL> to a pool's, as comma-separated KEY=VALUE lists.  A
+// player can have their own too, in a file named after them in
+// ANSICRAFT_USER_ENV_DIR with a KEY=VALUE per line.  Later ones win.
+
+#[cfg(feature = "server")]
+use std::fs;
+#[cfg(feature = "server")]
+use std::io;
+#[cfg(feature = "server")]
+use std::path::PathBuf;
+
+#[cfg(feature = "server")]
+use tracing::error;
+use tracing::warn;
+
+use crate::config::AppConfig;
+
+fn parse<'a>(entries: impl Iterator<Item = &'a str>, source: &str) -> Vec<(String, String)> {
+    entries
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| match entry.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => Some((key.trim().to_owned(), value.trim().to_owned())),
+            _ => {
+                warn!("Ignoring \"{}\" in {}, which should look like KEY=VALUE", entry, source);
+                None
+            }
+        })
+        .collect()
+}
+
+// What every game gets, which is all a warm instance has
+pub fn shared(settings: &AppConfig) -> Vec<(String, String)> {
+    parse(settings.game_env.split(','), "ANSICRAFT_GAME_ENV")
+}
+
+#[cfg(feature = "server")]
+fn user_path(username: &str, settings: &AppConfig) -> Option<PathBuf> {
+    // Usernames are hex digests, but never let one escape the directory
+    if username.is_empty() || !username.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    Some(settings.user_env_dir.join(username))
+}
+
+#[cfg(feature = "server")]
+fn user(username: &str, settings: &AppConfig) -> Vec<(String, String)> {
+    let Some(path) = user_path(username, settings) else {
+        return vec![];
+    };
+    match fs::read_to_string(&path) {
+        Ok(contents) => parse(contents.lines(), &path.display().to_string()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+        Err(e) => {
+            error!("Error reading {}: {}", path.display(), e);
+            vec![]
+        }
+    }
+}
+
+// Everything for a game in the pool, played by the player
+#[cfg(feature = "server")]
+pub fn for_session(pool: &str, username: &str, settings: &AppConfig) -> Vec<(String, String)> {
+    let mut env = shared(settings);
+    let pool = pool.to_uppercase();
+    if let (false, Some(spec)) = (pool.is_empty(), settings.pool_game_envs.get(&pool)) {
+        env.extend(parse(spec.split(','), &format!("ANSICRAFT_GAME_ENV_{}", pool)));
+    }
+    env.extend(user(username, settings));
+    env
+}
@@ -0,0 +1,117 @@
+// Things happening in the game that a player squinting at an 80 column viewport
+// could easily miss, picked out of its log.  Chat is shown as text below the video
+// for CHAT_LINE_DURATION, since the game's own is illegible at terminal sizes.
+// Dying, and being mentioned in chat, get the player's attention too, and the
+// death screen is explained with the option to respawn by pressing R.
+// With ANSICRAFT_NOTIFY set to "bell" (the default) they ring the terminal bell,
+// and with "osc9" they pop up a desktop notification in terminals that support
+// OSC 9 (iTerm2, WezTerm, kitty, Windows Terminal...).  Taking damage isn't
+// logged, so for that there's ANSICRAFT_AUDIO=bell.
+
+use std::time::{Duration, Instant};
+
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{NotifyMode, CHAT_LINE_DURATION, DEATH_MESSAGES};
+use crate::session::SessionState;
+
+pub enum GameEvent {
+    // Someone said something, or None for messages from the game itself
+    Chat { sender: Option<String>, text: String },
+    // The player died, with the game's message about how
+    Death(String),
+}
+
+// The in-game name the game was started with, which can differ from the session's
+// username when playing with a Microsoft account
+fn player_name(line: &str) -> Option<&str> {
+    line.split_once("Setting user: ").map(|(_, name)| name.trim())
+}
+
+// Reads a line of the game's log, which look like
+//   [12:34:56] [Render thread/INFO]: [CHAT] <Steve> hello
+//   [12:34:56] [Render thread/INFO]: [System] [CHAT] Steve was slain by Zombie
+pub fn parse(line: &str, player: &str) -> Option<GameEvent> {
+    let (_, message) = line.split_once("[CHAT] ")?;
+    let message = message.trim_end();
+    if let Some((sender, text)) = message.strip_prefix('<').and_then(|rest| rest.split_once("> ")) {
+        return Some(GameEvent::Chat { sender: Some(sender.to_owned()), text: text.to_owned() });
+    }
+    let died = message
+        .strip_prefix(player)
+        .and_then(|rest| rest.strip_prefix(' '))
+        .is_some_and(|how| DEATH_MESSAGES.iter().any(|death| how.starts_with(death)));
+    if died {
+        return Some(GameEvent::Death(message.to_owned()));
+    }
+    Some(GameEvent::Chat { sender: None, text: message.to_owned() })
+}
+
+// Whether a chat message calls the player by name, ignoring case
+fn mentions(text: &str, player: &str) -> bool {
+    let text = text.to_lowercase();
+    let player = player.to_lowercase();
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_')).any(|word| word == player)
+}
+
+// Goes through the game's log as it comes in, for as long as the session lasts
+pub async fn watch(
+    session_state: SessionState,
+    username: String,
+    mode: NotifyMode,
+    cancel: CancellationToken,
+) -> std::io::Result<()> {
+    let mut player = username;
+    let mut seen = 0;
+    let mut chat_shown: Option<Instant> = None;
+    let mut deaths = 0;
+    let mut check = tokio::time::interval(Duration::from_millis(250));
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => return Ok(()),
+            _ = check.tick() => {}
+        }
+        if chat_shown.is_some_and(|shown| shown.elapsed() >= CHAT_LINE_DURATION) {
+            session_state.overlay.lock().unwrap().hide_chat();
+            chat_shown = None;
+        }
+        let new_lines: Vec<String> = {
+            let log = session_state.game_log.lock().unwrap();
+            // Lines that have already scrolled out of the log are gone for good
+            let unseen = (log.received - seen).min(log.lines.len());
+            seen = log.received;
+            log.lines.iter().skip(log.lines.len() - unseen).cloned().collect()
+        };
+        for line in new_lines {
+            if let Some(name) = player_name(&line) {
+                player = name.to_owned();
+                continue;
+            }
+            let (chat, notify) = match parse(&line, &player) {
+                Some(GameEvent::Death(message)) => {
+                    deaths += 1;
+                    session_state.dead.store(true, std::sync::atomic::Ordering::SeqCst);
+                    session_state.overlay.lock().unwrap().show_death(deaths);
+                    (message, true)
+                }
+                Some(GameEvent::Chat { sender: Some(sender), text }) => {
+                    let mentioned = sender != player && mentions(&text, &player);
+                    (format!("<{}> {}", sender, text), mentioned)
+                }
+                Some(GameEvent::Chat { sender: None, text }) => (text, false),
+                None => continue,
+            };
+            let mut overlay = session_state.overlay.lock().unwrap();
+            overlay.show_chat(chat.clone());
+            chat_shown = Some(Instant::now());
+            if !notify {
+                continue;
+            }
+            match mode {
+                NotifyMode::Off => {}
+                NotifyMode::Bell => overlay.ring_bell(),
+                NotifyMode::Osc9 => overlay.notify(chat),
+            }
+        }
+    }
+}
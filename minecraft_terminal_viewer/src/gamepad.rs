@@ -0,0 +1,154 @@
+//! Gamepad-over-terminal input protocol.
+//!
+//! A terminal has no idea a gamepad exists, so the `ansicraft-gamepad` helper reads
+//! the controller on the client side and injects its state into the SSH input
+//! stream as APC escape sequences (which terminals never generate on their own):
+//!
+//! ```text
+//! ESC _ ansicraft;pad;<lx>;<ly>;<rx>;<ry>;<buttons> ESC \
+//! ESC _ ansicraft;resize;<cols>;<rows> ESC \
+//! ```
+//!
+//! Axes are signed 16 bit values (-32767..=32767, positive is right/down) and
+//! `buttons` is a bitmask of the `BUTTON_*` constants.  The helper sends the whole
+//! pad state whenever it changes and keeps repeating it while a stick is deflected,
+//! so the server only ever needs the most recent state.  The resize message exists
+//! because ssh can't read the window size when its stdin is the helper's pipe.
+
+pub const BUTTON_A: u16 = 1 << 0;
+pub const BUTTON_B: u16 = 1 << 1;
+pub const BUTTON_X: u16 = 1 << 2;
+pub const BUTTON_Y: u16 = 1 << 3;
+pub const BUTTON_LB: u16 = 1 << 4;
+pub const BUTTON_RB: u16 = 1 << 5;
+pub const BUTTON_LT: u16 = 1 << 6;
+pub const BUTTON_RT: u16 = 1 << 7;
+pub const BUTTON_BACK: u16 = 1 << 8;
+pub const BUTTON_START: u16 = 1 << 9;
+pub const BUTTON_LSTICK: u16 = 1 << 10;
+pub const BUTTON_RSTICK: u16 = 1 << 11;
+
+const APC_START: &[u8] = b"\x1b_";
+const ST: &[u8] = b"\x1b\\";
+const PREFIX: &str = "ansicraft;";
+
+// Don't hold on to an unterminated sequence forever
+const MAX_PENDING: usize = 128;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GamepadState {
+    pub left_x: i16,
+    pub left_y: i16,
+    pub right_x: i16,
+    pub right_y: i16,
+    pub buttons: u16,
+}
+
+impl GamepadState {
+    pub fn pressed(&self, button: u16) -> bool {
+        self.buttons & button != 0
+    }
+
+    // Used by the ansicraft-gamepad helper
+    #[allow(dead_code)]
+    pub fn encode(&self) -> String {
+        format!(
+            "\x1b_{}pad;{};{};{};{};{}\x1b\\",
+            PREFIX, self.left_x, self.left_y, self.right_x, self.right_y, self.buttons
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlMessage {
+    Pad(GamepadState),
+    Resize { cols: u16, rows: u16 },
+}
+
+impl ControlMessage {
+    // Used by the ansicraft-gamepad helper
+    #[allow(dead_code)]
+    pub fn encode(&self) -> String {
+        match self {
+            ControlMessage::Pad(state) => state.encode(),
+            ControlMessage::Resize { cols, rows } => {
+                format!("\x1b_{}resize;{};{}\x1b\\", PREFIX, cols, rows)
+            }
+        }
+    }
+
+    fn parse(payload: &[u8]) -> Option<Self> {
+        let payload = std::str::from_utf8(payload).ok()?.strip_prefix(PREFIX)?;
+        let mut fields = payload.split(';');
+        match fields.next()? {
+            "pad" => {
+                let mut axis = || fields.next()?.parse::<i16>().ok();
+                let (left_x, left_y, right_x, right_y) = (axis()?, axis()?, axis()?, axis()?);
+                let buttons = fields.next()?.parse().ok()?;
+                Some(ControlMessage::Pad(GamepadState {
+                    left_x,
+                    left_y,
+                    right_x,
+                    right_y,
+                    buttons,
+                }))
+            }
+            "resize" => {
+                let cols = fields.next()?.parse().ok()?;
+                let rows = fields.next()?.parse().ok()?;
+                Some(ControlMessage::Resize { cols, rows })
+            }
+            _ => None,
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+// Splits control messages out of the raw input stream, handing everything else
+// back so it can go through the regular terminal input parser.
+#[derive(Default)]
+pub struct ControlDecoder {
+    pending: Vec<u8>,
+}
+
+impl ControlDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, data: &[u8], mut on_message: impl FnMut(ControlMessage)) -> Vec<u8> {
+        let mut buffer = std::mem::take(&mut self.pending);
+        buffer.extend_from_slice(data);
+
+        let mut passthrough = Vec::with_capacity(buffer.len());
+        let mut position = 0;
+        while let Some(start) = find(&buffer[position..], APC_START).map(|i| i + position) {
+            passthrough.extend_from_slice(&buffer[position..start]);
+            let body_start = start + APC_START.len();
+            match find(&buffer[body_start..], ST).map(|i| i + body_start) {
+                Some(end) => {
+                    // Unknown APC sequences are dropped too; the terminal parser
+                    // would only turn them into garbage keypresses.
+                    if let Some(message) = ControlMessage::parse(&buffer[body_start..end]) {
+                        on_message(message);
+                    }
+                    position = end + ST.len();
+                }
+                None if buffer.len() - start > MAX_PENDING => {
+                    passthrough.extend_from_slice(&buffer[start..]);
+                    return passthrough;
+                }
+                None => {
+                    // The rest of the sequence is still in flight
+                    self.pending = buffer[start..].to_vec();
+                    return passthrough;
+                }
+            }
+        }
+        passthrough.extend_from_slice(&buffer[position..]);
+        passthrough
+    }
+}
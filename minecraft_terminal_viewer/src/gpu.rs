@@ -0,0 +1,36 @@
+// Rendering on a GPU instead of with Mesa's software renderer, which makes a huge
+// difference to the game's frame rate.  Games on displays tagged "gpu" are run
+// through VirtualGL, which renders on the GPU's own X server (ANSICRAFT_VGL_DISPLAY)
+// or headless with EGL, and copies the result to the display that's captured.
+
+use std::process::Command;
+
+use crate::config::{AppConfig, GpuMode};
+
+// How many sessions one on a GPU display counts as against the pool's capacity
+#[cfg(feature = "server")]
+pub fn weight(settings: &AppConfig) -> usize {
+    if settings.gpu_render == GpuMode::Off { 1 } else { settings.gpu_weight }
+}
+
+// Runs the command through vglrun instead, keeping its arguments and environment
+pub fn wrap(cmd: Command, settings: &AppConfig) -> Command {
+    let device = match settings.gpu_render {
+        GpuMode::Off => return cmd,
+        GpuMode::VirtualGl => &settings.vgl_display,
+        // Either "egl" for the first GPU, or a DRI device like /dev/dri/card1
+        GpuMode::Egl => &settings.egl_device,
+    };
+    let mut wrapped = Command::new("vglrun");
+    wrapped.args(["-d", device]).arg(cmd.get_program()).args(cmd.get_args());
+    for (key, value) in cmd.get_envs() {
+        match value {
+            Some(value) => wrapped.env(key, value),
+            None => wrapped.env_remove(key),
+        };
+    }
+    if let Some(dir) = cmd.get_current_dir() {
+        wrapped.current_dir(dir);
+    }
+    wrapped
+}
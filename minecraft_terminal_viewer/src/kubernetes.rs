@@ -0,0 +1,181 @@
+// Each game in a pod of its own, so the games can be spread across a cluster while
+// the SSH side stays in one place.  The pod's Xvfb is only reachable on the pod's
+// own localhost, and a `kubectl port-forward` makes it display localhost:N here, so
+// capturing frames and sending input work the same as for a local display.  Each
+// pod's X server takes a cookie of its own, which is added to our Xauthority.  Set
+// ANSICRAFT_PROVISIONER=kubernetes to use this.
+//
+// It drives kubectl rather than talking to the API with kube-rs: the game's output
+// comes back through `kubectl run --attach` and its display through
+// `kubectl port-forward`, which kube-rs only does over its websocket feature, and
+// it'd bring an HTTP and TLS stack into every build for the one provisioner.
+// kubectl also takes whatever kubeconfig and auth plugins the operator already has.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::sync::{LazyLock, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::{AppConfig, Provisioner, KUBERNETES_START_TIMEOUT};
+
+// Starts the X server with the cookie, where only the port forward can reach it
+// (it comes in on the pod's localhost), then runs the game
+const POD_SCRIPT: &str = r#"export XAUTHORITY=/tmp/Xauthority
+xauth -q add :0 . "$2"
+Xvfb :0 -screen 0 "$1" -auth "$XAUTHORITY" -nolisten tcp -noreset &
+export DISPLAY=:0
+until xset q >/dev/null 2>&1; do sleep 0.1; done
+socat TCP-LISTEN:6000,bind=127.0.0.1,reuseaddr,fork UNIX-CONNECT:/tmp/.X11-unix/X0 &
+xsetroot -cursor /root/blank_cursor.xbm /root/blank_cursor.xbm
+shift 2
+exec "$@""#;
+
+// The port forwards to each display's pod
+static PORT_FORWARDS: LazyLock<Mutex<HashMap<String, Child>>> = LazyLock::new(Default::default);
+
+pub fn enabled(settings: &AppConfig) -> bool {
+    settings.provisioner == Some(Provisioner::Kubernetes)
+}
+
+fn display_number(display: &str) -> u16 {
+    display.rsplit(':').next().and_then(|number| number.split('.').next()?.parse().ok()).unwrap_or(0)
+}
+
+fn pod_name(display: &str) -> String {
+    format!("ansicraft-session-{}", display_number(display))
+}
+
+fn kubectl(settings: &AppConfig) -> Command {
+    let mut cmd = Command::new("kubectl");
+    if let Some(namespace) = &settings.k8s_namespace {
+        cmd.args(["--namespace", namespace]);
+    }
+    cmd
+}
+
+// A new X cookie, in hex
+fn new_cookie() -> io::Result<String> {
+    let mut cookie = [0; 16];
+    File::open("/dev/urandom")?.read_exact(&mut cookie)?;
+    Ok(cookie.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+// Lets our X clients in to the display with the cookie, replacing the last pod's.
+// It's added for any host, as xauth and libxcb don't agree on what localhost is.
+fn authorize(display: &str, cookie: &str) -> io::Result<()> {
+    let number = display_number(display).to_string();
+    let hex = |text: &str| text.bytes().map(|byte| format!("{:02x}", byte)).collect::<String>();
+    let entry = format!(
+        "ffff 0000  {:04x} {} {:04x} {} {:04x} {}\n",
+        number.len(),
+        hex(&number),
+        "MIT-MAGIC-COOKIE-1".len(),
+        hex("MIT-MAGIC-COOKIE-1"),
+        cookie.len() / 2,
+        cookie
+    );
+    let mut xauth = Command::new("xauth").args(["-q", "nmerge", "-"]).stdin(Stdio::piped()).spawn()?;
+    xauth.stdin.take().expect("stdin is piped").write_all(entry.as_bytes())?;
+    let status = xauth.wait()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("xauth exited with {}", status)));
+    }
+    Ok(())
+}
+
+// Runs the command in a new pod instead, attached so its output still comes here
+pub fn wrap(cmd: Command, display: &str, (width, height): (u16, u16), settings: &AppConfig) -> io::Result<Command> {
+    // Left over from a session that didn't get cleaned up
+    remove(display, settings);
+    let cookie = new_cookie()?;
+    authorize(display, &cookie)?;
+
+    let mut run = kubectl(settings);
+    run.args(["run", &pod_name(display), "--attach", "--restart=Never", "--image", &settings.session_image])
+        .args(["--labels", "app=ansicraft-session"]);
+    for (key, value) in cmd.get_envs() {
+        // It has a display of its own in there
+        if key == "DISPLAY" {
+            continue;
+        }
+        if let Some(value) = value {
+            run.arg(format!("--env={}={}", key.to_string_lossy(), value.to_string_lossy()));
+        }
+    }
+    // Anything else, like --overrides for resource limits or a node selector
+    run.args(settings.kubectl_args.split_whitespace());
+    run.args(["--command", "--", "sh", "-c", POD_SCRIPT, "sh"])
+        .arg(format!("{}x{}x24", width, height))
+        .arg(cookie)
+        .arg(cmd.get_program())
+        .args(cmd.get_args());
+    Ok(run)
+}
+
+// Waits for the pod, then forwards the display's port to it
+pub fn connect(display: &str, pod: &mut Child, settings: &AppConfig) -> io::Result<()> {
+    let name = pod_name(display);
+    let started = Instant::now();
+    let timed_out = || started.elapsed() > KUBERNETES_START_TIMEOUT;
+    let ready = || {
+        kubectl(settings)
+            .args(["wait", "--for=condition=Ready", &format!("pod/{}", name), "--timeout=5s"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    };
+    while !ready() {
+        if let Some(status) = pod.try_wait()? {
+            return Err(io::Error::other(format!("Pod {} exited with {}", name, status)));
+        }
+        if timed_out() {
+            return Err(io::Error::other(format!("Pod {} didn't start in time", name)));
+        }
+    }
+
+    let port = 6000 + display_number(display);
+    let forward = kubectl(settings)
+        .args(["port-forward", &format!("pod/{}", name), &format!("{}:6000", port)])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    PORT_FORWARDS.lock().unwrap().insert(display.to_owned(), forward);
+
+    // Then for the X server at the other end to answer
+    let answering = || {
+        TcpStream::connect(("127.0.0.1", port)).is_ok()
+            && Command::new("xset")
+                .arg("q")
+                .env("DISPLAY", display)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .is_ok_and(|status| status.success())
+    };
+    while !answering() {
+        if timed_out() {
+            return Err(io::Error::other(format!("Couldn't reach the display in pod {}", name)));
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+    Ok(())
+}
+
+// Stops forwarding to the display's pod and deletes it, whether or not the game
+// in it has exited
+pub fn remove(display: &str, settings: &AppConfig) {
+    if let Some(mut forward) = PORT_FORWARDS.lock().unwrap().remove(display) {
+        let _ = forward.kill();
+        let _ = forward.wait();
+    }
+    let _ = kubectl(settings)
+        .args(["delete", "pod", &pod_name(display), "--ignore-not-found"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
@@ -0,0 +1,465 @@
+// Starts Minecraft without a launcher script: installs the version from Mojang's
+// manifest into the shared Minecraft directory if it isn't there yet, then runs
+// Java with the classpath and arguments the version's JSON asks for.  Versions
+// installed by mod loaders (with "inheritsFrom") work too, as long as the version
+// they're based on can be installed.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{LazyLock, Mutex};
+
+use serde_json::Value;
+use tracing::info;
+
+use crate::accounts;
+use crate::config::{AppConfig, ASSETS_URL, DEFAULT_GAME_VERSION, LIBRARIES_URL, VERSION_MANIFEST_URL};
+
+// Only one install at a time, so two sessions don't download the same files over
+// each other
+static INSTALLING: LazyLock<Mutex<()>> = LazyLock::new(Default::default);
+
+#[derive(Debug, thiserror::Error)]
+pub enum LaunchError {
+    // Not in Mojang's list, nor installed already
+    #[error("There's no Minecraft version {0}")]
+    UnknownVersion(String),
+    // Not one of PrismLauncher's instances
+    #[error("There's no instance \"{0}\" to launch")]
+    UnknownInstance(String),
+    #[error("Couldn't download {url}: {reason}")]
+    Download { url: String, reason: String },
+    // A version or asset index that doesn't look like one
+    #[error("Couldn't read {}: {reason}", path.display())]
+    BadMetadata { path: PathBuf, reason: String },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+// What a game is launched with
+pub struct LaunchOptions<'a> {
+    pub username: &'a str,
+    // Joined straight away if not empty
+    pub server: &'a str,
+    pub display: &'a str,
+    // Empty for the default
+    pub version: &'a str,
+    pub game_dir: Option<&'a Path>,
+    pub world: Option<&'a str>,
+    pub jvm_args: &'a [String],
+    // The player's saved Microsoft login
+    pub account: Option<&'a Path>,
+    // Extra environment variables the game's getting, which can say which Java to use
+    pub env: &'a [(String, String)],
+    pub settings: &'a AppConfig,
+}
+
+// A file to download, and its size if known so a partial one can be told apart
+struct Download {
+    url: String,
+    path: PathBuf,
+    size: Option<u64>,
+}
+
+impl Download {
+    fn new(url: &str, path: PathBuf, size: Option<u64>) -> Self {
+        Self { url: url.to_owned(), path, size }
+    }
+
+    fn needed(&self) -> bool {
+        match (fs::metadata(&self.path), self.size) {
+            (Ok(metadata), Some(size)) => metadata.len() != size,
+            (Ok(_), None) => false,
+            (Err(_), _) => true,
+        }
+    }
+}
+
+// Downloads whichever of the files are missing, with one curl fetching them in parallel
+fn download_all(downloads: &[Download]) -> Result<(), LaunchError> {
+    let needed: Vec<&Download> = downloads.iter().filter(|download| download.needed()).collect();
+    if needed.is_empty() {
+        return Ok(());
+    }
+    info!("Downloading {} files", needed.len());
+    let mut curl = Command::new("curl")
+        .args(["--parallel", "--fail", "--silent", "--show-error", "--location", "--create-dirs", "--config", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    {
+        let mut stdin = curl.stdin.take().expect("stdin is piped");
+        for download in &needed {
+            writeln!(stdin, "url = \"{}\"", download.url)?;
+            writeln!(stdin, "output = \"{}\"", download.path.display())?;
+        }
+    }
+    let output = curl.wait_with_output()?;
+    match needed.iter().find(|download| download.needed()) {
+        Some(missing) => Err(LaunchError::Download {
+            url: missing.url.clone(),
+            reason: String::from_utf8_lossy(&output.stderr).lines().next().unwrap_or("curl failed").to_owned(),
+        }),
+        None => Ok(()),
+    }
+}
+
+fn read_json(path: &Path) -> Result<Value, LaunchError> {
+    let text = fs::read_to_string(path)?;
+    serde_json::from_str(&text).map_err(|e| LaunchError::BadMetadata { path: path.to_owned(), reason: e.to_string() })
+}
+
+fn bad_metadata(path: &Path, reason: &str) -> LaunchError {
+    LaunchError::BadMetadata { path: path.to_owned(), reason: reason.to_owned() }
+}
+
+fn version_path(dir: &Path, version: &str, extension: &str) -> PathBuf {
+    dir.join("versions").join(version).join(format!("{}.{}", version, extension))
+}
+
+// The version's JSON, downloaded if it isn't installed, and merged with the one
+// it inherits from
+fn version_json(dir: &Path, version: &str) -> Result<Value, LaunchError> {
+    let path = version_path(dir, version, "json");
+    if !path.exists() {
+        let manifest_path = dir.join("versions").join("version_manifest_v2.json");
+        let _ = fs::remove_file(&manifest_path);
+        download_all(&[Download::new(VERSION_MANIFEST_URL, manifest_path.clone(), None)])?;
+        let manifest = read_json(&manifest_path)?;
+        let url = manifest["versions"]
+            .as_array()
+            .ok_or_else(|| bad_metadata(&manifest_path, "no versions"))?
+            .iter()
+            .find(|entry| entry["id"].as_str() == Some(version))
+            .and_then(|entry| entry["url"].as_str())
+            .ok_or_else(|| LaunchError::UnknownVersion(version.to_owned()))?;
+        download_all(&[Download::new(url, path.clone(), None)])?;
+    }
+
+    let json = read_json(&path)?;
+    match json["inheritsFrom"].as_str() {
+        Some(parent) => Ok(inherit(version_json(dir, parent)?, json)),
+        None => Ok(json),
+    }
+}
+
+// A mod loader's version on top of the one it's for: its libraries go first, its
+// arguments after, and anything else replaces the parent's
+fn inherit(mut parent: Value, child: Value) -> Value {
+    let Value::Object(child) = child else {
+        return parent;
+    };
+    for (key, value) in child {
+        match key.as_str() {
+            "libraries" => {
+                let mut libraries = value.as_array().cloned().unwrap_or_default();
+                libraries.extend(parent["libraries"].as_array().cloned().unwrap_or_default());
+                parent["libraries"] = Value::Array(libraries);
+            }
+            "arguments" => {
+                for kind in ["game", "jvm"] {
+                    if let Some(extra) = value[kind].as_array() {
+                        let mut arguments = parent["arguments"][kind].as_array().cloned().unwrap_or_default();
+                        arguments.extend(extra.iter().cloned());
+                        parent["arguments"][kind] = Value::Array(arguments);
+                    }
+                }
+            }
+            _ => parent[key.as_str()] = value,
+        }
+    }
+    parent
+}
+
+fn os_name() -> &'static str {
+    match std::env::consts::OS {
+        "macos" => "osx",
+        os => os,
+    }
+}
+
+// Whether a library or argument applies here.  Optional features (demo mode,
+// custom resolutions, quick play) are all off, since they're handled separately.
+fn allowed(rules: &Value) -> bool {
+    let Some(rules) = rules.as_array() else {
+        return true;
+    };
+    let mut allowed = false;
+    for rule in rules {
+        let os = &rule["os"];
+        let os_matches = os["name"].as_str().is_none_or(|name| name == os_name())
+            && os["arch"].as_str().is_none_or(|arch| (arch == "x86") == cfg!(target_arch = "x86"));
+        let features_match = rule["features"]
+            .as_object()
+            .is_none_or(|features| features.values().all(|wanted| wanted == &Value::Bool(false)));
+        if os_matches && features_match {
+            allowed = rule["action"].as_str() == Some("allow");
+        }
+    }
+    allowed
+}
+
+// Where a library is in a Maven repository, from a name like group:artifact:version
+fn maven_path(name: &str) -> Option<String> {
+    let mut parts = name.split(':');
+    let (group, artifact, version) = (parts.next()?, parts.next()?, parts.next()?);
+    let classifier = parts.next().map(|classifier| format!("-{}", classifier)).unwrap_or_default();
+    Some(format!(
+        "{}/{}/{}/{}-{}{}.jar",
+        group.replace('.', "/"),
+        artifact,
+        version,
+        artifact,
+        version,
+        classifier
+    ))
+}
+
+struct Libraries {
+    classpath: Vec<PathBuf>,
+    // Jars with native code to unpack, for versions from before LWJGL did it itself
+    natives: Vec<PathBuf>,
+    downloads: Vec<Download>,
+}
+
+fn libraries(dir: &Path, json: &Value) -> Libraries {
+    let library_dir = dir.join("libraries");
+    let mut libraries = Libraries { classpath: vec![], natives: vec![], downloads: vec![] };
+    for library in json["libraries"].as_array().into_iter().flatten() {
+        if !allowed(&library["rules"]) {
+            continue;
+        }
+        let downloads = &library["downloads"];
+        if let Some(artifact) = downloads["artifact"].as_object() {
+            if let (Some(path), Some(url)) = (artifact["path"].as_str(), artifact["url"].as_str()) {
+                let path = library_dir.join(path);
+                libraries.downloads.push(Download::new(url, path.clone(), artifact["size"].as_u64()));
+                libraries.classpath.push(path);
+            }
+        } else if downloads.is_null() {
+            // Mod loaders' libraries only give a name and the repository it's in
+            if let Some(path) = library["name"].as_str().and_then(maven_path) {
+                let base = library["url"].as_str().unwrap_or(LIBRARIES_URL);
+                let url = format!("{}/{}", base.trim_end_matches('/'), path);
+                let path = library_dir.join(path);
+                libraries.downloads.push(Download::new(&url, path.clone(), None));
+                libraries.classpath.push(path);
+            }
+        }
+
+        let Some(classifier) = library["natives"][os_name()].as_str() else {
+            continue;
+        };
+        let bits = if cfg!(target_pointer_width = "64") { "64" } else { "32" };
+        let native = &downloads["classifiers"][classifier.replace("${arch}", bits).as_str()];
+        if let (Some(path), Some(url)) = (native["path"].as_str(), native["url"].as_str()) {
+            let path = library_dir.join(path);
+            libraries.downloads.push(Download::new(url, path.clone(), native["size"].as_u64()));
+            libraries.natives.push(path);
+        }
+    }
+    libraries
+}
+
+// The asset index, and the sounds and textures in it
+fn assets(dir: &Path, json: &Value, version: &str) -> Result<(String, Vec<Download>), LaunchError> {
+    let index = &json["assetIndex"];
+    let id = index["id"].as_str().unwrap_or(version).to_owned();
+    let index_path = dir.join("assets").join("indexes").join(format!("{}.json", id));
+    if let Some(url) = index["url"].as_str() {
+        download_all(&[Download::new(url, index_path.clone(), index["size"].as_u64())])?;
+    }
+    let objects = read_json(&index_path)?;
+    let objects_dir = dir.join("assets").join("objects");
+    let downloads = objects["objects"]
+        .as_object()
+        .ok_or_else(|| bad_metadata(&index_path, "no objects"))?
+        .values()
+        .filter_map(|object| {
+            let hash = object["hash"].as_str().filter(|hash| hash.len() > 2)?;
+            let url = format!("{}/{}/{}", ASSETS_URL, &hash[..2], hash);
+            Some(Download::new(&url, objects_dir.join(&hash[..2]).join(hash), object["size"].as_u64()))
+        })
+        .collect();
+    Ok((id, downloads))
+}
+
+// Everything needed to launch an installed version
+struct Installed {
+    json: Value,
+    classpath: Vec<PathBuf>,
+    natives_dir: PathBuf,
+    asset_index: String,
+}
+
+// Downloads whatever's missing of the version, e.g. ahead of the first session
+// that wants it
+#[cfg(feature = "server")]
+pub fn install(version: &str, settings: &AppConfig) -> Result<(), LaunchError> {
+    install_version(&settings.minecraft_dir, or_default(version)).map(|_| ())
+}
+
+fn or_default(version: &str) -> &str {
+    if version.is_empty() { DEFAULT_GAME_VERSION } else { version }
+}
+
+fn install_version(dir: &Path, version: &str) -> Result<Installed, LaunchError> {
+    let _installing = INSTALLING.lock().unwrap();
+    let json = version_json(dir, version)?;
+    let path = version_path(dir, version, "json");
+
+    // Mod loaders' versions run the game jar of the version they're based on
+    let jar_version = json["jar"].as_str().or(json["inheritsFrom"].as_str()).unwrap_or(version);
+    let jar = version_path(dir, jar_version, "jar");
+    let client = &json["downloads"]["client"];
+    let client_url = client["url"].as_str().ok_or_else(|| bad_metadata(&path, "no client download"))?;
+
+    let Libraries { mut classpath, natives, mut downloads } = libraries(dir, &json);
+    downloads.push(Download::new(client_url, jar.clone(), client["size"].as_u64()));
+    let (asset_index, assets) = assets(dir, &json, version)?;
+    downloads.extend(assets);
+    download_all(&downloads)?;
+    classpath.push(jar);
+
+    let natives_dir = dir.join("versions").join(version).join("natives");
+    fs::create_dir_all(&natives_dir)?;
+    for native in natives {
+        let output = Command::new("unzip")
+            .args(["-o", "-q"])
+            .arg(&native)
+            .arg("-d")
+            .arg(&natives_dir)
+            .args(["-x", "META-INF/*"])
+            .stderr(Stdio::piped())
+            .output()?;
+        if !output.status.success() {
+            let reason = String::from_utf8_lossy(&output.stderr).lines().next().unwrap_or_default().to_owned();
+            let message = format!("unzip {} exited with {}: {}", native.display(), output.status, reason);
+            return Err(io::Error::other(message).into());
+        }
+    }
+    Ok(Installed { json, classpath, natives_dir, asset_index })
+}
+
+// The arguments that apply here, from the "arguments" lists in newer versions
+fn arguments(list: &Value) -> Vec<String> {
+    let mut arguments = vec![];
+    for argument in list.as_array().into_iter().flatten() {
+        if let Some(argument) = argument.as_str() {
+            arguments.push(argument.to_owned());
+        } else if allowed(&argument["rules"]) {
+            match &argument["value"] {
+                Value::String(value) => arguments.push(value.clone()),
+                Value::Array(values) => arguments.extend(values.iter().filter_map(Value::as_str).map(str::to_owned)),
+                _ => {}
+            }
+        }
+    }
+    arguments
+}
+
+// Settings that suit playing in a terminal, only for a new game directory
+fn default_options(game_dir: &Path) -> io::Result<()> {
+    let options = game_dir.join("options.txt");
+    if options.exists() {
+        return Ok(());
+    }
+    info!("Creating options.txt file...");
+    fs::write(
+        options,
+        "rawMouseInput:false\nfullscreen:true\nautoJump:true\ngraphicsMode:0\nguiScale:0\nmaxFps:30\n",
+    )
+}
+
+// Installs the version if needed, and returns the command that runs it
+pub fn command(options: &LaunchOptions) -> Result<Command, LaunchError> {
+    let dir = options.settings.minecraft_dir.clone();
+    let version = or_default(options.version);
+    let game_dir = options.game_dir.map(Path::to_owned).unwrap_or_else(|| dir.clone());
+    fs::create_dir_all(&game_dir)?;
+    default_options(&game_dir)?;
+    let installed = install_version(&dir, version)?;
+
+    // Playing offline unless they have a login that still works
+    let (mut name, mut uuid, mut token) =
+        (options.username.to_owned(), "00000000-0000-0000-0000-000000000000".to_owned(), String::new());
+    if let Some(account) = options.account {
+        match accounts::refresh(account, options.settings) {
+            Ok(profile) => {
+                info!("Logged in as {}", profile.name);
+                (name, uuid, token) = (profile.name, profile.id, profile.token);
+            }
+            Err(e) => info!("Couldn't log in with the saved Microsoft account, playing offline: {}", e),
+        }
+    }
+
+    let classpath = std::env::join_paths(&installed.classpath)
+        .map_err(|e| io::Error::other(e.to_string()))?
+        .to_string_lossy()
+        .into_owned();
+    let json = &installed.json;
+    let assets_dir = dir.join("assets");
+    let values: HashMap<&str, String> = HashMap::from([
+        ("natives_directory", installed.natives_dir.to_string_lossy().into_owned()),
+        ("launcher_name", "ansicraft".to_owned()),
+        ("launcher_version", env!("CARGO_PKG_VERSION").to_owned()),
+        ("classpath", classpath),
+        ("classpath_separator", if cfg!(windows) { ";" } else { ":" }.to_owned()),
+        ("library_directory", dir.join("libraries").to_string_lossy().into_owned()),
+        ("version_name", version.to_owned()),
+        ("version_type", json["type"].as_str().unwrap_or("release").to_owned()),
+        ("game_directory", game_dir.to_string_lossy().into_owned()),
+        ("assets_root", assets_dir.to_string_lossy().into_owned()),
+        ("game_assets", assets_dir.to_string_lossy().into_owned()),
+        ("assets_index_name", installed.asset_index.clone()),
+        ("auth_player_name", name),
+        ("auth_uuid", uuid),
+        ("auth_access_token", token.clone()),
+        ("auth_session", token),
+        ("auth_xuid", "0".to_owned()),
+        ("clientid", String::new()),
+        ("user_type", "msa".to_owned()),
+        ("user_properties", "{}".to_owned()),
+    ]);
+    let fill_in = |argument: &str| {
+        let mut argument = argument.to_owned();
+        for (key, value) in &values {
+            argument = argument.replace(&format!("${{{}}}", key), value);
+        }
+        argument
+    };
+
+    // Versions from before 1.13 have a string of game arguments and no JVM ones
+    let jvm_args = match json["arguments"]["jvm"].as_array() {
+        Some(_) => arguments(&json["arguments"]["jvm"]),
+        None => vec!["-Djava.library.path=${natives_directory}".to_owned(), "-cp".to_owned(), "${classpath}".to_owned()],
+    };
+    let game_args = match json["minecraftArguments"].as_str() {
+        Some(arguments) => arguments.split_whitespace().map(str::to_owned).collect(),
+        None => arguments(&json["arguments"]["game"]),
+    };
+    let main_class =
+        json["mainClass"].as_str().ok_or_else(|| bad_metadata(&version_path(&dir, version, "json"), "no main class"))?;
+
+    let java = match options.env.iter().rev().find(|(key, _)| key == "JAVA_HOME") {
+        Some((_, java_home)) => Path::new(java_home).join("bin").join("java").to_string_lossy().into_owned(),
+        None => options.settings.java.clone(),
+    };
+    let mut cmd = Command::new(java);
+    cmd.args(options.jvm_args)
+        .args(jvm_args.iter().map(|arg| fill_in(arg)))
+        .arg(main_class)
+        .args(game_args.iter().map(|arg| fill_in(arg)))
+        .current_dir(&game_dir)
+        .env("DISPLAY", options.display);
+    if !options.server.is_empty() {
+        cmd.args(["--quickPlayMultiplayer", options.server]);
+    }
+    if let Some(world) = options.world {
+        cmd.args(["--quickPlaySingleplayer", world]);
+    }
+    Ok(cmd)
+}
@@ -0,0 +1,143 @@
+// The terminal viewer as a library, for other projects to reuse its pipeline:
+// `render` captures video with ffmpeg (from an X display, or a recording) and
+// encodes frames as ANSI, `xdo` turns terminal input (and `gamepad`'s, which the
+// ansicraft-gamepad helper sends) into X input, `xserver` starts X servers to
+// capture from, and `queueing` shares displays out among sessions.  `minecraft`
+// puts them together for a game, with `session_log` and `supervisor` keeping
+// track of what it runs, `plugins` lets other projects hook into sessions (and
+// `scripting` operators), and `synthetic` stands in for the display where there
+// isn't one.  `run` is the whole program, which the binary just calls.  On
+// Windows there's only `local`, playing a window that's already open, so what
+// runs the game itself (`minecraft` and what it uses) is left out there.
+
+// Modules both sides share have plenty that only the server uses
+
+// The server's sessions each need an X display, so there's no server on Windows
+#[cfg(all(windows, feature = "server"))]
+compile_error!("The server feature needs X, so build for Windows with --no-default-features");
+
+#[cfg(unix)]
+mod accounts;
+#[cfg(feature = "server")]
+mod admin;
+#[cfg(unix)]
+mod app;
+#[cfg(feature = "server")]
+mod attract;
+#[cfg(unix)]
+mod audio;
+#[cfg(feature = "server")]
+pub mod autoscale;
+#[cfg(unix)]
+pub mod backups;
+mod bench;
+mod cli;
+pub mod config;
+#[cfg(feature = "server")]
+mod cluster;
+#[cfg(feature = "server")]
+mod daemon;
+#[cfg(feature = "server")]
+mod dashboard;
+#[cfg(unix)]
+mod docker;
+#[cfg(unix)]
+mod doctor;
+#[cfg(unix)]
+mod game_env;
+#[cfg(unix)]
+mod game_events;
+pub mod gamepad;
+#[cfg(unix)]
+mod gpu;
+#[cfg(unix)]
+mod kubernetes;
+#[cfg(unix)]
+mod launcher;
+#[cfg(unix)]
+mod limits;
+#[cfg(feature = "server")]
+mod lobby;
+mod local;
+mod logging;
+#[cfg(unix)]
+mod metrics;
+#[cfg(unix)]
+pub mod minecraft;
+mod overlay;
+pub mod plugins;
+#[cfg(feature = "server")]
+mod preferences;
+#[cfg(unix)]
+mod prism;
+#[cfg(unix)]
+pub mod queueing;
+pub mod render;
+#[cfg(unix)]
+mod sandbox;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+#[cfg(windows)]
+mod sendinput;
+pub mod session;
+pub mod session_log;
+#[cfg(feature = "server")]
+mod session_settings;
+#[cfg(unix)]
+mod session_users;
+#[cfg(feature = "server")]
+mod sftp;
+#[cfg(feature = "server")]
+mod sshng;
+pub mod supervisor;
+pub mod synthetic;
+#[cfg(unix)]
+mod warm;
+#[cfg(unix)]
+mod webhooks;
+mod wm;
+pub mod xdo;
+pub mod xserver;
+
+use std::io::{self, IsTerminal};
+
+use cli::Command;
+
+// Does what the command line says, until it's done
+pub async fn run() -> anyhow::Result<()> {
+    let command = cli::command(io::stdin().is_terminal());
+    let overrides = command.clone();
+    let settings = config::LiveConfig::load(move |config| overrides.apply(config)).map_err(anyhow::Error::msg)?;
+    // Before logging starts, so only the server in the background logs
+    #[cfg(feature = "server")]
+    if let Command::Serve(args) = &command
+        && args.daemon
+    {
+        return daemon::start(&args.pidfile, &settings.get()).await;
+    }
+    logging::init(&settings.get());
+    #[cfg(feature = "scripting")]
+    if let Some(path) = &settings.get().script {
+        scripting::load(path);
+    }
+    match command {
+        #[cfg(feature = "server")]
+        Command::Serve(_) => {
+            let mut server = sshng::MinecraftSshServer::new(&settings);
+            server.run().await
+        }
+        Command::Local(args) => local::play(&args, &settings).await,
+        #[cfg(feature = "server")]
+        Command::Stop(args) => std::process::exit(if daemon::stop(&args.pidfile) { 0 } else { 1 }),
+        #[cfg(feature = "server")]
+        Command::Admin(args) => std::process::exit(admin::attach(&settings.get().admin_socket, &args.command).await?),
+        #[cfg(feature = "server")]
+        Command::Worker(_) => sshng::MinecraftSshServer::new(&settings).work().await,
+        #[cfg(unix)]
+        Command::Doctor => std::process::exit(if doctor::run(&settings.get()) { 0 } else { 1 }),
+        Command::Bench(args) => {
+            bench::run(args.width, args.seconds);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,82 @@
+// Keeps one runaway game from starving the rest of the host.  Each game goes in a
+// cgroup of its own with a memory cap and CPU weight, when cgroups are writable
+// (e.g. Docker with a private cgroup namespace), and is niced either way.
+
+use std::fs;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::Command;
+
+use tracing::error;
+
+use crate::config::{ResourceLimits, CGROUP_ROOT};
+
+// Makes the command start niced, if it should be
+pub fn apply(cmd: &mut Command, limits: &ResourceLimits) {
+    let Some(nice) = limits.nice else {
+        return;
+    };
+    // SAFETY: setpriority is async-signal-safe, and nothing else happens between fork and exec
+    unsafe {
+        cmd.pre_exec(move || {
+            if libc::setpriority(libc::PRIO_PROCESS as _, 0, nice) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+// The same limits for a container, which Docker puts in a cgroup itself
+pub fn docker_args(limits: &ResourceLimits) -> Vec<String> {
+    let mut args = vec![];
+    if let Some(memory_max) = &limits.memory_max {
+        args.push(format!("--memory={}", memory_max));
+    }
+    // Docker's shares are out of 1024 rather than 100
+    if let Some(cpu_weight) = limits.cpu_weight {
+        args.push(format!("--cpu-shares={}", (cpu_weight * 1024 / 100).max(2)));
+    }
+    args
+}
+
+fn cgroup_path(display: &str) -> PathBuf {
+    PathBuf::from(CGROUP_ROOT).join(format!("ansicraft-{}", display.trim_start_matches(':')))
+}
+
+// Moves a just-launched game into its display's cgroup.  Anything it starts
+// afterwards (i.e. Java) ends up in there too.
+pub fn confine(pid: u32, display_name: &str, limits: &ResourceLimits) {
+    if !limits.uses_cgroup() {
+        return;
+    }
+    let path = cgroup_path(display_name);
+    let result = (|| -> io::Result<()> {
+        // The controllers have to be handed down before the cgroup can use them
+        let _ = fs::write(PathBuf::from(CGROUP_ROOT).join("cgroup.subtree_control"), "+memory +cpu");
+        fs::create_dir_all(&path)?;
+        if let Some(memory_max) = &limits.memory_max {
+            fs::write(path.join("memory.max"), memory_max)?;
+        }
+        if let Some(cpu_weight) = limits.cpu_weight {
+            fs::write(path.join("cpu.weight"), cpu_weight.to_string())?;
+        }
+        fs::write(path.join("cgroup.procs"), pid.to_string())
+    })();
+    if let Err(e) = result {
+        error!("Couldn't limit the game on display {} with a cgroup: {}", display_name, e);
+    }
+}
+
+// Cleans up a display's cgroup once the game in it has exited
+pub fn release(display_name: &str, limits: &ResourceLimits) {
+    if !limits.uses_cgroup() {
+        return;
+    }
+    match fs::remove_dir(cgroup_path(display_name)) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => error!("Error removing the cgroup for display {}: {}", display_name, e),
+    }
+}
@@ -0,0 +1,609 @@
+// The screen people see while they wait in the queue: a logo, where they are in
+// line, some tips, and a little game of snake that runs entirely on the server.
+// Before that, if there's more than one pool, a menu to pick one.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::queueing::PoolStatus;
+
+const LOGO: [&str; 2] = [
+    "▄▀█ █▄ █ █▀ █ █▀▀ █▀█ ▄▀█ █▀▀ ▀█▀",
+    "█▀█ █ ▀█ ▄█ █ █▄▄ █▀▄ █▀█ █▀   █ ",
+];
+// 256 color palette the logo cycles through
+const LOGO_COLORS: [u8; 12] = [46, 47, 48, 49, 50, 51, 45, 39, 33, 39, 45, 51];
+const SPINNER: [char; 8] = ['⣾', '⣽', '⣻', '⢿', '⡿', '⣟', '⣯', '⣷'];
+
+const TIPS: [&str; 7] = [
+    "Press Escape twice in game to open the ansicraft menu.",
+    "Z holds down the left mouse button, so you can mine without holding a key.",
+    "[ and ] change the frame rate if your connection is struggling.",
+    "F8 switches between true color, 256 color, braille and ASCII rendering.",
+    "` toggles between mouse look and pointing at things in menus.",
+    "Your inventory and settings are tied to your SSH key, so use the same one next time.",
+    "In a hurry? Connect with the `short` option for a shorter wait and a shorter session.",
+];
+const TIP_INTERVAL: Duration = Duration::from_secs(8);
+
+const MAX_BOARD_WIDTH: u16 = 30;
+const MAX_BOARD_HEIGHT: u16 = 15;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+enum Key {
+    Direction(Direction),
+    Enter,
+    Quit,
+    Digit(u8),
+    Watch,
+}
+
+// Turns terminal input into keys, keeping any escape sequence split across reads
+// in `pending` for next time
+fn parse_keys(pending: &mut Vec<u8>, data: &[u8]) -> Vec<Key> {
+    let mut input = std::mem::take(pending);
+    input.extend_from_slice(data);
+
+    let mut keys = vec![];
+    let mut i = 0;
+    while i < input.len() {
+        let key = match input[i] {
+            b'q' | b'Q' | 0x03 => Some(Key::Quit),
+            b'\r' | b'\n' | b' ' => Some(Key::Enter),
+            b'w' | b'W' | b'k' => Some(Key::Direction(Direction::Up)),
+            b's' | b'S' | b'j' => Some(Key::Direction(Direction::Down)),
+            b'a' | b'A' | b'h' => Some(Key::Direction(Direction::Left)),
+            b'd' | b'D' | b'l' => Some(Key::Direction(Direction::Right)),
+            digit @ b'1'..=b'9' => Some(Key::Digit(digit - b'0')),
+            b'v' | b'V' => Some(Key::Watch),
+            0x1b if i + 2 >= input.len() => {
+                *pending = input[i..].to_vec();
+                break;
+            }
+            0x1b if input[i + 1] == b'[' || input[i + 1] == b'O' => {
+                i += 2;
+                match input[i] {
+                    b'A' => Some(Key::Direction(Direction::Up)),
+                    b'B' => Some(Key::Direction(Direction::Down)),
+                    b'C' => Some(Key::Direction(Direction::Right)),
+                    b'D' => Some(Key::Direction(Direction::Left)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        keys.extend(key);
+        i += 1;
+    }
+    keys
+}
+
+impl Direction {
+    fn opposite(self) -> Self {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+}
+
+struct Snake {
+    width: u16,
+    height: u16,
+    body: VecDeque<(u16, u16)>,
+    direction: Direction,
+    // Turns wait here until the next step so two quick presses can't reverse the snake
+    next_direction: Direction,
+    food: (u16, u16),
+    score: u32,
+    alive: bool,
+    rng: u64,
+}
+
+impl Snake {
+    fn new(width: u16, height: u16, seed: u64) -> Self {
+        let start = (width / 2, height / 2);
+        let mut snake = Self {
+            width,
+            height,
+            body: VecDeque::from([start, (start.0.saturating_sub(1), start.1)]),
+            direction: Direction::Right,
+            next_direction: Direction::Right,
+            food: (0, 0),
+            score: 0,
+            alive: true,
+            rng: seed | 1,
+        };
+        snake.place_food();
+        snake
+    }
+
+    // xorshift, plenty for placing apples
+    fn random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+
+    fn place_food(&mut self) {
+        if self.body.len() >= (self.width * self.height) as usize {
+            return;
+        }
+        loop {
+            let x = (self.random() % self.width as u64) as u16;
+            let y = (self.random() % self.height as u64) as u16;
+            if !self.body.contains(&(x, y)) {
+                self.food = (x, y);
+                return;
+            }
+        }
+    }
+
+    fn turn(&mut self, direction: Direction) {
+        if direction != self.direction.opposite() {
+            self.next_direction = direction;
+        }
+    }
+
+    fn step(&mut self) {
+        if !self.alive {
+            return;
+        }
+        self.direction = self.next_direction;
+        let (x, y) = self.body[0];
+        let head = match self.direction {
+            Direction::Up if y > 0 => (x, y - 1),
+            Direction::Down if y + 1 < self.height => (x, y + 1),
+            Direction::Left if x > 0 => (x - 1, y),
+            Direction::Right if x + 1 < self.width => (x + 1, y),
+            _ => {
+                self.alive = false;
+                return;
+            }
+        };
+
+        let eating = head == self.food;
+        if !eating {
+            self.body.pop_back();
+        }
+        if self.body.contains(&head) {
+            self.alive = false;
+            return;
+        }
+        self.body.push_front(head);
+        if eating {
+            self.score += 1;
+            self.place_food();
+        }
+    }
+}
+
+pub struct WaitingRoom {
+    started: Instant,
+    frame: usize,
+    position: Option<usize>,
+    // The first position we were told, and when, for the progress bar and ETA
+    first_position: Option<(usize, Instant)>,
+    snake: Option<Snake>,
+    // Set by a keypress when there's no game going; the board is sized when drawn
+    new_game: bool,
+    best_score: u32,
+    // Bytes of an escape sequence split across reads
+    pending_input: Vec<u8>,
+    // Whether there's a game to watch instead, and whether we are
+    can_watch: bool,
+    watching: bool,
+}
+
+impl WaitingRoom {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            frame: 0,
+            position: None,
+            first_position: None,
+            snake: None,
+            new_game: false,
+            best_score: 0,
+            pending_input: vec![],
+            can_watch: false,
+            watching: false,
+        }
+    }
+
+    pub fn set_can_watch(&mut self, can_watch: bool) {
+        self.can_watch = can_watch;
+        self.watching &= can_watch;
+    }
+
+    pub fn watching(&self) -> bool {
+        self.watching
+    }
+
+    pub fn set_position(&mut self, position: usize) {
+        self.position = Some(position);
+        // Moving back (behind a VIP, say) restarts the bar rather than running it backwards
+        if self.first_position.is_none_or(|(first, _)| position > first) {
+            self.first_position = Some((position, Instant::now()));
+        }
+    }
+
+    // Guesses how long is left from how quickly we've moved up so far
+    fn eta(&self) -> Option<Duration> {
+        let (first, since) = self.first_position?;
+        let position = self.position?;
+        let moved = first - position;
+        if moved == 0 {
+            return None;
+        }
+        Some(since.elapsed() / moved as u32 * (position + 1) as u32)
+    }
+
+    fn progress_bar(&self, cols: usize) -> Option<String> {
+        let (first, _) = self.first_position?;
+        let position = self.position?;
+        let fraction = (first - position + 1) as f64 / (first + 1) as f64;
+
+        let eta = match self.eta() {
+            Some(eta) if eta.as_secs() < 60 => "ETA under a minute".to_owned(),
+            Some(eta) => format!("ETA ~{} min", eta.as_secs().div_ceil(60)),
+            None => "ETA estimating...".to_owned(),
+        };
+        let width = cols.saturating_sub(eta.len() + 10).min(40);
+        if width < 10 {
+            return None;
+        }
+        let filled = ((fraction * width as f64) as usize).min(width);
+        let text_width = width + eta.len() + 7;
+        Some(format!(
+            "{}\x1b[32m{}\x1b[2m{}\x1b[m {:>3}%  {}",
+            " ".repeat(cols.saturating_sub(text_width) / 2),
+            "█".repeat(filled),
+            "░".repeat(width - filled),
+            (fraction * 100.0) as usize,
+            eta
+        ))
+    }
+
+    // Handles keypresses, returning false if the player asked to leave
+    pub fn handle_input(&mut self, data: &[u8]) -> bool {
+        for key in parse_keys(&mut self.pending_input, data) {
+            match key {
+                Key::Quit => return false,
+                Key::Watch if self.can_watch => self.watching = !self.watching,
+                Key::Direction(direction) if !self.watching => self.steer(direction),
+                _ => {}
+            }
+        }
+        true
+    }
+
+    fn steer(&mut self, direction: Direction) {
+        match &mut self.snake {
+            Some(snake) if snake.alive => snake.turn(direction),
+            // Any direction starts a new game
+            _ => self.new_game = true,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.frame += 1;
+        // The snake waits for you to come back
+        if self.watching {
+            return;
+        }
+        if let Some(snake) = &mut self.snake {
+            snake.step();
+            self.best_score = self.best_score.max(snake.score);
+        }
+    }
+
+    fn status(&self) -> String {
+        let spinner = SPINNER[self.frame % SPINNER.len()];
+        match self.position {
+            Some(0) => format!("{} You're next in line!", spinner),
+            Some(position) => format!("{} You are #{} in the queue", spinner, position + 1),
+            None => format!("{} Looking for a free slot...", spinner),
+        }
+    }
+
+    // A line along the bottom of the screen while watching, below the video
+    pub fn render_watching(&self, cols: usize, rows: usize) -> String {
+        let line = format!("{}  ·  v to go back, q to leave", self.status());
+        format!("\x1b[{};1H\x1b[0;1m{}{}\x1b[m\x1b[K", rows, centre_padding(&line, cols), line)
+    }
+
+    pub fn render(&mut self, cols: usize, rows: usize) -> String {
+        let mut lines = logo_lines(LOGO_COLORS[self.frame / 2 % LOGO_COLORS.len()], cols);
+
+        let status = self.status();
+        let waited = self.started.elapsed().as_secs();
+        let waited = format!("Waiting for {}:{:02}", waited / 60, waited % 60);
+        lines.push(format!("{}\x1b[1m{}\x1b[m", centre_padding(&status, cols), status));
+        lines.extend(self.progress_bar(cols));
+        lines.push(format!("{}\x1b[2m{}\x1b[m", centre_padding(&waited, cols), waited));
+        lines.push(String::new());
+
+        let tip = TIPS[(self.started.elapsed().as_secs() / TIP_INTERVAL.as_secs()) as usize % TIPS.len()];
+        let tip = format!("Tip: {}", tip);
+        lines.push(format!("{}\x1b[36m{}\x1b[m", centre_padding(&tip, cols), tip));
+        lines.push(String::new());
+
+        let space = rows.saturating_sub(lines.len() + 1);
+        self.render_snake(&mut lines, cols, space);
+
+        screen(lines, rows)
+    }
+
+    fn render_snake(&mut self, lines: &mut Vec<String>, cols: usize, space: usize) {
+        // Two columns per cell keeps the board roughly square
+        let width = ((cols.saturating_sub(2) / 2) as u16).min(MAX_BOARD_WIDTH);
+        let height = (space.saturating_sub(3) as u16).min(MAX_BOARD_HEIGHT);
+        if width < 8 || height < 4 {
+            return;
+        }
+
+        // A new game, or the terminal changed size under the old one
+        let resized = self
+            .snake
+            .as_ref()
+            .is_some_and(|snake| snake.width != width || snake.height != height);
+        if self.new_game || resized {
+            self.new_game = false;
+            let seed = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_nanos() as u64)
+                .unwrap_or(1);
+            self.snake = Some(Snake::new(width, height, seed));
+        }
+
+        let caption = match &self.snake {
+            None if self.can_watch => "Arrow keys or WASD to play snake, v to watch a game, q to leave".to_owned(),
+            None => "Arrow keys or WASD to play snake while you wait, q to leave".to_owned(),
+            Some(snake) if !snake.alive => format!(
+                "Game over! Score {} (best {}). Press a direction to play again",
+                snake.score, self.best_score
+            ),
+            Some(snake) => format!("Score {}  Best {}", snake.score, self.best_score),
+        };
+        lines.push(format!("{}{}", centre_padding(&caption, cols), caption));
+
+        let left = " ".repeat(cols.saturating_sub(width as usize * 2 + 2) / 2);
+        lines.push(format!("{}┌{}┐", left, "──".repeat(width as usize)));
+        for y in 0..height {
+            let mut row = format!("{}│", left);
+            for x in 0..width {
+                let cell = match &self.snake {
+                    Some(snake) if snake.body.front() == Some(&(x, y)) => "\x1b[92m██\x1b[m",
+                    Some(snake) if snake.body.contains(&(x, y)) => "\x1b[32m██\x1b[m",
+                    Some(snake) if snake.food == (x, y) => "\x1b[91m██\x1b[m",
+                    _ => "  ",
+                };
+                row.push_str(cell);
+            }
+            row.push('│');
+            lines.push(row);
+        }
+        lines.push(format!("{}└{}┘", left, "──".repeat(width as usize)));
+    }
+}
+
+pub enum MenuChoice {
+    Picked(String),
+    Leave,
+}
+
+// A screen that asks for something before joining the queue
+pub trait Picker {
+    fn handle_input(&mut self, data: &[u8]) -> Option<MenuChoice>;
+    fn render(&self, cols: usize, rows: usize) -> String;
+    // Updates the details shown next to each entry, for those that have them
+    fn set_entries(&mut self, _entries: Vec<MenuEntry>) {}
+}
+
+pub struct MenuEntry {
+    pub name: String,
+    // Shown to the right of the name
+    pub detail: String,
+}
+
+// Pool status as menu entries
+pub fn pool_entries(pools: Vec<PoolStatus>) -> Vec<MenuEntry> {
+    pools
+        .into_iter()
+        .map(|pool| MenuEntry {
+            detail: if pool.available > 0 {
+                format!("{} of {} free", pool.available, pool.capacity)
+            } else {
+                format!("full, {} waiting", pool.waiting)
+            },
+            name: pool.name,
+        })
+        .collect()
+}
+
+// Picks one of a list, e.g. which pool to queue for
+pub struct Menu {
+    title: &'static str,
+    entries: Vec<MenuEntry>,
+    selected: usize,
+    pending_input: Vec<u8>,
+}
+
+impl Menu {
+    pub fn new(title: &'static str, entries: Vec<MenuEntry>) -> Self {
+        Self {
+            title,
+            entries,
+            selected: 0,
+            pending_input: vec![],
+        }
+    }
+}
+
+impl Picker for Menu {
+    fn set_entries(&mut self, entries: Vec<MenuEntry>) {
+        self.entries = entries;
+        self.selected = self.selected.min(self.entries.len().saturating_sub(1));
+    }
+
+    fn handle_input(&mut self, data: &[u8]) -> Option<MenuChoice> {
+        for key in parse_keys(&mut self.pending_input, data) {
+            match key {
+                Key::Quit => return Some(MenuChoice::Leave),
+                Key::Direction(Direction::Up) => self.selected = self.selected.saturating_sub(1),
+                Key::Direction(Direction::Down) => {
+                    self.selected = (self.selected + 1).min(self.entries.len().saturating_sub(1))
+                }
+                Key::Digit(number) if (number as usize) <= self.entries.len() => {
+                    return Some(MenuChoice::Picked(self.entries[number as usize - 1].name.clone()));
+                }
+                Key::Enter if !self.entries.is_empty() => {
+                    return Some(MenuChoice::Picked(self.entries[self.selected].name.clone()));
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn render(&self, cols: usize, rows: usize) -> String {
+        let mut lines = logo_lines(LOGO_COLORS[0], cols);
+
+        lines.push(format!("{}\x1b[1m{}\x1b[m", centre_padding(self.title, cols), self.title));
+        lines.push(String::new());
+
+        let entries: Vec<String> = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| format!("{}. {:<16} {}", i + 1, entry.name, entry.detail))
+            .collect();
+        let width = entries.iter().map(|entry| entry.chars().count()).max().unwrap_or(0);
+        let left = " ".repeat(cols.saturating_sub(width + 2) / 2);
+        for (i, entry) in entries.iter().enumerate() {
+            if i == self.selected {
+                lines.push(format!("{}\x1b[1;7m {:<width$} \x1b[m", left, entry, width = width));
+            } else {
+                lines.push(format!("{} {} ", left, entry));
+            }
+        }
+        lines.push(String::new());
+
+        let hint = "Arrow keys and Enter, or a number, to pick. q to leave";
+        lines.push(format!("{}\x1b[2m{}\x1b[m", centre_padding(hint, cols), hint));
+
+        screen(lines, rows)
+    }
+}
+
+// Checks what was typed, giving what it stands for or why it won't do
+type Validator = Box<dyn Fn(&str) -> Result<String, String> + Send + Sync>;
+
+// Asks for a line of text, like a server address, which has to pass `validate`
+pub struct TextPrompt {
+    title: &'static str,
+    text: String,
+    error: Option<String>,
+    validate: Validator,
+    // Left over from an escape sequence split across reads
+    in_escape: bool,
+}
+
+impl TextPrompt {
+    pub fn new(title: &'static str, validate: impl Fn(&str) -> Result<String, String> + Send + Sync + 'static) -> Self {
+        Self {
+            title,
+            text: String::new(),
+            error: None,
+            validate: Box::new(validate),
+            in_escape: false,
+        }
+    }
+}
+
+impl Picker for TextPrompt {
+    fn handle_input(&mut self, data: &[u8]) -> Option<MenuChoice> {
+        let mut bytes = data.iter().copied().peekable();
+        while let Some(byte) = bytes.next() {
+            if self.in_escape {
+                // Sequences like arrow keys end with a letter
+                self.in_escape = !byte.is_ascii_alphabetic() && byte != b'~';
+                continue;
+            }
+            match byte {
+                0x03 => return Some(MenuChoice::Leave),
+                0x1b if bytes.peek().is_some_and(|next| *next == b'[' || *next == b'O') => {
+                    bytes.next();
+                    self.in_escape = true;
+                }
+                0x1b => return Some(MenuChoice::Leave),
+                b'\r' | b'\n' => match (self.validate)(&self.text) {
+                    Ok(value) => return Some(MenuChoice::Picked(value)),
+                    Err(e) => self.error = Some(e),
+                },
+                0x7f | 0x08 => {
+                    self.text.pop();
+                    self.error = None;
+                }
+                0x20..=0x7e if self.text.len() < 255 => {
+                    self.text.push(byte as char);
+                    self.error = None;
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn render(&self, cols: usize, rows: usize) -> String {
+        let mut lines = logo_lines(LOGO_COLORS[0], cols);
+
+        lines.push(format!("{}\x1b[1m{}\x1b[m", centre_padding(self.title, cols), self.title));
+        lines.push(String::new());
+
+        let width = 40.min(cols.saturating_sub(4));
+        let shown: String = self.text.chars().skip(self.text.len().saturating_sub(width - 1)).collect();
+        let left = " ".repeat(cols.saturating_sub(width + 2) / 2);
+        lines.push(format!("{}\x1b[7m {:<width$}\x1b[m", left, format!("{}█", shown), width = width + 1));
+        lines.push(String::new());
+
+        if let Some(error) = &self.error {
+            lines.push(format!("{}\x1b[31m{}\x1b[m", centre_padding(error, cols), error));
+        }
+        let hint = "Enter to connect, Escape to leave";
+        lines.push(format!("{}\x1b[2m{}\x1b[m", centre_padding(hint, cols), hint));
+
+        screen(lines, rows)
+    }
+}
+
+fn logo_lines(color: u8, cols: usize) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    for line in LOGO {
+        lines.push(format!("{}\x1b[1;38;5;{}m{}\x1b[m", centre_padding(line, cols), color, line));
+    }
+    lines.push(String::new());
+    lines
+}
+
+// Draws over the whole screen from the top.  No newline after the last line, so
+// the screen never scrolls.
+fn screen(mut lines: Vec<String>, rows: usize) -> String {
+    lines.truncate(rows);
+    format!("\x1b[H{}\x1b[K\x1b[J", lines.join("\x1b[K\r\n"))
+}
+
+fn centre_padding(text: &str, cols: usize) -> String {
+    " ".repeat(cols.saturating_sub(text.chars().count()) / 2)
+}
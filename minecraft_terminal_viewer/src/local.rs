@@ -0,0 +1,388 @@
+// `termcast local` plays in the terminal it's run from, on a display that's
+// already running, without any of the SSH server's sessions or queues.  Windows
+// has no displays to start the game on, so there it plays a window that's already
+// open instead (or the whole screen), captured with gdigrab and sent input with
+// SendInput.
+
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+#[cfg(windows)]
+use std::time::{Duration, Instant};
+
+use crossterm::{
+    cursor,
+    event::{self},
+    execute,
+    terminal::{self, Clear, ClearType},
+};
+use tokio::io::AsyncWriteExt;
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::cli::LocalArgs;
+use crate::config::{self, LiveConfig, TerminalSize};
+use crate::plugins::SessionInfo;
+#[cfg(unix)]
+use crate::{app, game_env, minecraft, wm};
+use crate::{logging, render, session_log, xdo};
+#[cfg(windows)]
+use crate::{plugins, render::VideoSource, sendinput, session::SessionState, supervisor::Supervisor};
+
+// Function to clean up terminal state, then show what was logged while it was playing
+pub fn cleanup_terminal() -> io::Result<()> {
+    let restored = restore_terminal();
+    logging::release();
+    restored
+}
+
+fn restore_terminal() -> io::Result<()> {
+    let mut stdout = io::stdout();
+    execute!(
+        stdout,
+        event::DisableMouseCapture,
+        terminal::LeaveAlternateScreen,
+        cursor::Show
+    )?;
+    #[cfg(windows)]
+    virtual_terminal_input(false)?;
+    terminal::disable_raw_mode()?;
+    Ok(())
+}
+
+// Has the console send keys and the mouse as escape sequences, like a terminal
+// anywhere else does, instead of only the characters typed
+#[cfg(windows)]
+fn virtual_terminal_input(enabled: bool) -> io::Result<()> {
+    use windows_sys::Win32::System::Console::{
+        GetConsoleMode, GetStdHandle, SetConsoleMode, ENABLE_VIRTUAL_TERMINAL_INPUT, STD_INPUT_HANDLE,
+    };
+
+    let mut mode = 0;
+    // SAFETY: the handle's the process's own stdin, and mode outlives the call
+    unsafe {
+        let stdin = GetStdHandle(STD_INPUT_HANDLE);
+        if GetConsoleMode(stdin, &mut mode) == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mode = if enabled {
+            mode | ENABLE_VIRTUAL_TERMINAL_INPUT
+        } else {
+            mode & !ENABLE_VIRTUAL_TERMINAL_INPUT
+        };
+        if SetConsoleMode(stdin, mode) == 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+// The game's frames go straight to this terminal
+impl render::Screen for tokio::io::Stdout {
+    async fn show(&mut self, bytes: Vec<u8>) -> io::Result<()> {
+        self.write_all(&bytes).await?;
+        self.flush().await
+    }
+}
+
+// Reads what's typed on a thread of its own, since stdin can't be read without
+// blocking, and passes it on like the SSH server does
+pub(crate) fn read_stdin() -> xdo::InputSource {
+    let (input_tx, input_rx) = tokio::sync::mpsc::unbounded_channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin();
+        let mut buf = [0u8; 64];
+        while let Ok(n) = stdin.read(&mut buf) {
+            if n == 0 || input_tx.send(buf[..n].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    Arc::new(tokio::sync::Mutex::new(input_rx))
+}
+
+// What the game's shown with to begin with, in the best colors this terminal has
+fn render_settings(settings: &LiveConfig) -> config::RenderSettings {
+    let settings = settings.get();
+    let mut render = settings.render.clone();
+    if settings.detect_color {
+        let var = |name| std::env::var(name).unwrap_or_default();
+        render.mode = config::RenderMode::detect(&var("TERM"), &var("COLORTERM")).unwrap_or(render.mode);
+    }
+    render
+}
+
+fn resize(terminal_size: &Mutex<TerminalSize>) -> io::Result<()> {
+    let (cols, _) = terminal::size()?;
+    let mut size = terminal_size.lock().unwrap();
+    size.target_width = (cols as usize).max(2);
+    size.target_height = render::get_height_from_width(size.target_width);
+    Ok(())
+}
+
+// Resizes the game whenever the terminal's told it's been resized (SIGWINCH).
+// crossterm's own resize events would need it reading stdin, which read_stdin does.
+#[cfg(unix)]
+async fn follow_resizes(terminal_size: Arc<Mutex<TerminalSize>>) -> io::Result<()> {
+    let mut resized = signal(SignalKind::window_change())?;
+    while resized.recv().await.is_some() {
+        resize(&terminal_size)?;
+    }
+    Ok(())
+}
+
+// Windows consoles don't signal resizes, so the size is checked every so often
+#[cfg(windows)]
+async fn follow_resizes(terminal_size: Arc<Mutex<TerminalSize>>) -> io::Result<()> {
+    let mut check = tokio::time::interval(Duration::from_millis(250));
+    loop {
+        check.tick().await;
+        resize(&terminal_size)?;
+    }
+}
+
+// Puts the terminal back if anything panics, before the panic's reported (and
+// the process aborts, in release builds), so it isn't left raw and blank
+fn restore_terminal_on_panic() {
+    let report = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = cleanup_terminal();
+        report(info);
+    }));
+}
+
+// Ends the game on SIGINT, SIGTERM or SIGHUP, like quitting it would.  A second
+// one doesn't wait for that, but still puts the terminal back.
+#[cfg(unix)]
+async fn stop_on_signal(running: Arc<AtomicBool>) -> io::Result<()> {
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut hangup = signal(SignalKind::hangup())?;
+    for stops in 0..2 {
+        tokio::select! {
+            _ = interrupt.recv() => {}
+            _ = terminate.recv() => {}
+            _ = hangup.recv() => {}
+        }
+        if stops == 0 {
+            running.store(false, Ordering::SeqCst);
+        }
+    }
+    let _ = cleanup_terminal();
+    std::process::exit(130);
+}
+
+// The same for Ctrl+Break, or the console being closed
+#[cfg(windows)]
+async fn stop_on_signal(running: Arc<AtomicBool>) -> io::Result<()> {
+    use tokio::signal::windows::{ctrl_break, ctrl_close};
+
+    let mut interrupt = ctrl_break()?;
+    let mut close = ctrl_close()?;
+    for stops in 0..2 {
+        tokio::select! {
+            _ = interrupt.recv() => {}
+            _ = close.recv() => {}
+        }
+        if stops == 0 {
+            running.store(false, Ordering::SeqCst);
+        }
+    }
+    let _ = cleanup_terminal();
+    std::process::exit(130);
+}
+
+pub async fn play(args: &LocalArgs, settings: &LiveConfig) -> anyhow::Result<()> {
+    restore_terminal_on_panic();
+    // Clear the terminal
+    let mut stdout = io::stdout();
+    execute!(
+        stdout,
+        terminal::EnterAlternateScreen,
+        Clear(ClearType::All),
+        cursor::Hide
+    )?;
+
+    terminal::enable_raw_mode()?;
+    #[cfg(windows)]
+    virtual_terminal_input(true)?;
+    // Anything logged to stderr would be drawn in the middle of the game
+    logging::hold();
+
+    let running = Arc::new(AtomicBool::new(true));
+    let stopping = tokio::spawn(stop_on_signal(running.clone()));
+
+    // The terminal's width, unless one's been given
+    let columns = terminal::size().ok().map(|(cols, _)| cols as usize);
+    let target_width = args.width.or(columns).unwrap_or(40).max(2);
+    let target_height = render::get_height_from_width(target_width);
+
+    let terminal_size = Arc::new(Mutex::new(TerminalSize {
+        target_width,
+        target_height,
+    }));
+    // Follows the terminal as it's resized, unless the width was given
+    let resizing = args.width.is_none().then(|| tokio::spawn(follow_resizes(terminal_size.clone())));
+
+    let result = run(args, settings, running, terminal_size).await;
+    if let Some(resizing) = resizing {
+        resizing.abort();
+    }
+    stopping.abort();
+    // Whether or not the game worked
+    cleanup_terminal()?;
+    result?;
+
+    // crossterm::execute!(
+    //     output_channel,
+    //     event::EnableMouseCapture,
+    //     event::EnableFocusChange,
+    //     terminal::EnterAlternateScreen,
+    //     cursor::Hide
+    // );
+
+    // crossterm::execute!(
+    //     output_channel,
+    //     event::DisableMouseCapture,
+    //     event::DisableFocusChange,
+    //     terminal::LeaveAlternateScreen,
+    //     cursor::Show,
+    // );
+
+    Ok(())
+}
+
+// Runs the game on the display, like the server's sessions do
+#[cfg(unix)]
+async fn run(
+    args: &LocalArgs,
+    settings: &LiveConfig,
+    running: Arc<AtomicBool>,
+    terminal_size: Arc<Mutex<TerminalSize>>,
+) -> anyhow::Result<()> {
+    let current = settings.get();
+    wm::supervise(&args.display, current.window_manager.as_deref());
+    minecraft::run(
+        minecraft::MinecraftConfig {
+            app: app::from_config(&current.app),
+            xorg_display: args.display.clone(),
+            resolution: (config::GAME_WIDTH, config::GAME_HEIGHT),
+            username: args.username.clone(),
+            server_address: args.server.clone().unwrap_or_else(|| current.server_address.clone()),
+            version: current.game_versions().into_iter().next().unwrap_or_default(),
+            game_dir: None,
+            backup_dir: None,
+            env: game_env::shared(&current),
+            sandbox: current.sandboxed(""),
+            session_user: None,
+            render_settings: Arc::new(Mutex::new(render_settings(settings))),
+            world: None,
+            jvm_args: current.jvm_args(1),
+            gpu: false,
+            account: None,
+            logs: session_log::SessionLogs::default(),
+            audio_port: None,
+            input_settings: Arc::new(Mutex::new(current.input.clone())),
+            reclaim_policy: None,
+            session_info: SessionInfo {
+                username: args.username.clone(),
+                display: Some(args.display.clone()),
+                ..Default::default()
+            },
+            settings: current.clone(),
+        },
+        settings,
+        running,
+        tokio::io::stdout(),
+        read_stdin(),
+        terminal_size,
+    )
+    .await?;
+    Ok(())
+}
+
+// Shows the window and sends it input, until the player quits or the window's gone
+#[cfg(windows)]
+async fn run(
+    args: &LocalArgs,
+    settings: &LiveConfig,
+    running: Arc<AtomicBool>,
+    terminal_size: Arc<Mutex<TerminalSize>>,
+) -> anyhow::Result<()> {
+    let logs = session_log::SessionLogs::default();
+    let session_state = SessionState {
+        running: running.clone(),
+        terminal_size: terminal_size.clone(),
+        input_settings: Arc::new(Mutex::new(settings.get().input.clone())),
+        render_settings: Arc::new(Mutex::new(render_settings(settings))),
+        overlay: Default::default(),
+        last_input: Arc::new(Mutex::new(Instant::now())),
+        game_log: Default::default(),
+        dead: Arc::new(AtomicBool::new(false)),
+        logs: logs.clone(),
+        session_info: Arc::new(SessionInfo {
+            display: args.window.clone(),
+            ..Default::default()
+        }),
+    };
+    plugins::session_start(&session_state.session_info);
+    let mut supervisor = Supervisor::new(running);
+    let cancel = supervisor.cancellation();
+    let (frames_tx, frames_rx) = tokio::sync::mpsc::channel(1);
+    let repaint = render::Repaint::default();
+    let streamed = Arc::new(render::Streamed::default());
+    let (input_tx, input_rx) = std::sync::mpsc::channel();
+
+    let render_session = (
+        terminal_size.clone(),
+        session_state.render_settings.clone(),
+        settings.clone(),
+        VideoSource::Window(args.window.clone()),
+        supervisor.processes(),
+        logs.clone(),
+        repaint.clone(),
+        streamed.throttle.clone(),
+    );
+    let render_cancel = cancel.clone();
+    supervisor.spawn_restarting("ffmpeg_render", move || {
+        let (terminal_size, render_settings, settings, source, processes, logs, repaint, throttle) =
+            render_session.clone();
+        render::render_video(
+            frames_tx.clone(),
+            Some(repaint),
+            throttle,
+            terminal_size,
+            render_settings,
+            settings,
+            source,
+            render_cancel.clone(),
+            processes,
+            logs,
+        )
+    });
+    supervisor.spawn(
+        "render",
+        render::show_frames(
+            frames_rx,
+            tokio::io::stdout(),
+            terminal_size,
+            session_state.overlay.clone(),
+            repaint,
+            streamed,
+            session_state.session_info.clone(),
+            cancel.clone(),
+        ),
+    );
+    supervisor.spawn("input_capture", xdo::capture_input(read_stdin(), input_tx, cancel.clone()));
+    let sink = sendinput::SendInput {
+        window: args.window.clone(),
+        logs,
+    };
+    let game_size = sink.size();
+    supervisor.spawn_blocking("input_forwarding", move || {
+        xdo::forward_input_to_minecraft(input_rx, session_state, sink, game_size, false)
+    });
+    supervisor.wait().await;
+    Ok(())
+}
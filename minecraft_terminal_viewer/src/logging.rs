@@ -0,0 +1,198 @@
+// Log lines go through tracing, to stderr or a file, as text or JSON.  What's kept
+// is filtered the way RUST_LOG would, so a noisy module can be turned down without
+// losing the rest, e.g.
+//
+//   ANSICRAFT_LOG=info,minecraft_terminal_viewer::render=warn,russh=warn
+//
+// Session logs (session_log.rs) are kept apart from these, per player.  The macros
+// bring tracing's own `display` and `debug` into scope, so variables logged with
+// them need other names.
+//
+// Nothing else should print while the program's running: in `local`, stdout and
+// stderr are the terminal the game's drawn on, so lines there would end up in
+// the middle of frames.  Log lines to stderr are held back while the game's
+// showing instead, and anything logging over and over is cut down to size.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Mutex, PoisonError};
+use std::time::Instant;
+
+use tracing::callsite::Identifier;
+use tracing::{warn, Event, Metadata};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::{self, writer::BoxMakeWriter};
+use tracing_subscriber::layer::{Context, Filter};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::EnvFilter;
+
+use crate::config::{AppConfig, LogFormat, LogRotation, LOG_BURST, LOG_BURST_WINDOW, LOG_LINES_HELD};
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+// Log lines to stderr waiting for the terminal back, while there are any
+#[derive(Default)]
+struct Held {
+    lines: VecDeque<Vec<u8>>,
+    // Ones there wasn't room for
+    dropped: usize,
+}
+
+static HELD: Mutex<Option<Held>> = Mutex::new(None);
+
+// Stderr, or the lines held back from it
+struct Stderr;
+
+impl Write for Stderr {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Each line's written all at once
+        let mut held = HELD.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(held) = held.as_mut() else {
+            return io::stderr().write(buf);
+        };
+        if held.lines.len() == LOG_LINES_HELD {
+            held.lines.pop_front();
+            held.dropped += 1;
+        }
+        held.lines.push_back(buf.to_vec());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+// Holds log lines back from stderr, while something else is using the terminal
+pub fn hold() {
+    HELD.lock().unwrap_or_else(PoisonError::into_inner).get_or_insert_default();
+}
+
+// Prints the lines held back, and lets the rest through from now on
+pub fn release() {
+    let Some(held) = HELD.lock().unwrap_or_else(PoisonError::into_inner).take() else {
+        return;
+    };
+    let mut stderr = io::stderr().lock();
+    if held.dropped > 0 {
+        let _ = writeln!(stderr, "({} earlier log lines left out)", held.dropped);
+    }
+    for line in held.lines {
+        let _ = stderr.write_all(&line);
+    }
+}
+
+// Problems found before logging's started are printed, since that's all there is,
+// and any after are logged
+pub fn report(message: &str) {
+    if STARTED.load(Ordering::SeqCst) {
+        warn!("{}", message);
+    } else {
+        eprintln!("{}", message);
+    }
+}
+
+// How much a place in the code has logged lately
+struct Burst {
+    started: Instant,
+    lines: u32,
+    left_out: u32,
+    metadata: &'static Metadata<'static>,
+}
+
+static BURSTS: LazyLock<Mutex<HashMap<Identifier, Burst>>> = LazyLock::new(Default::default);
+
+// Keeps each place in the code to LOG_BURST lines every LOG_BURST_WINDOW
+struct RateLimit;
+
+impl<S> Filter<S> for RateLimit {
+    fn enabled(&self, _metadata: &Metadata<'_>, _cx: &Context<'_, S>) -> bool {
+        true
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, _cx: &Context<'_, S>) -> bool {
+        let metadata = event.metadata();
+        let mut bursts = BURSTS.lock().unwrap_or_else(PoisonError::into_inner);
+        let burst = bursts.entry(metadata.callsite()).or_insert_with(|| Burst {
+            started: Instant::now(),
+            lines: 0,
+            left_out: 0,
+            metadata,
+        });
+        if burst.started.elapsed() >= LOG_BURST_WINDOW {
+            burst.started = Instant::now();
+            burst.lines = 0;
+        }
+        burst.lines += 1;
+        if burst.lines > LOG_BURST {
+            burst.left_out += 1;
+            return false;
+        }
+        true
+    }
+}
+
+// Says how many lines were left out, every so often.  Not from the filter, since
+// nothing logged while an event's being logged goes anywhere.
+fn report_left_out() {
+    loop {
+        std::thread::sleep(LOG_BURST_WINDOW);
+        let left_out: Vec<(u32, &'static Metadata<'static>)> = BURSTS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values_mut()
+            .filter(|burst| burst.left_out > 0)
+            .map(|burst| (std::mem::take(&mut burst.left_out), burst.metadata))
+            .collect();
+        for (lines, metadata) in left_out {
+            let place = format!("{}:{}", metadata.file().unwrap_or("?"), metadata.line().unwrap_or(0));
+            warn!("Left out {} lines from {} ({}), which was logging too often", lines, metadata.target(), place);
+        }
+    }
+}
+
+// A file that starts afresh as often as the config says, with the date on the end
+fn log_file(path: &Path, rotation: LogRotation) -> Result<RollingFileAppender, String> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name = path.file_name().ok_or_else(|| format!("{} isn't a file", path.display()))?;
+    let rotation = match rotation {
+        LogRotation::Daily => Rotation::DAILY,
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Never => Rotation::NEVER,
+    };
+    RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(name.to_string_lossy())
+        .build(dir)
+        .map_err(|e| e.to_string())
+}
+
+// Sets up logging once at startup.  Nothing's logged before this, so problems
+// with it are printed instead.
+pub fn init(config: &AppConfig) {
+    let filter = EnvFilter::try_new(&config.log_filter).unwrap_or_else(|e| {
+        eprintln!("Ignoring ANSICRAFT_LOG, which isn't a filter ({}), and logging at info", e);
+        EnvFilter::new("info")
+    });
+    let file = config.log_file.as_ref().and_then(|path| {
+        log_file(path, config.log_rotation)
+            .map_err(|e| eprintln!("Error opening log file {}, logging to stderr: {}", path.display(), e))
+            .ok()
+    });
+    // Colors only for people reading it as it happens
+    let colored = file.is_none() && std::io::stderr().is_terminal();
+    let writer = match file {
+        Some(file) => BoxMakeWriter::new(file),
+        None => BoxMakeWriter::new(|| Stderr),
+    };
+    let layer = fmt::layer().with_writer(writer).with_ansi(colored);
+    let layer = match config.log_format {
+        LogFormat::Pretty => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    };
+    tracing_subscriber::registry().with(filter).with(layer.with_filter(RateLimit)).init();
+    STARTED.store(true, Ordering::SeqCst);
+    std::thread::spawn(report_left_out);
+}
@@ -0,0 +1,70 @@
+// A tiny process-wide metrics registry.  Anything can bump a counter or set a gauge
+// by name, and operators read the lot in Prometheus text format with `admin metrics`.
+// Without the metrics feature, nothing's counted.
+
+use std::collections::BTreeMap;
+use std::sync::{LazyLock, Mutex};
+
+#[derive(Default)]
+struct Summary {
+    count: u64,
+    sum: f64,
+    max: f64,
+}
+
+#[derive(Default)]
+struct Registry {
+    counters: BTreeMap<&'static str, u64>,
+    gauges: BTreeMap<&'static str, f64>,
+    summaries: BTreeMap<&'static str, Summary>,
+}
+
+static REGISTRY: LazyLock<Mutex<Registry>> = LazyLock::new(Default::default);
+
+pub fn increment(name: &'static str) {
+    if !cfg!(feature = "metrics") {
+        return;
+    }
+    *REGISTRY.lock().unwrap().counters.entry(name).or_default() += 1;
+}
+
+pub fn set_gauge(name: &'static str, value: f64) {
+    if !cfg!(feature = "metrics") {
+        return;
+    }
+    REGISTRY.lock().unwrap().gauges.insert(name, value);
+}
+
+// Records one observation of something like a latency
+pub fn observe(name: &'static str, value: f64) {
+    if !cfg!(feature = "metrics") {
+        return;
+    }
+    let mut registry = REGISTRY.lock().unwrap();
+    let summary = registry.summaries.entry(name).or_default();
+    summary.count += 1;
+    summary.sum += value;
+    summary.max = summary.max.max(value);
+}
+
+#[cfg(feature = "server")]
+pub fn render() -> String {
+    if !cfg!(feature = "metrics") {
+        return "# Built without the metrics feature\n".to_owned();
+    }
+    let registry = REGISTRY.lock().unwrap();
+    let mut output = String::new();
+    for (name, value) in &registry.counters {
+        output.push_str(&format!("# TYPE {} counter\n{} {}\n", name, name, value));
+    }
+    for (name, value) in &registry.gauges {
+        output.push_str(&format!("# TYPE {} gauge\n{} {}\n", name, name, value));
+    }
+    for (name, summary) in &registry.summaries {
+        output.push_str(&format!(
+            "# TYPE {} summary\n{}_count {}\n{}_sum {}\n{}_max {}\n",
+            name, name, summary.count, name, summary.sum, name, summary.max
+        ));
+    }
+    output
+}
@@ -1,247 +1,764 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use std::{io, thread};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::config::TerminalSize;
-use crate::{render, xdo};
-use crossterm::terminal::{self, BeginSynchronizedUpdate, Clear, EndSynchronizedUpdate};
-use crossterm::{self, cursor, event, queue};
-use nix::sys::signal::{self, Signal};
-use nix::unistd::Pid;
+use crate::config::{
+    AppConfig, InputSettings, LiveConfig, RenderSettings, TerminalSize, DISCONNECT_PATTERNS,
+    APP_READY_TIMEOUT, AUDIO_NOTICE_DURATION, QUIT_START_PATTERNS, QUIT_START_TIMEOUT, SAVED_PATTERNS, SAVE_TIMEOUT, DISPLAY_CHECK_FAILURES, DISPLAY_CHECK_INTERVAL,
+    MAX_TASK_RESTARTS, RECONNECT_DELAY,
+};
+use crate::overlay::Overlay;
+use crate::plugins::{self, SessionInfo};
+use crate::render::{Screen, VideoSource};
+use crate::session_log::SessionLogs;
+pub use crate::session::{GameLog, SessionState};
+use crate::session;
+use crate::supervisor::Supervisor;
+use crate::app::AppLauncher;
+use crate::launcher::{LaunchError, LaunchOptions};
+use crate::{
+    audio, backups, docker, game_env, game_events, gpu, kubernetes, launcher, limits, prism, render, sandbox, session_users,
+    warm, wm, xdo, xserver,
+};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{ChildStderr, ChildStdout};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
 
 #[derive(Clone)]
 pub struct MinecraftConfig {
+    // What to run, which is Minecraft unless configured otherwise
+    pub app: Arc<dyn AppLauncher>,
     pub xorg_display: String,
+    // The size of the display's screen, which is what gets captured
+    pub resolution: (u16, u16),
     pub username: String,
     pub server_address: String,
+    // Passed to the launcher, empty for its default
+    pub version: String,
+    // Where the game keeps options and worlds, None for the launcher's default
+    pub game_dir: Option<std::path::PathBuf>,
+    // Where to back up the world played in singleplayer, when the game exits cleanly
+    pub backup_dir: Option<std::path::PathBuf>,
+    // Extra environment variables for the game, from the operator
+    pub env: Vec<(String, String)>,
+    // Whether the game runs in a sandbox
+    pub sandbox: bool,
+    // The unprivileged user the game runs as, if sessions each have one
+    pub session_user: Option<u32>,
+    // How it's shown, which the player can change while it runs
+    pub render_settings: Arc<Mutex<RenderSettings>>,
+    // A saved world to load straight into, for singleplayer
+    pub world: Option<String>,
+    pub jvm_args: Vec<String>,
+    // Whether the display is tagged for rendering on the GPU
+    pub gpu: bool,
+    // The player's saved Microsoft login, to play online as themselves
+    pub account: Option<std::path::PathBuf>,
+    // Where the game's sound is streamed, if it is
+    pub audio_port: Option<u16>,
+    pub input_settings: Arc<Mutex<InputSettings>>,
+    pub reclaim_policy: Option<ReclaimPolicy>,
+    pub logs: SessionLogs,
+    // Who's playing, for plugins' hooks
+    pub session_info: SessionInfo,
+    // The server's settings as they were when the session started
+    pub settings: Arc<AppConfig>,
 }
 
-// TODO: Maybe I should put this in the render crate...?
-fn display_render_thread<Writer: std::io::Write + Send + 'static>(
-    completed_frames: mpsc::Receiver<String>,
-    output_channel: Arc<Mutex<Writer>>,
-) -> io::Result<()> {
-    let mut writer = output_channel.lock().expect("Failed to lock mutex");
-
-    // To be clear, I really don't think these (or the cleanup commands) belong here...
-    // but I'm not quite proficient enough with rust's borrow checker to understand
-    // how to put them higher up.  Maybe later.
-    crossterm::execute!(
-        writer,
-        event::EnableMouseCapture,
-        terminal::EnterAlternateScreen,
-        cursor::Hide
-    )?;
+// Why a session was ended to give its slot to someone else
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReclaimReason {
+    Idle,
+    TimeUp,
+}
+
+// When to end a session so someone who's waiting can have its slot
+#[derive(Clone)]
+pub struct ReclaimPolicy {
+    pub afk_timeout: Duration,
+    pub afk_warning: Duration,
+    pub max_duration: Option<Duration>,
+    pub time_up_warning: Duration,
+    pub others_waiting: Arc<dyn Fn() -> bool + Send + Sync>,
+    // Set when the session was ended to free its slot
+    pub reclaimed: Arc<Mutex<Option<ReclaimReason>>>,
+}
 
+async fn reclaim_watchdog(
+    policy: ReclaimPolicy,
+    session_state: SessionState,
+    cancel: CancellationToken,
+) -> io::Result<()> {
+    let started = Instant::now();
+    let mut warned_since: Option<(ReclaimReason, Instant)> = None;
     loop {
-        match completed_frames.recv_timeout(Duration::from_millis(1)) {
-            Ok(frame) => {
-                queue!(writer, BeginSynchronizedUpdate)?;
-                // I wonder if we want to add a clear here.
-                writer.write(frame.as_bytes())?;
-                queue!(
-                    writer,
-                    Clear(crossterm::terminal::ClearType::FromCursorDown)
-                )?;
-                queue!(writer, EndSynchronizedUpdate)?;
-                writer.flush()?;
-            }
-            Err(mpsc::RecvTimeoutError::Timeout) => {
-                continue;
-            }
-            Err(mpsc::RecvTimeoutError::Disconnected) => {
-                break;
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+        }
+
+        let idle = session_state.last_input.lock().unwrap().elapsed();
+        let reason = if !(policy.others_waiting)() {
+            None
+        } else if policy.max_duration.is_some_and(|max| started.elapsed() >= max) {
+            Some(ReclaimReason::TimeUp)
+        } else if idle >= policy.afk_timeout {
+            Some(ReclaimReason::Idle)
+        } else {
+            None
+        };
+        let Some(reason) = reason else {
+            if warned_since.take().is_some() {
+                session_state.overlay.lock().unwrap().hide_notice();
             }
+            continue;
+        };
+
+        // A different reason starts a new countdown
+        if warned_since.is_some_and(|(warned_reason, _)| warned_reason != reason) {
+            warned_since = None;
+        }
+        let (_, since) = *warned_since.get_or_insert((reason, Instant::now()));
+        let warning = match reason {
+            ReclaimReason::Idle => policy.afk_warning,
+            ReclaimReason::TimeUp => policy.time_up_warning,
+        };
+        let remaining = warning.saturating_sub(since.elapsed());
+        if remaining.is_zero() {
+            info!("Ending session ({:?}) since others are waiting", reason);
+            crate::metrics::increment(match reason {
+                ReclaimReason::Idle => "ansicraft_afk_reclaims_total",
+                ReclaimReason::TimeUp => "ansicraft_time_limit_reclaims_total",
+            });
+            *policy.reclaimed.lock().unwrap() = Some(reason);
+            cancel.cancel();
+            break;
         }
-    }
 
-    crossterm::execute!(
-        writer,
-        event::DisableMouseCapture,
-        terminal::LeaveAlternateScreen,
-        cursor::Show,
-    )?;
+        let seconds = remaining.as_secs() + 1;
+        session_state.overlay.lock().unwrap().show_notice(match reason {
+            ReclaimReason::Idle => format!(
+                "You look AFK and others are waiting. Move within {}s to keep your spot.",
+                seconds
+            ),
+            ReclaimReason::TimeUp => format!(
+                "Time's up and others are waiting. Your session ends in {}s.",
+                seconds
+            ),
+        });
+    }
+    Ok(())
+}
 
+// Ends the session once its display stops answering, so it can move to another.
+// Containers' displays only come up with the game, so it waits for it to answer once.
+async fn display_watchdog(
+    display_name: String,
+    cancel: CancellationToken,
+    display_lost: Arc<AtomicBool>,
+) -> io::Result<()> {
+    let mut answered = false;
+    let mut failures = 0;
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = tokio::time::sleep(DISPLAY_CHECK_INTERVAL) => {}
+        }
+        let display = display_name.clone();
+        if tokio::task::spawn_blocking(move || xserver::answers(&display)).await.unwrap_or(false) {
+            answered = true;
+            failures = 0;
+            continue;
+        }
+        if !answered || cancel.is_cancelled() {
+            continue;
+        }
+        failures += 1;
+        if failures >= DISPLAY_CHECK_FAILURES {
+            warn!("Display {} stopped answering, ending its session", display_name);
+            display_lost.store(true, Ordering::SeqCst);
+            cancel.cancel();
+        }
+    }
     Ok(())
 }
 
-fn run_minecraft(config: MinecraftConfig, running: Arc<AtomicBool>) -> io::Result<()> {
-    use std::process::Command;
+// How a session went, for the player to see when it's over
+pub struct SessionSummary {
+    pub played: Duration,
+    pub frames: u64,
+    // Whether it ended because the display (or capturing it) failed
+    pub display_lost: bool,
+}
 
-    // Set the DISPLAY environment variable based on config.xorg_display
-    let display_env = config.xorg_display.clone();
+impl SessionSummary {
+    pub fn average_fps(&self) -> f64 {
+        self.frames as f64 / self.played.as_secs_f64().max(1.0)
+    }
+}
 
-    // Find the Python script location relative to the current executable
-    let launch_script = "/root/launch_minecraft.py";
+// The command that starts the game on the given display, with the built-in
+// launcher unless ANSICRAFT_PRISM or ANSICRAFT_LAUNCHER names another
+pub fn launch_command(options: &LaunchOptions) -> Result<std::process::Command, LaunchError> {
+    if options.settings.prism.enabled() {
+        return prism::command(options);
+    }
+    let launcher = &options.settings.launcher;
+    if launcher.launcher.is_empty() {
+        return launcher::command(options);
+    }
+    let game_dir_arg = options.game_dir.map(|dir| dir.to_string_lossy().into_owned()).unwrap_or_default();
+    let account_arg = options.account.map(|account| account.to_string_lossy().into_owned()).unwrap_or_default();
+    let fill_in = |template: &str| -> Vec<String> {
+        template
+            .split_whitespace()
+            .map(|arg| {
+                arg.replace("{username}", options.username)
+                    .replace("{server}", options.server)
+                    .replace("{display}", options.display)
+                    .replace("{version}", options.version)
+                    .replace("{game_dir}", &game_dir_arg)
+                    .replace("{world}", options.world.unwrap_or_default())
+                    .replace("{account}", &account_arg)
+            })
+            .collect()
+    };
 
     // Build command with proper arguments
-    let mut cmd = Command::new("python3");
-    cmd.arg(launch_script)
-        .arg("--username")
-        .arg(&config.username)
-        .env("DISPLAY", &display_env);
+    let mut cmd = if launcher.interpreter.is_empty() {
+        std::process::Command::new(&launcher.launcher)
+    } else {
+        let mut cmd = std::process::Command::new(&launcher.interpreter);
+        cmd.arg(&launcher.launcher);
+        cmd
+    };
+    cmd.args(fill_in(&launcher.args)).env("DISPLAY", options.display);
+    if !launcher.jvm_arg.is_empty() {
+        for arg in options.jvm_args {
+            cmd.arg(launcher.jvm_arg.replace("{arg}", arg));
+        }
+    }
+
+    if !options.version.is_empty() {
+        cmd.args(fill_in(&launcher.version_args));
+    }
+    if options.game_dir.is_some() {
+        cmd.args(fill_in(&launcher.game_dir_args));
+    }
+    if options.world.is_some() {
+        cmd.args(fill_in(&launcher.world_args));
+    }
 
     // Add server address if specified and not empty
-    if !config.server_address.is_empty() {
-        cmd.arg("--server").arg(&config.server_address);
+    if !options.server.is_empty() {
+        cmd.args(fill_in(&launcher.server_args));
+    }
+    if options.account.is_some() {
+        cmd.args(fill_in(&launcher.account_args));
     }
 
     // Redirect standard output and error
     // cmd.stdout(Stdio::piped())
     //    .stderr(Stdio::piped());
 
-    // Execute the command
-    println!(
-        "Launching Minecraft with username: {} on display: {}",
-        config.username, display_env
+    Ok(cmd)
+}
+
+// Cleans up the container or pod the game ran in, if it had one
+fn remove_container(display: &str, settings: &AppConfig) {
+    if docker::enabled(settings) {
+        docker::remove(display);
+    } else if kubernetes::enabled(settings) {
+        kubernetes::remove(display, settings);
+    }
+}
+
+// Starts the game, with tasks collecting its output and watching it for a lost
+// connection
+fn launch(config: &MinecraftConfig, lost_connection: Arc<AtomicBool>, log: Arc<Mutex<GameLog>>) -> io::Result<Child> {
+    info!(
+        "Launching {} with username: {} on display: {}",
+        config.app.name(),
+        config.username,
+        config.xorg_display
     );
     if !config.server_address.is_empty() {
-        println!("Connecting to server: {}", config.server_address);
+        info!("Connecting to server: {}", config.server_address);
+    }
+
+    let settings = &config.settings;
+    let mut cmd = config.app.command(config).map_err(io::Error::other)?;
+    cmd.envs(config.env.iter().cloned());
+    if config.gpu {
+        cmd = gpu::wrap(cmd, settings);
+    }
+    audio::apply(&mut cmd, &config.xorg_display, settings);
+    let containerized = docker::enabled(settings) || kubernetes::enabled(settings);
+    if docker::enabled(settings) {
+        cmd = docker::wrap(cmd, &config.xorg_display, config.resolution, config.game_dir.as_deref(), settings);
+    } else if kubernetes::enabled(settings) {
+        cmd = kubernetes::wrap(cmd, &config.xorg_display, config.resolution, settings)?;
+    } else {
+        if let (Some(uid), Some(game_dir)) = (config.session_user, &config.game_dir) {
+            session_users::apply(&mut cmd, uid, game_dir)?;
+        }
+        if config.sandbox {
+            cmd = sandbox::wrap(cmd, &config.xorg_display, config.game_dir.as_deref(), settings);
+        }
+        limits::apply(&mut cmd, &settings.limits);
+    }
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    if containerized {
+        let display_up = if docker::enabled(settings) {
+            docker::wait_for_display(&config.xorg_display, &mut child)
+        } else {
+            kubernetes::connect(&config.xorg_display, &mut child, settings)
+        };
+        if let Err(e) = display_up {
+            let _ = child.kill();
+            let _ = child.wait();
+            remove_container(&config.xorg_display, settings);
+            return Err(e);
+        }
+    } else {
+        limits::confine(child.id(), &config.xorg_display, &settings.limits);
+    }
+
+    let stderr = ChildStderr::from_std(child.stderr.take().expect("stderr is piped"))?;
+    let (stderr_log, stderr_logs) = (log.clone(), config.logs.clone());
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(stderr).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            stderr_logs.line("game", &line);
+            stderr_log.lock().unwrap().push(line);
+        }
+    });
+
+    let stdout = ChildStdout::from_std(child.stdout.take().expect("stdout is piped"))?;
+    let logs = config.logs.clone();
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(stdout).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if DISCONNECT_PATTERNS.iter().any(|pattern| line.contains(pattern)) {
+                lost_connection.store(true, Ordering::SeqCst);
+            }
+            logs.line("game", &line);
+            log.lock().unwrap().push(line);
+        }
+    });
+    Ok(child)
+}
+
+// Tells the player the game is starting until it shows up on the display
+async fn watch_for_ready(
+    app: Arc<dyn AppLauncher>,
+    display: String,
+    resolution: (u16, u16),
+    session_state: SessionState,
+    cancel: CancellationToken,
+) -> io::Result<()> {
+    let starting = format!("Starting {}...", app.name());
+    session_state.overlay.lock().unwrap().show_notice(starting.clone());
+    let started = Instant::now();
+    loop {
+        let ready = {
+            let (app, display) = (app.clone(), display.clone());
+            tokio::task::spawn_blocking(move || app.ready(&display)).await.map_err(io::Error::other)?
+        };
+        if ready || cancel.is_cancelled() {
+            break;
+        }
+        if started.elapsed() > APP_READY_TIMEOUT {
+            session_state.overlay.lock().unwrap().hide_notice_if(&starting);
+            session_state.overlay.lock().unwrap().show_notice(format!(
+                "{} is taking a while to start. F9 shows what it's saying.",
+                app.name()
+            ));
+            return Ok(());
+        }
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+        }
+    }
+    session_state.overlay.lock().unwrap().hide_notice_if(&starting);
+    if let Some(name) = app.window_name() {
+        let name = name.to_owned();
+        tokio::task::spawn_blocking(move || wm::fit_window(&display, &name, resolution))
+            .await
+            .map_err(io::Error::other)?;
     }
+    Ok(())
+}
 
-    // Start the command but don't wait for it to complete
-    let child = cmd.spawn()?;
-    let pid = child.id();
+// Whether the game's said any of these since it had said `seen` lines
+fn logged_since(log: &Mutex<GameLog>, seen: usize, patterns: &[&str]) -> bool {
+    let log = log.lock().unwrap();
+    let new = (log.received - seen).min(log.lines.len());
+    log.lines.iter().skip(log.lines.len() - new).any(|line| patterns.iter().any(|pattern| line.contains(pattern)))
+}
 
-    println!("Minecraft launched (PID: {})", pid);
+// Has the game save and quit to its title screen, and waits for it to finish
+// saving, so the world isn't left half written when it's stopped
+fn save_and_quit(config: &MinecraftConfig, process: &mut Child, log: &Mutex<GameLog>) {
+    let seen = log.lock().unwrap().received;
+    let mut attempts = 0;
+    loop {
+        if process.try_wait().ok().flatten().is_some() || !config.app.save_and_quit(&config.xorg_display) {
+            return;
+        }
+        attempts += 1;
+        let started = Instant::now();
+        while started.elapsed() < QUIT_START_TIMEOUT && !logged_since(log, seen, QUIT_START_PATTERNS) {
+            thread::sleep(Duration::from_millis(100));
+        }
+        if logged_since(log, seen, QUIT_START_PATTERNS) {
+            break;
+        }
+        // The first Escape may have closed a menu that was already open
+        if attempts == 2 {
+            warn!("{} didn't save and quit, stopping it anyway", config.app.name());
+            return;
+        }
+    }
+    let started = Instant::now();
+    while started.elapsed() < SAVE_TIMEOUT && !logged_since(log, seen, SAVED_PATTERNS) {
+        if process.try_wait().ok().flatten().is_some() {
+            return;
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+    if logged_since(log, seen, SAVED_PATTERNS) {
+        info!("{} saved and quit to its title screen", config.app.name());
+    } else {
+        warn!("{} didn't say it had saved in time, stopping it anyway", config.app.name());
+    }
+}
+
+async fn run_minecraft(
+    config: MinecraftConfig,
+    session_state: SessionState,
+    supervisor: &mut Supervisor,
+) -> io::Result<()> {
+    let cancel = supervisor.cancellation();
+    let display_env = config.xorg_display.clone();
+    let lost_connection = Arc::new(AtomicBool::new(false));
 
-    // Create a separate thread to manage the minecraft process
-    let minecraft_process_running = running.clone();
-    thread::spawn(move || {
+    // A game that's already sitting at the title screen saves a long wait, but it
+    // can't join a server as us, since the username is set at launch.  It uses the
+    // shared game directory and environment too.
+    let warm_instance = if config.server_address.is_empty()
+        && config.game_dir.is_none()
+        && config.account.is_none()
+        && config.env == game_env::shared(&config.settings)
+        && config.sandbox == config.settings.sandboxed("")
+        && config.session_user.is_none()
+    {
+        warm::take(&display_env, &config.version)
+    } else {
+        None
+    };
+    let child = match warm_instance {
+        Some(child) => {
+            info!("Using the warm instance on display: {}", display_env);
+            // The screen may have been resized for this session
+            if let Some(name) = config.app.window_name() {
+                tokio::task::block_in_place(|| wm::fit_window(&display_env, name, config.resolution));
+            }
+            child
+        }
+        None => {
+            let child = tokio::task::block_in_place(|| {
+                launch(&config, lost_connection.clone(), session_state.game_log.clone())
+            })?;
+            supervisor.spawn(
+                "ready",
+                watch_for_ready(
+                    config.app.clone(),
+                    display_env.clone(),
+                    config.resolution,
+                    session_state.clone(),
+                    cancel.clone(),
+                ),
+            );
+            child
+        }
+    };
+
+    info!("{} launched (PID: {})", config.app.name(), child.id());
+
+    // Create a separate task to manage the minecraft process.  Stopping and
+    // relaunching it block, but only now and then.
+    let max_reconnects = config.settings.max_reconnects;
+    let max_restarts = config.settings.max_restarts;
+    let processes = supervisor.processes();
+    supervisor.spawn("game", async move {
         let mut process = child;
+        let mut _tracked = processes.track("game", process.id());
+        let mut reconnects = 0;
+        let mut restarts = 0;
+        let mut gave_up = false;
 
         // Check if we should terminate the process
-        while minecraft_process_running.load(Ordering::SeqCst) {
+        while !cancel.is_cancelled() {
             // Check if process has exited on its own
-            match process.try_wait() {
-                Ok(Some(status)) => {
-                    println!("Minecraft process exited with status: {}", status);
+            let exit_status = match process.try_wait() {
+                Ok(status) => status,
+                Err(e) => {
+                    error!("Error checking Minecraft process status: {}", e);
+                    break;
+                }
+            };
+            let notice = match exit_status {
+                // Quitting from the game's menu ends the session
+                Some(status) if status.success() => {
+                    info!("Minecraft process exited with status: {}", status);
                     break;
                 }
-                Ok(None) => {
+                Some(status) => {
+                    warn!("Minecraft crashed with status: {}", status);
+                    for line in &session_state.game_log.lock().unwrap().lines {
+                        info!("{}", line);
+                    }
+                    crate::metrics::increment("ansicraft_game_crashes_total");
+                    if restarts >= max_restarts {
+                        // Long enough to read before the session goes away
+                        session_state
+                            .overlay
+                            .lock()
+                            .unwrap()
+                            .show_notice("The game keeps crashing, so that's the end of the session. Sorry!".to_owned());
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                        gave_up = true;
+                        break;
+                    }
+                    restarts += 1;
+                    format!("The game crashed. Restarting it ({} of {})...", restarts, max_restarts)
+                }
+                None if lost_connection.swap(false, Ordering::SeqCst) => {
+                    // Nothing to reconnect to in singleplayer
+                    if config.server_address.is_empty() || reconnects >= max_reconnects {
+                        break;
+                    }
+                    reconnects += 1;
+                    info!("Reconnecting to {} ({} of {})", config.server_address, reconnects, max_reconnects);
+                    tokio::task::block_in_place(|| config.app.stop(&mut process));
+                    format!(
+                        "Lost the connection to the server. Reconnecting ({} of {})...",
+                        reconnects, max_reconnects
+                    )
+                }
+                None => {
                     // Process still running, sleep and check again
-                    thread::sleep(Duration::from_millis(500));
+                    tokio::select! {
+                        _ = cancel.cancelled() => {}
+                        _ = tokio::time::sleep(Duration::from_millis(500)) => {}
+                    }
+                    continue;
+                }
+            };
+            session_state.overlay.lock().unwrap().show_notice(notice);
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                _ = tokio::time::sleep(RECONNECT_DELAY) => {}
+            }
+            let relaunched = tokio::task::block_in_place(|| {
+                launch(&config, lost_connection.clone(), session_state.game_log.clone())
+            });
+            match relaunched {
+                Ok(child) => {
+                    _tracked = processes.track("game", child.id());
+                    process = child;
                 }
                 Err(e) => {
-                    eprintln!("Error checking Minecraft process status: {}", e);
+                    error!("Error relaunching Minecraft: {}", e);
                     break;
                 }
             }
+            session_state.overlay.lock().unwrap().hide_notice();
         }
 
-        // Ensure the running flag is set to false when shutting down
-        if minecraft_process_running.load(Ordering::SeqCst) {
-            minecraft_process_running.store(false, Ordering::SeqCst);
-        }
-
-        println!("Shutting down minecraft.");
-
-        // Check if process is still running before sending signals
-        match process.try_wait() {
-            Ok(Some(status)) => {
-                println!("Minecraft process already exited with status: {}", status);
-            }
-            Ok(None) => {
-                // Process is still running, try SIGTERM first
-                println!("Sending SIGTERM to Minecraft process (PID: {})...", pid);
-                if let Err(e) = signal::kill(Pid::from_raw(process.id() as i32), Signal::SIGTERM) {
-                    println!("Could not send SIGTERM to process: {}", e);
-                } else {
-                    // Wait for up to 5 seconds for the process to exit gracefully
-                    let mut terminated = false;
-                    for _ in 0..10 {
-                        thread::sleep(Duration::from_millis(500));
-                        match process.try_wait() {
-                            Ok(Some(status)) => {
-                                println!(
-                                    "Minecraft process exited gracefully with status: {}",
-                                    status
-                                );
-                                terminated = true;
-                                break;
-                            }
-                            Ok(None) => continue, // Still running
-                            Err(e) => {
-                                eprintln!("Error checking process status: {}", e);
-                                break;
-                            }
-                        }
-                    }
+        // The session's over once the game is
+        cancel.cancel();
 
-                    // If process is still alive, force kill it
-                    if !terminated {
-                        println!("Process didn't exit after SIGTERM, attempting to kill...");
-                        match process.kill() {
-                            Ok(_) => println!("Successfully terminated Minecraft process."),
-                            Err(e) => eprintln!("Failed to terminate Minecraft process: {}", e),
-                        }
-                    }
-                }
+        info!("Shutting down minecraft.");
+        tokio::task::block_in_place(|| {
+            if config.server_address.is_empty() && !gave_up {
+                save_and_quit(&config, &mut process, &session_state.game_log);
             }
-            Err(e) => {
-                eprintln!("Error checking Minecraft process status: {}", e);
+            config.app.stop(&mut process);
+            // A game that had to be killed may not have finished saving
+            let clean = !gave_up && process.try_wait().ok().flatten().is_some_and(|status| status.signal() != Some(9));
+            if let (true, true, Some(game_dir), Some(backup_dir)) =
+                (clean, config.server_address.is_empty(), &config.game_dir, &config.backup_dir)
+                && let Err(e) = backups::snapshot(game_dir, backup_dir, config.settings.world_backups)
+            {
+                error!("Error backing up the world in {}: {}", game_dir.display(), e);
             }
-        }
+            limits::release(&config.xorg_display, &config.settings.limits);
+            remove_container(&config.xorg_display, &config.settings);
+        });
+        Ok::<_, io::Error>(())
     });
-
     Ok(())
 }
 
-pub fn run<Writer: std::io::Write + Send + 'static, Reader: std::io::Read + Send + 'static>(
+// Runs a session: the game, ffmpeg capturing it, and the player's input, as tasks
+// that all stop when it's over.  Stopping the game blocks in place, so this needs
+// the multi-threaded runtime.
+pub async fn run<S: Screen>(
     config: MinecraftConfig,
+    settings: &LiveConfig,
     running: Arc<AtomicBool>,
-    output_channel: Arc<Mutex<Writer>>,
-    input_channel: Arc<Mutex<Reader>>,
+    screen: S,
+    input: xdo::InputSource,
     terminal_size: Arc<Mutex<TerminalSize>>,
-) -> io::Result<()> {
-    // First, launch Minecraft in the background
-    run_minecraft(config.clone(), running.clone())?;
-
-    let (completed_frames_tx, completed_frames_rx) = mpsc::sync_channel(1);
+) -> io::Result<SessionSummary> {
+    let started = Instant::now();
+    let streamed = Arc::new(render::Streamed::default());
+    let (completed_frames_tx, completed_frames_rx) = tokio::sync::mpsc::channel(1);
+    let repaint = render::Repaint::default();
     let (input_event_tx, input_event_rx) = mpsc::channel();
 
-    let mut children = vec![];
-
-    // Clone Arc for each thread
-    let running_render = Arc::clone(&running);
-    let running_input = Arc::clone(&running);
-    let running_forward = Arc::clone(&running);
-    let terminal_size_render = Arc::clone(&terminal_size);
-    let terminal_size_forward = Arc::clone(&terminal_size);
-    let display_for_forward = config.xorg_display.clone();
-    let display_for_ffmpeg = config.xorg_display.clone();
-
-    children.push(thread::spawn(move || {
-        render::render_x11_window(
-            completed_frames_tx,
-            terminal_size_render,
-            display_for_ffmpeg,
-            running_render,
-        )
-    }));
-    children.push(thread::Builder::new().name("render_thread".to_owned()).spawn(move || {
-        display_render_thread(completed_frames_rx, output_channel)
-    })?);
-    children.push(thread::Builder::new().name("input_capture".to_owned()).spawn(move || {
-        xdo::capture_input(input_channel, input_event_tx, running_input)
-    })?);
-    children.push(thread::Builder::new().name("input_forwarding".to_owned()).spawn(move || {
+    let mut supervisor = Supervisor::new(running.clone());
+    let cancel = supervisor.cancellation();
+    let display_lost = Arc::new(AtomicBool::new(false));
+
+    let session_state = SessionState {
+        running: Arc::clone(&running),
+        terminal_size: Arc::clone(&terminal_size),
+        input_settings: Arc::clone(&config.input_settings),
+        render_settings: Arc::clone(&config.render_settings),
+        overlay: Arc::new(Mutex::new(Overlay::default())),
+        last_input: Arc::new(Mutex::new(Instant::now())),
+        game_log: Arc::new(Mutex::new(GameLog::default())),
+        dead: Arc::new(AtomicBool::new(false)),
+        logs: config.logs.clone(),
+        session_info: Arc::new(config.session_info.clone()),
+    };
+    plugins::session_start(&session_state.session_info);
+    // Off the admin dashboard again once the session's over
+    let _listed = session::list(session_state.clone(), streamed.clone());
+    config.logs.line(
+        "session",
+        &format!("Started {} for {} on display {}", config.app.name(), config.username, config.xorg_display),
+    );
+
+    // First, launch Minecraft in the background
+    run_minecraft(config.clone(), session_state.clone(), &mut supervisor).await?;
+    // Stops when this goes out of scope at the end of the session
+    let audio_capture = audio::AudioCapture::start(
+        &config.xorg_display,
+        config.audio_port,
+        session_state.overlay.clone(),
+        supervisor.processes(),
+        config.logs.clone(),
+        &config.settings,
+    );
+    if let (Some(_), Some(port)) = (&audio_capture, config.audio_port) {
+        let overlay = session_state.overlay.clone();
+        tokio::spawn(async move {
+            let notice = format!("For sound, play port {} on this server, e.g. ffplay -nodisp tcp://HOST:{}", port, port);
+            overlay.lock().unwrap().show_notice(notice.clone());
+            tokio::time::sleep(AUDIO_NOTICE_DURATION).await;
+            overlay.lock().unwrap().hide_notice_if(&notice);
+        });
+    }
+
+    supervisor.spawn(
+        "game_events",
+        game_events::watch(session_state.clone(), config.username.clone(), config.settings.notify, cancel.clone()),
+    );
+    if let Some(policy) = config.reclaim_policy.clone() {
+        supervisor.spawn("reclaim_watchdog", reclaim_watchdog(policy, session_state.clone(), cancel.clone()));
+    }
+    supervisor.spawn(
+        "display_watchdog",
+        display_watchdog(config.xorg_display.clone(), cancel.clone(), display_lost.clone()),
+    );
+    // ffmpeg can fall over, e.g. if the X server hiccups, and is started again.
+    // If it keeps on falling over, the display's no good.
+    let render_failures = Arc::new(AtomicU32::new(0));
+    let render_session = (
+        terminal_size.clone(),
+        session_state.render_settings.clone(),
+        settings.clone(),
+        VideoSource::Display(config.xorg_display.clone(), config.resolution),
+        supervisor.processes(),
+        config.logs.clone(),
+        display_lost.clone(),
+    );
+    let (render_cancel, render_repaint, throttle) = (cancel.clone(), repaint.clone(), streamed.throttle.clone());
+    supervisor.spawn_restarting("ffmpeg_render", move || {
+        let (terminal_size, render_settings, settings, source, processes, logs, display_lost) = render_session.clone();
+        let (frames_tx, cancel) = (completed_frames_tx.clone(), render_cancel.clone());
+        let (repaint, throttle) = (render_repaint.clone(), throttle.clone());
+        let render_failures = render_failures.clone();
+        async move {
+            let result = render::render_video(
+                frames_tx,
+                Some(repaint),
+                throttle,
+                terminal_size,
+                render_settings,
+                settings,
+                source,
+                cancel.clone(),
+                processes,
+                logs,
+            )
+            .await;
+            if !cancel.is_cancelled() && render_failures.fetch_add(1, Ordering::SeqCst) >= MAX_TASK_RESTARTS {
+                display_lost.store(true, Ordering::SeqCst);
+            }
+            result
+        }
+    });
+    supervisor.spawn(
+        "render",
+        render::show_frames(
+            completed_frames_rx,
+            screen,
+            terminal_size.clone(),
+            session_state.overlay.clone(),
+            repaint,
+            streamed.clone(),
+            session_state.session_info.clone(),
+            cancel.clone(),
+        ),
+    );
+    supervisor.spawn("input_capture", xdo::capture_input(input, input_event_tx, cancel.clone()));
+    // Sending input runs xdotool for nearly every event, so it has a thread
+    let sink = xdo::Xdotool {
+        display: config.xorg_display.clone(),
+        logs: config.logs.clone(),
+    };
+    supervisor.spawn_blocking("input_forwarding", move || {
         xdo::forward_input_to_minecraft(
             input_event_rx,
-            terminal_size_forward,
-            running_forward,
-            display_for_forward,
+            session_state,
+            sink,
+            config.resolution,
             config.server_address == "",
         )
-    })?);
+    });
 
-    for child in children {
-        // Wait for the thread to finish. Returns a result.
-        println!("waiting for {:?} to finish...", child.thread());
-        let _ = child.join();
-    }
+    supervisor.wait().await;
 
-    Ok(())
+    let summary = SessionSummary {
+        played: started.elapsed(),
+        frames: streamed.frames.load(Ordering::Relaxed),
+        display_lost: display_lost.load(Ordering::SeqCst),
+    };
+    config.logs.line(
+        "session",
+        &format!("Ended after {:?}, averaging {:.1} fps", summary.played, summary.average_fps()),
+    );
+    Ok(summary)
 }
@@ -0,0 +1,182 @@
+// Text drawn on top of the game video by the display thread, for things like the
+// local menu.  Anything that wants to show something sets it here, and the display
+// thread redraws whenever it changes.
+
+#[derive(Default)]
+pub struct Overlay {
+    panel: Option<Vec<String>>,
+    notice: Option<String>,
+    chat: Option<String>,
+    // How many times the player's died this session, while they're on the death screen
+    death: Option<u32>,
+    bell: bool,
+    notification: Option<String>,
+    dirty: bool,
+}
+
+impl Overlay {
+    // Shows a box in the middle of the screen
+    pub fn show_panel(&mut self, lines: Vec<String>) {
+        self.panel = Some(lines);
+        self.dirty = true;
+    }
+
+    pub fn hide_panel(&mut self) {
+        if self.panel.take().is_some() {
+            self.dirty = true;
+        }
+    }
+
+    // Shows a warning bar across the top of the screen
+    pub fn show_notice(&mut self, text: String) {
+        if self.notice.as_ref() != Some(&text) {
+            self.notice = Some(text);
+            self.dirty = true;
+        }
+    }
+
+    pub fn hide_notice(&mut self) {
+        if self.notice.take().is_some() {
+            self.dirty = true;
+        }
+    }
+
+    // Shows a line of chat as text in the row below the video, where it's a lot
+    // easier to read than the game's own
+    pub fn show_chat(&mut self, text: String) {
+        self.chat = Some(text);
+        self.dirty = true;
+    }
+
+    pub fn hide_chat(&mut self) {
+        if self.chat.take().is_some() {
+            self.dirty = true;
+        }
+    }
+
+    // Explains the death screen, which is just a lot of red at terminal sizes
+    pub fn show_death(&mut self, deaths: u32) {
+        self.death = Some(deaths);
+        self.dirty = true;
+    }
+
+    pub fn hide_death(&mut self) {
+        if self.death.take().is_some() {
+            self.dirty = true;
+        }
+    }
+
+    // Rings the terminal bell with the next redraw
+    pub fn ring_bell(&mut self) {
+        self.bell = true;
+        self.dirty = true;
+    }
+
+    pub fn take_bell(&mut self) -> bool {
+        std::mem::take(&mut self.bell)
+    }
+
+    // Pops up a desktop notification (OSC 9) with the next redraw
+    pub fn notify(&mut self, text: String) {
+        self.notification = Some(text);
+        self.dirty = true;
+    }
+
+    // The escape sequence for the notification waiting to go out, if there is one
+    pub fn take_notification(&mut self) -> Option<String> {
+        let text = self.notification.take()?;
+        // Anything that could end the sequence early is left out
+        let text: String = text.chars().filter(|c| !c.is_control()).collect();
+        Some(format!("\x1b]9;{}\x07", text))
+    }
+
+    // Hides the notice only if it still says this, and not something newer
+    pub fn hide_notice_if(&mut self, text: &str) {
+        if self.notice.as_deref() == Some(text) {
+            self.hide_notice();
+        }
+    }
+
+    pub fn notice(&self) -> Option<&str> {
+        self.notice.as_deref()
+    }
+
+    // Whether the overlay changed since the last call
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    // Escape sequences drawing the overlay over a video of the given size in cells
+    pub fn render(&self, cols: usize, rows: usize) -> String {
+        let mut output = String::new();
+        if let Some(text) = &self.notice {
+            draw_notice(&mut output, text, cols);
+        }
+        if let Some(lines) = &self.panel {
+            draw_panel(&mut output, lines, cols, rows);
+        } else if let Some(deaths) = self.death {
+            let lines = [
+                "You died!".to_owned(),
+                "Press R to respawn".to_owned(),
+                format!("Deaths this session: {}", deaths),
+            ];
+            draw_panel(&mut output, &lines, cols, rows);
+        }
+        if let Some(text) = &self.chat {
+            draw_chat(&mut output, text, cols, rows);
+        }
+        output
+    }
+}
+
+fn draw_panel(output: &mut String, lines: &[String], cols: usize, rows: usize) {
+    let inner_width = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0)
+        .min(cols.saturating_sub(4));
+    let box_width = inner_width + 4;
+    let box_height = lines.len().min(rows.saturating_sub(2)) + 2;
+    let left = cols.saturating_sub(box_width) / 2 + 1;
+    let top = rows.saturating_sub(box_height) / 2 + 1;
+
+    // Plain 16 color attributes so the panel works in every render mode
+    output.push_str("\x1b[0;97;40m");
+    output.push_str(&format!("\x1b[{};{}H┌{}┐", top, left, "─".repeat(box_width - 2)));
+    for (i, line) in lines.iter().take(box_height - 2).enumerate() {
+        let text: String = line.chars().take(inner_width).collect();
+        let padding = inner_width - text.chars().count();
+        output.push_str(&format!(
+            "\x1b[{};{}H│ {}{} │",
+            top + 1 + i,
+            left,
+            text,
+            " ".repeat(padding)
+        ));
+    }
+    output.push_str(&format!(
+        "\x1b[{};{}H└{}┘",
+        top + box_height - 1,
+        left,
+        "─".repeat(box_width - 2)
+    ));
+    output.push_str("\x1b[m");
+}
+
+fn draw_chat(output: &mut String, text: &str, cols: usize, rows: usize) {
+    let text: String = text.chars().filter(|c| !c.is_control()).take(cols).collect();
+    let padding = cols - text.chars().count();
+    output.push_str(&format!("\x1b[{};1H\x1b[0;97;40m{}{}\x1b[m", rows + 1, text, " ".repeat(padding)));
+}
+
+fn draw_notice(output: &mut String, text: &str, cols: usize) {
+    let text: String = text.chars().take(cols).collect();
+    let padding = cols - text.chars().count();
+    output.push_str(&format!(
+        "\x1b[1;1H\x1b[0;97;41m{}{}{}\x1b[m",
+        " ".repeat(padding / 2),
+        text,
+        " ".repeat(padding - padding / 2)
+    ));
+}
@@ -0,0 +1,83 @@
+// Hooks into sessions, for projects using this as a library to add to what a
+// session does without patching it: logging somewhere of their own, drawing
+// overlays over the game, keeping an eye out for cheating.  Plugins are registered
+// before `run`, and every plugin's hook is called, in the order they were
+// registered, on whichever task the event happened on, so hooks shouldn't block.
+
+use std::net::IpAddr;
+use std::sync::{Arc, LazyLock, PoisonError, RwLock};
+
+use crate::xdo::ClientInput;
+
+// Who a hook's about
+#[derive(Clone, Debug, Default)]
+pub struct SessionInfo {
+    // Made from the player's key, or the name played as locally
+    pub username: String,
+    // The player's key's SHA256 fingerprint, empty playing locally
+    pub fingerprint: String,
+    pub source_ip: Option<IpAddr>,
+    // The display the game's on, once there is one
+    pub display: Option<String>,
+}
+
+// Everything's optional, so a plugin only has the hooks it needs
+pub trait Plugin: Send + Sync {
+    // A player's connected over SSH, and their key's been accepted
+    fn on_connect(&self, _session: &SessionInfo) {}
+
+    // The player's game is starting, over SSH or locally
+    fn on_session_start(&self, _session: &SessionInfo) {}
+
+    // A frame's about to be sent, with the overlay already drawn over it.  Anything
+    // added goes out with it, e.g. escape codes drawing something of the plugin's.
+    fn on_frame(&self, _session: &SessionInfo, _frame: &mut Vec<u8>) {}
+
+    // Input from the player on its way to the game, which doesn't get there (or do
+    // anything else) if any plugin returns false
+    fn on_input(&self, _session: &SessionInfo, _input: &ClientInput) -> bool {
+        true
+    }
+
+    // The player's disconnected from SSH, whether or not their game carries on
+    fn on_disconnect(&self, _session: &SessionInfo) {}
+}
+
+static PLUGINS: LazyLock<RwLock<Vec<Arc<dyn Plugin>>>> = LazyLock::new(Default::default);
+
+// Adds a plugin for every session from now on
+pub fn register(plugin: impl Plugin + 'static) {
+    PLUGINS.write().unwrap_or_else(PoisonError::into_inner).push(Arc::new(plugin));
+}
+
+// The plugins as they are now, so none are called with the lock held
+fn plugins() -> Vec<Arc<dyn Plugin>> {
+    PLUGINS.read().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+#[cfg(feature = "server")]
+pub(crate) fn connect(session: &SessionInfo) {
+    plugins().iter().for_each(|plugin| plugin.on_connect(session));
+}
+
+pub(crate) fn session_start(session: &SessionInfo) {
+    plugins().iter().for_each(|plugin| plugin.on_session_start(session));
+}
+
+pub(crate) fn frame(session: &SessionInfo, frame: &mut Vec<u8>) {
+    plugins().iter().for_each(|plugin| plugin.on_frame(session, frame));
+}
+
+// Whether the input should go on to the game.  Every plugin sees it either way.
+pub(crate) fn input(session: &SessionInfo, input: &ClientInput) -> bool {
+    let mut allowed = true;
+    for plugin in plugins() {
+        allowed &= plugin.on_input(session, input);
+    }
+    allowed
+}
+
+#[cfg(feature = "server")]
+pub(crate) fn disconnect(session: &SessionInfo) {
+    plugins().iter().for_each(|plugin| plugin.on_disconnect(session));
+}
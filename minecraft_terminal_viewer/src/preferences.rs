@@ -0,0 +1,106 @@
+// Per-player input preferences, stored as small key=value files named after the
+// player's key fingerprint so they survive between sessions.
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use tracing::{error, warn};
+
+use crate::config::InputSettings;
+
+const PREFERENCES_DIR: &str = "input_preferences";
+
+fn preferences_path(username: &str) -> Option<PathBuf> {
+    // Usernames are hex digests, but never let one escape the directory
+    if username.is_empty() || !username.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    Some(PathBuf::from(PREFERENCES_DIR).join(username))
+}
+
+// Loads a player's preferences on top of the server defaults
+pub fn load(username: &str, defaults: &InputSettings) -> InputSettings {
+    let mut settings = defaults.clone();
+    let Some(path) = preferences_path(username) else {
+        return settings;
+    };
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return settings,
+        Err(e) => {
+            error!("Error reading preferences for {}: {}", username, e);
+            return settings;
+        }
+    };
+
+    for line in contents.lines() {
+        let applied = line.split_once('=').is_none_or(|(key, value)| apply(&mut settings, key, value));
+        if !applied {
+            warn!("Ignoring preference line for {}: {:?}", username, line);
+        }
+    }
+    settings
+}
+
+// Sets the setting a key=value line is for, returning whether there was one
+pub(crate) fn apply(settings: &mut InputSettings, key: &str, value: &str) -> bool {
+    let value = value.trim();
+    match key.trim() {
+        "look_dead_zone" => value.parse().map(|v| settings.look_dead_zone = v).is_ok(),
+        "look_scale_x" => value.parse().map(|v| settings.look_scale_x = v).is_ok(),
+        "look_scale_y" => value.parse().map(|v| settings.look_scale_y = v).is_ok(),
+        "gamepad_look_speed" => value.parse().map(|v| settings.gamepad_look_speed = v).is_ok(),
+        "fps_down_key" => value.parse().map(|v| settings.fps_down_key = v).is_ok(),
+        "fps_up_key" => value.parse().map(|v| settings.fps_up_key = v).is_ok(),
+        "jump_latch" => value.parse().map(|v| settings.jump_latch = v).is_ok(),
+        "mining_latch_key" => value.parse().map(|v| settings.mining_latch_key = v).is_ok(),
+        "keymap" => value.parse().map(|v| settings.keymap = v).is_ok(),
+        "absolute_mouse" => value
+            .parse()
+            .map(|v| settings.absolute_mouse = Some(v))
+            .is_ok(),
+        _ => false,
+    }
+}
+
+// The settings as key=value lines, for `apply` to read back
+pub(crate) fn lines(settings: &InputSettings) -> String {
+    let mut contents = format!(
+        "look_dead_zone={}\nlook_scale_x={}\nlook_scale_y={}\ngamepad_look_speed={}\nfps_down_key={}\nfps_up_key={}\njump_latch={}\nmining_latch_key={}\nkeymap={}\n",
+        settings.look_dead_zone,
+        settings.look_scale_x,
+        settings.look_scale_y,
+        settings.gamepad_look_speed,
+        settings.fps_down_key,
+        settings.fps_up_key,
+        settings.jump_latch,
+        settings.mining_latch_key,
+        settings.keymap
+    );
+    if let Some(absolute_mouse) = settings.absolute_mouse {
+        contents.push_str(&format!("absolute_mouse={}\n", absolute_mouse));
+    }
+    contents
+}
+
+// Saves what the player changed from the server defaults, and only that, so the
+// rest follows the defaults if the operator changes them later
+pub fn save(username: &str, settings: &InputSettings, defaults: &InputSettings) -> io::Result<()> {
+    let Some(path) = preferences_path(username) else {
+        return Ok(());
+    };
+    let defaults = lines(defaults);
+    let changed: String = lines(settings)
+        .lines()
+        .filter(|line| !defaults.lines().any(|default| default == *line))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    if changed.is_empty() {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    fs::create_dir_all(PREFERENCES_DIR)?;
+    fs::write(path, changed)
+}
@@ -0,0 +1,103 @@
+// Games launched through PrismLauncher (or MultiMC, which has the same command
+// line), so operators can set up modpacks in it and offer them to players.  With
+// ANSICRAFT_PRISM set to the launcher's binary, its instances are the versions
+// players pick from in the lobby, unless ANSICRAFT_VERSIONS lists just some.
+// ANSICRAFT_PRISM_DIR is its data directory, where instances are looked for, and
+// ANSICRAFT_PRISM_ARGS and friends change what it's run with, in the same way as
+// ANSICRAFT_LAUNCHER_ARGS.  Instances keep their own game directory, Java
+// arguments and accounts, so players' own directories and logins aren't used.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::config::AppConfig;
+use crate::launcher::{LaunchError, LaunchOptions};
+
+fn data_dir(settings: &AppConfig) -> PathBuf {
+    if let Some(dir) = &settings.prism.dir {
+        return dir.clone();
+    }
+    let home = std::env::var("HOME").unwrap_or_else(|_| "/root".to_owned());
+    PathBuf::from(home).join(".local/share/PrismLauncher")
+}
+
+// A KEY=VALUE setting from one of the launcher's .cfg files
+fn setting(path: PathBuf, key: &str) -> Option<String> {
+    let contents = fs::read_to_string(path).ok()?;
+    contents.lines().find_map(|line| {
+        let (name, value) = line.split_once('=')?;
+        (name.trim() == key).then(|| value.trim().to_owned())
+    })
+}
+
+// Where its instances are, which can be moved in its settings
+fn instances_dir(settings: &AppConfig) -> PathBuf {
+    let dir = data_dir(settings);
+    let configured = ["prismlauncher.cfg", "multimc.cfg"]
+        .into_iter()
+        .find_map(|cfg| setting(dir.join(cfg), "InstanceDir"))
+        .filter(|instances| !instances.is_empty());
+    // Relative to the data directory, unless it's absolute
+    dir.join(configured.unwrap_or_else(|| "instances".to_owned()))
+}
+
+// The IDs (directory names) of its instances, sorted
+pub fn instances(settings: &AppConfig) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(instances_dir(settings)) else {
+        return vec![];
+    };
+    let mut instances: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().join("instance.cfg").is_file())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        // Its own folders (like _MMC_TEMP) and hidden ones
+        .filter(|name| !name.starts_with(['.', '_']))
+        .collect();
+    instances.sort();
+    instances
+}
+
+// The name the instance is given in the launcher, for the lobby's menu
+#[cfg(feature = "server")]
+pub fn describe(instance: &str, settings: &AppConfig) -> String {
+    setting(instances_dir(settings).join(instance).join("instance.cfg"), "name")
+        .filter(|name| name != instance)
+        .unwrap_or_default()
+}
+
+// Launches the instance (the version picked) straight into the game, offline as the player
+pub fn command(options: &LaunchOptions) -> Result<Command, LaunchError> {
+    let settings = options.settings;
+    let instance = if options.version.is_empty() {
+        instances(settings).into_iter().next().unwrap_or_default()
+    } else {
+        options.version.to_owned()
+    };
+    // Never let a name escape the instances directory
+    if instance.is_empty() || instance.contains(['/', '\\']) || !instances_dir(settings).join(&instance).is_dir() {
+        return Err(LaunchError::UnknownInstance(instance));
+    }
+    let fill_in = |template: &str| -> Vec<String> {
+        template
+            .split_whitespace()
+            .map(|arg| {
+                arg.replace("{instance}", &instance)
+                    .replace("{username}", options.username)
+                    .replace("{server}", options.server)
+                    .replace("{world}", options.world.unwrap_or_default())
+            })
+            .collect()
+    };
+
+    let mut cmd = Command::new(&settings.prism.binary);
+    cmd.arg("--dir").arg(data_dir(settings)).env("DISPLAY", options.display);
+    cmd.args(fill_in(&settings.prism.args));
+    if !options.server.is_empty() {
+        cmd.args(fill_in(&settings.prism.server_args));
+    }
+    if options.world.is_some() {
+        cmd.args(fill_in(&settings.prism.world_args));
+    }
+    Ok(cmd)
+}
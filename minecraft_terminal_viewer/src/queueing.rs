@@ -1,178 +1,1071 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::path::PathBuf;
 use std::sync::{
-    Arc,
+    Arc, Mutex, PoisonError,
     atomic::{AtomicUsize, Ordering},
 };
 use tokio::sync::{mpsc, oneshot};
 
+use crate::metrics;
+use crate::webhooks::{self, QueueEvent};
+use std::time::SystemTime;
+use tokio::time::{Duration, Instant};
+use tracing::{info, warn};
+
+// How often resources that failed a health check get another look
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+// Waiters hear about a new place in line at most this often
+const POSITION_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+// Higher priorities are served first, FIFO among equals
+pub const PRIORITY_NORMAL: u8 = 0;
+// For players who settle for a shorter session in return
+pub const PRIORITY_SHORT: u8 = 1;
+pub const PRIORITY_VIP: u8 = 2;
+pub const PRIORITY_RESERVED: u8 = u8::MAX;
+
+// Why a request was turned away
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PoolError {
+    #[error("The server is going down for maintenance, please try again in a few minutes")]
+    Draining,
+    #[error("There's no server called \"{0}\"")]
+    UnknownPool(String),
+    #[error("You already have a session running")]
+    AlreadyPlaying,
+    #[error("That reservation code isn't valid right now")]
+    InvalidReservation,
+    #[error("The server is full and so is the queue, please try again later")]
+    QueueFull,
+    // The same owner asked again, from somewhere else
+    #[error("Replaced by a newer connection")]
+    Replaced,
+}
+
 #[derive(Debug, Clone)]
-pub enum ResourceStatus {
-    Success(u32),
-    Failed(String),
+pub enum ResourceStatus<T> {
+    Success(T),
+    Failed(PoolError),
     QueuePosition(usize),
     Cancelled,
+    TimedOut,
+}
+
+// Everything a session needs to know about the resource it was given
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceDescriptor {
+    pub id: u32,
+    // Which pool (game mode) this belongs to
+    pub pool: String,
+    pub display: String,
+    // Ports set aside for whatever runs on this resource
+    pub ports: Vec<u16>,
+    pub gpu: bool,
+    pub resolution: (u16, u16),
+    // How many sessions it counts as against the capacity
+    pub weight: usize,
+    pub provision: Provision,
+    // Where the game keeps its files, or None for the launcher's default
+    pub data_dir: Option<PathBuf>,
+}
+
+// What starts the X server a resource's game runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provision {
+    // It's already running, e.g. from entry-point.sh
+    Running,
+    // The session starts Xvfb itself
+    SpawnX,
+    // The session's container starts it, along with the game
+    Docker,
+    // Likewise for a pod, with the display forwarded here
+    Kubernetes,
+    // A slot on a worker, which plays the game on a display of its own.  The
+    // resource's display is the worker's address.
+    Worker,
+}
+
+// Something the pool hands out.  These are called from the queue manager, so they
+// should be quick.
+pub trait Resource: std::fmt::Debug + Clone + Send + 'static {
+    fn id(&self) -> u32;
+
+    // Requests are only given resources from the pool they asked for
+    fn pool(&self) -> &str {
+        ""
+    }
+
+    // Gets the resource ready to be handed out.  A resource that fails is set
+    // aside like an unhealthy one.
+    fn acquire(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    // Cleans up after the resource comes back
+    fn release(&self) {}
+
+    fn healthy(&self) -> bool {
+        true
+    }
+
+    // Resources that can be used straight away are handed out first
+    fn warm(&self) -> bool {
+        false
+    }
+
+    // Whether the resource has something a requester asked for, like "gpu"
+    fn has_tag(&self, _tag: &str) -> bool {
+        false
+    }
+
+    // How many sessions using this counts as against the capacity.  Heavier ones
+    // are kept back for requesters who ask for what they have.
+    fn weight(&self) -> usize {
+        1
+    }
+}
+
+impl Resource for ResourceDescriptor {
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn pool(&self) -> &str {
+        &self.pool
+    }
+
+    fn acquire(&self) -> Result<(), String> {
+        crate::warm::mark_busy(&self.display);
+        Ok(())
+    }
+
+    fn release(&self) {
+        crate::warm::mark_idle(&self.display);
+    }
+
+    fn warm(&self) -> bool {
+        crate::warm::is_warm(&self.display)
+    }
+
+    // "gpu", or a resolution like "854x480" that the screen is at least as big as
+    fn has_tag(&self, tag: &str) -> bool {
+        if tag == "gpu" {
+            return self.gpu;
+        }
+        match tag.split_once('x').map(|(width, height)| (width.parse::<u16>(), height.parse::<u16>())) {
+            Some((Ok(width), Ok(height))) => self.resolution.0 >= width && self.resolution.1 >= height,
+            _ => false,
+        }
+    }
+
+    fn weight(&self) -> usize {
+        self.weight
+    }
+
+    // The X server is up if its socket is there, and a worker if it's answering
+    fn healthy(&self) -> bool {
+        #[cfg(feature = "server")]
+        if self.provision == Provision::Worker {
+            return crate::cluster::reachable(&self.display);
+        }
+        if self.provision != Provision::Running {
+            return true;
+        }
+        let display_number = self.display.trim_start_matches(':').split('.').next().unwrap_or("");
+        std::path::Path::new(&format!("/tmp/.X11-unix/X{}", display_number)).exists()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PoolOptions {
+    // Requests that would wait behind this many others get turned away instead
+    pub max_queue_length: Option<usize>,
+    // How long a request may wait before it's given up on
+    pub queue_timeout: Option<Duration>,
+    pub reservations: Vec<Reservation>,
+    // Take turns between source addresses instead of strict FIFO, so one busy
+    // address can't fill the queue ahead of everyone else
+    pub fair_by_source: bool,
+    // How long a player who dropped out of a game has to reconnect and get their
+    // slot back
+    pub reconnect_grace: Option<Duration>,
+    // How many sessions can run at once at different times of day.  Empty means
+    // as many as there are resources.
+    pub capacity_schedule: Vec<CapacityWindow>,
+    // Where to tell about people joining and leaving the queue
+    pub webhooks: Vec<String>,
+}
+
+impl PoolOptions {
+    // The queue's settings from the server's config.  Reservations and the capacity
+    // schedule are left for whoever sets the pool up to read and parse.
+    pub fn from_config(config: &crate::config::AppConfig) -> Self {
+        Self {
+            max_queue_length: config.max_queue_length,
+            queue_timeout: config.queue_timeout,
+            fair_by_source: config.fair_queue,
+            reconnect_grace: config.reconnect_grace,
+            webhooks: config.webhooks.clone(),
+            ..Default::default()
+        }
+    }
 }
 
-pub struct ResourcePool {
-    request_tx: mpsc::UnboundedSender<ResourceRequest>,
-    release_tx: mpsc::UnboundedSender<u32>,
+// From `start` (minutes past midnight UTC) until the next window starts, at most
+// `capacity` sessions run at once, with heavier ones (like on a GPU) counting as more
+#[derive(Debug, Clone)]
+pub struct CapacityWindow {
+    pub start: u32,
+    pub capacity: usize,
+}
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+// The capacity in effect at a given time, and how long until it changes
+fn scheduled_capacity(schedule: &[CapacityWindow], time: SystemTime) -> Option<(usize, Duration)> {
+    let since_midnight = time.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs() % (MINUTES_PER_DAY as u64 * 60);
+    let minute = (since_midnight / 60) as u32;
+
+    // The latest window to have started, which might have been yesterday
+    let current = schedule
+        .iter()
+        .filter(|window| window.start <= minute)
+        .max_by_key(|window| window.start)
+        .or_else(|| schedule.iter().max_by_key(|window| window.start))?;
+    let next_start = schedule
+        .iter()
+        .map(|window| window.start)
+        .filter(|start| *start > minute)
+        .min()
+        .or_else(|| schedule.iter().map(|window| window.start + MINUTES_PER_DAY).min())?;
+    let until_next = Duration::from_secs(next_start as u64 * 60 - since_midnight);
+    Some((current.capacity, until_next))
+}
+
+// A code that lets someone skip the queue.  Codes with a window also keep a
+// resource free for the whole window, from whichever pool has one going.  Either
+// kind can only be used once.
+#[derive(Debug, Clone)]
+pub struct Reservation {
+    pub code: String,
+    pub window: Option<(SystemTime, SystemTime)>,
+}
+
+impl Reservation {
+    fn valid_at(&self, time: SystemTime) -> bool {
+        self.window.is_none_or(|(start, end)| start <= time && time < end)
+    }
+}
+
+// Who is asking for a resource
+#[derive(Debug, Clone)]
+pub struct Requester {
+    pub owner: String,
+    pub pool: String,
+    // Tags the requester would like their resource to have, if one's free
+    pub wants: Vec<String>,
+    pub priority: u8,
+    pub reservation: Option<String>,
+    pub source: Option<IpAddr>,
+    // Shown to operators, since owner names are opaque
+    pub fingerprint: String,
+}
+
+// How busy one pool is
+#[derive(Debug, Clone)]
+pub struct PoolStatus {
+    pub name: String,
+    pub capacity: usize,
+    pub available: usize,
+    pub waiting: usize,
+}
+
+// What the queue looks like at one point in time, for operators
+#[derive(Debug, Clone)]
+pub struct QueueSnapshot {
+    pub draining: bool,
+    pub pools: Vec<PoolStatus>,
+    pub available: Vec<u32>,
+    pub reserved: Vec<(String, u32)>,
+    // Owners who disconnected mid-game, and the resource kept for them
+    pub held_for_reconnect: Vec<(String, u32)>,
+    // The scheduled limit on sessions, if there is one
+    pub capacity: Option<usize>,
+    pub unhealthy: Vec<u32>,
+    pub allocated: Vec<(u32, Requester, Duration)>,
+    // Requests in queue order, with how long they've waited
+    pub pending: Vec<(usize, Requester, Duration)>,
+}
+
+// Requests for the queue manager other than getting and releasing resources
+enum PoolCommand<T> {
+    Cancel(usize),
+    Snapshot(oneshot::Sender<QueueSnapshot>),
+    Drain(bool),
+    // New options from a config reload.  Reservations are left as they are, since
+    // the ones already used would be back otherwise.
+    Reconfigure(PoolOptions),
+    // Resources added or taken away while it's running, e.g. by the autoscaler
+    Grow(Vec<T>),
+    Shrink(Vec<u32>, oneshot::Sender<bool>),
+}
+
+pub struct ResourcePool<T: Resource> {
+    request_tx: mpsc::UnboundedSender<ResourceRequest<T>>,
+    release_tx: mpsc::UnboundedSender<Release>,
+    command_tx: mpsc::UnboundedSender<PoolCommand<T>>,
     next_id: Arc<AtomicUsize>,
+    queue_lengths: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+// A resource coming back, and whether that's because its user dropped out
+struct Release {
+    id: u32,
+    disconnected: bool,
 }
 
-impl ResourcePool {
-    pub fn new(resource_count: u32) -> Self {
-        let (request_tx, request_rx) = mpsc::unbounded_channel::<ResourceRequest>();
-        let (release_tx, release_rx) = mpsc::unbounded_channel::<u32>();
-        let available_resources = VecDeque::from((0..resource_count).collect::<Vec<_>>());
-        let pending_requests = VecDeque::new();
+impl<T: Resource> ResourcePool<T> {
+    pub fn new(resources: Vec<T>, options: PoolOptions) -> Self {
+        let (request_tx, request_rx) = mpsc::unbounded_channel::<ResourceRequest<T>>();
+        let (release_tx, release_rx) = mpsc::unbounded_channel::<Release>();
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
         let next_id = Arc::new(AtomicUsize::new(0));
+        let queue_lengths = Arc::new(Mutex::new(HashMap::new()));
 
-        println!("Resource pool created with {} resources", resource_count);
+        // Pools in the order they were given, with their sizes
+        let mut pools: Vec<(String, usize)> = vec![];
+        for resource in &resources {
+            match pools.iter_mut().find(|(name, _)| name == resource.pool()) {
+                Some((_, size)) => *size += 1,
+                None => pools.push((resource.pool().to_owned(), 1)),
+            }
+        }
+        for (name, size) in &pools {
+            info!("Resource pool \"{}\" created with {} resources", name, size);
+        }
+
+        let state = QueueState {
+            pools,
+            available_resources: VecDeque::from(resources),
+            pending_requests: VecDeque::new(),
+            allocations: HashMap::new(),
+            unhealthy_resources: vec![],
+            reservations: options.reservations.clone(),
+            held_for_reservations: HashMap::new(),
+            held_for_reconnect: HashMap::new(),
+            capacity_schedule: options.capacity_schedule.clone(),
+            capacity: None,
+            webhooks: options.webhooks.clone(),
+            draining: false,
+        };
 
         tokio::spawn(Self::resource_queue_manager(
-            available_resources,
-            pending_requests,
+            options,
+            state,
             request_rx,
             release_rx,
+            command_rx,
+            queue_lengths.clone(),
         ));
 
         Self {
             request_tx,
             release_tx,
+            command_tx,
             next_id,
+            queue_lengths,
         }
     }
 
     async fn resource_queue_manager(
-        mut available_resources: VecDeque<u32>,
-        mut pending_requests: VecDeque<ResourceRequest>,
-        mut request_rx: mpsc::UnboundedReceiver<ResourceRequest>,
-        mut release_rx: mpsc::UnboundedReceiver<u32>,
+        mut options: PoolOptions,
+        mut state: QueueState<T>,
+        mut request_rx: mpsc::UnboundedReceiver<ResourceRequest<T>>,
+        mut release_rx: mpsc::UnboundedReceiver<Release>,
+        mut command_rx: mpsc::UnboundedReceiver<PoolCommand<T>>,
+        queue_lengths: Arc<Mutex<HashMap<String, usize>>>,
     ) {
+        let mut health_check = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+        // When a position update that was held back can go out
+        let mut next_notification: Option<Instant> = None;
+
         loop {
-            println!("Resource queue manager loop");
-            println!("Available resources: {:?}", available_resources);
-            println!("Pending requests: {:?}", pending_requests);
+            let next_timeout = options.queue_timeout.and_then(|timeout| {
+                state.pending_requests.iter().map(|req| req.created + timeout).min()
+            });
+            let next_wake = next_timeout
+                .into_iter()
+                .chain(state.next_reservation_change())
+                .chain(next_notification)
+                .chain(state.held_for_reconnect.values().map(|(_, until)| *until))
+                .chain(state.next_capacity_change())
+                .min();
+            // Biased so a release and then a request from the same player (rejoining
+            // the queue) are seen in that order
             tokio::select! {
-                Some(mut req) = request_rx.recv() => {
-                    if let Some(res_id) = available_resources.pop_front() {
-                        if req.cancel.try_recv().is_err() {
-                            let _ = req.status.send(ResourceStatus::Success(res_id));
-                        } else {
-                            available_resources.push_back(res_id);
-                            let _ = req.status.send(ResourceStatus::Cancelled);
+                biased;
+
+                Some(release) = release_rx.recv() => {
+                    if let Some(allocation) = state.allocations.remove(&release.id) {
+                        allocation.resource.release();
+                        match options.reconnect_grace {
+                            Some(grace) if release.disconnected && !state.draining => {
+                                info!("Holding resource {} for {} to reconnect", release.id, allocation.requester.owner);
+                                state
+                                    .held_for_reconnect
+                                    .insert(allocation.requester.owner, (allocation.resource, Instant::now() + grace));
+                            }
+                            _ => state.available_resources.push_back(allocation.resource),
                         }
-                    } else {
-                        pending_requests.push_back(req);
                     }
-                },
+                    if state.draining && state.allocations.is_empty() {
+                        info!("Pool drained, no sessions are running");
+                    }
+                }
 
-                Some(res_id) = release_rx.recv() => {
-                    let mut allocated = false;
-                    while let Some(mut req) = pending_requests.pop_front() {
-                        if req.cancel.try_recv().is_ok() {
+                Some(command) = command_rx.recv() => match command {
+                    PoolCommand::Cancel(req_id) => {
+                        // Requests that were already granted are released the normal way
+                        let index = state.pending_requests.iter().position(|req| req.id == req_id);
+                        if let Some(req) = index.and_then(|index| state.pending_requests.remove(index)) {
+                            metrics::increment("ansicraft_queue_cancellations_total");
+                            webhooks::publish(&state.webhooks, QueueEvent::Abandoned {
+                                pool: &req.requester.pool,
+                                waited: req.created.elapsed(),
+                                timed_out: false,
+                            });
                             let _ = req.status.send(ResourceStatus::Cancelled);
-                            continue;
                         }
-                        let _ = req.status.send(ResourceStatus::Success(res_id));
-                        allocated = true; // I hate this but I'm tired and I can fix it later.
-                        break;
                     }
-                    if !allocated {
-                        available_resources.push_back(res_id);
+                    PoolCommand::Snapshot(reply) => {
+                        let _ = reply.send(state.snapshot());
+                        continue;
+                    }
+                    PoolCommand::Drain(draining) => {
+                        info!(
+                            "{} ({} sessions running)",
+                            if draining { "Draining pool" } else { "No longer draining pool" },
+                            state.allocations.len()
+                        );
+                        state.draining = draining;
+                    }
+                    PoolCommand::Reconfigure(new_options) => {
+                        info!("Queue options changed");
+                        state.capacity_schedule = new_options.capacity_schedule.clone();
+                        state.webhooks = new_options.webhooks.clone();
+                        options = PoolOptions { reservations: options.reservations, ..new_options };
+                    }
+                    PoolCommand::Grow(resources) => state.grow(resources),
+                    PoolCommand::Shrink(ids, reply) => {
+                        let _ = reply.send(state.shrink(&ids));
+                    }
+                },
+
+                Some(req) = request_rx.recv() => {
+                    metrics::increment("ansicraft_queue_requests_total");
+                    state.handle_request(req, &options);
+                }
+
+                _ = health_check.tick() => {
+                    if !state.recheck_unhealthy() {
+                        continue;
+                    }
+                }
+
+                _ = tokio::time::sleep_until(next_wake.unwrap_or_else(Instant::now)), if next_wake.is_some() => {
+                    if let Some(timeout) = options.queue_timeout {
+                        let now = Instant::now();
+                        state.pending_requests.retain(|req| {
+                            let expired = req.created + timeout <= now;
+                            if expired {
+                                metrics::increment("ansicraft_queue_timeouts_total");
+                                webhooks::publish(&state.webhooks, QueueEvent::Abandoned {
+                                    pool: &req.requester.pool,
+                                    waited: req.created.elapsed(),
+                                    timed_out: true,
+                                });
+                                let _ = req.status.send(ResourceStatus::TimedOut);
+                            }
+                            !expired
+                        });
                     }
                 }
             }
 
-            for (i, req) in pending_requests.iter().enumerate() {
-                let _ = req.status.send(ResourceStatus::QueuePosition(i));
+            state.dispatch();
+            state.record_metrics();
+
+            // Positions are counted within each pool.  Only changes are sent, and not
+            // too often, so a busy queue doesn't flood everyone in it.
+            let now = Instant::now();
+            next_notification = None;
+            let mut positions: HashMap<String, usize> = HashMap::new();
+            for req in state.pending_requests.iter_mut() {
+                let position = positions.entry(req.requester.pool.clone()).or_default();
+                match req.notified {
+                    Some((notified, _)) if notified == *position => {}
+                    Some((_, at)) if now < at + POSITION_UPDATE_INTERVAL => {
+                        let due = at + POSITION_UPDATE_INTERVAL;
+                        next_notification = Some(next_notification.map_or(due, |next| next.min(due)));
+                    }
+                    _ => {
+                        let _ = req.status.send(ResourceStatus::QueuePosition(*position));
+                        req.notified = Some((*position, now));
+                    }
+                }
+                *position += 1;
+            }
+            // Only counts, so a panic elsewhere while they were held can't leave them half-written
+            *queue_lengths.lock().unwrap_or_else(PoisonError::into_inner) = positions;
+        }
+    }
+}
+
+// Everything the queue manager keeps track of
+struct QueueState<T> {
+    // Every pool's name and how many resources it has
+    pools: Vec<(String, usize)>,
+    available_resources: VecDeque<T>,
+    pending_requests: VecDeque<ResourceRequest<T>>,
+    // Granted resources by id
+    allocations: HashMap<u32, Allocation<T>>,
+    // Set aside until they pass a health check again
+    unhealthy_resources: Vec<T>,
+    reservations: Vec<Reservation>,
+    held_for_reservations: HashMap<String, T>,
+    // Kept for players who disconnected mid-game until they come back or time runs out
+    held_for_reconnect: HashMap<String, (T, Instant)>,
+    capacity_schedule: Vec<CapacityWindow>,
+    // Sessions over this carry on, but no new ones start until enough have ended
+    capacity: Option<usize>,
+    webhooks: Vec<String>,
+    // Running sessions carry on, but nobody new gets a resource
+    draining: bool,
+}
+
+struct Allocation<T> {
+    resource: T,
+    requester: Requester,
+    since: Instant,
+}
+
+impl<T: Resource> QueueState<T> {
+    fn handle_request(&mut self, mut req: ResourceRequest<T>, options: &PoolOptions) {
+        let owner = req.requester.owner.clone();
+        if self.draining {
+            metrics::increment("ansicraft_queue_rejections_total");
+            let _ = req.status.send(ResourceStatus::Failed(PoolError::Draining));
+            return;
+        }
+
+        if !self.pools.iter().any(|(name, _)| *name == req.requester.pool) {
+            metrics::increment("ansicraft_queue_rejections_total");
+            let _ = req.status.send(ResourceStatus::Failed(PoolError::UnknownPool(req.requester.pool.clone())));
+            return;
+        }
+
+        if self.allocations.values().any(|allocation| allocation.requester.owner == owner) {
+            metrics::increment("ansicraft_queue_rejections_total");
+            let _ = req.status.send(ResourceStatus::Failed(PoolError::AlreadyPlaying));
+            return;
+        }
+
+        if let Some((resource, _)) = self.held_for_reconnect.remove(&owner) {
+            if resource.pool() == req.requester.pool && resource.healthy() && resource.acquire().is_ok() {
+                info!("{} reconnected and gets resource {} back", owner, resource.id());
+                self.grant(req, resource);
+                return;
+            }
+            // They want a different pool, or their slot went bad, but they still skip the line
+            self.available_resources.push_back(resource);
+            req.requester.priority = PRIORITY_RESERVED;
+        }
+
+        if let Some(code) = req.requester.reservation.clone() {
+            match self.redeem(&code) {
+                Some(Some(resource)) => {
+                    self.grant(req, resource);
+                    return;
+                }
+                // Valid, but no slot is being held for it, so it goes to the front of the line
+                Some(None) => req.requester.priority = PRIORITY_RESERVED,
+                None => {
+                    metrics::increment("ansicraft_queue_rejections_total");
+                    let _ = req.status.send(ResourceStatus::Failed(PoolError::InvalidReservation));
+                    return;
+                }
+            }
+        }
+
+        // Reconnecting while queued takes over the old place in line
+        let queued = self.pending_requests.iter().position(|queued| queued.requester.owner == owner);
+        if let Some((index, existing)) = queued.and_then(|index| Some((index, self.pending_requests.remove(index)?))) {
+            let _ = existing.status.send(ResourceStatus::Failed(PoolError::Replaced));
+            req.created = existing.created;
+            if req.requester.priority == existing.requester.priority && req.requester.pool == existing.requester.pool {
+                self.pending_requests.insert(index, req);
+            } else {
+                self.enqueue(req, options.fair_by_source);
+            }
+            return;
+        }
+
+        let pool = &req.requester.pool;
+        let will_wait = self.at_capacity() || !self.available_resources.iter().any(|res| res.pool() == pool);
+        let waiting = self.pending_requests.iter().filter(|queued| queued.requester.pool == *pool).count();
+        if will_wait && options.max_queue_length.is_some_and(|max| waiting >= max) {
+            metrics::increment("ansicraft_queue_rejections_total");
+            let _ = req.status.send(ResourceStatus::Failed(PoolError::QueueFull));
+            return;
+        }
+
+        if will_wait {
+            webhooks::publish(&self.webhooks, QueueEvent::Joined {
+                pool,
+                waiting: waiting + 1,
+            });
+        }
+        self.enqueue(req, options.fair_by_source);
+    }
+
+    fn enqueue(&mut self, req: ResourceRequest<T>, fair_by_source: bool) {
+        let priority = req.requester.priority;
+        let source = req.requester.source;
+
+        // With fairness, each source gets one request per "round" within a priority,
+        // and the new request goes at the end of its round
+        let round = if fair_by_source {
+            self.pending_requests
+                .iter()
+                .filter(|queued| queued.requester.priority == priority && queued.requester.source == source)
+                .count()
+        } else {
+            0
+        };
+        let mut rounds: HashMap<Option<IpAddr>, usize> = HashMap::new();
+
+        // Queue behind everyone with a higher priority, and the same priority unless
+        // they're from a later round
+        let position = self
+            .pending_requests
+            .iter()
+            .position(|queued| {
+                if queued.requester.priority != priority {
+                    return queued.requester.priority < priority;
+                }
+                if !fair_by_source {
+                    return false;
+                }
+                let seen = rounds.entry(queued.requester.source).or_default();
+                let queued_round = *seen;
+                *seen += 1;
+                queued_round > round
+            })
+            .unwrap_or(self.pending_requests.len());
+        self.pending_requests.insert(position, req);
+    }
+
+    fn grant(&mut self, req: ResourceRequest<T>, resource: T) {
+        if req.status.send(ResourceStatus::Success(resource.clone())).is_err() {
+            // They hung up before it got to them, so it goes to the next person
+            info!("Requester for resource {} has gone, returning it", resource.id());
+            resource.release();
+            self.available_resources.push_front(resource);
+            return;
+        }
+        metrics::increment("ansicraft_queue_grants_total");
+        metrics::observe("ansicraft_queue_wait_seconds", req.created.elapsed().as_secs_f64());
+        webhooks::publish(&self.webhooks, QueueEvent::Granted {
+            pool: &req.requester.pool,
+            resource: resource.id(),
+            waited: req.created.elapsed(),
+        });
+        self.allocations.insert(
+            resource.id(),
+            Allocation {
+                resource,
+                requester: req.requester,
+                since: Instant::now(),
+            },
+        );
+    }
+
+    // Uses up a reservation code.  Returns None if the code isn't valid right now,
+    // otherwise the resource that was being held for it, if any.
+    fn redeem(&mut self, code: &str) -> Option<Option<T>> {
+        let now = SystemTime::now();
+        let index = self
+            .reservations
+            .iter()
+            .position(|reservation| reservation.code == code && reservation.valid_at(now))?;
+        self.reservations.remove(index);
+        Some(self.held_for_reservations.remove(code))
+    }
+
+    // When a reservation window next opens or closes
+    fn next_reservation_change(&self) -> Option<Instant> {
+        let now = SystemTime::now();
+        self.reservations
+            .iter()
+            .filter_map(|reservation| reservation.window)
+            .flat_map(|(start, end)| [start, end])
+            .filter_map(|time| time.duration_since(now).ok())
+            .min()
+            .map(|wait| Instant::now() + wait)
+    }
+
+    // Follows the capacity schedule
+    fn update_capacity(&mut self) {
+        let capacity = scheduled_capacity(&self.capacity_schedule, SystemTime::now()).map(|(capacity, _)| capacity);
+        if capacity != self.capacity {
+            info!("Capacity is now {:?}", capacity);
+            self.capacity = capacity;
+        }
+    }
+
+    fn next_capacity_change(&self) -> Option<Instant> {
+        scheduled_capacity(&self.capacity_schedule, SystemTime::now()).map(|(_, until_next)| Instant::now() + until_next)
+    }
+
+    fn at_capacity(&self) -> bool {
+        let used: usize = self
+            .allocations
+            .values()
+            .map(|allocation| allocation.resource.weight())
+            .chain(self.held_for_reconnect.values().map(|(resource, _)| resource.weight()))
+            .sum();
+        self.capacity.is_some_and(|capacity| used >= capacity)
+    }
+
+    fn record_metrics(&self) {
+        metrics::set_gauge("ansicraft_queue_depth", self.pending_requests.len() as f64);
+        metrics::set_gauge("ansicraft_pool_available", self.available_resources.len() as f64);
+        metrics::set_gauge("ansicraft_pool_in_use", self.allocations.len() as f64);
+        metrics::set_gauge("ansicraft_pool_reserved", self.held_for_reservations.len() as f64);
+        metrics::set_gauge("ansicraft_pool_held_for_reconnect", self.held_for_reconnect.len() as f64);
+        metrics::set_gauge("ansicraft_pool_unhealthy", self.unhealthy_resources.len() as f64);
+        metrics::set_gauge("ansicraft_pool_draining", if self.draining { 1.0 } else { 0.0 });
+        if let Some(capacity) = self.capacity {
+            metrics::set_gauge("ansicraft_pool_capacity", capacity as f64);
+        }
+    }
+
+    fn snapshot(&self) -> QueueSnapshot {
+        let mut allocated: Vec<_> = self
+            .allocations
+            .iter()
+            .map(|(res_id, allocation)| (*res_id, allocation.requester.clone(), allocation.since.elapsed()))
+            .collect();
+        allocated.sort_by_key(|(res_id, _, _)| *res_id);
+
+        let pools = self
+            .pools
+            .iter()
+            .map(|(name, capacity)| PoolStatus {
+                name: name.clone(),
+                capacity: *capacity,
+                available: self.available_resources.iter().filter(|res| res.pool() == name).count(),
+                waiting: self.pending_requests.iter().filter(|req| req.requester.pool == *name).count(),
+            })
+            .collect();
+
+        QueueSnapshot {
+            draining: self.draining,
+            pools,
+            available: self.available_resources.iter().map(|res| res.id()).collect(),
+            reserved: self
+                .held_for_reservations
+                .iter()
+                .map(|(code, res)| (code.clone(), res.id()))
+                .collect(),
+            held_for_reconnect: self
+                .held_for_reconnect
+                .iter()
+                .map(|(owner, (res, _))| (owner.clone(), res.id()))
+                .collect(),
+            capacity: self.capacity,
+            unhealthy: self.unhealthy_resources.iter().map(|res| res.id()).collect(),
+            allocated,
+            pending: self
+                .pending_requests
+                .iter()
+                .map(|req| (req.id, req.requester.clone(), req.created.elapsed()))
+                .collect(),
+        }
+    }
+
+    // Takes the next free resource that's healthy and ready to go, from the given
+    // pool or any of them.  Ones with the wanted tags come first, then light ones,
+    // then warm ones.
+    fn take_resource(&mut self, pool: Option<&str>, wants: &[String]) -> Option<T> {
+        loop {
+            let index = self
+                .available_resources
+                .iter()
+                .enumerate()
+                .filter(|(_, res)| pool.is_none_or(|pool| res.pool() == pool))
+                .max_by_key(|(index, res)| {
+                    (
+                        wants.iter().all(|tag| res.has_tag(tag)),
+                        std::cmp::Reverse(res.weight()),
+                        res.warm(),
+                        std::cmp::Reverse(*index),
+                    )
+                })
+                .map(|(index, _)| index)?;
+            let resource = self.available_resources.remove(index)?;
+            let ready = if resource.healthy() {
+                resource.acquire()
+            } else {
+                Err("failed its health check".to_owned())
+            };
+            match ready {
+                Ok(()) => return Some(resource),
+                Err(e) => {
+                    warn!("Setting resource {} aside: {}", resource.id(), e);
+                    self.unhealthy_resources.push(resource);
+                }
+            }
+        }
+    }
+
+    // Puts resources that have recovered back into service, returning whether any did
+    fn recheck_unhealthy(&mut self) -> bool {
+        let (recovered, still_unhealthy) = std::mem::take(&mut self.unhealthy_resources)
+            .into_iter()
+            .partition::<Vec<_>, _>(|resource| resource.healthy());
+        self.unhealthy_resources = still_unhealthy;
+        for resource in &recovered {
+            info!("Resource {} is healthy again", resource.id());
+        }
+        let any_recovered = !recovered.is_empty();
+        self.available_resources.extend(recovered);
+        any_recovered
+    }
+
+    fn grow(&mut self, resources: Vec<T>) {
+        for resource in resources {
+            match self.pools.iter_mut().find(|(name, _)| name == resource.pool()) {
+                Some((_, size)) => *size += 1,
+                None => self.pools.push((resource.pool().to_owned(), 1)),
+            }
+            info!("Resource {} added to pool \"{}\"", resource.id(), resource.pool());
+            self.available_resources.push_back(resource);
+        }
+    }
+
+    // Takes the resources out for good, but only if none of them are in use or held
+    // for anyone, returning whether they were.  Their pools stay, even if they're
+    // empty, so players can still queue for them.
+    fn shrink(&mut self, ids: &[u32]) -> bool {
+        let listed = |resource: &T| ids.contains(&resource.id());
+        let in_use = ids.iter().any(|id| self.allocations.contains_key(id))
+            || self.held_for_reservations.values().any(listed)
+            || self.held_for_reconnect.values().any(|(resource, _)| listed(resource));
+        if in_use {
+            return false;
+        }
+        let removed: Vec<T> = self
+            .available_resources
+            .iter()
+            .chain(&self.unhealthy_resources)
+            .filter(|resource| listed(resource))
+            .cloned()
+            .collect();
+        self.available_resources.retain(|resource| !listed(resource));
+        self.unhealthy_resources.retain(|resource| !listed(resource));
+        for resource in removed {
+            if let Some((_, size)) = self.pools.iter_mut().find(|(name, _)| name == resource.pool()) {
+                *size -= 1;
+            }
+            info!("Resource {} taken out of pool \"{}\"", resource.id(), resource.pool());
+        }
+        true
+    }
+
+    // Hands out free resources, reservations first
+    fn dispatch(&mut self) {
+        self.update_capacity();
+
+        // Players who didn't make it back in time lose their slot
+        let now = Instant::now();
+        let expired: Vec<String> = self
+            .held_for_reconnect
+            .iter()
+            .filter(|(_, (_, until))| *until <= now)
+            .map(|(owner, _)| owner.clone())
+            .collect();
+        for owner in expired {
+            if let Some((resource, _)) = self.held_for_reconnect.remove(&owner) {
+                self.available_resources.push_back(resource);
+            }
+        }
+
+        if self.draining {
+            return;
+        }
+        let now = SystemTime::now();
+
+        // Slots held for windows that have closed go back to everyone else
+        let held = &mut self.held_for_reservations;
+        let available = &mut self.available_resources;
+        self.reservations.retain(|reservation| {
+            let expired = reservation.window.is_some_and(|(_, end)| end <= now);
+            if expired && let Some(resource) = held.remove(&reservation.code) {
+                resource.release();
+                available.push_back(resource);
+            }
+            !expired
+        });
+
+        let opened: Vec<String> = self
+            .reservations
+            .iter()
+            .filter(|reservation| reservation.window.is_some_and(|(start, _)| start <= now))
+            .filter(|reservation| !self.held_for_reservations.contains_key(&reservation.code))
+            .map(|reservation| reservation.code.clone())
+            .collect();
+        for code in opened {
+            if let Some(resource) = self.take_resource(None, &[]) {
+                self.held_for_reservations.insert(code, resource);
+            }
+        }
+
+        // Someone waiting for a full pool doesn't hold up people behind them
+        // waiting for a different one
+        let mut index = 0;
+        while index < self.pending_requests.len() && !self.at_capacity() {
+            let requester = &self.pending_requests[index].requester;
+            let (pool, wants) = (requester.pool.clone(), requester.wants.clone());
+            let Some(resource) = self.take_resource(Some(&pool), &wants) else {
+                index += 1;
+                continue;
+            };
+            match self.pending_requests.remove(index) {
+                Some(req) => self.grant(req, resource),
+                None => {
+                    resource.release();
+                    self.available_resources.push_front(resource);
+                }
             }
         }
     }
 }
 
 #[derive(Clone)]
-pub struct ResourceAllocator {
-    request_tx: mpsc::UnboundedSender<ResourceRequest>,
-    release_tx: mpsc::UnboundedSender<u32>,
+pub struct ResourceAllocator<T: Resource> {
+    request_tx: mpsc::UnboundedSender<ResourceRequest<T>>,
+    release_tx: mpsc::UnboundedSender<Release>,
+    command_tx: mpsc::UnboundedSender<PoolCommand<T>>,
     next_id: Arc<AtomicUsize>,
-    cancel_tx: Arc<tokio::sync::Mutex<Option<oneshot::Sender<()>>>>,
+    queue_lengths: Arc<Mutex<HashMap<String, usize>>>,
+    // The most recent request made through this allocator (or any of its clones)
+    current_request: Arc<Mutex<Option<usize>>>,
 }
 
-impl ResourceAllocator {
-    pub fn new(pool: &ResourcePool) -> Self {
+impl<T: Resource> ResourceAllocator<T> {
+    pub fn new(pool: &ResourcePool<T>) -> Self {
         Self {
             request_tx: pool.request_tx.clone(),
             release_tx: pool.release_tx.clone(),
+            command_tx: pool.command_tx.clone(),
             next_id: Arc::clone(&pool.next_id),
-            cancel_tx: Arc::new(tokio::sync::Mutex::new(None)),
+            queue_lengths: Arc::clone(&pool.queue_lengths),
+            current_request: Arc::new(Mutex::new(None)),
         }
     }
 
-    /// Sends a resource request and returns an UnboundedReceiver for status updates
-    pub fn request_resource(&self) -> mpsc::UnboundedReceiver<ResourceStatus> {
+    /// Sends a resource request and returns an UnboundedReceiver for status updates.
+    /// Each owner gets at most one resource or place in the queue.
+    pub fn request_resource(&self, requester: Requester) -> mpsc::UnboundedReceiver<ResourceStatus<T>> {
         let (status_tx, status_rx) = mpsc::unbounded_channel();
-        let cancel_tx = self.cancel_tx.clone();
 
         let req_id = self.next_id.fetch_add(1, Ordering::Relaxed);
-        let (res_tx, res_rx) = oneshot::channel();
-        let (cancel_sender, cancel_receiver) = oneshot::channel();
-
-        // Store cancel sender in the Mutex
-        tokio::spawn({
-            let cancel_tx_store = cancel_tx.clone();
-            async move {
-                let mut guard = cancel_tx_store.lock().await;
-                *guard = Some(cancel_sender);
-            }
-        });
+        *self.current_request.lock().unwrap_or_else(PoisonError::into_inner) = Some(req_id);
 
         let req = ResourceRequest {
             id: req_id,
-            response: res_tx,
-            cancel: cancel_receiver,
-            status: status_tx.clone(),
+            requester,
+            created: Instant::now(),
+            status: status_tx,
+            notified: None,
         };
 
         // Send the request to the pool
         let _ = self.request_tx.send(req);
 
-        // Spawn a task that listens for the oneshot result and sends failure if cancelled
-        tokio::spawn(async move {
-            match res_rx.await {
-                Ok(_res_id) => {
-                    // Successfully got resource, do nothing here
-                }
-                Err(_) => {
-                    let _ = status_tx.send(ResourceStatus::Failed("Request cancelled".into()));
-                }
-            }
-        });
-
         status_rx
     }
 
-    pub fn release(&self, resource_id: u32) {
-        println!("Releasing resource {}", resource_id);
-        let _ = self.release_tx.send(resource_id);
+    // How many requests are currently waiting for a resource from the given pool
+    pub fn queue_length(&self, pool: &str) -> usize {
+        self.queue_lengths.lock().unwrap_or_else(PoisonError::into_inner).get(pool).copied().unwrap_or(0)
+    }
+
+    // Asks the queue manager what it's doing
+    pub async fn snapshot(&self) -> Option<QueueSnapshot> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.command_tx.send(PoolCommand::Snapshot(reply_tx)).ok()?;
+        reply_rx.await.ok()
+    }
+
+    pub fn reconfigure(&self, options: PoolOptions) {
+        let _ = self.command_tx.send(PoolCommand::Reconfigure(options));
     }
 
-    pub async fn cancel(&self) {
-        let mut guard = self.cancel_tx.lock().await;
-        if let Some(cancel_sender) = guard.take() {
-            let _ = cancel_sender.send(());
+    // Adds resources to their pools (new ones, if there aren't any by those names)
+    pub fn grow(&self, resources: Vec<T>) {
+        let _ = self.command_tx.send(PoolCommand::Grow(resources));
+    }
+
+    // Takes resources out of their pools for good, unless any are in use, returning
+    // whether they were taken out
+    pub async fn shrink(&self, ids: Vec<u32>) -> bool {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        if self.command_tx.send(PoolCommand::Shrink(ids, reply_tx)).is_err() {
+            return false;
         }
+        reply_rx.await.unwrap_or(false)
+    }
 
-        // Gotta fix this somehow so we have a way to notify a waiter that the request was cancelled
-        // I'm tired and don't want to deal with it right now
-        // let _ = status_tx.send(ResourceStatus::Cancelled);
+    // Stops (or resumes) handing out resources, for maintenance
+    pub fn set_draining(&self, draining: bool) {
+        let _ = self.command_tx.send(PoolCommand::Drain(draining));
+    }
+
+    pub fn release(&self, resource_id: u32) {
+        info!("Releasing resource {}", resource_id);
+        let _ = self.release_tx.send(Release {
+            id: resource_id,
+            disconnected: false,
+        });
+    }
+
+    // Releases a resource whose user dropped out, so it's kept for them for a while
+    pub fn release_after_disconnect(&self, resource_id: u32) {
+        info!("Releasing resource {} after a disconnect", resource_id);
+        let _ = self.release_tx.send(Release {
+            id: resource_id,
+            disconnected: true,
+        });
+    }
+
+    // Gives up our place in the queue, if we're still waiting.  Not async so it can
+    // be called from Drop.
+    pub fn cancel(&self) {
+        if let Some(req_id) = self.current_request.lock().unwrap_or_else(PoisonError::into_inner).take() {
+            let _ = self.command_tx.send(PoolCommand::Cancel(req_id));
+        }
     }
 }
 
 #[derive(Debug)]
-pub struct ResourceRequest {
+pub struct ResourceRequest<T> {
     pub id: usize,
-    pub response: oneshot::Sender<u32>,
-    pub cancel: oneshot::Receiver<()>,
-    pub status: mpsc::UnboundedSender<ResourceStatus>,
+    pub requester: Requester,
+    pub created: Instant,
+    pub status: mpsc::UnboundedSender<ResourceStatus<T>>,
+    // The last position sent to the requester, and when
+    notified: Option<(usize, Instant)>,
 }
@@ -1,132 +1,258 @@
 // filepath: /home/mike/source/docker-minecraft-rtsp/minecraft_terminal_viewer/src/render.rs
-use std::collections::VecDeque;
-use std::io::{self, ErrorKind, Read};
-use std::os::unix::io::{AsRawFd, RawFd};
-use std::process::{Command, Stdio};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex, mpsc};
-use std::thread;
+use std::future::Future;
+use std::io::{self, ErrorKind};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crossterm::terminal::{self, BeginSynchronizedUpdate, Clear, EndSynchronizedUpdate};
+use crossterm::{cursor, event, queue};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::error;
+
+use crate::config::{
+    AppConfig, DELTA_GAP, FPS_STEPS, FULL_FRAME_INTERVAL, LiveConfig, RenderMode, RenderSettings, TerminalSize,
+    THROTTLE_AFTER, UNTHROTTLE_AFTER,
+};
+use crate::overlay::Overlay;
+use crate::plugins::{self, SessionInfo};
+use crate::session_log::SessionLogs;
+use crate::supervisor::{Processes, Tracked};
 
+pub fn get_height_from_width(width: usize) -> usize {
+    // TODO: dynamically get aspect ratio from config GAME_WIDTH and GAME_HEIGHT
+    let target_height = ((width * 10 / 16 + 1) / 2) * 2;
+    return target_height;
+}
 
-use crate::config::{FFMPEG_BINARY, GAME_HEIGHT, GAME_WIDTH, TerminalSize};
+// Why capturing stopped
+#[derive(Debug, thiserror::Error)]
+pub enum CaptureError {
+    #[error("Couldn't start ffmpeg: {0}")]
+    Spawn(#[source] io::Error),
+    // Usually because the X server went away
+    #[error("ffmpeg exited with {0}")]
+    Exited(std::process::ExitStatus),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
 
-// Helper function to set or unset nonblocking mode on a file descriptor
-fn set_nonblocking(fd: RawFd, nonblocking: bool) -> io::Result<()> {
-    use libc::{F_GETFL, F_SETFL, O_NONBLOCK, fcntl};
+// Something frames can be captured from
+pub trait CaptureBackend: Send + Sync {
+    // Starts capturing raw RGB frames, scaled to the given size, at the given rate
+    fn start(
+        &self,
+        width: usize,
+        height: usize,
+        fps: u32,
+        logs: &SessionLogs,
+        settings: &AppConfig,
+    ) -> Result<Capture, CaptureError>;
+}
 
-    unsafe {
-        let mut flags = fcntl(fd, F_GETFL, 0);
-        if flags < 0 {
-            return Err(io::Error::last_os_error());
-        }
+// A capture that's going: its frames, and the process making them if there is one
+pub struct Capture {
+    pub frames: Box<dyn AsyncRead + Send + Unpin>,
+    pub process: Option<Child>,
+}
 
-        if nonblocking {
-            flags |= O_NONBLOCK;
-        } else {
-            flags &= !O_NONBLOCK;
-        }
+// Where frames come from, captured with ffmpeg
+#[derive(Clone, PartialEq, Eq)]
+pub enum VideoSource {
+    // An X display's screen, of the given size
+    Display(String, (u16, u16)),
+    // A recording, played on a loop
+    File(String),
+    // A window on Windows, by its title, or the whole screen without one
+    Window(Option<String>),
+}
 
-        if fcntl(fd, F_SETFL, flags) < 0 {
-            return Err(io::Error::last_os_error());
+impl VideoSource {
+    fn ffmpeg_input(&self, fps: u32) -> Vec<String> {
+        match self {
+            VideoSource::Display(display, (width, height)) => vec![
+                "-f".to_owned(),
+                "x11grab".to_owned(),
+                "-framerate".to_owned(),
+                fps.to_string(),
+                "-video_size".to_owned(),
+                format!("{}x{}", width, height),
+                "-i".to_owned(),
+                display.clone(),
+            ],
+            VideoSource::File(path) => vec![
+                "-re".to_owned(),
+                "-stream_loop".to_owned(),
+                "-1".to_owned(),
+                "-i".to_owned(),
+                path.clone(),
+                "-r".to_owned(),
+                fps.to_string(),
+            ],
+            VideoSource::Window(title) => vec![
+                "-f".to_owned(),
+                "gdigrab".to_owned(),
+                "-framerate".to_owned(),
+                fps.to_string(),
+                "-i".to_owned(),
+                title.as_ref().map_or_else(|| "desktop".to_owned(), |title| format!("title={}", title)),
+            ],
         }
     }
-
-    Ok(())
 }
 
-pub fn get_height_from_width(width: usize) -> usize {
-    // TODO: dynamically get aspect ratio from config GAME_WIDTH and GAME_HEIGHT
-    let target_height = ((width * 10 / 16 + 1) / 2) * 2;
-    return target_height;
+impl CaptureBackend for VideoSource {
+    fn start(
+        &self,
+        width: usize,
+        height: usize,
+        fps: u32,
+        logs: &SessionLogs,
+        settings: &AppConfig,
+    ) -> Result<Capture, CaptureError> {
+        let output_args = [
+            "-f",
+            "rawvideo",
+            "-vf",
+            &format!("scale={}x{},setsar=1:1", width, height),
+            "-pix_fmt",
+            "rgb24",
+            "pipe:",
+        ];
+        let mut process = Command::new(&settings.ffmpeg)
+            .args(self.ffmpeg_input(fps))
+            .args(output_args)
+            .stdout(Stdio::piped())
+            .stderr(logs.stdio("ffmpeg"))
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(CaptureError::Spawn)?;
+        // Always there, since it's piped
+        let stdout = process.stdout.take().ok_or_else(|| io::Error::other("ffmpeg has no stdout"))?;
+        Ok(Capture {
+            frames: Box::new(stdout),
+            process: Some(process),
+        })
+    }
 }
 
-// Renders the Minecraft X11 screen directly to the terminal with resize support
-pub fn render_x11_window(
-    render_tx: mpsc::SyncSender<String>,
+// Renders frames from the source, restarting its capture to follow the terminal
+// size and frame rate.  Returns an error if ffmpeg exits by itself.
+#[allow(clippy::too_many_arguments)]
+pub async fn render_video(
+    render_tx: mpsc::Sender<String>,
+    // Frames only redraw what's changed, unless this is None, for whatever might
+    // skip frames.  The Repaint has the next drawn whole.
+    deltas: Option<Repaint>,
+    // Turns the frame rate down while the link can't keep up with it
+    throttle: Throttle,
     term_size: Arc<Mutex<TerminalSize>>,
-    display: String,
-    running: Arc<AtomicBool>,
-) -> io::Result<()> {
-    let mut current_process: Option<std::process::Child> = None;
+    render_settings: Arc<Mutex<RenderSettings>>,
+    // For the frame rate cap, which can change while it's going
+    config: LiveConfig,
+    source: impl CaptureBackend,
+    cancel: CancellationToken,
+    processes: Processes,
+    logs: SessionLogs,
+) -> Result<(), CaptureError> {
+    let mut current_process: Option<(Child, Tracked)> = None;
+    let mut readers: Vec<JoinHandle<()>> = vec![];
+    // Stops the current capture's reader, for captures whose output doesn't end when they're killed
+    let mut current_reader: Option<CancellationToken> = None;
     let mut last_width = 0;
     let mut last_height = 0;
+    let mut last_fps = 0;
+    let mut result = Ok(());
+    let mut check = tokio::time::interval(Duration::from_millis(50));
+
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = check.tick() => {}
+        }
+        if let Some((process, _)) = current_process.as_mut() {
+            match process.try_wait() {
+                Ok(None) => {}
+                Ok(Some(status)) => {
+                    logs.line("ffmpeg", &format!("ffmpeg exited with {}", status));
+                    result = Err(CaptureError::Exited(status));
+                    break;
+                }
+                Err(e) => {
+                    result = Err(e.into());
+                    break;
+                }
+            }
+        }
 
-    while running.load(Ordering::SeqCst) {
         // Get current terminal dimensions
         let (target_width, target_height) = {
             let size = term_size.lock().unwrap();
             (size.target_width, size.target_height)
         };
+        let settings = config.get();
+        let fps = settings.capped_fps(render_settings.lock().unwrap().fps);
+        let fps = throttle.limit().map_or(fps, |limit| fps.min(limit));
 
-        // Only restart ffmpeg if the dimensions actually changed
-        if target_width != last_width || target_height != last_height {
+        // Only restart the capture if the dimensions or frame rate actually changed
+        if target_width != last_width || target_height != last_height || fps != last_fps {
             // Kill previous ffmpeg process if it exists
-            if let Some(mut process) = current_process.take() {
-                let _ = process.kill();
-                let _ = process.wait();
+            if let Some((mut process, _tracked)) = current_process.take() {
+                let _ = process.kill().await;
+            }
+            if let Some(reader) = current_reader.take() {
+                reader.cancel();
             }
 
-            // Start a new ffmpeg process with updated dimensions
-            let x11_grab_args = [
-                "-f",
-                "x11grab",
-                "-framerate",
-                "30",
-                "-video_size",
-                &format!("{}x{}", GAME_WIDTH, GAME_HEIGHT),
-                "-i",
-                &display,
-                "-f",
-                "rawvideo",
-                "-vf",
-                &format!("scale={}x{},setsar=1:1", target_width, target_height),
-                "-pix_fmt",
-                "rgb24",
-                "pipe:",
-            ];
-
-            let mut ffmpeg_process = Command::new(FFMPEG_BINARY)
-                .args(&x11_grab_args)
-                .stdout(Stdio::piped())
-                .stderr(Stdio::null()) // Redirect stderr to /dev/null
-                .spawn()?;
-
-            let ffmpeg_stdout = ffmpeg_process.stdout.take().unwrap();
-            current_process = Some(ffmpeg_process);
-
-            // Clone necessary channels and values for the render thread
-            let render_tx_clone = render_tx.clone();
-            let running_clone = Arc::clone(&running);
-
-            // Spawn a thread to handle the rendering for this process
-            let _render_thread = thread::spawn(move || {
-                if let Err(e) = render_byte_stream(
-                    ffmpeg_stdout,
-                    target_height,
-                    target_width,
-                    0,
-                    0,
-                    render_tx_clone,
-                    running_clone,
-                ) {
-                    eprintln!("Render error: {}", e);
+            // Start a new capture with updated dimensions
+            let capture = match source.start(target_width, target_height, fps, &logs, &settings) {
+                Ok(capture) => capture,
+                Err(e) => {
+                    result = Err(e);
+                    break;
                 }
+            };
+            current_process = capture.process.map(|process| {
+                let tracked = processes.track("ffmpeg", process.id().unwrap_or_default());
+                (process, tracked)
             });
 
+            // Encode this capture's frames until its output ends
+            let reader = cancel.child_token();
+            readers.retain(|reader| !reader.is_finished());
+            readers.push(tokio::spawn(read_frames(
+                capture.frames,
+                target_height,
+                target_width,
+                render_tx.clone(),
+                deltas.clone(),
+                render_settings.clone(),
+                reader.clone(),
+            )));
+            current_reader = Some(reader);
+
             // Update last dimensions
             last_width = target_width;
             last_height = target_height;
+            last_fps = fps;
         }
-        thread::sleep(std::time::Duration::from_millis(50));
     }
 
     // Ensure the current process is killed
-    if let Some(mut process) = current_process {
-        let _ = process.kill();
-        let _ = process.wait();
+    if let Some((mut process, _tracked)) = current_process {
+        let _ = process.kill().await;
+    }
+    // They finish once ffmpeg's output ends
+    for reader in readers {
+        let _ = reader.await;
     }
 
-    Ok(())
+    result
 }
 
 // Function to convert RGB to ANSI 256-color palette index
@@ -151,7 +277,7 @@ fn rgb_to_ansi_256(r: u8, g: u8, b: u8) -> u8 {
     16 + 36 * r_index + 6 * g_index + b_index
 }
 
-fn frame_to_rgb_ansi(frame_data: &Vec<u8>, height: usize, width: usize, offset_x: usize, offset_y: usize) -> String {
+fn frame_to_rgb_ansi(frame_data: &[u8], height: usize, width: usize, offset_x: usize, offset_y: usize) -> String {
     let mut output = String::with_capacity(13 + (height / 2) * (width * 41 + 8));
     output.push_str(&format!("\x1b[{};{}H", offset_y + 1, offset_x + 1));
 
@@ -176,7 +302,7 @@ fn frame_to_rgb_ansi(frame_data: &Vec<u8>, height: usize, width: usize, offset_x
     return output;
 }
 
-fn frame_to_256_ansi(frame_data: &Vec<u8>, height: usize, width: usize, offset_x: usize, offset_y: usize) -> String {
+fn frame_to_256_ansi(frame_data: &[u8], height: usize, width: usize, offset_x: usize, offset_y: usize) -> String {
     let mut output = String::with_capacity(13 + (height / 2) * (width * 18 + 8));
     output.push_str(&format!("\x1b[{};{}H", offset_y + 1, offset_x + 1));
 
@@ -211,93 +337,509 @@ fn frame_to_256_ansi(frame_data: &Vec<u8>, height: usize, width: usize, offset_x
     return output;
 }
 
-// Renders an arbitrary bytes buffer to the terminal using non-blocking I/O
-fn render_byte_stream<R: Read + AsRawFd>(
-    mut buffer: R,
+// The 16 colors' usual xterm values, in SGR order (the last 8 being the bright ones)
+const ANSI_16: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+// The nearest of the 16, as its foreground SGR code (add 10 for the background)
+fn rgb_to_ansi_16(r: u8, g: u8, b: u8) -> u8 {
+    let distance = |&(pr, pg, pb): &(u8, u8, u8)| {
+        let (dr, dg, db) = (r as i32 - pr as i32, g as i32 - pg as i32, b as i32 - pb as i32);
+        dr * dr + dg * dg + db * db
+    };
+    let index = (0..16).min_by_key(|&index| distance(&ANSI_16[index])).unwrap_or(0) as u8;
+    if index < 8 { 30 + index } else { 90 + index - 8 }
+}
+
+fn frame_to_16_ansi(frame_data: &[u8], height: usize, width: usize, offset_x: usize, offset_y: usize) -> String {
+    let mut output = String::with_capacity(13 + (height / 2) * (width * 12 + 8));
+    output.push_str(&format!("\x1b[{};{}H", offset_y + 1, offset_x + 1));
+
+    // Half-blocks like the others, for terminals with only the basic colors
+    for row_index in (0..height).step_by(2) {
+        for column_index in 0..width {
+            let top_pixel_start = ((row_index * width) + column_index) * 3;
+            let bottom_pixel_start = (((row_index + 1).min(height - 1) * width) + column_index) * 3;
+            let top = &frame_data[top_pixel_start..top_pixel_start + 3];
+            let bottom = &frame_data[bottom_pixel_start..bottom_pixel_start + 3];
+            let bg_color = rgb_to_ansi_16(top[0], top[1], top[2]) + 10;
+            let fg_color = rgb_to_ansi_16(bottom[0], bottom[1], bottom[2]);
+            output.push_str(&format!("\x1b[{};{}m▄", bg_color, fg_color));
+        }
+        output.push_str(&format!("\x1b[B\x1b[{}D", width));
+    }
+    output
+}
+
+fn luminance(r: u8, g: u8, b: u8) -> u8 {
+    ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8
+}
+
+// Braille dot bits for each dot row, as (left column, right column)
+const BRAILLE_DOTS: [(u32, u32); 4] = [(0x01, 0x08), (0x02, 0x10), (0x04, 0x20), (0x40, 0x80)];
+// 2x2 ordered dither thresholds, as fractions of 4
+const DITHER_2X2: [[u32; 2]; 2] = [[0, 2], [3, 1]];
+
+fn frame_to_braille(frame_data: &[u8], height: usize, width: usize, offset_x: usize, offset_y: usize) -> String {
+    let mut output = String::with_capacity(13 + (height / 2) * (width * 15 + 8));
+    output.push_str(&format!("\x1b[{};{}H\x1b[49m", offset_y + 1, offset_x + 1));
+
+    // Each cell covers a top and a bottom pixel, and each pixel gets a 2x2 block of
+    // dots, dithered by its brightness.  The cell is tinted with the average color.
+    for row_index in (0..height).step_by(2) {
+        for column_index in 0..width {
+            let top_pixel_start = ((row_index * width) + column_index) * 3;
+            let bottom_pixel_start = (((row_index + 1).min(height - 1) * width) + column_index) * 3;
+
+            let mut bits = 0u32;
+            let mut color = [0u32; 3];
+            for (half, pixel_start) in [top_pixel_start, bottom_pixel_start].into_iter().enumerate() {
+                let pixel = &frame_data[pixel_start..pixel_start + 3];
+                let level = luminance(pixel[0], pixel[1], pixel[2]) as u32 * 4;
+                for dot_row in 0..2 {
+                    let (left, right) = BRAILLE_DOTS[half * 2 + dot_row];
+                    if level > (DITHER_2X2[dot_row][0] * 2 + 1) * 128 {
+                        bits |= left;
+                    }
+                    if level > (DITHER_2X2[dot_row][1] * 2 + 1) * 128 {
+                        bits |= right;
+                    }
+                }
+                for channel in 0..3 {
+                    color[channel] += pixel[channel] as u32;
+                }
+            }
+
+            let fg_color = rgb_to_ansi_256((color[0] / 2) as u8, (color[1] / 2) as u8, (color[2] / 2) as u8);
+            let dots = char::from_u32(0x2800 + bits).unwrap_or(' ');
+            output.push_str(&format!("\x1b[38;5;{}m{}", fg_color, dots));
+        }
+        output.push_str(&format!("\x1b[B\x1b[{}D", width));
+    }
+    return output;
+}
+
+// Characters ordered from darkest to brightest
+const ASCII_RAMP: &[u8] = b" .:-=+*#%@";
+
+fn frame_to_ascii(frame_data: &[u8], height: usize, width: usize, offset_x: usize, offset_y: usize) -> String {
+    let mut output = String::with_capacity(13 + (height / 2) * (width + 8));
+    // No colors at all, for terminals that can't be trusted with them
+    output.push_str(&format!("\x1b[{};{}H\x1b[m", offset_y + 1, offset_x + 1));
+
+    for row_index in (0..height).step_by(2) {
+        for column_index in 0..width {
+            let top_pixel_start = ((row_index * width) + column_index) * 3;
+            let bottom_pixel_start = (((row_index + 1).min(height - 1) * width) + column_index) * 3;
+
+            let top = &frame_data[top_pixel_start..top_pixel_start + 3];
+            let bottom = &frame_data[bottom_pixel_start..bottom_pixel_start + 3];
+            let brightness = (luminance(top[0], top[1], top[2]) as usize
+                + luminance(bottom[0], bottom[1], bottom[2]) as usize)
+                / 2;
+            output.push(ASCII_RAMP[brightness * (ASCII_RAMP.len() - 1) / 255] as char);
+        }
+        output.push_str(&format!("\x1b[B\x1b[{}D", width));
+    }
+    return output;
+}
+
+// Turns an RGB frame into what draws it on the terminal at the given position
+pub fn encode_frame(
+    mode: RenderMode,
+    frame_data: &[u8],
     height: usize,
     width: usize,
     offset_x: usize,
     offset_y: usize,
-    render_tx: mpsc::SyncSender<String>,
-    running: Arc<AtomicBool>,
-) -> io::Result<()> {
-    // One frame is (height * width * 3) bytes (RGB for each pixel)
-    let frame_size = height * width * 3;
+) -> String {
+    let mut output = encode_rows(mode, frame_data, height, width, offset_x, offset_y);
+    // Reset colors
+    output.push_str("\x1b[m");
+    output
+}
 
-    // Set non-blocking mode on the raw file descriptor
-    set_nonblocking(buffer.as_raw_fd(), true)?;
+fn encode_rows(
+    mode: RenderMode,
+    frame_data: &[u8],
+    height: usize,
+    width: usize,
+    offset_x: usize,
+    offset_y: usize,
+) -> String {
+    match mode {
+        RenderMode::TrueColor => frame_to_rgb_ansi(frame_data, height, width, offset_x, offset_y),
+        RenderMode::Ansi256 => frame_to_256_ansi(frame_data, height, width, offset_x, offset_y),
+        RenderMode::Ansi16 => frame_to_16_ansi(frame_data, height, width, offset_x, offset_y),
+        RenderMode::Braille => frame_to_braille(frame_data, height, width, offset_x, offset_y),
+        RenderMode::Ascii => frame_to_ascii(frame_data, height, width, offset_x, offset_y),
+    }
+}
 
-    // Use a VecDeque to store incoming frames
-    let mut frame_queue = VecDeque::new();
+// Encodes frames as only the cells that changed since the last one, which is far
+// less to send over a slow link than every cell every time.  The first, and one
+// every so often after, is the whole frame.
+#[derive(Default)]
+pub struct FrameDiffer {
+    previous: Vec<u8>,
+    mode: RenderMode,
+    last_full: Option<Instant>,
+}
 
-    // Storage for the frame we'll actually process
-    let mut frame_data = vec![0u8; frame_size];
+impl FrameDiffer {
+    // Draws from the top left like encode_frame, leaving the cursor where a whole
+    // frame would, under it
+    pub fn encode(&mut self, mode: RenderMode, frame_data: &[u8], height: usize, width: usize) -> String {
+        let due = self.last_full.is_none_or(|at| at.elapsed() >= FULL_FRAME_INTERVAL);
+        let delta = if due || mode != self.mode || self.previous.len() != frame_data.len() {
+            None
+        } else {
+            self.delta(mode, frame_data, height, width)
+        };
+        let output = delta.unwrap_or_else(|| {
+            self.last_full = Some(Instant::now());
+            encode_frame(mode, frame_data, height, width, 0, 0)
+        });
+        self.previous.clear();
+        self.previous.extend_from_slice(frame_data);
+        self.mode = mode;
+        output
+    }
 
-    // Temporary buffer for reading data
-    let mut read_buffer = vec![0u8; frame_size];
-    let mut partial_buffer = Vec::with_capacity(frame_size);
+    // None if so much has changed that the whole frame's hardly any more to send
+    fn delta(&self, mode: RenderMode, frame_data: &[u8], height: usize, width: usize) -> Option<String> {
+        let pixel = |row: usize, column: usize| (row.min(height - 1) * width + column) * 3;
+        let changed = |row: usize, column: usize| {
+            [pixel(row, column), pixel(row + 1, column)]
+                .into_iter()
+                .any(|start| frame_data[start..start + 3] != self.previous[start..start + 3])
+        };
 
-    while running.load(Ordering::SeqCst) {
-        // Read as much data as possible without blocking
-        let mut read_something = false;
+        let mut output = String::new();
+        let mut changed_cells = 0;
+        let cells = height.div_ceil(2) * width;
+        for row_index in (0..height).step_by(2) {
+            let mut column = 0;
+            while column < width {
+                if !changed(row_index, column) {
+                    column += 1;
+                    continue;
+                }
+                let start = column;
+                let mut end = column + 1;
+                column += 1;
+                while column < width && column - end < DELTA_GAP {
+                    if changed(row_index, column) {
+                        end = column + 1;
+                    }
+                    column += 1;
+                }
+                column = end;
+                changed_cells += end - start;
+                if changed_cells * 4 > cells * 3 {
+                    return None;
+                }
 
-        loop {
-            match buffer.read(&mut read_buffer) {
-                Ok(0) => break, // EOF
-                Ok(n) => {
-                    read_something = true;
+                // Drawn as a frame of its own of just this run of cells, without
+                // the move to the next row
+                let mut run = frame_data[pixel(row_index, start)..pixel(row_index, end - 1) + 3].to_vec();
+                run.extend_from_slice(&frame_data[pixel(row_index + 1, start)..pixel(row_index + 1, end - 1) + 3]);
+                let run = encode_rows(mode, &run, 2, end - start, start, row_index / 2);
+                let row_end = format!("\x1b[B\x1b[{}D", end - start);
+                output.push_str(run.strip_suffix(&row_end).unwrap_or(&run));
+            }
+        }
+        output.push_str(&format!("\x1b[{};1H\x1b[m", height.div_ceil(2) + 1));
+        Some(output)
+    }
+}
 
-                    // Add the new data to our partial buffer
-                    partial_buffer.extend_from_slice(&read_buffer[0..n]);
+// Asks for the next frame to be drawn whole, e.g. once the overlay's gone from
+// over cells that haven't changed since
+#[derive(Clone, Default)]
+pub struct Repaint(Arc<AtomicBool>);
 
-                    // Process complete frames from the partial buffer
-                    while partial_buffer.len() >= frame_size {
-                        // Extract a complete frame
-                        let frame = partial_buffer.drain(0..frame_size).collect::<Vec<u8>>();
+impl Repaint {
+    pub fn request(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
 
-                        // Add to the queue, limiting queue size to avoid memory issues
-                        frame_queue.push_back(frame);
-                    }
-                }
-                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
-                    // No more data available right now
-                    break;
+    fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+// Encodes frames as the capture writes them.  It blocks writing while a frame's being
+// encoded and sent, so at most the pipe's worth of frames are ever behind.
+async fn read_frames(
+    mut stdout: Box<dyn AsyncRead + Send + Unpin>,
+    height: usize,
+    width: usize,
+    render_tx: mpsc::Sender<String>,
+    deltas: Option<Repaint>,
+    render_settings: Arc<Mutex<RenderSettings>>,
+    cancel: CancellationToken,
+) {
+    // One frame is (height * width * 3) bytes (RGB for each pixel)
+    let mut frame = vec![0u8; height * width * 3];
+    let mut differ = deltas.as_ref().map(|_| FrameDiffer::default());
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => return,
+            read = stdout.read_exact(&mut frame) => match read {
+                Ok(_) => {}
+                // ffmpeg was stopped
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return,
+                Err(e) => {
+                    error!("Render error: {}", e);
+                    return;
                 }
-                Err(e) => return Err(e), // Actual error
-            }
+            },
         }
 
-        // Render the most recent frame if available
-        if let Some(latest_frame) = frame_queue.pop_back() {
-            // Put any remaining frames back at the end of the queue
-            // This effectively drops all but the latest frame
-            if !frame_queue.is_empty() {
-                let dropped_count = frame_queue.len();
-                frame_queue.clear();
-                eprintln!("Dropping {} frames for real-time display", dropped_count);
+        // The mode is read every frame so it can be switched without restarting ffmpeg
+        let mode = render_settings.lock().unwrap().mode;
+        if deltas.as_ref().is_some_and(Repaint::take) {
+            differ = Some(FrameDiffer::default());
+        }
+        // Encoding takes long enough that it would hold up other sessions' tasks
+        let encoded = tokio::task::spawn_blocking(move || {
+            let output = match differ.as_mut() {
+                Some(differ) => differ.encode(mode, &frame, height, width),
+                None => encode_frame(mode, &frame, height, width, 0, 0),
+            };
+            (output, frame, differ)
+        });
+        let output = match encoded.await {
+            Ok((output, buffer, kept)) => {
+                frame = buffer;
+                differ = kept;
+                output
+            }
+            Err(e) => {
+                error!("Render error: {}", e);
+                return;
             }
+        };
 
-            // Copy the latest frame to our frame data buffer
-            frame_data.copy_from_slice(&latest_frame);
+        if render_tx.send(output).await.is_err() {
+            return; // Receiver dropped
+        }
+    }
+}
 
-            let mut output = frame_to_rgb_ansi(&frame_data, height, width, offset_x, offset_y);
+// Where a session's frames are shown: the player's SSH channel, or this terminal
+pub trait Screen: Send + 'static {
+    // Sends everything on at once, so a frame's never half drawn
+    fn show(&mut self, bytes: Vec<u8>) -> impl Future<Output = io::Result<()>> + Send;
 
-            // Reset colors
-            output.push_str("\x1b[m");
+    // Whether there's a terminal now that hasn't seen the frames so far, e.g. the
+    // player's come back on a new connection, so the next has to be drawn whole
+    fn take_repaint(&mut self) -> bool {
+        false
+    }
+}
 
-            // Send the rendered output
-            if render_tx.send(output).is_err() {
-                break; // Receiver dropped
-            }
-        } else if !read_something && partial_buffer.len() < frame_size {
-            // If we didn't read anything and don't have a full frame, sleep briefly
-            // to avoid busy-waiting
-            std::thread::sleep(std::time::Duration::from_millis(10));
+// How much a session's been sent, for its summary and the admin dashboard
+#[derive(Default)]
+pub struct Streamed {
+    pub frames: AtomicU64,
+    pub bytes: AtomicU64,
+    pub throttle: Throttle,
+}
+
+// The frame rate the player's link keeps up with, if it's been turned down for it
+#[derive(Clone, Default)]
+pub struct Throttle(Arc<AtomicU32>);
+
+impl Throttle {
+    pub fn limit(&self) -> Option<u32> {
+        Some(self.0.load(Ordering::Relaxed)).filter(|&limit| limit > 0)
+    }
+
+    fn set(&self, limit: Option<u32>) {
+        self.0.store(limit.unwrap_or(0), Ordering::Relaxed);
+    }
+}
+
+// How long frames take to send against how often they come, as moving averages
+struct Pace {
+    sending: f64,
+    interval: f64,
+    last_frame: Option<Instant>,
+    // Whether the link's struggling (Some(true)) or has room to spare (Some(false)),
+    // and since when
+    state: (Option<bool>, Instant),
+}
+
+impl Pace {
+    fn new() -> Self {
+        Self {
+            sending: 0.0,
+            interval: 0.0,
+            last_frame: None,
+            state: (None, Instant::now()),
+        }
+    }
+
+    // Counts a frame that took this long to send, turning the frame rate down a step
+    // (to below what's getting through) if the link's been struggling, or back up one
+    fn sent(&mut self, took: Duration, throttle: &Throttle) {
+        let now = Instant::now();
+        let Some(last_frame) = self.last_frame.replace(now) else {
+            return;
+        };
+        let average = |average: f64, sample: f64| if average == 0.0 { sample } else { average * 0.8 + sample * 0.2 };
+        self.sending = average(self.sending, took.as_secs_f64());
+        self.interval = average(self.interval, (now - last_frame).as_secs_f64().max(0.001));
+
+        let busy = self.sending / self.interval;
+        let struggling = if busy > 0.8 {
+            Some(true)
+        } else if busy < 0.4 {
+            Some(false)
+        } else {
+            None
+        };
+        if struggling != self.state.0 {
+            self.state = (struggling, now);
         }
+        let limit = match struggling {
+            Some(true) if self.state.1.elapsed() >= THROTTLE_AFTER => {
+                let getting_through = 1.0 / self.interval;
+                let lower = FPS_STEPS.iter().rev().copied().find(|&fps| (fps as f64) < getting_through * 0.75);
+                let lower = lower.unwrap_or(FPS_STEPS[0]);
+                if throttle.limit() == Some(lower) {
+                    return;
+                }
+                Some(lower)
+            }
+            Some(false) if self.state.1.elapsed() >= UNTHROTTLE_AFTER => {
+                let Some(limit) = throttle.limit() else {
+                    return;
+                };
+                FPS_STEPS.iter().copied().find(|&fps| fps > limit)
+            }
+            _ => return,
+        };
+        throttle.set(limit);
+        self.state.1 = now;
     }
+}
 
-    // Restore blocking mode before returning
-    let _ = set_nonblocking(buffer.as_raw_fd(), false);
+// Shows frames as they're rendered, with the overlay over them, until cancelled
+#[allow(clippy::too_many_arguments)]
+pub async fn show_frames<S: Screen>(
+    mut completed_frames: tokio::sync::mpsc::Receiver<String>,
+    mut screen: S,
+    terminal_size: Arc<Mutex<TerminalSize>>,
+    overlay: Arc<Mutex<Overlay>>,
+    // Asked for when frames might not cover what's on the terminal
+    repaint: Repaint,
+    streamed: Arc<Streamed>,
+    session_info: Arc<SessionInfo>,
+    cancel: CancellationToken,
+) -> io::Result<()> {
+    // To be clear, I really don't think these (or the cleanup commands) belong here...
+    // but I'm not quite proficient enough with rust's borrow checker to understand
+    // how to put them higher up.  Maybe later.
+    let mut setup = vec![];
+    crossterm::execute!(
+        setup,
+        event::EnableMouseCapture,
+        terminal::EnterAlternateScreen,
+        cursor::Hide
+    )?;
+    screen.show(setup).await?;
+
+    let mut pace = Pace::new();
+    // Redraws the last frame when only the overlay changed, so menus stay
+    // responsive even at very low frame rates.  It may only be what changed in it,
+    // so the next frame's drawn whole for whatever the overlay's left behind.
+    let mut overlay_check = tokio::time::interval(Duration::from_millis(10));
+    let mut last_frame: Option<String> = None;
+    loop {
+        let (frame, new) = tokio::select! {
+            _ = cancel.cancelled() => break,
+            frame = completed_frames.recv() => match frame {
+                Some(frame) => {
+                    overlay.lock().unwrap().take_dirty();
+                    streamed.frames.fetch_add(1, Ordering::Relaxed);
+                    (frame, true)
+                }
+                None => break,
+            },
+            _ = overlay_check.tick() => {
+                if !overlay.lock().unwrap().take_dirty() {
+                    continue;
+                }
+                repaint.request();
+                match last_frame.take() {
+                    Some(frame) => (frame, false),
+                    None => continue,
+                }
+            }
+        };
+
+        if screen.take_repaint() {
+            repaint.request();
+        }
+        let (cols, rows) = {
+            let size = terminal_size.lock().unwrap();
+            (size.target_width, size.target_height / 2)
+        };
+        let mut writer = vec![];
+        queue!(writer, BeginSynchronizedUpdate)?;
+        // I wonder if we want to add a clear here.
+        writer.extend_from_slice(frame.as_bytes());
+        queue!(
+            writer,
+            Clear(crossterm::terminal::ClearType::FromCursorDown)
+        )?;
+        {
+            let mut overlay = overlay.lock().unwrap();
+            writer.extend_from_slice(overlay.render(cols, rows).as_bytes());
+            if overlay.take_bell() {
+                writer.push(0x07);
+            }
+            if let Some(notification) = overlay.take_notification() {
+                writer.extend_from_slice(notification.as_bytes());
+            }
+        }
+        plugins::frame(&session_info, &mut writer);
+        queue!(writer, EndSynchronizedUpdate)?;
+        streamed.bytes.fetch_add(writer.len() as u64, Ordering::Relaxed);
+        // For the player's SSH session, this is how long the link takes a frame
+        let sending = Instant::now();
+        screen.show(writer).await?;
+        if new {
+            pace.sent(sending.elapsed(), &streamed.throttle);
+        }
+        last_frame = Some(frame);
+    }
 
-    Ok(())
+    let mut cleanup = vec![];
+    crossterm::execute!(
+        cleanup,
+        event::DisableMouseCapture,
+        terminal::LeaveAlternateScreen,
+        cursor::Show,
+    )?;
+    screen.show(cleanup).await
 }
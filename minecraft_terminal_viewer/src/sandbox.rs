@@ -0,0 +1,131 @@
+// Games run in a bubblewrap sandbox, with ANSICRAFT_SANDBOX=bwrap (or
+// ANSICRAFT_SANDBOX_<POOL> for one pool's), so a malicious mod or a compromised JVM
+// can't get at the host or anyone else's session.  The sandbox sees the system
+// read-only, the game files read-only, its own game directory and X display, and
+// nothing else: no other players' worlds, logins, logs or keys, and no other
+// processes.  It keeps the network, and drops every capability.
+//
+// Anything else the game needs to read (a launcher somewhere unusual, a modpack)
+// can be listed in ANSICRAFT_SANDBOX_BIND, separated by commas.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+
+use crate::config::AppConfig;
+
+// What the system's made of, shared read-only
+const SYSTEM_DIRS: &[&str] = &["/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/etc", "/opt"];
+
+// What the server keeps about its players, which is hidden even if it's somewhere
+// the game can otherwise see (like the shared game directory)
+pub fn private_paths(settings: &AppConfig) -> Vec<PathBuf> {
+    let mut paths = vec![
+        settings.accounts_dir.clone(),
+        settings.backups_dir.clone(),
+        settings.user_env_dir.clone(),
+        settings.vip_keys.clone(),
+        settings.admin_keys.clone(),
+        settings.admin_socket.clone(),
+        settings.reservations.clone(),
+        settings.host_key.clone(),
+        PathBuf::from("input_preferences"),
+        PathBuf::from("session_settings"),
+    ];
+    paths.extend(settings.instances_dir.clone());
+    paths.extend(settings.session_logs_dir.clone());
+    paths.into_iter().filter_map(|path| fs::canonicalize(path).ok()).collect()
+}
+
+// Runs the command in a sandbox instead, keeping its arguments and environment.
+// The game directory is the only place it can write, besides a /tmp of its own.
+pub fn wrap(cmd: Command, display: &str, game_dir: Option<&Path>, settings: &AppConfig) -> Command {
+    let minecraft_dir = &settings.minecraft_dir;
+    let game_dir = game_dir.map(Path::to_path_buf).unwrap_or_else(|| minecraft_dir.clone());
+    let mut wrapped = Command::new("bwrap");
+    wrapped.args(["--unshare-all", "--share-net", "--die-with-parent", "--new-session", "--cap-drop", "ALL"]);
+    for dir in SYSTEM_DIRS {
+        wrapped.args(["--ro-bind-try", dir, dir]);
+    }
+    wrapped.args(["--proc", "/proc", "--dev", "/dev", "--tmpfs", "/tmp"]);
+    // For games rendering on a GPU
+    wrapped.args(["--dev-bind-try", "/dev/dri", "/dev/dri"]);
+    // Only its own display
+    let socket = format!("/tmp/.X11-unix/X{}", display.trim_start_matches(':').split('.').next().unwrap_or(""));
+    wrapped.args(["--bind", &socket, &socket]);
+    // PulseAudio's socket, for sound
+    if let Ok(entries) = fs::read_dir("/tmp") {
+        for entry in entries.filter_map(Result::ok) {
+            if entry.file_name().to_string_lossy().starts_with("pulse-") {
+                wrapped.arg("--bind").arg(entry.path()).arg(entry.path());
+            }
+        }
+    }
+    if let Ok(runtime_dir) = std::env::var("XDG_RUNTIME_DIR") {
+        let pulse = Path::new(&runtime_dir).join("pulse");
+        wrapped.arg("--bind-try").arg(&pulse).arg(&pulse);
+    }
+    for dir in ["versions", "libraries", "assets"] {
+        let dir = minecraft_dir.join(dir);
+        wrapped.arg("--ro-bind-try").arg(&dir).arg(&dir);
+    }
+    for path in &settings.sandbox_bind {
+        wrapped.args(["--ro-bind-try", path, path]);
+    }
+    wrapped.arg("--bind").arg(&game_dir).arg(&game_dir);
+    for path in private_paths(settings) {
+        // Covered up, unless it's the game directory itself (a player's instance)
+        if game_dir.starts_with(&path) && path.is_dir() {
+            continue;
+        }
+        if path.is_dir() {
+            wrapped.arg("--tmpfs").arg(&path);
+        } else {
+            wrapped.args(["--ro-bind", "/dev/null"]).arg(&path);
+        }
+    }
+    // Other players' directories next to this one
+    if let Some(instances) = settings.instances_dir.as_ref().and_then(|dir| fs::canonicalize(dir).ok())
+        && game_dir.starts_with(&instances)
+        && game_dir != instances
+    {
+        wrapped.arg("--tmpfs").arg(&instances).arg("--bind").arg(&game_dir).arg(&game_dir);
+    }
+    wrapped.arg("--chdir").arg(cmd.get_current_dir().unwrap_or(&game_dir));
+
+    wrapped.arg("--").arg(cmd.get_program()).args(cmd.get_args());
+    for (key, value) in cmd.get_envs() {
+        match value {
+            Some(value) => wrapped.env(key, value),
+            None => wrapped.env_remove(key),
+        };
+    }
+    wrapped
+}
+
+// Everything running under the process
+fn descendants(pid: u32) -> Vec<u32> {
+    let children = fs::read_to_string(format!("/proc/{0}/task/{0}/children", pid)).unwrap_or_default();
+    children
+        .split_whitespace()
+        .filter_map(|child| child.parse().ok())
+        .flat_map(|child| std::iter::once(child).chain(descendants(child)))
+        .collect()
+}
+
+// Asks a game to stop.  A sandboxed game is signalled itself, since bubblewrap
+// would just die and take the game with it before it could save.
+pub fn signal_game(pid: u32, stop_signal: Signal) -> nix::Result<()> {
+    let sandboxed = fs::read_to_string(format!("/proc/{}/comm", pid)).is_ok_and(|comm| comm.trim() == "bwrap");
+    if !sandboxed {
+        return signal::kill(Pid::from_raw(pid as i32), stop_signal);
+    }
+    // The sandbox's own init ignores it, and the game gets it
+    for descendant in descendants(pid) {
+        let _ = signal::kill(Pid::from_raw(descendant as i32), stop_signal);
+    }
+    Ok(())
+}
@@ -0,0 +1,162 @@
+// An operator's script, in Rhai, for tweaks of their own without rebuilding.  The
+// file ANSICRAFT_SCRIPT names can define
+//
+//     fn on_input(session, command)
+//
+// which gets everything about to be sent to the game, as the xdotool command it
+// would be (like ["keydown", "w"]), and returns the commands to send instead: an
+// array of them, each an array or a string like "key e".  So it can remap keys,
+// drop them (with []) or send a sequence for one.  Returning nothing sends the
+// command as it was.  And
+//
+//     fn overlay(session)
+//
+// returns text to draw over the top left of the game, a line for each "\n", or ""
+// for none.  `session` has the player's username, fingerprint and display in it.
+// Either can be left out, and a call that fails is logged and otherwise ignored.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use tracing::{error, info};
+
+use crate::config::SCRIPT_MAX_OPERATIONS;
+use crate::plugins::{self, Plugin, SessionInfo};
+use crate::xdo::{InputError, InputSink};
+
+static SCRIPT: OnceLock<Option<Script>> = OnceLock::new();
+
+fn script() -> Option<&'static Script> {
+    SCRIPT.get()?.as_ref()
+}
+
+struct Script {
+    engine: Engine,
+    ast: AST,
+    on_input: bool,
+    overlay: bool,
+}
+
+impl Script {
+    fn load(path: PathBuf) -> Result<Self, Box<EvalAltResult>> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(SCRIPT_MAX_OPERATIONS);
+        let ast = engine.compile_file(path)?;
+        let defines = |name: &str, params: usize| {
+            ast.iter_functions().any(|f| f.name == name && f.params.len() == params)
+        };
+        let (on_input, overlay) = (defines("on_input", 2), defines("overlay", 1));
+        Ok(Self {
+            engine,
+            ast,
+            on_input,
+            overlay,
+        })
+    }
+
+    fn call(&self, name: &str, args: impl rhai::FuncArgs) -> Option<Dynamic> {
+        match self.engine.call_fn(&mut Scope::new(), &self.ast, name, args) {
+            Ok(result) => Some(result),
+            Err(e) => {
+                error!("Error in the script's {}: {}", name, e);
+                None
+            }
+        }
+    }
+
+    // What to send instead of the command, or None to send it as it is
+    fn input(&self, session: &SessionInfo, command: &[&str]) -> Option<Vec<Vec<String>>> {
+        if !self.on_input {
+            return None;
+        }
+        let command: Array = command.iter().map(|arg| Dynamic::from(arg.to_string())).collect();
+        let result = self.call("on_input", (session_map(session), command))?;
+        if result.is_unit() {
+            return None;
+        }
+        let Some(commands) = result.try_cast::<Array>() else {
+            error!("The script's on_input should return an array of commands");
+            return None;
+        };
+        Some(commands.into_iter().filter_map(command_args).collect())
+    }
+
+    fn overlay(&self, session: &SessionInfo) -> Option<String> {
+        if !self.overlay {
+            return None;
+        }
+        let text = self.call("overlay", (session_map(session),))?;
+        text.into_string().ok().filter(|text| !text.is_empty())
+    }
+}
+
+fn session_map(session: &SessionInfo) -> Map {
+    let mut map = Map::new();
+    map.insert("username".into(), session.username.clone().into());
+    map.insert("fingerprint".into(), session.fingerprint.clone().into());
+    map.insert("display".into(), session.display.clone().unwrap_or_default().into());
+    map
+}
+
+// A command's arguments, from an array of them or a string of them
+fn command_args(command: Dynamic) -> Option<Vec<String>> {
+    let args = if command.is_string() {
+        command.into_string().ok()?.split_whitespace().map(str::to_owned).collect()
+    } else {
+        command.try_cast::<Array>()?.into_iter().map(|arg| arg.to_string()).collect()
+    };
+    Some(args).filter(|args: &Vec<String>| !args.is_empty())
+}
+
+// Draws the script's overlay on every frame
+struct ScriptOverlay;
+
+impl Plugin for ScriptOverlay {
+    fn on_frame(&self, session: &SessionInfo, frame: &mut Vec<u8>) {
+        let Some(text) = script().and_then(|script| script.overlay(session)) else {
+            return;
+        };
+        for (row, line) in text.lines().enumerate() {
+            // Only text, so a script can't leave the terminal in a mess
+            let line: String = line.chars().filter(|c| !c.is_control()).collect();
+            frame.extend_from_slice(format!("\x1b[{};1H\x1b[0;97;40m{}\x1b[m", row + 1, line).as_bytes());
+        }
+    }
+}
+
+// Loads the script before any sessions start.  Only the first one loaded is used.
+pub fn load(path: &Path) {
+    SCRIPT.get_or_init(|| match Script::load(path.to_owned()) {
+        Ok(script) => {
+            info!("Loaded {}", path.display());
+            if script.overlay {
+                plugins::register(ScriptOverlay);
+            }
+            Some(script)
+        }
+        Err(e) => {
+            error!("Error loading the script {}: {}", path.display(), e);
+            None
+        }
+    });
+}
+
+// Passes input to the game through the script's on_input
+pub struct Scripted<S> {
+    pub sink: S,
+    pub session: Arc<SessionInfo>,
+}
+
+impl<S: InputSink> InputSink for Scripted<S> {
+    fn send(&self, args: &[&str]) -> Result<(), InputError> {
+        let Some(commands) = script().and_then(|script| script.input(&self.session, args)) else {
+            return self.sink.send(args);
+        };
+        for command in commands {
+            let command: Vec<&str> = command.iter().map(String::as_str).collect();
+            self.sink.send(&command)?;
+        }
+        Ok(())
+    }
+}
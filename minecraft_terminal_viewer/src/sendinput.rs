@@ -0,0 +1,243 @@
+// Input for a window on Windows, sent with SendInput instead of xdotool, from the
+// same commands `xdo` gives xdotool.  Keys go by scan code as well as virtual key,
+// since games (GLFW's, at least) look at the scan code.  Windows only gives input
+// to the window in front, so the window's brought to the front for it, which
+// means the terminal playing it needs to be somewhere that still gets typing then.
+
+use std::ptr;
+
+use windows_sys::Win32::Foundation::{HWND, POINT, RECT};
+use windows_sys::Win32::Graphics::Gdi::ClientToScreen;
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+    MapVirtualKeyW, SendInput as send_input, VkKeyScanW, INPUT, INPUT_0, INPUT_KEYBOARD, INPUT_MOUSE, KEYBDINPUT,
+    KEYEVENTF_EXTENDEDKEY, KEYEVENTF_KEYUP, KEYEVENTF_SCANCODE, MAPVK_VK_TO_VSC, MOUSEEVENTF_LEFTDOWN,
+    MOUSEEVENTF_LEFTUP, MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP, MOUSEEVENTF_MOVE, MOUSEEVENTF_RIGHTDOWN,
+    MOUSEEVENTF_RIGHTUP, MOUSEEVENTF_WHEEL, MOUSEINPUT, MOUSE_EVENT_FLAGS, VIRTUAL_KEY, VK_BACK, VK_DELETE, VK_DOWN,
+    VK_END, VK_ESCAPE, VK_F1, VK_HOME, VK_INSERT, VK_LCONTROL, VK_LEFT, VK_LSHIFT, VK_NEXT, VK_PRIOR, VK_RETURN,
+    VK_RIGHT, VK_TAB, VK_UP,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    FindWindowW, GetClientRect, GetDesktopWindow, GetForegroundWindow, SetCursorPos, SetForegroundWindow,
+    WHEEL_DELTA,
+};
+
+use crate::config::{GAME_HEIGHT, GAME_WIDTH};
+use crate::session_log::SessionLogs;
+use crate::xdo::{InputError, InputSink, KEYSYM_NAMES};
+
+// Sends input to the window with the title, or whatever's in front without one
+pub struct SendInput {
+    pub window: Option<String>,
+    pub logs: SessionLogs,
+}
+
+impl SendInput {
+    // Looked up each time, since the game can close and open it again.  The whole
+    // screen's the desktop window.
+    fn window(&self) -> Option<HWND> {
+        let Some(title) = &self.window else {
+            // SAFETY: no arguments, and there's always a desktop
+            return Some(unsafe { GetDesktopWindow() });
+        };
+        let title: Vec<u16> = title.encode_utf16().chain([0]).collect();
+        // SAFETY: the title's null terminated and outlives the call
+        let window = unsafe { FindWindowW(ptr::null(), title.as_ptr()) };
+        (!window.is_null()).then_some(window)
+    }
+
+    // The size of the window's inside, which mouse positions are scaled to
+    pub fn size(&self) -> (u16, u16) {
+        let mut rect = RECT {
+            left: 0,
+            top: 0,
+            right: 0,
+            bottom: 0,
+        };
+        // SAFETY: the window came from FindWindowW, and rect outlives the call
+        match self.window() {
+            Some(window) if unsafe { GetClientRect(window, &mut rect) } != 0 => (rect.right as u16, rect.bottom as u16),
+            _ => {
+                self.logs.line("input", "Couldn't find the window, so going by the game's usual size");
+                (GAME_WIDTH, GAME_HEIGHT)
+            }
+        }
+    }
+
+    // The inputs an xdotool command stands for, or None if it's not one xdo sends
+    fn inputs(&self, window: HWND, args: &[&str]) -> Option<Vec<INPUT>> {
+        let inputs = match args {
+            ["key", keysyms @ ..] => {
+                let mut inputs = vec![];
+                for keysym in keysyms {
+                    let (key, shifted) = virtual_key(keysym)?;
+                    if shifted {
+                        inputs.push(key_input(VK_LSHIFT, false));
+                    }
+                    inputs.extend([key_input(key, false), key_input(key, true)]);
+                    if shifted {
+                        inputs.push(key_input(VK_LSHIFT, true));
+                    }
+                }
+                inputs
+            }
+            ["keydown", keysym] => vec![key_input(virtual_key(keysym)?.0, false)],
+            ["keyup", keysym] => vec![key_input(virtual_key(keysym)?.0, true)],
+            ["mousedown", button] => vec![mouse_input(0, 0, 0, mouse_button(button)?.0)],
+            ["mouseup", button] => vec![mouse_input(0, 0, 0, mouse_button(button)?.1)],
+            ["click", "4"] => vec![mouse_input(0, 0, WHEEL_DELTA as i32, MOUSEEVENTF_WHEEL)],
+            ["click", "5"] => vec![mouse_input(0, 0, -(WHEEL_DELTA as i32), MOUSEEVENTF_WHEEL)],
+            ["click", button] => {
+                let (down, up) = mouse_button(button)?;
+                vec![mouse_input(0, 0, 0, down), mouse_input(0, 0, 0, up)]
+            }
+            ["mousemove", x, y] => {
+                let mut point = POINT {
+                    x: x.parse().ok()?,
+                    y: y.parse().ok()?,
+                };
+                // An absolute move is just putting the cursor there, relative to the window
+                // SAFETY: point outlives the calls
+                unsafe {
+                    ClientToScreen(window, &mut point);
+                    SetCursorPos(point.x, point.y);
+                }
+                vec![]
+            }
+            ["mousemove_relative", "--", dx, dy] => {
+                vec![mouse_input(dx.parse().ok()?, dy.parse().ok()?, 0, MOUSEEVENTF_MOVE)]
+            }
+            _ => return None,
+        };
+        Some(inputs)
+    }
+}
+
+impl InputSink for SendInput {
+    fn send(&self, args: &[&str]) -> Result<(), InputError> {
+        let Some(window) = self.window() else {
+            self.logs.line("input", &format!("Couldn't find the window for {}", args.join(" ")));
+            return Ok(());
+        };
+        // SAFETY: neither takes anything that has to stay valid
+        unsafe {
+            if self.window.is_some() && GetForegroundWindow() != window {
+                SetForegroundWindow(window);
+            }
+        }
+        let Some(inputs) = self.inputs(window, args) else {
+            self.logs.line("input", &format!("Can't send {} with SendInput", args.join(" ")));
+            return Ok(());
+        };
+        if inputs.is_empty() {
+            return Ok(());
+        }
+        // SAFETY: the inputs are all initialized, and the size is theirs
+        let sent = unsafe { send_input(inputs.len() as u32, inputs.as_ptr(), size_of::<INPUT>() as i32) };
+        if sent as usize != inputs.len() {
+            // Usually another program's in the way, e.g. one running as administrator
+            let args = args.join(" ");
+            self.logs.line("input", &format!("Only {} of {} inputs for {} got through", sent, inputs.len(), args));
+        }
+        Ok(())
+    }
+}
+
+// The virtual key for an xdotool keysym, and whether the character's typed with Shift
+fn virtual_key(keysym: &str) -> Option<(VIRTUAL_KEY, bool)> {
+    let key = match keysym {
+        "Return" => VK_RETURN,
+        "BackSpace" => VK_BACK,
+        "Tab" => VK_TAB,
+        "Escape" => VK_ESCAPE,
+        "Delete" => VK_DELETE,
+        "Home" => VK_HOME,
+        "End" => VK_END,
+        "Page_Up" => VK_PRIOR,
+        "Page_Down" => VK_NEXT,
+        "Up" => VK_UP,
+        "Down" => VK_DOWN,
+        "Left" => VK_LEFT,
+        "Right" => VK_RIGHT,
+        "Shift_L" => VK_LSHIFT,
+        "Control_L" => VK_LCONTROL,
+        _ => {
+            let function = keysym.strip_prefix('F').and_then(|n| n.parse::<u16>().ok());
+            if let Some(n) = function.filter(|n| (1..=24).contains(n)) {
+                return Some((VK_F1 + n - 1, false));
+            }
+            // Otherwise it's a character, in this keyboard layout
+            let c = keysym_char(keysym)?;
+            if c.len_utf16() != 1 {
+                return None;
+            }
+            // SAFETY: takes a character and nothing else
+            let scan = unsafe { VkKeyScanW(c as u16) };
+            if scan == -1 {
+                return None;
+            }
+            return Some(((scan & 0xff) as VIRTUAL_KEY, scan & 0x100 != 0));
+        }
+    };
+    Some((key, false))
+}
+
+// The character for a keysym xdo made from one
+fn keysym_char(keysym: &str) -> Option<char> {
+    let mut chars = keysym.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => KEYSYM_NAMES.iter().find(|(_, name)| *name == keysym).map(|(c, _)| *c),
+    }
+}
+
+fn key_input(key: VIRTUAL_KEY, up: bool) -> INPUT {
+    // SAFETY: takes a key code and nothing else
+    let scan = unsafe { MapVirtualKeyW(key as u32, MAPVK_VK_TO_VSC) } as u16;
+    let mut flags = KEYEVENTF_SCANCODE;
+    // These share scan codes with the number pad's, and are told apart by this
+    let extended = [VK_UP, VK_DOWN, VK_LEFT, VK_RIGHT, VK_HOME, VK_END, VK_PRIOR, VK_NEXT, VK_INSERT, VK_DELETE];
+    if extended.contains(&key) {
+        flags |= KEYEVENTF_EXTENDEDKEY;
+    }
+    if up {
+        flags |= KEYEVENTF_KEYUP;
+    }
+    INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: key,
+                wScan: scan,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: 0,
+            },
+        },
+    }
+}
+
+fn mouse_input(dx: i32, dy: i32, wheel: i32, flags: MOUSE_EVENT_FLAGS) -> INPUT {
+    INPUT {
+        r#type: INPUT_MOUSE,
+        Anonymous: INPUT_0 {
+            mi: MOUSEINPUT {
+                dx,
+                dy,
+                mouseData: wheel as u32,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: 0,
+            },
+        },
+    }
+}
+
+// What pressing and releasing one of xdotool's mouse buttons are
+fn mouse_button(button: &str) -> Option<(MOUSE_EVENT_FLAGS, MOUSE_EVENT_FLAGS)> {
+    match button {
+        "1" => Some((MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP)),
+        "2" => Some((MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP)),
+        "3" => Some((MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP)),
+        _ => None,
+    }
+}
@@ -0,0 +1,90 @@
+// What a session's tasks share while it runs, apart from the game itself, so the
+// input and rendering sides don't need `minecraft` (or X) to use it, and the list
+// of sessions running for the admin dashboard.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+use std::time::Instant;
+
+#[cfg(unix)]
+use crate::config::GAME_OUTPUT_LINES;
+use crate::config::{InputSettings, RenderSettings, TerminalSize};
+use crate::overlay::Overlay;
+use crate::plugins::SessionInfo;
+use crate::render::Streamed;
+use crate::session_log::SessionLogs;
+
+// State shared between a session's threads that can change while it runs
+#[derive(Clone)]
+pub struct SessionState {
+    pub running: Arc<AtomicBool>,
+    pub terminal_size: Arc<Mutex<TerminalSize>>,
+    pub input_settings: Arc<Mutex<InputSettings>>,
+    pub render_settings: Arc<Mutex<RenderSettings>>,
+    pub overlay: Arc<Mutex<Overlay>>,
+    pub last_input: Arc<Mutex<Instant>>,
+    pub game_log: Arc<Mutex<GameLog>>,
+    // Whether the player's on the death screen, going by the game's log
+    pub dead: Arc<AtomicBool>,
+    pub logs: SessionLogs,
+    // Who's playing, for plugins' hooks
+    pub session_info: Arc<SessionInfo>,
+}
+
+// The latest of the launcher's output, for the log viewer
+#[derive(Default)]
+pub struct GameLog {
+    pub lines: VecDeque<String>,
+    // Every line there's been, so viewers can tell when there's more
+    pub received: usize,
+}
+
+impl GameLog {
+    #[cfg(unix)]
+    pub(crate) fn push(&mut self, line: String) {
+        if self.lines.len() == GAME_OUTPUT_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+        self.received += 1;
+    }
+}
+
+// A session that's running, as the admin dashboard sees it
+#[derive(Clone)]
+pub struct LiveSession {
+    pub id: u64,
+    pub state: SessionState,
+    pub streamed: Arc<Streamed>,
+    pub started: Instant,
+}
+
+static LIVE: LazyLock<Mutex<BTreeMap<u64, LiveSession>>> = LazyLock::new(Default::default);
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+// Keeps a session on the list until it's dropped
+pub struct Listed(u64);
+
+impl Drop for Listed {
+    fn drop(&mut self) {
+        LIVE.lock().unwrap_or_else(PoisonError::into_inner).remove(&self.0);
+    }
+}
+
+pub fn list(state: SessionState, streamed: Arc<Streamed>) -> Listed {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let session = LiveSession {
+        id,
+        state,
+        streamed,
+        started: Instant::now(),
+    };
+    LIVE.lock().unwrap_or_else(PoisonError::into_inner).insert(id, session);
+    Listed(id)
+}
+
+// Every session running right now, oldest first
+pub fn live() -> Vec<LiveSession> {
+    LIVE.lock().unwrap_or_else(PoisonError::into_inner).values().cloned().collect()
+}
@@ -0,0 +1,105 @@
+// Each session's logs, kept in a directory of their own so an operator can look
+// into "it was black-screened for me" after the fact.  The directory is named for
+// when the session started and the player's key fingerprint, under
+// ANSICRAFT_SESSION_LOGS_DIR (no logs are kept unless that's set).  In it are the
+// game's output, ffmpeg's complaints, and trouble sending input.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::error;
+
+#[derive(Clone, Default)]
+pub struct SessionLogs {
+    // None when they're not being kept
+    dir: Option<PathBuf>,
+    files: Arc<Mutex<HashMap<String, File>>>,
+}
+
+// The time as e.g. 20240131-235959 (UTC), which sorts in order
+pub fn timestamp() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+    let (days, time) = ((secs / 86400) as i64, secs % 86400);
+    // Days since the epoch to a date, from Howard Hinnant's civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+impl SessionLogs {
+    // Starts the logs for a session by the player with this key fingerprint, in a
+    // directory of their own under `base`
+    pub fn create(base: Option<&Path>, fingerprint: &str) -> Self {
+        let Some(base) = base else {
+            return Self::default();
+        };
+        // Fingerprints are base64, which can have slashes in it
+        let fingerprint: String = fingerprint
+            .trim_start_matches("SHA256:")
+            .chars()
+            .map(|c| match c {
+                '/' => '_',
+                '+' => '-',
+                c if c.is_ascii_alphanumeric() => c,
+                _ => '-',
+            })
+            .collect();
+        let dir = base.join(format!("{}-{}", timestamp(), fingerprint));
+        if let Err(e) = fs::create_dir_all(&dir) {
+            error!("Error creating the session log directory {}: {}", dir.display(), e);
+            return Self::default();
+        }
+        Self { dir: Some(dir), files: Default::default() }
+    }
+
+    fn open(&self, name: &str) -> Option<File> {
+        let dir = self.dir.as_ref()?;
+        let mut files = self.files.lock().unwrap();
+        if !files.contains_key(name) {
+            let path = dir.join(format!("{}.log", name));
+            match OpenOptions::new().create(true).append(true).open(&path) {
+                Ok(file) => {
+                    files.insert(name.to_owned(), file);
+                }
+                Err(e) => {
+                    error!("Error opening {}: {}", path.display(), e);
+                    return None;
+                }
+            }
+        }
+        files.get(name)?.try_clone().ok()
+    }
+
+    // Adds a line to the named log
+    pub fn line(&self, name: &str, line: &str) {
+        if let Some(mut file) = self.open(name) {
+            let _ = writeln!(file, "{} {}", timestamp(), line);
+        }
+    }
+
+    // For a process to write its output to the named log, or nowhere if logs
+    // aren't being kept
+    pub fn stdio(&self, name: &str) -> Stdio {
+        self.open(name).map(Stdio::from).unwrap_or_else(Stdio::null)
+    }
+}
@@ -0,0 +1,114 @@
+// A player's session as they last had it set (the screen size they asked for, the
+// color mode, frame rate and keys), in a small key=value file named after them.
+// It's saved when they drop out and when each game ends, and the next game starts
+// from it if that's within SESSION_SETTINGS_KEPT, so what they changed with hotkeys
+// carries on after reconnecting, rejoining or moving off a failed display.  Games
+// played on a worker are sent the same lines, and send them back when they end.
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use tracing::{error, warn};
+
+use crate::config::{InputSettings, RenderMode, RenderSettings, SESSION_SETTINGS_KEPT};
+use crate::preferences;
+
+const SESSION_SETTINGS_DIR: &str = "session_settings";
+
+#[derive(Clone, Debug)]
+pub struct SessionSettings {
+    // The screen size the player asked for, if they did
+    pub size: Option<(u16, u16)>,
+    pub render: RenderSettings,
+    pub input: InputSettings,
+}
+
+fn settings_path(username: &str) -> Option<PathBuf> {
+    // Usernames are hex digests, but never let one escape the directory
+    if username.is_empty() || !username.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    Some(PathBuf::from(SESSION_SETTINGS_DIR).join(username))
+}
+
+// What a player's game starts with: their last session's settings if it was
+// recent enough, and otherwise the server's `render` and their preferences on
+// top of its `input`
+pub fn load(username: &str, render: RenderSettings, input: &InputSettings) -> SessionSettings {
+    let mut settings = SessionSettings {
+        size: None,
+        render,
+        input: preferences::load(username, input),
+    };
+    let Some(path) = settings_path(username) else {
+        return settings;
+    };
+    let fresh = fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age < SESSION_SETTINGS_KEPT));
+    if !fresh {
+        return settings;
+    }
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            error!("Error reading session settings for {}: {}", username, e);
+            return settings;
+        }
+    };
+
+    for line in apply(&mut settings, &contents) {
+        warn!("Ignoring session setting line for {}: {:?}", username, line);
+    }
+    settings
+}
+
+// Sets what each key=value line says, returning the lines that didn't make sense
+pub(crate) fn apply<'a>(settings: &mut SessionSettings, contents: &'a str) -> Vec<&'a str> {
+    let mut ignored = vec![];
+    for line in contents.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        let applied = match key.trim() {
+            "size" => match value.split_once('x').map(|(width, height)| (width.parse(), height.parse())) {
+                Some((Ok(width), Ok(height))) => {
+                    settings.size = Some((width, height));
+                    true
+                }
+                _ => false,
+            },
+            "mode" => RenderMode::from_str(value, true).map(|mode| settings.render.mode = mode).is_ok(),
+            "fps" => value.parse().map(|fps: u32| settings.render.fps = fps.max(1)).is_ok(),
+            key => preferences::apply(&mut settings.input, key, value),
+        };
+        if !applied {
+            ignored.push(line);
+        }
+    }
+    ignored
+}
+
+pub fn save(username: &str, settings: &SessionSettings) -> io::Result<()> {
+    let Some(path) = settings_path(username) else {
+        return Ok(());
+    };
+    fs::create_dir_all(SESSION_SETTINGS_DIR)?;
+    fs::write(path, lines(settings))
+}
+
+// The settings as key=value lines, for `apply` to read back
+pub(crate) fn lines(settings: &SessionSettings) -> String {
+    let mut contents = String::new();
+    if let Some((width, height)) = settings.size {
+        contents.push_str(&format!("size={}x{}\n", width, height));
+    }
+    if let Some(mode) = settings.render.mode.to_possible_value() {
+        contents.push_str(&format!("mode={}\n", mode.get_name()));
+    }
+    contents.push_str(&format!("fps={}\n", settings.render.fps));
+    contents.push_str(&preferences::lines(&settings.input));
+    contents
+}
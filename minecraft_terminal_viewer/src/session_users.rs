@@ -0,0 +1,94 @@
+// Each session's game run as an unprivileged user of its own, from the range of
+// UIDs in ANSICRAFT_SESSION_UIDS (e.g. 10000-10099), so one session can't read
+// another's worlds or the server's logins and keys.  The user doesn't have to
+// exist: the game runs with that UID (and the same GID) and nothing else.  Its
+// game directory is handed over to it, and a game without a directory of its own
+// gets an empty one for the session instead of the shared one.
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+#[cfg(feature = "server")]
+use std::path::PathBuf;
+use std::process::Command;
+
+#[cfg(feature = "server")]
+use tracing::{error, warn};
+
+#[cfg(feature = "server")]
+use crate::config::AppConfig;
+#[cfg(feature = "server")]
+use crate::sandbox;
+
+// The user a session on the resource runs as, if there's one for it
+#[cfg(feature = "server")]
+pub fn uid_for(resource_id: u32, settings: &AppConfig) -> Option<u32> {
+    let range = settings.session_uids.as_ref()?;
+    range.start().checked_add(resource_id).filter(|uid| range.contains(uid))
+}
+
+// An empty game directory for the session, with nothing left from the last one
+#[cfg(feature = "server")]
+pub fn scratch_dir(uid: u32) -> io::Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("ansicraft-{}", uid));
+    match fs::remove_dir_all(&dir) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+#[cfg(feature = "server")]
+fn add_mode(path: &Path, bits: u32) -> io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | bits);
+    fs::set_permissions(path, permissions)
+}
+
+// Lets session users get at the game files but nothing of the server's, once at
+// startup.  Run as root, that's usually a matter of /root being traversable.
+#[cfg(feature = "server")]
+pub fn prepare(sessions: usize, settings: &AppConfig) {
+    let Some(range) = settings.session_uids.clone() else {
+        return;
+    };
+    if range.clone().count() < sessions {
+        warn!(
+            "ANSICRAFT_SESSION_UIDS only has {} users for {} sessions, so the rest can't start",
+            range.count(),
+            sessions
+        );
+    }
+    for dir in settings.minecraft_dir.ancestors() {
+        if let Err(e) = add_mode(dir, 0o001) {
+            error!("Error letting session users into {}: {}", dir.display(), e);
+        }
+    }
+    let instances = settings.instances_dir.as_ref().and_then(|dir| fs::canonicalize(dir).ok());
+    for path in sandbox::private_paths(settings) {
+        // Players' own directories are in there, but they can't see each other's
+        let mode = match (&instances, path.is_dir()) {
+            (Some(instances), _) if *instances == path => 0o711,
+            (_, true) => 0o700,
+            (_, false) => 0o600,
+        };
+        if let Err(e) = fs::set_permissions(&path, fs::Permissions::from_mode(mode)) {
+            error!("Error hiding {} from session users: {}", path.display(), e);
+        }
+    }
+}
+
+// Runs the command as the user, after giving them the game directory
+pub fn apply(cmd: &mut Command, uid: u32, game_dir: &Path) -> io::Result<()> {
+    let status = Command::new("chown").arg("-R").arg(format!("{0}:{0}", uid)).arg(game_dir).status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("chown of {} exited with {}", game_dir.display(), status)));
+    }
+    fs::set_permissions(game_dir, fs::Permissions::from_mode(0o700))?;
+    // Java keeps a few things in the home directory
+    cmd.uid(uid).gid(uid).env("HOME", game_dir);
+    Ok(())
+}
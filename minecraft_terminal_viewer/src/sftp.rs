@@ -0,0 +1,156 @@
+// A read-only SFTP server for players to download their world backups, e.g. with
+// `sftp -P 2222 host`.  They only see their own backup directory, as /.
+
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+use russh_sftp::protocol::{Attrs, Data, File, FileAttributes, Handle, Name, OpenFlags, Status, StatusCode, Version};
+
+enum OpenHandle {
+    File(fs::File),
+    // Listed all at once, then nothing left on the next read
+    Dir(Option<Vec<File>>),
+}
+
+pub struct BackupFiles {
+    root: PathBuf,
+    handles: HashMap<String, OpenHandle>,
+    next_handle: u64,
+}
+
+impl BackupFiles {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root, handles: HashMap::new(), next_handle: 0 }
+    }
+
+    // The file a client's path refers to.  There are no subdirectories, so
+    // anything but a plain name is the root.
+    fn resolve(&self, path: &str) -> Result<PathBuf, StatusCode> {
+        let name = path.trim_start_matches("./").trim_matches('/');
+        if name.is_empty() || name == "." {
+            return Ok(self.root.clone());
+        }
+        if name.contains('/') || name == ".." {
+            return Err(StatusCode::NoSuchFile);
+        }
+        Ok(self.root.join(name))
+    }
+
+    fn add_handle(&mut self, handle: OpenHandle) -> String {
+        self.next_handle += 1;
+        let name = self.next_handle.to_string();
+        self.handles.insert(name.clone(), handle);
+        name
+    }
+}
+
+fn attributes(path: &Path) -> Result<FileAttributes, StatusCode> {
+    fs::metadata(path).map(|metadata| FileAttributes::from(&metadata)).map_err(|_| StatusCode::NoSuchFile)
+}
+
+fn ok(id: u32) -> Status {
+    Status { id, status_code: StatusCode::Ok, error_message: "Ok".to_owned(), language_tag: "en-US".to_owned() }
+}
+
+impl russh_sftp::server::Handler for BackupFiles {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(&mut self, _version: u32, _extensions: HashMap<String, String>) -> Result<Version, Self::Error> {
+        // Nothing's there until the first backup, but it shouldn't look broken
+        let _ = fs::create_dir_all(&self.root);
+        Ok(Version::new())
+    }
+
+    async fn realpath(&mut self, id: u32, path: String) -> Result<Name, Self::Error> {
+        let path = self.resolve(&path)?;
+        let name = match path.strip_prefix(&self.root) {
+            Ok(name) if !name.as_os_str().is_empty() => format!("/{}", name.display()),
+            _ => "/".to_owned(),
+        };
+        Ok(Name { id, files: vec![File::dummy(name)] })
+    }
+
+    async fn stat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+        Ok(Attrs { id, attrs: attributes(&self.resolve(&path)?)? })
+    }
+
+    async fn lstat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+        self.stat(id, path).await
+    }
+
+    async fn fstat(&mut self, id: u32, handle: String) -> Result<Attrs, Self::Error> {
+        match self.handles.get(&handle) {
+            Some(OpenHandle::File(file)) => {
+                let metadata = file.metadata().map_err(|_| StatusCode::Failure)?;
+                Ok(Attrs { id, attrs: FileAttributes::from(&metadata) })
+            }
+            Some(OpenHandle::Dir(_)) => Ok(Attrs { id, attrs: attributes(&self.root)? }),
+            None => Err(StatusCode::Failure),
+        }
+    }
+
+    async fn opendir(&mut self, id: u32, path: String) -> Result<Handle, Self::Error> {
+        let dir = self.resolve(&path)?;
+        if dir != self.root {
+            return Err(StatusCode::NoSuchFile);
+        }
+        let files = fs::read_dir(&dir)
+            .map_err(|_| StatusCode::NoSuchFile)?
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok().filter(|metadata| metadata.is_file())?;
+                Some(File::new(entry.file_name().to_string_lossy(), FileAttributes::from(&metadata)))
+            })
+            .collect();
+        Ok(Handle { id, handle: self.add_handle(OpenHandle::Dir(Some(files))) })
+    }
+
+    async fn readdir(&mut self, id: u32, handle: String) -> Result<Name, Self::Error> {
+        match self.handles.get_mut(&handle) {
+            Some(OpenHandle::Dir(files)) => match files.take() {
+                Some(files) => Ok(Name { id, files }),
+                None => Err(StatusCode::Eof),
+            },
+            _ => Err(StatusCode::Failure),
+        }
+    }
+
+    async fn open(
+        &mut self,
+        id: u32,
+        filename: String,
+        pflags: OpenFlags,
+        _attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        if pflags.intersects(OpenFlags::WRITE | OpenFlags::APPEND | OpenFlags::CREATE | OpenFlags::TRUNCATE) {
+            return Err(StatusCode::PermissionDenied);
+        }
+        let path = self.resolve(&filename)?;
+        let file = fs::File::open(&path).map_err(|_| StatusCode::NoSuchFile)?;
+        Ok(Handle { id, handle: self.add_handle(OpenHandle::File(file)) })
+    }
+
+    async fn read(&mut self, id: u32, handle: String, offset: u64, len: u32) -> Result<Data, Self::Error> {
+        let Some(OpenHandle::File(file)) = self.handles.get(&handle) else {
+            return Err(StatusCode::Failure);
+        };
+        let mut data = vec![0; len.min(256 * 1024) as usize];
+        let read = file.read_at(&mut data, offset).map_err(|_| StatusCode::Failure)?;
+        if read == 0 {
+            return Err(StatusCode::Eof);
+        }
+        data.truncate(read);
+        Ok(Data { id, data })
+    }
+
+    async fn close(&mut self, id: u32, handle: String) -> Result<Status, Self::Error> {
+        self.handles.remove(&handle);
+        Ok(ok(id))
+    }
+}
@@ -1,35 +1,67 @@
 use std::{
-    io::{Read, Write}, path::Path, sync::{
-        Arc, Mutex,
+    collections::{HashMap, HashSet}, path::{Path, PathBuf}, sync::{
+        Arc, LazyLock, Mutex, MutexGuard, PoisonError,
     }
 };
 
 use crate::{
-    minecraft,
-    queueing::{self, ResourceAllocator, ResourcePool},
+    accounts, admin, app, attract, audio, autoscale, backups, cluster, config, dashboard, docker, game_env, kubernetes, launcher, lobby, minecraft, gpu, plugins, preferences, prism, session_settings, session_users, sftp, warm, wm, xdo, xserver,
+    queueing::{self, PoolOptions, Provision, ResourceAllocator, ResourceDescriptor, ResourcePool},
+    render::Screen,
+    session_log::SessionLogs,
 };
 
 use anyhow;
 use rand_core::OsRng;
 use russh::{self, keys::{ssh_key::{self, public}, PublicKeyBase64}, server::Server};
 use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
 
-const MAX_SIMULTANEOUS_SESSIONS: u32 = 10;
+const DEFAULT_POOL: &str = "default";
+// Goes to the title screen instead of loading a world
+const NEW_WORLD: &str = "+ New world";
+// The server menu's entries that aren't a listed server
+const DEFAULT_SERVER: &str = "Default";
+const TYPE_SERVER: &str = "+ Type an address";
+
+// How a game ended, which decides what happens to the session next
+enum GameEnd {
+    Over,
+    // Cut short to give its slot to someone else
+    Reclaimed(minecraft::ReclaimReason),
+    // The display or capturing it failed, so it can carry on on another
+    DisplayLost,
+}
+
+// Why a client's connection was dropped
+#[derive(Debug, thiserror::Error)]
+pub enum SessionError {
+    #[error(transparent)]
+    Ssh(#[from] russh::Error),
+    // A panic somewhere else left it half-updated
+    #[error("{0} was poisoned by a panic")]
+    Poisoned(&'static str),
+}
+
+// Locks something the session shares, failing just this session if it's poisoned
+fn lock<'a, T>(mutex: &'a Mutex<T>, what: &'static str) -> Result<MutexGuard<'a, T>, SessionError> {
+    mutex.lock().map_err(|_| SessionError::Poisoned(what))
+}
 
 // Function to load or create SSH key
-pub fn load_or_create_ssh_key() -> russh::keys::PrivateKey {
+pub fn load_or_create_ssh_key(key_path: &Path) -> russh::keys::PrivateKey {
     // Honestly, maybe errors in this function should result in a panic.
-    let key_path = Path::new("ssh_server_key");
 
     // Try to load existing key
     if key_path.exists() {
         match russh::keys::load_secret_key(key_path, None) {
             Ok(key) => {
-                println!("Loaded existing SSH key");
+                info!("Loaded existing SSH key");
                 return key;
             }
             Err(e) => {
-                eprintln!("Error loading SSH key: {:?}, generating new one", e);
+                error!("Error loading SSH key: {:?}, generating new one", e);
             }
         }
     }
@@ -38,23 +70,676 @@ pub fn load_or_create_ssh_key() -> russh::keys::PrivateKey {
 
     match key.write_openssh_file(key_path, ssh_key::LineEnding::LF) {
         Ok(()) => {
-            println!("Generated new SSH key");
+            info!("Generated new SSH key");
         }
         Err(e) => {
-            eprintln!("Error saving SSH key: {:?}", e);
+            error!("Error saving SSH key: {:?}", e);
         }
     }
     return key;
 }
 
+// Loads a list of public keys in authorized_keys format, returning their base64 parts
+pub fn load_key_list(path: &Path) -> HashSet<String> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return HashSet::new(),
+        Err(e) => {
+            error!("Error reading key list {}: {}", path.display(), e);
+            return HashSet::new();
+        }
+    };
+
+    let keys: HashSet<String> = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            // "<type> <base64> [comment]", or just the base64 on its own
+            let mut fields = line.split_whitespace();
+            let first = fields.next()?;
+            Some(fields.next().unwrap_or(first).to_owned())
+        })
+        .collect();
+    info!("Loaded {} keys from {}", keys.len(), path.display());
+    keys
+}
+
+// Loads reservation codes, one per line as "<code> [<start> <end>]" with the window
+// in unix seconds.  Codes without a window are one-time invites.
+pub fn load_reservations(path: &Path) -> Vec<queueing::Reservation> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return vec![],
+        Err(e) => {
+            error!("Error reading reservations {}: {}", path.display(), e);
+            return vec![];
+        }
+    };
+
+    let unix_time = |field: &str| -> Option<std::time::SystemTime> {
+        let seconds = field.parse().ok()?;
+        Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(seconds))
+    };
+
+    let mut reservations = vec![];
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let window = match fields[1..] {
+            [] => None,
+            [start, end] => match (unix_time(start), unix_time(end)) {
+                (Some(start), Some(end)) => Some((start, end)),
+                _ => {
+                    warn!("Ignoring reservation with an invalid window: {}", line);
+                    continue;
+                }
+            },
+            _ => {
+                warn!("Ignoring malformed reservation: {}", line);
+                continue;
+            }
+        };
+        reservations.push(queueing::Reservation {
+            code: fields[0].to_owned(),
+            window,
+        });
+    }
+    info!("Loaded {} reservations from {}", reservations.len(), path.display());
+    reservations
+}
+
+// Parses a capacity schedule like "18:00=10,02:00=2,09:00=5", with times in UTC
+pub fn parse_capacity_schedule(spec: &str) -> Result<Vec<queueing::CapacityWindow>, String> {
+    let mut schedule = vec![];
+    for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let window = entry.split_once('=').and_then(|(time, capacity)| {
+            let (hours, minutes) = time.split_once(':')?;
+            let (hours, minutes) = (hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?);
+            if hours >= 24 || minutes >= 60 {
+                return None;
+            }
+            Some(queueing::CapacityWindow {
+                start: hours * 60 + minutes,
+                capacity: capacity.parse().ok()?,
+            })
+        });
+        schedule.push(window.ok_or_else(|| format!("\"{}\" should look like HH:MM=slots", entry))?);
+    }
+    Ok(schedule)
+}
+
+// Some of a pool's displays, and what they can do
+pub struct DisplayRange {
+    pub pool: String,
+    pub displays: std::ops::RangeInclusive<u32>,
+    pub gpu: bool,
+    pub resolution: (u16, u16),
+}
+
+// Parses pool definitions like "survival=1-6,creative=7-9,demo=10", giving each
+// pool a name and a range of X displays.  A pool can be listed more than once, and
+// tags after the range describe those displays, e.g. "survival=7-8:gpu:1280x720".
+pub fn parse_pools(spec: &str) -> Result<Vec<DisplayRange>, String> {
+    let mut ranges: Vec<DisplayRange> = vec![];
+    for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (name, rest) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("\"{}\" should look like name=first-last", entry))?;
+        let mut fields = rest.split(':');
+        let range = fields.next().unwrap_or("");
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        let (Ok(first), Ok(last)) = (first.parse::<u32>(), last.parse::<u32>()) else {
+            return Err(format!("\"{}\" isn't a range of display numbers", range));
+        };
+        if first == 0 || first > last {
+            return Err(format!("\"{}\" isn't a range of display numbers", range));
+        }
+        if let Some(other) = ranges
+            .iter()
+            .find(|other| first <= *other.displays.end() && *other.displays.start() <= last)
+        {
+            return Err(format!("Pools {} and {} share displays", other.pool, name));
+        }
+
+        let mut display_range = DisplayRange {
+            pool: name.to_owned(),
+            displays: first..=last,
+            gpu: false,
+            resolution: (config::GAME_WIDTH, config::GAME_HEIGHT),
+        };
+        for tag in fields {
+            if tag == "gpu" {
+                display_range.gpu = true;
+                continue;
+            }
+            match tag.split_once('x').map(|(width, height)| (width.parse(), height.parse())) {
+                Some((Ok(width), Ok(height))) => display_range.resolution = (width, height),
+                _ => return Err(format!("Unknown tag \"{}\" in \"{}\"", tag, entry)),
+            }
+        }
+        ranges.push(display_range);
+    }
+    if ranges.is_empty() {
+        return Err("No pools defined".to_owned());
+    }
+    Ok(ranges)
+}
+
+// Options passed as the SSH command, e.g. `ssh -t -p 2222 host code=abc123 short size=854x480 fps=10`
+#[derive(Clone, Default)]
+struct SessionOptions {
+    reservation: Option<String>,
+    // Skips the menu when there's more than one pool
+    pool: Option<String>,
+    // Trades a time limit for a better place in the queue
+    short: bool,
+    // Tags the player would like their display to have, if one's free
+    wants: Vec<String>,
+    // The screen size to play at, instead of the display's own
+    size: Option<(u16, u16)>,
+    // The frame rate cap to start with, instead of the last game's
+    fps: Option<u32>,
+    // Skips the menu when there's more than one game version
+    version: Option<String>,
+    // The saved world to load, for singleplayer
+    world: Option<String>,
+    // The server to join instead of the pool's, "" for singleplayer
+    server: Option<String>,
+    // Log in to a Microsoft account first, or forget the saved one
+    login: bool,
+    logout: bool,
+}
+
+impl SessionOptions {
+    fn parse(command: &str, settings: &config::AppConfig) -> Result<Self, String> {
+        let mut options = Self::default();
+        for word in command.split_whitespace() {
+            match word {
+                "short" => {
+                    options.short = true;
+                    continue;
+                }
+                "gpu" => {
+                    options.wants.push(word.to_owned());
+                    continue;
+                }
+                "login" => {
+                    options.login = true;
+                    continue;
+                }
+                "logout" => {
+                    options.logout = true;
+                    continue;
+                }
+                _ => {}
+            }
+            match word.split_once('=') {
+                Some(("code", code)) => options.reservation = Some(code.to_owned()),
+                Some(("pool", pool)) => options.pool = Some(pool.to_owned()),
+                Some(("version", version)) if settings.game_versions().iter().any(|known| known == version) => {
+                    options.version = Some(version.to_owned())
+                }
+                Some(("version", version)) => return Err(format!("Unknown version \"{}\"", version)),
+                Some(("server", server)) => options.server = Some(allowed_server(server, settings)?),
+                Some(("size", size)) => {
+                    let parsed = size.split_once('x').map(|(width, height)| (width.parse(), height.parse()));
+                    let Some((Ok(width), Ok(height))) = parsed else {
+                        return Err(format!("\"{}\" isn't a size like 854x480", size));
+                    };
+                    // The display has to be at least that big to be resized to it
+                    options.wants.push(size.to_owned());
+                    options.size = Some((width, height));
+                }
+                Some(("fps", fps)) => match fps.parse::<u32>() {
+                    Ok(fps) => options.fps = Some(fps.max(1)),
+                    Err(_) => return Err(format!("\"{}\" isn't a frame rate", fps)),
+                },
+                _ => return Err(format!("Unknown option \"{}\"", word)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+// Servers players can pick from, from ANSICRAFT_SERVERS, e.g.
+// "Hypixel=mc.hypixel.net,Friends=10.0.0.5:25566", as (name, address)
+fn listed_servers(settings: &config::AppConfig) -> Vec<(String, String)> {
+    settings
+        .servers
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .filter_map(|entry| {
+            let (name, address) = entry.split_once('=').unwrap_or((entry, entry));
+            match parse_server_address(address) {
+                Ok(address) => Some((name.trim().to_owned(), address)),
+                Err(e) => {
+                    warn!("Ignoring server \"{}\" in ANSICRAFT_SERVERS: {}", entry, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+// Checks a server address is a host name or IP, with an optional port, and
+// nothing the launcher could take for something else
+fn parse_server_address(text: &str) -> Result<String, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("Enter a server address".to_owned());
+    }
+    let (host, port) = match text.strip_prefix('[') {
+        // [IPv6]:port
+        Some(rest) => {
+            let (host, after) = rest.split_once(']').ok_or("Missing ] after the IPv6 address")?;
+            host.parse::<std::net::Ipv6Addr>().map_err(|_| format!("\"{}\" isn't an IPv6 address", host))?;
+            let port = match after {
+                "" => None,
+                _ => Some(after.strip_prefix(':').ok_or("Expected :port after the IPv6 address")?),
+            };
+            (format!("[{}]", host), port)
+        }
+        None => {
+            let (host, port) = match text.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (text, None),
+            };
+            let valid = host.len() <= 253
+                && host.split('.').all(|label| {
+                    !label.is_empty()
+                        && label.len() <= 63
+                        && !label.starts_with('-')
+                        && !label.ends_with('-')
+                        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                });
+            if !valid {
+                return Err(format!("\"{}\" isn't a valid host name", host));
+            }
+            (host.to_lowercase(), port)
+        }
+    };
+    match port {
+        None => Ok(host),
+        Some(port) => match port.parse::<u16>() {
+            Ok(port) if port > 0 => Ok(format!("{}:{}", host, port)),
+            _ => Err(format!("\"{}\" isn't a valid port", port)),
+        },
+    }
+}
+
+// Whether a glob with * in it matches the whole of some text
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((prefix, rest)) => {
+            let Some(text) = text.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=text.len()).any(|skip| text.is_char_boundary(skip) && glob_matches(rest, &text[skip..]))
+        }
+    }
+}
+
+// Whether an address matches any of a comma-separated list of patterns like
+// "*.example.com", "10.0.0.*:25565" or "play.example.net:*".  Without a port a
+// pattern matches any port.
+fn matches_server_patterns(address: &str, patterns: &str) -> bool {
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) if !port.ends_with(']') => (host, port),
+        _ => (address, "25565"),
+    };
+    patterns
+        .split(',')
+        .map(|pattern| pattern.trim().to_lowercase())
+        .filter(|pattern| !pattern.is_empty())
+        .any(|pattern| {
+            let (host_pattern, port_pattern) = match pattern.rsplit_once(':') {
+                Some((host, port)) if !port.ends_with(']') => (host.to_owned(), port.to_owned()),
+                _ => (pattern, "*".to_owned()),
+            };
+            glob_matches(&host_pattern, host) && glob_matches(&port_pattern, port)
+        })
+}
+
+// Whether an address is the host's own or on its network, which players can't
+// send the game to
+fn internal_address(ip: std::net::IpAddr) -> bool {
+    match ip {
+        std::net::IpAddr::V4(ip) => {
+            ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified() || ip.is_broadcast()
+        }
+        std::net::IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => internal_address(ip.into()),
+            None => ip.is_loopback() || ip.is_unspecified() || ip.is_unique_local() || ip.is_unicast_link_local(),
+        },
+    }
+}
+
+// A server a player asked for, by name or address, if they're allowed to join it.
+// Listed servers always are, and typed ones have to be on ANSICRAFT_SERVER_ALLOW
+// if it's set and can't lead anywhere internal.  Nothing on ANSICRAFT_SERVER_DENY
+// is, so the host can't be used to reach anything the operator doesn't want it to.
+fn allowed_server(text: &str, settings: &config::AppConfig) -> Result<String, String> {
+    let listed = listed_servers(settings);
+    let address = match listed.iter().find(|(name, _)| name.eq_ignore_ascii_case(text)) {
+        Some((_, address)) => address.clone(),
+        None => parse_server_address(text)?,
+    };
+    let denied =
+        |address: &str| settings.server_deny.as_ref().is_some_and(|deny| matches_server_patterns(address, deny));
+    let is_listed = listed.iter().any(|(_, listed)| *listed == address);
+    let allowed = is_listed
+        || (settings.custom_servers
+            && settings.server_allow.as_ref().is_none_or(|allow| matches_server_patterns(&address, allow)));
+    let refused = || format!("Joining \"{}\" isn't allowed here", address);
+    if !allowed || denied(&address) {
+        return Err(refused());
+    }
+    if is_listed {
+        return Ok(address);
+    }
+    // Names and odd ways of writing an IP can still lead somewhere internal, so
+    // it's where a typed address goes that counts
+    let target = match address.rsplit_once(':') {
+        Some((_, port)) if !port.ends_with(']') => address.clone(),
+        _ => format!("{}:25565", address),
+    };
+    let resolved: Vec<std::net::SocketAddr> = std::net::ToSocketAddrs::to_socket_addrs(&target)
+        .map_err(|e| format!("Couldn't look up \"{}\": {}", address, e))?
+        .collect();
+    if resolved.iter().any(|socket| internal_address(socket.ip()) || denied(&socket.to_string())) {
+        return Err(refused());
+    }
+    Ok(address)
+}
+
+// A directory of the player's own under ANSICRAFT_INSTANCES_DIR for the game's
+// options, resource packs and worlds, if players get their own
+fn player_game_dir(username: &str, settings: &config::AppConfig) -> Option<PathBuf> {
+    // PrismLauncher's instances each have their own
+    if settings.prism.enabled() {
+        return None;
+    }
+    let instances = settings.instances_dir.as_ref()?;
+    // Usernames are hex digests, but never let one escape the directory
+    if username.is_empty() || !username.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let dir = instances.join(username);
+    match std::fs::create_dir_all(&dir).and_then(|_| dir.canonicalize()) {
+        Ok(dir) => Some(dir),
+        Err(e) => {
+            error!("Error creating game directory {}, using the shared one: {}", dir.display(), e);
+            None
+        }
+    }
+}
+
+// The worlds in a game directory, most recently played first
+fn saved_worlds(game_dir: &Path) -> Vec<lobby::MenuEntry> {
+    let Ok(entries) = std::fs::read_dir(game_dir.join("saves")) else {
+        return vec![];
+    };
+    let mut worlds: Vec<(std::time::SystemTime, String)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let played = entry.path().join("level.dat").metadata().ok()?.modified().ok()?;
+            Some((played, entry.file_name().into_string().ok()?))
+        })
+        .collect();
+    worlds.sort_by(|a, b| b.cmp(a));
+    worlds
+        .into_iter()
+        .map(|(played, name)| {
+            let days = played.elapsed().unwrap_or_default().as_secs() / (24 * 60 * 60);
+            let detail = match days {
+                0 => "played today".to_owned(),
+                1 => "played yesterday".to_owned(),
+                days => format!("played {} days ago", days),
+            };
+            lobby::MenuEntry { name, detail }
+        })
+        .collect()
+}
+
+// Whether to start an X server (or a whole container) for each session instead of
+// using ones that are already running
+pub fn provision(settings: &config::AppConfig) -> Provision {
+    if docker::enabled(settings) {
+        Provision::Docker
+    } else if kubernetes::enabled(settings) {
+        Provision::Kubernetes
+    } else if settings.spawn_x {
+        Provision::SpawnX
+    } else {
+        Provision::Running
+    }
+}
+
+// The displays with an X server running, going by their sockets.  :0 is left out
+// since it's usually somebody's desktop.
+pub fn discover_displays() -> Vec<u32> {
+    let mut displays: Vec<u32> = match std::fs::read_dir("/tmp/.X11-unix") {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().to_str()?.strip_prefix('X')?.parse().ok())
+            .filter(|display| *display > 0)
+            .collect(),
+        Err(e) => {
+            error!("Error looking for X displays: {}", e);
+            vec![]
+        }
+    };
+    displays.sort();
+    displays
+}
+
+// The X servers started by entry-point.sh, on displays :1 and up, or the displays
+// to start them on
+fn x_server_resources(
+    ranges: &[DisplayRange],
+    provision: Provision,
+    settings: &config::AppConfig,
+) -> Vec<ResourceDescriptor> {
+    ranges
+        .iter()
+        .flat_map(|range| {
+            range.displays.clone().map(move |display| ResourceDescriptor {
+                id: display - 1,
+                pool: range.pool.clone(),
+                // Pods' displays are forwarded to TCP ports here
+                display: if provision == Provision::Kubernetes {
+                    format!("localhost:{}", display)
+                } else {
+                    format!(":{}", display)
+                },
+                ports: audio::port(display - 1, settings).into_iter().collect(),
+                gpu: range.gpu,
+                resolution: range.resolution,
+                weight: if range.gpu { gpu::weight(settings) } else { 1 },
+                provision,
+                data_dir: None,
+            })
+        })
+        .collect()
+}
+
 pub struct MinecraftSshServer {
-    x_server_pool: ResourcePool,
+    x_server_pool: ResourcePool<ResourceDescriptor>,
+    vip_keys: KeyList,
+    admin_keys: KeyList,
+    jvm_args: Arc<Vec<String>>,
+    app: Arc<dyn app::AppLauncher>,
+    settings: config::LiveConfig,
+    // Where the autoscaler starts numbering the resources it adds
+    next_resource_id: u32,
+}
+
+// A list of keys, which is loaded again when the config's reloaded
+type KeyList = Arc<std::sync::RwLock<HashSet<String>>>;
+
+// The queue's options from the config, but for reservations, which are only read
+// at startup
+fn pool_options(settings: &config::AppConfig) -> PoolOptions {
+    let mut options = PoolOptions::from_config(settings);
+    if !settings.capacity_schedule.is_empty() {
+        options.capacity_schedule = parse_capacity_schedule(&settings.capacity_schedule).unwrap_or_else(|e| {
+            warn!("Ignoring ANSICRAFT_CAPACITY_SCHEDULE: {}", e);
+            vec![]
+        });
+    } else if let Some(capacity) = settings.capacity {
+        // The same all day
+        options.capacity_schedule = vec![queueing::CapacityWindow { start: 0, capacity }];
+    }
+    options
+}
+
+// Reads the config again and applies it to what's running.  Sessions read the rest
+// from it as they start.
+fn reload(
+    settings: &config::LiveConfig,
+    vip_keys: &KeyList,
+    admin_keys: &KeyList,
+    allocator: &ResourceAllocator<ResourceDescriptor>,
+) -> Result<String, String> {
+    let config = settings.reload()?;
+    *vip_keys.write().unwrap_or_else(PoisonError::into_inner) = load_key_list(&config.vip_keys);
+    *admin_keys.write().unwrap_or_else(PoisonError::into_inner) = load_key_list(&config.admin_keys);
+    allocator.reconfigure(pool_options(&config));
+    Ok("Reloaded the config. Games already running keep their settings, but for the frame rate cap.\n".to_owned())
 }
 
 impl MinecraftSshServer {
-    pub fn new() -> Self {
+    pub fn new(live_settings: &config::LiveConfig) -> Self {
+        let settings = live_settings.get();
+        let mut pool_options = pool_options(&settings);
+        pool_options.reservations = load_reservations(&settings.reservations);
+
+        // A gateway plays its games on workers, so it's no displays of its own to set up
+        if !settings.workers.is_empty() {
+            let resources = cluster::worker_resources(&settings.workers, DEFAULT_POOL, 0).unwrap_or_else(|e| {
+                error!("Ignoring ANSICRAFT_WORKERS: {}", e);
+                vec![]
+            });
+            info!("Playing games on workers, up to {} at once", resources.len());
+            if settings.cluster_token.is_empty() {
+                warn!("ANSICRAFT_CLUSTER_TOKEN isn't set, so workers will turn every game away");
+            }
+            cluster::watch(resources.iter().map(|resource| resource.display.clone()));
+            let next_resource_id = resources.iter().map(|resource| resource.id + 1).max().unwrap_or(0);
+            return Self {
+                x_server_pool: ResourcePool::new(resources, pool_options),
+                vip_keys: Arc::new(std::sync::RwLock::new(load_key_list(&settings.vip_keys))),
+                admin_keys: Arc::new(std::sync::RwLock::new(load_key_list(&settings.admin_keys))),
+                jvm_args: Arc::new(vec![]),
+                app: app::from_config(&settings.app),
+                settings: live_settings.clone(),
+                next_resource_id,
+            };
+        }
+
+        let provision = provision(&settings);
+        let spawn_x = provision != Provision::Running;
+        let running_displays = if spawn_x { vec![] } else { discover_displays() };
+        if !spawn_x {
+            info!("Found X displays: {:?}", running_displays);
+        }
+
+        // One pool of every display unless told otherwise
+        let last_display = if spawn_x {
+            settings.max_sessions
+        } else {
+            // As many as there'd be started, when none can be found
+            running_displays.last().copied().unwrap_or(config::MAX_SESSIONS)
+        };
+        let default_pools = vec![DisplayRange {
+            pool: DEFAULT_POOL.to_owned(),
+            displays: 1..=last_display,
+            gpu: false,
+            resolution: (config::GAME_WIDTH, config::GAME_HEIGHT),
+        }];
+        let pools = match &settings.pools {
+            Some(spec) => parse_pools(spec).unwrap_or_else(|e| {
+                warn!("Ignoring ANSICRAFT_POOLS: {}", e);
+                default_pools
+            }),
+            None => default_pools,
+        };
+
+        let mut resources = x_server_resources(&pools, provision, &settings);
+        if provision == Provision::Docker {
+            info!("Starting a container per session, up to {} at once", resources.len());
+        } else if provision == Provision::Kubernetes {
+            info!("Starting a pod per session, up to {} at once", resources.len());
+        } else if spawn_x {
+            info!("Starting X servers per session, up to {} at once", resources.len());
+        } else if running_displays.is_empty() {
+            warn!("No X displays found, assuming {} will start", resources.len());
+        } else {
+            resources.retain(|resource| {
+                let running = running_displays.contains(&(resource.id + 1));
+                if !running {
+                    info!("Leaving out display {} since it isn't running", resource.display);
+                }
+                running
+            });
+        }
+        // Displays started per session get a window manager when they start
+        if !spawn_x {
+            for resource in &resources {
+                wm::supervise(&resource.display, settings.window_manager.as_deref());
+            }
+        }
+
+        // Sized so every session running at once fits in memory
+        let jvm_args = settings.jvm_args(resources.len());
+        info!("Launching games with JVM arguments {:?}", jvm_args);
+        session_users::prepare(resources.len(), &settings);
+
+        // Installing a version takes a while, so get it done before anyone wants it
+        if settings.app.is_minecraft() && !settings.prism.enabled() && settings.launcher.launcher.is_empty() {
+            let (mut versions, settings) = (settings.game_versions(), settings.clone());
+            std::thread::spawn(move || {
+                if versions.is_empty() {
+                    versions.push(String::new());
+                }
+                for version in versions {
+                    if let Err(e) = launcher::install(&version, &settings) {
+                        error!("Error installing Minecraft {}: {}", version, e);
+                    }
+                }
+            });
+        }
+
+        // Warm instances can't join a server, so they only go on singleplayer displays,
+        // and need the display to be running before anyone asks for it
+        warm::init(
+            resources
+                .iter()
+                .filter(|resource| settings.server_address(&resource.pool).is_empty())
+                // They're launched without VirtualGL
+                .filter(|resource| !resource.gpu || settings.gpu_render == config::GpuMode::Off)
+                .map(|resource| (resource.display.clone(), resource.resolution))
+                .collect(),
+            if spawn_x { config::WarmTarget::Fixed(0) } else { settings.warm },
+            jvm_args.clone(),
+            settings.clone(),
+        );
+
+        let next_resource_id = resources.iter().map(|resource| resource.id + 1).max().unwrap_or(0);
         Self {
-            x_server_pool: ResourcePool::new(MAX_SIMULTANEOUS_SESSIONS),
+            x_server_pool: ResourcePool::new(resources, pool_options),
+            // Patrons, admins and friends listed here skip ahead in the queue
+            vip_keys: Arc::new(std::sync::RwLock::new(load_key_list(&settings.vip_keys))),
+            // Keys allowed to run `admin` commands
+            admin_keys: Arc::new(std::sync::RwLock::new(load_key_list(&settings.admin_keys))),
+            jvm_args: Arc::new(jvm_args),
+            app: app::from_config(&settings.app),
+            settings: live_settings.clone(),
+            next_resource_id,
         }
     }
 
@@ -66,35 +751,153 @@ impl MinecraftSshServer {
             inactivity_timeout: Some(std::time::Duration::from_secs(3600)),
             auth_rejection_time: std::time::Duration::from_secs(0),
             auth_rejection_time_initial: Some(std::time::Duration::from_secs(0)),
-            keys: vec![load_or_create_ssh_key()],
+            keys: vec![load_or_create_ssh_key(&self.settings.get().host_key)],
             nodelay: true,
             channel_buffer_size: 1,
             methods: authentication_methods,
             ..Default::default()
         };
 
-        self.run_on_address(Arc::new(config), ("0.0.0.0", 2222))
+        // `kill -HUP` reloads the config, the same as `admin reload`
+        let (settings, vip_keys, admin_keys) = (self.settings.clone(), self.vip_keys.clone(), self.admin_keys.clone());
+        let allocator = ResourceAllocator::new(&self.x_server_pool);
+        let socket_reload = {
+            let (settings, vip_keys, admin_keys, allocator) =
+                (settings.clone(), vip_keys.clone(), admin_keys.clone(), allocator.clone());
+            move || reload(&settings, &vip_keys, &admin_keys, &allocator)
+        };
+        tokio::spawn(autoscale::run(allocator.clone(), self.settings.clone(), self.next_resource_id));
+        let admin_socket = admin::listen(self.settings.get().admin_socket.clone(), allocator.clone(), socket_reload);
+        tokio::spawn(async move {
+            if let Err(e) = admin_socket.await {
+                error!("Can't take admin commands on the admin socket: {}", e);
+            }
+        });
+        tokio::spawn(async move {
+            let mut hangups = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+                Ok(hangups) => hangups,
+                Err(e) => {
+                    error!("Can't reload the config on SIGHUP: {}", e);
+                    return;
+                }
+            };
+            while hangups.recv().await.is_some() {
+                match reload(&settings, &vip_keys, &admin_keys, &allocator) {
+                    Ok(message) => info!("{}", message.trim_end()),
+                    Err(e) => error!("Error reloading the config, leaving it as it was: {}", e),
+                }
+            }
+        });
+
+        let port = self.settings.get().port;
+        self.run_on_address(Arc::new(config), ("0.0.0.0", port))
             .await?;
         Ok(())
     }
+
+    // `termcast worker`: plays the games a gateway sends, on this machine's displays,
+    // queueing for them like its own players would but never waiting
+    pub async fn work(&self) -> Result<(), anyhow::Error> {
+        let settings = self.settings.get();
+        if settings.cluster_token.is_empty() {
+            anyhow::bail!("Set ANSICRAFT_CLUSTER_TOKEN to the gateway's, so only it can send games here");
+        }
+        let listener = tokio::net::TcpListener::bind(("0.0.0.0", settings.worker_port)).await?;
+        info!("Taking games from the gateway on port {}", settings.worker_port);
+        let host = GameHost {
+            jvm_args: self.jvm_args.clone(),
+            app: self.app.clone(),
+            settings: self.settings.clone(),
+        };
+        loop {
+            let (stream, _) = listener.accept().await?;
+            // Each game's request is its own, so one game can't cancel another's
+            let (host, allocator, settings) =
+                (host.clone(), ResourceAllocator::new(&self.x_server_pool), settings.clone());
+            tokio::spawn(async move {
+                if let Some(dispatch) = cluster::accept(stream, &settings).await {
+                    host.play_dispatched(dispatch, allocator).await;
+                }
+            });
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct MinecraftClientSession {
-    allocator: ResourceAllocator,
+    allocator: ResourceAllocator<ResourceDescriptor>,
     username: String,
-    my_request_id: Option<usize>, // I think this can be eliminated
+    priority: u8,
+    source_ip: Option<std::net::IpAddr>,
+    fingerprint: String,
+    is_admin: bool,
+    vip_keys: KeyList,
+    admin_keys: KeyList,
+    jvm_args: Arc<Vec<String>>,
+    settings: config::LiveConfig,
+    app: Arc<dyn app::AppLauncher>,
     my_x_session: Option<u32>,
     terminal_size: Arc<Mutex<crate::config::TerminalSize>>, // Store terminal size for resize events
+    terminal_rows: Arc<std::sync::atomic::AtomicUsize>, // The game works from the width, but the waiting room needs this
+    // The client's TERM and COLORTERM, for which colors the game's drawn with
+    term: String,
+    colorterm: String,
     input_channel_tx: mpsc::UnboundedSender<Vec<u8>>,
-    input_channel_rx: Arc<Mutex<mpsc::UnboundedReceiver<Vec<u8>>>>,
+    input_channel_rx: xdo::InputSource,
+    running: Arc<std::sync::atomic::AtomicBool>,
+    // Set when the client goes away, as opposed to the game ending
+    disconnected: Arc<std::sync::atomic::AtomicBool>,
+    // The game this client is showing, if it's playing one
+    game: Arc<Mutex<Option<GameLink>>>,
+    // Channels that haven't been asked for anything yet, held on to in case it's
+    // for SFTP, which needs the channel itself
+    pending_channels: Arc<Mutex<HashMap<russh::ChannelId, russh::Channel<russh::server::Msg>>>>,
+}
+
+// A running game and the client it's showing on, which changes if the player
+// drops out and comes back
+#[derive(Clone)]
+struct GameLink {
+    client: Arc<Mutex<Option<(russh::server::Handle, russh::ChannelId)>>>,
+    // Where the game reads input and terminal size changes from
+    input_tx: mpsc::UnboundedSender<Vec<u8>>,
+    terminal_size: Arc<Mutex<crate::config::TerminalSize>>,
+    display: String,
+    // Played on a worker, whose display can't be reached from here
+    remote: bool,
+    app: Arc<dyn app::AppLauncher>,
     running: Arc<std::sync::atomic::AtomicBool>,
+    // Bumped each time the player drops out, so a client from before knows to let go
+    detachments: Arc<std::sync::atomic::AtomicUsize>,
+    // Cancelled when a player who dropped out is back, or their game's over, so
+    // nothing's waiting to stop it any more
+    returned: CancellationToken,
+    // What the player asked for and has changed since, kept for their next game
+    size: Option<(u16, u16)>,
+    render_settings: Arc<Mutex<config::RenderSettings>>,
+    input_settings: Arc<Mutex<config::InputSettings>>,
+}
+
+impl GameLink {
+    fn save_settings(&self, username: &str) {
+        let settings = session_settings::SessionSettings {
+            size: self.size,
+            render: self.render_settings.lock().unwrap_or_else(PoisonError::into_inner).clone(),
+            input: self.input_settings.lock().unwrap_or_else(PoisonError::into_inner).clone(),
+        };
+        if let Err(e) = session_settings::save(username, &settings) {
+            error!("Error saving session settings for {}: {}", username, e);
+        }
+    }
 }
 
+// Paused games waiting for their players to reconnect, by username
+static DETACHED: LazyLock<Mutex<HashMap<String, GameLink>>> = LazyLock::new(Default::default);
+
 impl Server for MinecraftSshServer {
     type Handler = MinecraftClientSession;
 
-    fn new_client(&mut self, _peer_addr: Option<std::net::SocketAddr>) -> Self::Handler {
+    fn new_client(&mut self, peer_addr: Option<std::net::SocketAddr>) -> Self::Handler {
         // Create allocator from pool for each new client
         let allocator = ResourceAllocator::new(&self.x_server_pool);
 
@@ -102,16 +905,30 @@ impl Server for MinecraftSshServer {
 
         MinecraftClientSession {
             username: "".to_owned(),
+            priority: queueing::PRIORITY_NORMAL,
+            source_ip: peer_addr.map(|addr| addr.ip()),
+            fingerprint: "".to_owned(),
+            is_admin: false,
+            vip_keys: self.vip_keys.clone(),
+            admin_keys: self.admin_keys.clone(),
+            jvm_args: self.jvm_args.clone(),
+            settings: self.settings.clone(),
+            app: self.app.clone(),
             allocator,
-            my_request_id: None,
             my_x_session: None, // Sooo, due to the clone semantics, I'm pretty sure that this causes the session to not get cleaned up by drop because it only gets added after the clone happens.  Some arc/mutex action can fix this.  I'll deal with it later.
+            terminal_rows: Arc::new(std::sync::atomic::AtomicUsize::new(24)),
+            term: String::new(),
+            colorterm: String::new(),
             terminal_size: Arc::new(Mutex::new(crate::config::TerminalSize {
                 target_width: 10,
                 target_height: 10,
             })),
             input_channel_tx,
-            input_channel_rx: Arc::new(Mutex::new(input_channel_rx)),
+            input_channel_rx: Arc::new(tokio::sync::Mutex::new(input_channel_rx)),
             running: Arc::new(std::sync::atomic::AtomicBool::new(true)),
+            disconnected: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            game: Arc::new(Mutex::new(None)),
+            pending_channels: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -121,114 +938,980 @@ impl Server for MinecraftSshServer {
 }
 
 impl MinecraftClientSession {
+    // Who this is, for plugins
+    fn session_info(&self, display: Option<String>) -> plugins::SessionInfo {
+        plugins::SessionInfo {
+            username: self.username.clone(),
+            fingerprint: self.fingerprint.clone(),
+            source_ip: self.source_ip,
+            display,
+        }
+    }
+
     fn cleanup_resources(&mut self) {
+        // A paused game carries on without us until its player's back or time's up
+        let detached = DETACHED
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&self.username)
+            .is_some_and(|link| Arc::ptr_eq(&link.running, &self.running));
+        if detached {
+            self.allocator.cancel();
+            return;
+        }
+
+        // Before stopping the game, so whoever releases its resource knows why it stopped
+        self.disconnected.store(true, std::sync::atomic::Ordering::SeqCst);
         self.running.store(false, std::sync::atomic::Ordering::SeqCst);
 
-        if self.my_request_id.is_some() && self.my_x_session.is_none() {
-            // Currently no direct way to remove a pending request; can implement cancellation logic here later
-        }
+        // Frees our place in the queue if we were still waiting
+        self.allocator.cancel();
 
         if let Some(resource) = self.my_x_session.take() {
             // Use try_send so it works in sync Drop context
             // Gotta check for errors here
-            println!("Releasing resource {}", resource);
+            info!("Releasing resource {}", resource);
             let _release_response = self.allocator.release(resource);
         }
     }
 
-    fn set_terminal_size(&mut self, width: u32) -> anyhow::Result<()> {
-        let mut size = self.terminal_size.lock()
-            .map_err(|e| anyhow::anyhow!("Failed to lock terminal size mutex: {}", e))?;
+    // Keeps the game going (paused, if it can be) when the client drops out, for
+    // the player to come back to.  Returns whether it was kept.
+    fn detach_game(&self) -> bool {
+        let Some(link) = self.game.lock().unwrap_or_else(PoisonError::into_inner).take() else {
+            return false;
+        };
+        let Some(grace) = self.settings.get().keep_game else {
+            return false;
+        };
+        if !link.running.load(std::sync::atomic::Ordering::SeqCst) {
+            return false;
+        }
+        *link.client.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        // In case the game's gone when they reconnect, e.g. after a restart
+        link.save_settings(&self.username);
+        let detachment = link.detachments.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+        let link = GameLink { returned: CancellationToken::new(), ..link };
+        DETACHED.lock().unwrap_or_else(PoisonError::into_inner).insert(self.username.clone(), link.clone());
+        let kept = if !link.remote && link.app.pause(&link.display) { "Paused" } else { "Kept" };
+        info!("{} the game on {} for {} to reconnect within {:?}", kept, link.display, self.username, grace);
+
+        let username = self.username.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = link.returned.cancelled() => return,
+                _ = tokio::time::sleep(grace) => {}
+            }
+            let mut detached = DETACHED.lock().unwrap_or_else(PoisonError::into_inner);
+            // Unless they came back in the meantime
+            if link.detachments.load(std::sync::atomic::Ordering::SeqCst) == detachment
+                && detached.get(&username).is_some_and(|kept| Arc::ptr_eq(&kept.running, &link.running))
+            {
+                detached.remove(&username);
+                info!("{} didn't come back, stopping the game on {}", username, link.display);
+                link.running.store(false, std::sync::atomic::Ordering::SeqCst);
+            }
+        });
+        true
+    }
+
+    // Shows a paused game to the player who dropped out of it, passing their input
+    // along until the game ends or they drop out again
+    async fn reattach(&self, link: GameLink, session_handle: &russh::server::Handle, channel_id: russh::ChannelId) {
+        link.returned.cancel();
+        let detachment = link.detachments.load(std::sync::atomic::Ordering::SeqCst);
+        let mut setup = vec![];
+        let _ = crossterm::execute!(
+            setup,
+            crossterm::event::EnableMouseCapture,
+            crossterm::terminal::EnterAlternateScreen,
+            crossterm::cursor::Hide
+        );
+        let _ = session_handle.data(channel_id, setup.into()).await;
+        *link.client.lock().unwrap_or_else(PoisonError::into_inner) = Some((session_handle.clone(), channel_id));
+        *self.game.lock().unwrap_or_else(PoisonError::into_inner) = Some(link.clone());
+        if !link.remote {
+            link.app.resume(&link.display);
+        }
+        info!("{} reconnected to the game on {}", self.username, link.display);
+
+        // The flags and the terminal's size are checked between the player's input
+        let mut check = tokio::time::interval(std::time::Duration::from_millis(100));
+        let mut input = self.input_channel_rx.lock().await;
+        let mut width = 0;
+        while link.running.load(std::sync::atomic::Ordering::SeqCst)
+            && self.running.load(std::sync::atomic::Ordering::SeqCst)
+            && link.detachments.load(std::sync::atomic::Ordering::SeqCst) == detachment
+        {
+            let size = self.terminal_size.lock().unwrap_or_else(PoisonError::into_inner).clone();
+            if size.target_width != width {
+                width = size.target_width;
+                *link.terminal_size.lock().unwrap_or_else(PoisonError::into_inner) = size;
+            }
+            tokio::select! {
+                received = input.recv() => match received {
+                    Some(data) => {
+                        let _ = link.input_tx.send(data);
+                    }
+                    None => break,
+                },
+                _ = check.tick() => {}
+            }
+        }
+        drop(input);
+
+        // The game cleaned up the terminal when it ended
+        if !link.running.load(std::sync::atomic::Ordering::SeqCst) {
+            self.game.lock().unwrap_or_else(PoisonError::into_inner).take();
+            let _ = session_handle.close(channel_id).await;
+        }
+    }
+
+    fn requester(&self, options: &SessionOptions, pool: &str) -> queueing::Requester {
+        queueing::Requester {
+            owner: self.username.clone(),
+            pool: pool.to_owned(),
+            wants: options.wants.clone(),
+            priority: if options.short { self.priority.max(queueing::PRIORITY_SHORT) } else { self.priority },
+            reservation: options.reservation.clone(),
+            source: self.source_ip,
+            fingerprint: self.fingerprint.clone(),
+        }
+    }
+
+    // Waits for a keypress after a session ends, returning whether it was r
+    async fn wait_for_rejoin(&self) -> bool {
+        // Whatever was typed while the game was running doesn't count
+        while self.input_channel_rx.lock().await.try_recv().is_ok() {}
+
+        let keypress = async {
+            loop {
+                let received = self.input_channel_rx.lock().await.try_recv();
+                match received {
+                    Ok(data) => return data.first().is_some_and(|key| key.eq_ignore_ascii_case(&b'r')),
+                    Err(mpsc::error::TryRecvError::Empty) => {
+                        tokio::time::sleep(std::time::Duration::from_millis(50)).await
+                    }
+                    Err(mpsc::error::TryRecvError::Disconnected) => return false,
+                }
+            }
+        };
+        tokio::time::timeout(config::REJOIN_PROMPT_TIMEOUT, keypress)
+            .await
+            .unwrap_or(false)
+    }
+
+    fn start_session(
+        &mut self,
+        channel_id: russh::ChannelId,
+        session: &mut russh::server::Session,
+        options: SessionOptions,
+    ) {
+        // We have to run this as a background task because the channel won't work until the handler returns.
+        tokio::spawn(self.clone().handle_session_background(
+            options,
+            self.username.clone(),
+            session.handle().clone(),
+            channel_id,
+        ));
+    }
+
+    fn set_terminal_size(&mut self, width: u32, height: u32) -> Result<(), SessionError> {
+        self.terminal_rows.store(height as usize, std::sync::atomic::Ordering::Relaxed);
+        let mut size = lock(&self.terminal_size, "The terminal size")?;
         size.target_width = width as usize;
         size.target_height = crate::render::get_height_from_width(width as usize);
         Ok(())
     }
 
-    pub async fn handle_session_background(
+    async fn handle_session_background(
         self,
-        mut status_rx: mpsc::UnboundedReceiver<queueing::ResourceStatus>,
+        mut options: SessionOptions,
         username: String,
         session_handle: russh::server::Handle,
         channel_id: russh::ChannelId,
     ) {
-        let mut queue_position_interval = tokio::time::interval(std::time::Duration::from_secs(3));
+        if options.logout {
+            let message = match accounts::forget(&username, &self.settings.get()) {
+                Ok(()) => "👋 Forgot your Microsoft login\r\n".to_owned(),
+                Err(e) => format!("❌ Couldn't forget your Microsoft login: {}\r\n", e),
+            };
+            let _ = session_handle.data(channel_id, message.into()).await;
+        }
+        if options.login && !self.microsoft_login(&username, &session_handle, channel_id).await {
+            let _ = session_handle.close(channel_id).await;
+            return;
+        }
+
+        // Straight back into a game that was paused when they dropped out
+        let detached = DETACHED.lock().unwrap_or_else(PoisonError::into_inner).remove(&username);
+        if let Some(link) = detached {
+            self.reattach(link, &session_handle, channel_id).await;
+            return;
+        }
+
+        let pool = match options.pool.clone() {
+            Some(pool) => pool,
+            None => match self.choose_pool(&session_handle, channel_id).await {
+                Some(pool) => pool,
+                None => {
+                    let _ = session_handle.close(channel_id).await;
+                    return;
+                }
+            },
+        };
+        if options.server.is_none() {
+            match self.choose_server(&pool, &session_handle, channel_id).await {
+                Some(server) => options.server = Some(server),
+                None => {
+                    let _ = session_handle.close(channel_id).await;
+                    return;
+                }
+            }
+        }
+
+        // Empty leaves it up to the launcher
+        let settings = self.settings.get();
+        let versions = settings.game_versions();
+        let version = match options.version.take() {
+            Some(version) => version,
+            None if versions.len() > 1 => {
+                let entries = versions
+                    .into_iter()
+                    .map(|version| {
+                        let detail =
+                            if settings.prism.enabled() { prism::describe(&version, &settings) } else { String::new() };
+                        lobby::MenuEntry { name: version, detail }
+                    })
+                    .collect();
+                let title = if settings.prism.enabled() { "Choose a modpack" } else { "Choose a version" };
+                let menu = lobby::Menu::new(title, entries);
+                match self.show_menu(menu, false, &session_handle, channel_id).await {
+                    Some(version) => version,
+                    None => {
+                        let _ = session_handle.close(channel_id).await;
+                        return;
+                    }
+                }
+            }
+            None => versions.into_iter().next().unwrap_or_default(),
+        };
+        options.version = Some(version);
+
+        // Players with their own game directory can pick up where they left off
+        let worlds = match player_game_dir(&username, &settings) {
+            Some(game_dir) if options.server.as_ref().is_some_and(|server| server.is_empty()) => {
+                saved_worlds(&game_dir)
+            }
+            _ => vec![],
+        };
+        if !worlds.is_empty() {
+            let mut entries = vec![lobby::MenuEntry {
+                name: NEW_WORLD.to_owned(),
+                detail: String::new(),
+            }];
+            entries.extend(worlds);
+            let menu = lobby::Menu::new("Choose a world", entries);
+            match self.show_menu(menu, false, &session_handle, channel_id).await {
+                Some(world) => options.world = Some(world).filter(|world| world != NEW_WORLD),
+                None => {
+                    let _ = session_handle.close(channel_id).await;
+                    return;
+                }
+            }
+        }
 
+        let mut status_rx = self.allocator.request_resource(self.requester(&options, &pool));
+        // Reservation codes only work once
+        options.reservation = None;
+
+        // Each time around is one trip through the queue and one game
+        let mut migrations = 0;
         loop {
-            tokio::select! {
-                Some(status) = status_rx.recv() => {
-                    match status {
-                        queueing::ResourceStatus::Success(resource_id) => {
-                            let _ = session_handle
-                                .data(channel_id, format!("✅ Assigned session {}\r\n", resource_id).into())
-                                .await;
+            let Some(resource) = self.wait_in_queue(&mut status_rx, &session_handle, channel_id).await else {
+                return;
+            };
 
-                            // Get Minecraft server address from environment variable if set
-                            let server_address = std::env::var("MINECRAFT_SERVER_ADDRESS").unwrap_or_else(|_| "".to_string());
-                            let minecraft_config = minecraft::MinecraftConfig { xorg_display: format!(":{}", resource_id+1), username: username.clone(), server_address };
-
-                            // Output: send Minecraft output to SSH client
-                            let output_channel = Arc::new(Mutex::new(SessionWriter::new(session_handle.clone(), channel_id)));
-                            // Input: receive input from SSH client
-                            let input_channel = Arc::new(Mutex::new(SessionReader::new(self.input_channel_rx.clone())));
-
-                            // Run the Minecraft session (blocking call)
-                            tokio::spawn(async move {
-                                minecraft::run(
-                                    minecraft_config,
-                                    self.running.clone(),
-                                    output_channel,
-                                    input_channel,
-                                    self.terminal_size.clone(),
-                                ).unwrap();
-
-                                let _ = session_handle.close(channel_id).await;
-                                self.allocator.release(resource_id);
-
-                        });
-                            // let _ = session_handle.data(
-                            //     channel_id,
-                            //     russh::CryptoVec::from(format!("goodbye {}\r\n", username))
-                            // ).await;
-                            // let _ = session_handle.close(channel_id).await;
-                            // self.allocator.release(resource_id);
-                            break;
-                        }
-                        queueing::ResourceStatus::QueuePosition(pos) => {
-                            let _ = session_handle
-                                .data(channel_id, format!("⏳ You are position {} in queue\r\n", pos + 1).into())
-                                .await;
+            // Run the Minecraft session
+            let ended = self.run_game(&resource, &username, &options, &session_handle, channel_id).await;
+            if self.disconnected.load(std::sync::atomic::Ordering::SeqCst) {
+                self.allocator.release_after_disconnect(resource.id);
+                return;
+            }
+            self.allocator.release(resource.id);
+
+            match ended {
+                GameEnd::DisplayLost if migrations < config::MAX_MIGRATIONS => {
+                    migrations += 1;
+                    info!("Migrating {}'s session off display {}", username, resource.display);
+                    crate::metrics::increment("ansicraft_session_migrations_total");
+                    let _ = session_handle
+                        .data(channel_id, "\r\n🔁 The display failed, migrating your session...\r\n".into())
+                        .await;
+                    // Back into the world that was being played, which was saved as it went
+                    let game_dir = player_game_dir(&username, &settings);
+                    if let (Some(""), Some(game_dir)) = (options.server.as_deref(), game_dir) {
+                        options.world = saved_worlds(&game_dir).into_iter().next().map(|world| world.name);
+                    }
+                    self.running.store(true, std::sync::atomic::Ordering::SeqCst);
+                    status_rx = self.allocator.request_resource(self.requester(&options, &pool));
+                    continue;
+                }
+                GameEnd::DisplayLost => {
+                    let _ = session_handle
+                        .data(channel_id, "\r\n❌ The display failed, and so did moving to another. Sorry!\r\n".into())
+                        .await;
+                }
+                GameEnd::Reclaimed(minecraft::ReclaimReason::Idle) => {
+                    let _ = session_handle
+                        .data(channel_id, "💤 Disconnected for being idle while others were waiting\r\n".into())
+                        .await;
+                }
+                GameEnd::Reclaimed(minecraft::ReclaimReason::TimeUp) => {
+                    let _ = session_handle
+                        .data(channel_id, "⏰ Time's up, someone else was waiting for your spot.\r\nPress r to rejoin the back of the queue, or any other key to leave.\r\n".into())
+                        .await;
+                    if self.wait_for_rejoin().await {
+                        self.running.store(true, std::sync::atomic::Ordering::SeqCst);
+                        status_rx = self.allocator.request_resource(self.requester(&options, &pool));
+                        continue;
+                    }
+                }
+                GameEnd::Over => {}
+            }
+            let _ = session_handle.close(channel_id).await;
+            return;
+        }
+    }
+
+    // Asks which pool to queue for, if there's a choice
+    async fn choose_pool(
+        &self,
+        session_handle: &russh::server::Handle,
+        channel_id: russh::ChannelId,
+    ) -> Option<String> {
+        let Some(snapshot) = self.allocator.snapshot().await else {
+            let _ = session_handle
+                .data(channel_id, "❌ The queue isn't running, please try again later\r\n".into())
+                .await;
+            return None;
+        };
+        if let [pool] = snapshot.pools.as_slice() {
+            return Some(pool.name.clone());
+        }
+
+        let menu = lobby::Menu::new("Choose a server", lobby::pool_entries(snapshot.pools));
+        self.show_menu(menu, true, session_handle, channel_id).await
+    }
+
+    // Logs the player in to their Microsoft account with a code they type in on
+    // their phone, returning whether to carry on with the session
+    async fn microsoft_login(
+        &self,
+        username: &str,
+        session_handle: &russh::server::Handle,
+        channel_id: russh::ChannelId,
+    ) -> bool {
+        let settings = self.settings.get();
+        if !accounts::enabled(&settings) {
+            let _ = session_handle
+                .data(channel_id, "❌ Microsoft logins aren't set up here, playing offline\r\n".into())
+                .await;
+            return true;
+        }
+        let mut login = match accounts::Login::start(username, &settings) {
+            Ok(login) => login,
+            Err(e) => {
+                let _ = session_handle.data(channel_id, format!("❌ {}, playing offline\r\n", e).into()).await;
+                return true;
+            }
+        };
+
+        // Whatever was typed before doesn't count
+        while self.input_channel_rx.lock().await.try_recv().is_ok() {}
+        let mut keys = tokio::time::interval(std::time::Duration::from_millis(50));
+        loop {
+            if !self.running.load(std::sync::atomic::Ordering::SeqCst) {
+                return false;
+            }
+            tokio::select! {
+                event = login.events.recv() => {
+                    let message = match event {
+                        Some(accounts::LoginEvent::Code { url, code }) => format!(
+                            "🔑 To play online, go to {} on your phone and enter the code \x1b[1m{}\x1b[m\r\n   Press s to skip and play offline.\r\n",
+                            url, code
+                        ),
+                        Some(accounts::LoginEvent::LoggedIn(name)) => {
+                            let _ = session_handle.data(channel_id, format!("✅ Logged in as {}\r\n", name).into()).await;
+                            return true;
                         }
-                        queueing::ResourceStatus::Cancelled => {
+                        Some(accounts::LoginEvent::Failed(e)) => {
                             let _ = session_handle
-                                .data(channel_id, "❌ Request was cancelled\r\n".into())
+                                .data(channel_id, format!("❌ Couldn't log in ({}), playing offline\r\n", e).into())
                                 .await;
-                            break;
+                            return true;
                         }
-                        queueing::ResourceStatus::Failed(reason) => {
-                            let _ = session_handle
-                                .data(channel_id, format!("❌ Server error: {}\r\n", reason).into())
-                                .await;
-                            break;
+                        None => return true,
+                    };
+                    let _ = session_handle.data(channel_id, message.into()).await;
+                }
+                _ = keys.tick() => {
+                    let received = self.input_channel_rx.lock().await.try_recv();
+                    match received.as_deref() {
+                        Ok([0x03, ..]) => return false,
+                        Ok([b's' | b'S', ..]) => return true,
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    // Asks which server to join, if there's a choice besides the pool's own
+    async fn choose_server(
+        &self,
+        pool: &str,
+        session_handle: &russh::server::Handle,
+        channel_id: russh::ChannelId,
+    ) -> Option<String> {
+        let settings = self.settings.get();
+        let default = settings.server_address(pool).to_owned();
+        let listed = listed_servers(&settings);
+        if listed.is_empty() && !settings.custom_servers {
+            return Some(default);
+        }
+
+        let mut entries = vec![lobby::MenuEntry {
+            name: DEFAULT_SERVER.to_owned(),
+            detail: if default.is_empty() { "singleplayer".to_owned() } else { default.clone() },
+        }];
+        entries.extend(listed.iter().map(|(name, address)| lobby::MenuEntry {
+            name: name.clone(),
+            detail: address.clone(),
+        }));
+        if settings.custom_servers {
+            entries.push(lobby::MenuEntry {
+                name: TYPE_SERVER.to_owned(),
+                detail: String::new(),
+            });
+        }
+        let menu = lobby::Menu::new("Choose where to play", entries);
+        match self.show_menu(menu, false, session_handle, channel_id).await?.as_str() {
+            DEFAULT_SERVER => Some(default),
+            TYPE_SERVER => {
+                let settings = self.settings.get();
+                let prompt = lobby::TextPrompt::new("Server address", move |text| allowed_server(text, &settings));
+                self.show_menu(prompt, false, session_handle, channel_id).await
+            }
+            picked => listed.into_iter().find(|(name, _)| name == picked).map(|(_, address)| address),
+        }
+    }
+
+    // Shows a menu until something's picked, keeping the pool numbers up to date
+    // if it's a menu of pools
+    async fn show_menu(
+        &self,
+        mut menu: impl lobby::Picker,
+        pools: bool,
+        session_handle: &russh::server::Handle,
+        channel_id: russh::ChannelId,
+    ) -> Option<String> {
+        let mut frames = tokio::time::interval(config::LOBBY_FRAME_INTERVAL);
+        let mut refresh = tokio::time::interval(std::time::Duration::from_secs(1));
+
+        let _ = session_handle.data(channel_id, "\x1b[?1049h\x1b[?25l".into()).await;
+        let choice = loop {
+            if !self.running.load(std::sync::atomic::Ordering::SeqCst) {
+                break lobby::MenuChoice::Leave;
+            }
+            tokio::select! {
+                _ = refresh.tick(), if pools => {
+                    if let Some(snapshot) = self.allocator.snapshot().await {
+                        menu.set_entries(lobby::pool_entries(snapshot.pools));
+                    }
+                }
+                _ = frames.tick() => {
+                    let mut choice = None;
+                    loop {
+                        let received = self.input_channel_rx.lock().await.try_recv();
+                        match received {
+                            Ok(data) => choice = choice.or(menu.handle_input(&data)),
+                            Err(_) => break,
                         }
                     }
+                    if let Some(choice) = choice {
+                        break choice;
+                    }
+
+                    let cols = self.terminal_size.lock().unwrap_or_else(PoisonError::into_inner).target_width;
+                    let rows = self.terminal_rows.load(std::sync::atomic::Ordering::Relaxed);
+                    let _ = session_handle.data(channel_id, menu.render(cols, rows).into()).await;
+                }
+            }
+        };
+        let _ = session_handle.data(channel_id, "\x1b[?25h\x1b[?1049l".into()).await;
+
+        match choice {
+            lobby::MenuChoice::Picked(name) => Some(name),
+            lobby::MenuChoice::Leave => None,
+        }
+    }
+
+    // `admin dashboard`, until the admin leaves
+    async fn dashboard(self, session_handle: russh::server::Handle, channel_id: russh::ChannelId) {
+        let screen = AdminChannel(session_handle.clone(), channel_id);
+        let size = || {
+            let cols = self.terminal_size.lock().unwrap_or_else(PoisonError::into_inner).target_width;
+            let rows = self.terminal_rows.load(std::sync::atomic::Ordering::Relaxed);
+            (cols as u16, rows as u16)
+        };
+        if let Err(e) = dashboard::run(&self.allocator, screen, self.input_channel_rx.clone(), size).await {
+            info!("Admin dashboard closed: {}", e);
+        }
+        let _ = session_handle.exit_status_request(channel_id, 0).await;
+        let _ = session_handle.close(channel_id).await;
+    }
+
+    // Shows the attract feed, for anyone who just wants to look
+    async fn watch(self, session_handle: russh::server::Handle, channel_id: russh::ChannelId) {
+        let Some(mut feed) = attract::AttractFeed::start(self.terminal_size.clone(), self.settings.clone()) else {
+            let _ = session_handle
+                .data(channel_id, "😴 Nothing to watch right now, try again later\r\n".into())
+                .await;
+            let _ = session_handle.close(channel_id).await;
+            return;
+        };
+        let mut frames = tokio::time::interval(config::LOBBY_FRAME_INTERVAL);
+
+        let _ = session_handle.data(channel_id, "\x1b[?1049h\x1b[?25l\x1b[2J".into()).await;
+        while self.running.load(std::sync::atomic::Ordering::SeqCst) {
+            frames.tick().await;
+            let mut leaving = false;
+            loop {
+                let received = self.input_channel_rx.lock().await.try_recv();
+                match received {
+                    Ok(data) => leaving |= data.iter().any(|key| matches!(key, b'q' | b'Q' | 0x03)),
+                    Err(_) => break,
+                }
+            }
+            if leaving {
+                break;
+            }
+
+            let cols = self.terminal_size.lock().unwrap_or_else(PoisonError::into_inner).target_width;
+            let rows = self.terminal_rows.load(std::sync::atomic::Ordering::Relaxed);
+            let status = "Watching ansicraft  ·  q to leave";
+            let mut output = feed.latest_frame().unwrap_or_default();
+            output.push_str(&format!(
+                "\x1b[{};1H\x1b[0;1m{}{}\x1b[m\x1b[K",
+                rows,
+                " ".repeat(cols.saturating_sub(status.chars().count()) / 2),
+                status
+            ));
+            let _ = session_handle.data(channel_id, output.into()).await;
+        }
+        drop(feed);
+        let _ = session_handle.data(channel_id, "\x1b[?25h\x1b[?1049l".into()).await;
+        let _ = session_handle.close(channel_id).await;
+    }
+
+    // Shows the waiting room until we get a resource
+    async fn wait_in_queue(
+        &self,
+        status_rx: &mut mpsc::UnboundedReceiver<queueing::ResourceStatus<ResourceDescriptor>>,
+        session_handle: &russh::server::Handle,
+        channel_id: russh::ChannelId,
+    ) -> Option<ResourceDescriptor> {
+        let mut room = lobby::WaitingRoom::new();
+        let mut frames = tokio::time::interval(config::LOBBY_FRAME_INTERVAL);
+        // Something to watch instead of the waiting room, while it's switched on
+        let mut feed: Option<attract::AttractFeed> = None;
+
+        // Alternate screen, hidden cursor
+        let _ = session_handle.data(channel_id, "\x1b[?1049h\x1b[?25l".into()).await;
+        let result = loop {
+            tokio::select! {
+                status = status_rx.recv() => match status {
+                    Some(queueing::ResourceStatus::Success(resource)) => break Ok(resource),
+                    Some(queueing::ResourceStatus::QueuePosition(pos)) => room.set_position(pos),
+                    Some(queueing::ResourceStatus::Cancelled) | None => {
+                        break Err("❌ Request was cancelled".to_owned());
+                    }
+                    Some(queueing::ResourceStatus::TimedOut) => {
+                        break Err("⌛ Timed out waiting in the queue, please try again later".to_owned());
+                    }
+                    Some(queueing::ResourceStatus::Failed(reason)) => break Err(format!("❌ {}", reason)),
                 },
-                _ = queue_position_interval.tick() => {
-                    // No-op: status updates come from ResourceAllocator now
+                _ = frames.tick() => {
+                    let mut leaving = false;
+                    loop {
+                        let received = self.input_channel_rx.lock().await.try_recv();
+                        match received {
+                            Ok(data) => leaving |= !room.handle_input(&data),
+                            Err(_) => break,
+                        }
+                    }
+                    if leaving {
+                        self.allocator.cancel();
+                        break Err("👋 Left the queue".to_owned());
+                    }
+
+                    room.tick();
+                    room.set_can_watch(feed.is_some() || attract::source(&self.settings.get()).is_some());
+                    let cols = self.terminal_size.lock().unwrap_or_else(PoisonError::into_inner).target_width;
+                    let rows = self.terminal_rows.load(std::sync::atomic::Ordering::Relaxed);
+                    if !room.watching() {
+                        feed = None;
+                        let _ = session_handle.data(channel_id, room.render(cols, rows).into()).await;
+                        continue;
+                    }
+                    if feed.is_none() {
+                        feed = attract::AttractFeed::start(self.terminal_size.clone(), self.settings.clone());
+                        let _ = session_handle.data(channel_id, "\x1b[2J".into()).await;
+                    }
+                    let mut output = feed.as_mut().and_then(|feed| feed.latest_frame()).unwrap_or_default();
+                    output.push_str(&room.render_watching(cols, rows));
+                    let _ = session_handle.data(channel_id, output.into()).await;
+                }
+            }
+        };
+        let _ = session_handle.data(channel_id, "\x1b[?25h\x1b[?1049l".into()).await;
+
+        match result {
+            Ok(resource) => {
+                let _ = session_handle
+                    .data(channel_id, format!("✅ Assigned session {}\r\n", resource.id).into())
+                    .await;
+                Some(resource)
+            }
+            Err(message) => {
+                let _ = session_handle.data(channel_id, format!("{}\r\n", message).into()).await;
+                let _ = session_handle.close(channel_id).await;
+                None
+            }
+        }
+    }
+
+    // Plays one game on the given resource, returning how it ended
+    async fn run_game(
+        &self,
+        resource: &ResourceDescriptor,
+        username: &str,
+        options: &SessionOptions,
+        session_handle: &russh::server::Handle,
+        channel_id: russh::ChannelId,
+    ) -> GameEnd {
+        // As it was when the game started, even if it's reloaded while it's going
+        let session_config = self.settings.get();
+        // The player's pick, or else the pool's from the environment
+        let server_address =
+            options.server.clone().unwrap_or_else(|| session_config.server_address(&resource.pool).to_owned());
+        let reclaimed = Arc::new(Mutex::new(None));
+        let mut max_duration = session_config.max_session;
+        if options.short {
+            let short_session = session_config.short_session;
+            max_duration = Some(max_duration.map_or(short_session, |max| max.min(short_session)));
+        }
+        // Carrying on from the player's last session, if it was just now, or else in
+        // the best colors their terminal has
+        let mut render = session_config.render.clone();
+        if session_config.detect_color {
+            render.mode = config::RenderMode::detect(&self.term, &self.colorterm).unwrap_or(render.mode);
+        }
+        let mut saved = session_settings::load(username, render, &session_config.input);
+        if let Some(fps) = options.fps {
+            saved.render.fps = fps;
+        }
+        let game = cluster::Game {
+            session_info: self.session_info(Some(resource.display.clone())),
+            server_address,
+            version: options.version.clone().unwrap_or_default(),
+            world: options.world.clone(),
+            size: options.size.or(saved.size),
+            render_settings: Arc::new(Mutex::new(saved.render)),
+            input_settings: Arc::new(Mutex::new(saved.input)),
+            logs: SessionLogs::create(session_config.session_logs_dir.as_deref(), &self.fingerprint),
+            reclaim_policy: minecraft::ReclaimPolicy {
+                afk_timeout: session_config.afk_timeout,
+                afk_warning: config::AFK_WARNING,
+                max_duration,
+                time_up_warning: config::TIME_UP_WARNING,
+                others_waiting: Arc::new({
+                    let allocator = self.allocator.clone();
+                    let pool = resource.pool.clone();
+                    move || allocator.queue_length(&pool) > 0
+                }),
+                reclaimed: reclaimed.clone(),
+            },
+        };
+        let link = GameLink {
+            client: Arc::new(Mutex::new(Some((session_handle.clone(), channel_id)))),
+            input_tx: self.input_channel_tx.clone(),
+            terminal_size: self.terminal_size.clone(),
+            display: resource.display.clone(),
+            remote: resource.provision == Provision::Worker,
+            app: self.app.clone(),
+            running: self.running.clone(),
+            detachments: Default::default(),
+            returned: CancellationToken::new(),
+            size: game.size,
+            render_settings: game.render_settings.clone(),
+            input_settings: game.input_settings.clone(),
+        };
+        *self.game.lock().unwrap_or_else(PoisonError::into_inner) = Some(link.clone());
+
+        // Output: send Minecraft output to SSH client
+        let mut output = SessionWriter::new(link.client.clone(), link.detachments.clone());
+
+        let (running, input, terminal_size) =
+            (self.running.clone(), self.input_channel_rx.clone(), self.terminal_size.clone());
+        let played = if link.remote {
+            let token = &session_config.cluster_token;
+            Ok(cluster::play(resource, token, game, running, output.clone(), input, terminal_size).await)
+        } else {
+            let host = GameHost {
+                jvm_args: self.jvm_args.clone(),
+                app: self.app.clone(),
+                settings: self.settings.clone(),
+            };
+            host.play(resource, game, running, output.clone(), input, terminal_size).await
+        };
+        let summary = match played {
+            Ok(summary) => summary,
+            Err(message) => {
+                let _ = output.show(format!("{}\r\n", message).into()).await;
+                return GameEnd::Over;
+            }
+        };
+        self.game.lock().unwrap_or_else(PoisonError::into_inner).take();
+        {
+            let mut detached = DETACHED.lock().unwrap_or_else(PoisonError::into_inner);
+            if detached.get(username).is_some_and(|kept| Arc::ptr_eq(&kept.running, &self.running))
+                && let Some(kept) = detached.remove(username)
+            {
+                kept.returned.cancel();
+            }
+        }
+
+        let settings = link.input_settings.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        if let Err(e) = preferences::save(username, &settings, &session_config.input) {
+            error!("Error saving preferences for {}: {}", username, e);
+        }
+        link.save_settings(username);
+
+        let reclaimed = *reclaimed.lock().unwrap_or_else(PoisonError::into_inner);
+        // A session that's moving to another display isn't over yet
+        if summary.as_ref().is_some_and(|summary| summary.display_lost) {
+            return GameEnd::DisplayLost;
+        }
+        if let Some(summary) = summary.filter(|_| !self.disconnected.load(std::sync::atomic::Ordering::SeqCst)) {
+            let text = summary_text(&summary, reclaimed, &session_config.goodbye_message);
+            let _ = output.show(text.into()).await;
+        }
+        reclaimed.map_or(GameEnd::Over, GameEnd::Reclaimed)
+    }
+}
+
+// What playing games on this machine's displays takes, for its own players' games
+// or the ones a gateway sends it as a worker
+#[derive(Clone)]
+struct GameHost {
+    jvm_args: Arc<Vec<String>>,
+    app: Arc<dyn app::AppLauncher>,
+    settings: config::LiveConfig,
+}
+
+impl GameHost {
+    // Plays the game on the resource's display, returning how it went (if it got
+    // far enough to go anywhere), or what to tell the player if it couldn't start
+    async fn play<S: Screen>(
+        &self,
+        resource: &ResourceDescriptor,
+        mut game: cluster::Game,
+        running: Arc<std::sync::atomic::AtomicBool>,
+        output: S,
+        input: xdo::InputSource,
+        terminal_size: Arc<Mutex<config::TerminalSize>>,
+    ) -> Result<Option<minecraft::SessionSummary>, &'static str> {
+        let username = game.session_info.username.clone();
+        game.session_info.display = Some(resource.display.clone());
+        // The player's size, if the display's screen can be set to it.  X servers
+        // started for the session just start at that size.
+        let resolved = match game.size {
+            Some(size) if size != resource.resolution && resource.provision == Provision::Running => {
+                match xserver::resize(&resource.display, size) {
+                    Ok(()) => Some(size),
+                    Err(e) => {
+                        error!("Error resizing display {} to {}x{}: {}", resource.display, size.0, size.1, e);
+                        None
+                    }
                 }
             }
+            size => size,
+        };
+        let resolution = resolved.unwrap_or(resource.resolution);
+        // A game running as its own user gets a directory of its own, even when
+        // the player hasn't got one
+        let settings = self.settings.get();
+        let mut game_dir = player_game_dir(&username, &settings).or_else(|| resource.data_dir.clone());
+        let uid = settings.session_uids.is_some().then(|| session_users::uid_for(resource.id, &settings));
+        let session_user = match uid {
+            Some(Some(uid)) if game_dir.is_none() => match session_users::scratch_dir(uid) {
+                Ok(dir) => {
+                    game_dir = Some(dir);
+                    Ok(Some(uid))
+                }
+                Err(e) => Err(format!("Error making a game directory for user {}: {}", uid, e)),
+            },
+            Some(Some(uid)) => Ok(Some(uid)),
+            Some(None) => Err(format!("No session user for session {} in ANSICRAFT_SESSION_UIDS", resource.id)),
+            None => Ok(None),
+        };
+        let session_user = match session_user {
+            Ok(session_user) => session_user,
+            Err(e) => {
+                error!("{}", e);
+                return Err("❌ Couldn't set up the game for this session, please try again later");
+            }
+        };
+        let minecraft_config = minecraft::MinecraftConfig {
+            app: self.app.clone(),
+            xorg_display: resource.display.clone(),
+            resolution,
+            username: username.clone(),
+            server_address: game.server_address,
+            version: game.version,
+            world: game.world,
+            game_dir,
+            // Only worlds in the player's own directory are theirs to back up
+            backup_dir: player_game_dir(&username, &settings).and(backups::player_dir(&username, &settings)),
+            env: game_env::for_session(&resource.pool, &username, &settings),
+            sandbox: settings.sandboxed(&resource.pool),
+            session_user,
+            render_settings: game.render_settings,
+            jvm_args: self.jvm_args.to_vec(),
+            gpu: resource.gpu,
+            account: accounts::saved(&username, &settings),
+            logs: game.logs,
+            audio_port: resource.ports.first().copied(),
+            input_settings: game.input_settings,
+            session_info: game.session_info,
+            reclaim_policy: Some(game.reclaim_policy),
+            settings: settings.clone(),
+        };
+
+        // Stopped when this goes out of scope at the end of the game
+        let _x_server = if resource.provision == Provision::SpawnX {
+            match xserver::XServer::start(&resource.display, resolution, settings.window_manager.as_deref()) {
+                Ok(x_server) => Some(x_server),
+                Err(e) => {
+                    error!("Error starting X server for {}: {}", username, e);
+                    return Err("❌ Couldn't start a display for the game, please try again later");
+                }
+            }
+        } else {
+            None
+        };
+
+        let summary = match minecraft::run(minecraft_config, &self.settings, running, output, input, terminal_size).await
+        {
+            Ok(summary) => Some(summary),
+            Err(e) => {
+                error!("Minecraft session for {} failed: {}", username, e);
+                None
+            }
+        };
+        // Back to the pool's size for whoever's next
+        if resource.provision == Provision::Running
+            && resolution != resource.resolution
+            && let Err(e) = xserver::resize(&resource.display, resource.resolution)
+        {
+            error!("Error resizing display {} back: {}", resource.display, e);
+        }
+        Ok(summary)
+    }
+
+    // Plays a game the gateway sent, if there's a display free for it.  If not, the
+    // gateway's turned down and tries another worker.
+    async fn play_dispatched(&self, dispatch: cluster::Dispatch, allocator: ResourceAllocator<ResourceDescriptor>) {
+        let username = dispatch.game.session_info.username.clone();
+        let mut status_rx = allocator.request_resource(queueing::Requester {
+            owner: username.clone(),
+            pool: dispatch.pool.clone(),
+            wants: vec![],
+            priority: queueing::PRIORITY_NORMAL,
+            reservation: None,
+            source: dispatch.game.session_info.source_ip,
+            fingerprint: dispatch.game.session_info.fingerprint.clone(),
+        });
+        let resource = match status_rx.recv().await {
+            Some(queueing::ResourceStatus::Success(resource)) => resource,
+            _ => {
+                allocator.cancel();
+                warn!("No display free for {}'s game from the gateway", username);
+                dispatch.refuse("no display free").await;
+                return;
+            }
+        };
+        info!("Playing {}'s game from the gateway on display {}", username, resource.display);
+        let (running, input, terminal_size) =
+            (dispatch.running.clone(), dispatch.input.clone(), dispatch.terminal_size.clone());
+        let played = self
+            .play(&resource, dispatch.game.clone(), running, dispatch.screen(), input, terminal_size)
+            .await;
+        allocator.release(resource.id);
+        match played {
+            Ok(summary) => dispatch.finish(summary).await,
+            Err(message) => dispatch.refuse(message).await,
         }
     }
 }
 
+// What's shown when a game ends, before the channel closes or the player's asked
+// whether to rejoin
+fn summary_text(
+    summary: &minecraft::SessionSummary,
+    reclaimed: Option<minecraft::ReclaimReason>,
+    goodbye: &str,
+) -> String {
+    let seconds = summary.played.as_secs();
+    let played = match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds / 60 % 60),
+    };
+    let ended = match reclaimed {
+        Some(minecraft::ReclaimReason::Idle) => "ended for being idle",
+        Some(minecraft::ReclaimReason::TimeUp) => "ran out of time",
+        None => "is over",
+    };
+    format!(
+        "\r\n🏁 Your session {}\r\n   Played for {}\r\n   Streamed {} frames, {:.1} FPS on average\r\n\r\n   {}\r\n\r\n",
+        ended,
+        played,
+        summary.frames,
+        summary.average_fps(),
+        goodbye
+    )
+}
+
 impl russh::server::Handler for MinecraftClientSession {
-    type Error = anyhow::Error;
+    type Error = SessionError;
 
     async fn channel_close(
             &mut self,
             _channel: russh::ChannelId,
             _session: &mut russh::server::Session,
         ) -> Result<(), Self::Error> {
+        let display = lock(&self.game, "The game")?.as_ref().map(|link| link.display.clone());
+        plugins::disconnect(&self.session_info(display));
+        if self.detach_game() {
+            self.allocator.cancel();
+            return Ok(());
+        }
+        self.disconnected.store(true, std::sync::atomic::Ordering::SeqCst);
         self.running.store(false, std::sync::atomic::Ordering::SeqCst);
+        self.allocator.cancel();
 
         Ok(())
     }
@@ -236,21 +1919,85 @@ impl russh::server::Handler for MinecraftClientSession {
     async fn channel_open_session(
         &mut self,
         channel: russh::Channel<russh::server::Msg>,
-        session: &mut russh::server::Session,
+        _session: &mut russh::server::Session,
     ) -> Result<bool, Self::Error> {
-        let username = self.username.clone();
-        let session_handle = session.handle().clone();
-        let channel_id = channel.id();
+        // Joining the queue waits for the shell or exec request, which says what the client wants
+        lock(&self.pending_channels, "The pending channels")?.insert(channel.id(), channel);
+        Ok(true)
+    }
 
-        // We have to run this as a background task because the channel won't work until this function returns.
-        tokio::spawn(self.clone().handle_session_background(
-            self.allocator.request_resource(),
-            username,
-            session_handle,
-            channel_id,
-        ));
+    async fn shell_request(
+        &mut self,
+        channel: russh::ChannelId,
+        session: &mut russh::server::Session,
+    ) -> Result<(), Self::Error> {
+        lock(&self.pending_channels, "The pending channels")?.remove(&channel);
+        session.channel_success(channel)?;
+        self.start_session(channel, session, SessionOptions::default());
+        Ok(())
+    }
 
-        Ok(true)
+    async fn exec_request(
+        &mut self,
+        channel: russh::ChannelId,
+        data: &[u8],
+        session: &mut russh::server::Session,
+    ) -> Result<(), Self::Error> {
+        lock(&self.pending_channels, "The pending channels")?.remove(&channel);
+        session.channel_success(channel)?;
+        let command = String::from_utf8_lossy(data).into_owned();
+        let words: Vec<&str> = command.split_whitespace().collect();
+        if words.as_slice() == ["admin", "dashboard"] && self.is_admin {
+            tokio::spawn(self.clone().dashboard(session.handle().clone(), channel));
+            return Ok(());
+        }
+        if let ["admin", args @ ..] = words.as_slice() {
+            let result = if self.is_admin {
+                let reload = || reload(&self.settings, &self.vip_keys, &self.admin_keys, &self.allocator);
+                admin::run_command(args, &self.allocator, reload).await
+            } else {
+                Err("Your key isn't allowed to run admin commands\n".to_owned())
+            };
+            let (output, exit_status) = match result {
+                Ok(output) => (output, 0),
+                Err(output) => (output, 1),
+            };
+            session.data(channel, output.replace('\n', "\r\n").into())?;
+            session.exit_status_request(channel, exit_status)?;
+            session.eof(channel)?;
+            session.close(channel)?;
+            return Ok(());
+        }
+        if words.as_slice() == ["watch"] {
+            tokio::spawn(self.clone().watch(session.handle().clone(), channel));
+            return Ok(());
+        }
+        match SessionOptions::parse(&command, &self.settings.get()) {
+            Ok(options) => self.start_session(channel, session, options),
+            Err(message) => {
+                session.data(channel, format!("❌ {}\r\n", message).into())?;
+                session.close(channel)?;
+            }
+        }
+        Ok(())
+    }
+
+    // SFTP, for players to download their world backups
+    async fn subsystem_request(
+        &mut self,
+        channel: russh::ChannelId,
+        name: &str,
+        session: &mut russh::server::Session,
+    ) -> Result<(), Self::Error> {
+        let pending = lock(&self.pending_channels, "The pending channels")?.remove(&channel);
+        match (name, pending, backups::player_dir(&self.username, &self.settings.get())) {
+            ("sftp", Some(pending), Some(root)) => {
+                session.channel_success(channel)?;
+                tokio::spawn(russh_sftp::server::run(pending.into_stream(), sftp::BackupFiles::new(root)));
+            }
+            _ => session.channel_failure(channel)?,
+        }
+        Ok(())
     }
 
     async fn auth_publickey(
@@ -258,46 +2005,68 @@ impl russh::server::Handler for MinecraftClientSession {
         _user: &str,
         public_key: &russh::keys::ssh_key::PublicKey,
     ) -> Result<russh::server::Auth, Self::Error> {
+        self.fingerprint = public_key.fingerprint(ssh_key::HashAlg::Sha256).to_string();
+
         // Use first 12 characters of base64 public key as username for now
         let public_key = public_key
             .public_key_base64();
+        let vip_keys = self.vip_keys.read().map_err(|_| SessionError::Poisoned("The VIP keys"))?;
+        if vip_keys.contains(&public_key) {
+            self.priority = queueing::PRIORITY_VIP;
+        }
+        let admin_keys = self.admin_keys.read().map_err(|_| SessionError::Poisoned("The admin keys"))?;
+        self.is_admin = admin_keys.contains(&public_key);
         self.username = sha256::digest(public_key);
         self.username.truncate(12);
+        plugins::connect(&self.session_info(None));
 
         Ok(russh::server::Auth::Accept)
     }
 
     async fn authentication_banner(&mut self) -> Result<Option<String>, Self::Error> {
-        Ok(Some(
-            "If you are unable to log in, please be sure to generate a public key first.\n"
-                .to_owned(),
-        ))
+        Ok(Some(self.settings.get().banner.clone()))
     }
 
     async fn pty_request(
         &mut self,
         _channel: russh::ChannelId,
-        _term: &str,
+        term: &str,
         col_width: u32,
-        _row_height: u32,
+        row_height: u32,
         _pix_width: u32, // TODO MAKE THIS SUPPORT PIXEL MOUSE COORDS!!!!!
         _pix_height: u32,
         _modes: &[(russh::Pty, u32)],
         _session: &mut russh::server::Session,
     ) -> Result<(), Self::Error> {
-        self.set_terminal_size(col_width)
+        self.term = term.to_owned();
+        self.set_terminal_size(col_width, row_height)
+    }
+
+    // Only sent if the client's set up to (SendEnv COLORTERM), but it's the surest
+    // sign of 24-bit color there is
+    async fn env_request(
+        &mut self,
+        _channel: russh::ChannelId,
+        variable_name: &str,
+        variable_value: &str,
+        _session: &mut russh::server::Session,
+    ) -> Result<(), Self::Error> {
+        if variable_name == "COLORTERM" {
+            self.colorterm = variable_value.to_owned();
+        }
+        Ok(())
     }
 
     async fn window_change_request(
         &mut self,
         _channel: russh::ChannelId,
         col_width: u32,
-        _row_height: u32,
+        row_height: u32,
         _pix_width: u32,
         _pix_height: u32,
         _session: &mut russh::server::Session,
     ) -> Result<(), Self::Error> {
-        self.set_terminal_size(col_width)
+        self.set_terminal_size(col_width, row_height)
     }
 
     async fn data(
@@ -307,7 +2076,7 @@ impl russh::server::Handler for MinecraftClientSession {
         _session: &mut russh::server::Session,
     ) -> Result<(), Self::Error> {
         if let Err(e) = self.input_channel_tx.send(data.to_owned()) {
-            eprintln!("Failed to send data: {}", e);
+            error!("Failed to send data: {}", e);
         }
         Ok(())
     }
@@ -319,73 +2088,62 @@ impl Drop for MinecraftClientSession {
     }
 }
 
-// Stub for SessionWriter - to be implemented
+// Sends the game to whoever's watching it, nobody while it's paused for them to
+// come back
+#[derive(Clone)]
 struct SessionWriter {
-    session_handle: russh::server::Handle,
-    channel_id: russh::ChannelId,
-    buffer: Vec<u8>,
+    client: Arc<Mutex<Option<(russh::server::Handle, russh::ChannelId)>>>,
+    // The game link's detachments, and how many there had been when frames were
+    // last sent, so a client that's come back gets a whole frame
+    detachments: Arc<std::sync::atomic::AtomicUsize>,
+    shown_after: usize,
 }
 
 impl SessionWriter {
-    fn new(session_handle: russh::server::Handle, channel_id: russh::ChannelId) -> Self {
+    fn new(
+        client: Arc<Mutex<Option<(russh::server::Handle, russh::ChannelId)>>>,
+        detachments: Arc<std::sync::atomic::AtomicUsize>,
+    ) -> Self {
+        let shown_after = detachments.load(std::sync::atomic::Ordering::SeqCst);
         Self {
-            session_handle,
-            channel_id,
-            buffer: vec![],
+            client,
+            detachments,
+            shown_after,
         }
     }
 }
 
-impl Write for SessionWriter {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        // Send data to SSH client (blocking)
-        self.buffer.extend_from_slice(buf);
-        Ok(buf.len())
-    }
-    fn flush(&mut self) -> std::io::Result<()> {
-        // Handle any errors from the SSH session
-        if let Err(e) = futures::executor::block_on(
-            self.session_handle
-                .data(self.channel_id, self.buffer.clone().into()), // This is the actual write
-        ) {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("SSH session error: {:?}", e),
-            ));
-        }
-        self.buffer.clear();
-        Ok(())
-    }
-}
+// The admin's channel, which the dashboard stops drawing on once it's gone
+struct AdminChannel(russh::server::Handle, russh::ChannelId);
 
-// There's probably a cleaner way to do this without the SessionReader struct, but this works for now.
-struct SessionReader {
-    buffer: Arc<Mutex<mpsc::UnboundedReceiver<Vec<u8>>>>,
+impl Screen for AdminChannel {
+    async fn show(&mut self, bytes: Vec<u8>) -> std::io::Result<()> {
+        self.0
+            .data(self.1, bytes.into())
+            .await
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "the admin's gone"))
+    }
 }
 
-impl SessionReader {
-    fn new(buffer: Arc<Mutex<mpsc::UnboundedReceiver<Vec<u8>>>>) -> Self {
-        Self { buffer }
+impl Screen for SessionWriter {
+    async fn show(&mut self, bytes: Vec<u8>) -> std::io::Result<()> {
+        let client = self.client.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        let Some((session_handle, channel_id)) = client else {
+            return Ok(());
+        };
+        // A client that's gone is dropped from the game by channel_close, which may
+        // not have happened yet, so the frame is just lost
+        if let Err(e) = session_handle.data(channel_id, bytes.into()).await {
+            error!("SSH session error: {:?}", e);
+        }
+        Ok(())
     }
-}
 
-impl Read for SessionReader {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        // Lock the receiver for exclusive access
-        let mut receiver = self.buffer.lock().unwrap();
-        match receiver.try_recv() {
-            Ok(data) => {
-                let to_copy = std::cmp::min(buf.len(), data.len());
-                buf[..to_copy].copy_from_slice(&data[..to_copy]);
-                Ok(to_copy)
-            }
-            Err(mpsc::error::TryRecvError::Empty) => {
-                Err(std::io::Error::new(std::io::ErrorKind::WouldBlock, ""))
-            }
-            Err(mpsc::error::TryRecvError::Disconnected) => Err(std::io::Error::new(
-                std::io::ErrorKind::UnexpectedEof,
-                "Channel closed",
-            )),
+    fn take_repaint(&mut self) -> bool {
+        if self.client.lock().unwrap_or_else(PoisonError::into_inner).is_none() {
+            return false;
         }
+        let detachments = self.detachments.load(std::sync::atomic::Ordering::SeqCst);
+        std::mem::replace(&mut self.shown_after, detachments) != detachments
     }
 }
@@ -0,0 +1,204 @@
+// Everything a session runs: its tasks, and the processes they start (the game,
+// ffmpeg).  Tasks are async, on the runtime's shared threads, except the few that
+// have to block (sending input with xdotool), which get a blocking thread each.
+// They all stop on one cancellation token, cancelled when the session ends, and
+// are waited for then.  Any process still going SUPERVISOR_STOP_TIMEOUT after that
+// is killed, so a stuck task can't leave anything behind.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[cfg(unix)]
+use nix::sys::signal::{self, Signal};
+#[cfg(unix)]
+use nix::unistd::Pid;
+use tokio::task::{Id, JoinError, JoinSet};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, warn};
+
+use crate::config::{MAX_TASK_RESTARTS, SUPERVISOR_STOP_TIMEOUT, TASK_RESTART_DELAY};
+
+// The processes a session has going, by PID
+#[derive(Clone, Default)]
+pub struct Processes(Arc<Mutex<HashMap<u32, String>>>);
+
+impl Processes {
+    // Keeps track of the process until the guard is dropped, which should be once
+    // it's been waited for
+    pub fn track(&self, name: &str, pid: u32) -> Tracked {
+        self.0.lock().unwrap().insert(pid, name.to_owned());
+        Tracked { pid, processes: self.clone() }
+    }
+
+    fn kill_all(&self) {
+        for (pid, name) in self.0.lock().unwrap().drain() {
+            warn!("Killing {} (PID: {}), which didn't stop with its session", name, pid);
+            kill(pid);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn kill(pid: u32) {
+    let _ = signal::kill(Pid::from_raw(pid as i32), Signal::SIGKILL);
+}
+
+#[cfg(windows)]
+fn kill(pid: u32) {
+    let _ = std::process::Command::new("taskkill")
+        .args(["/F", "/PID", &pid.to_string()])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status();
+}
+
+pub struct Tracked {
+    pid: u32,
+    processes: Processes,
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.processes.0.lock().unwrap().remove(&self.pid);
+    }
+}
+
+pub struct Supervisor {
+    // What the rest of the server (and blocking tasks) go by
+    running: Arc<AtomicBool>,
+    cancel: CancellationToken,
+    tasks: JoinSet<()>,
+    names: HashMap<Id, String>,
+    processes: Processes,
+}
+
+impl Supervisor {
+    pub fn new(running: Arc<AtomicBool>) -> Self {
+        Self {
+            running,
+            cancel: CancellationToken::new(),
+            tasks: JoinSet::new(),
+            names: HashMap::new(),
+            processes: Processes::default(),
+        }
+    }
+
+    pub fn processes(&self) -> Processes {
+        self.processes.clone()
+    }
+
+    // Cancelled when the session ends, which cancelling it also does
+    pub fn cancellation(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    // Runs the task for the rest of the session
+    pub fn spawn<F, E>(&mut self, name: &str, task: F)
+    where
+        F: Future<Output = Result<(), E>> + Send + 'static,
+        E: fmt::Display + Send,
+    {
+        let task_name = name.to_owned();
+        let handle = self.tasks.spawn(async move {
+            if let Err(e) = task.await {
+                error!("Error in {}: {}", task_name, e);
+            }
+        });
+        self.names.insert(handle.id(), name.to_owned());
+    }
+
+    // Like spawn, for a task that blocks, on a thread of its own
+    pub fn spawn_blocking<F, E>(&mut self, name: &str, task: F)
+    where
+        F: FnOnce() -> Result<(), E> + Send + 'static,
+        E: fmt::Display + Send,
+    {
+        let task_name = name.to_owned();
+        let handle = self.tasks.spawn_blocking(move || {
+            if let Err(e) = task() {
+                error!("Error in {}: {}", task_name, e);
+            }
+        });
+        self.names.insert(handle.id(), name.to_owned());
+    }
+
+    // Like spawn, but runs the task again if it stops while the session's still
+    // going.  One that keeps failing ends the session, since it's no good without it.
+    pub fn spawn_restarting<F, T, E>(&mut self, name: &str, mut task: F)
+    where
+        F: FnMut() -> T + Send + 'static,
+        T: Future<Output = Result<(), E>> + Send,
+        E: fmt::Display + Send,
+    {
+        let cancel = self.cancel.clone();
+        let task_name = name.to_owned();
+        self.spawn(name, async move {
+            let mut restarts = 0;
+            loop {
+                let result = task().await;
+                if cancel.is_cancelled() {
+                    return result.map_err(|e| io::Error::other(e.to_string()));
+                }
+                match result {
+                    Ok(()) => warn!("{} stopped, restarting it", task_name),
+                    Err(e) => error!("Error in {}, restarting it: {}", task_name, e),
+                }
+                if restarts >= MAX_TASK_RESTARTS {
+                    cancel.cancel();
+                    return Err(io::Error::other(format!("{} keeps stopping, ending the session", task_name)));
+                }
+                restarts += 1;
+                tokio::select! {
+                    _ = cancel.cancelled() => return Ok(()),
+                    _ = tokio::time::sleep(TASK_RESTART_DELAY) => {}
+                }
+            }
+        });
+    }
+
+    // Waits for the session to end, then for everything in it to stop
+    pub async fn wait(mut self) {
+        // The SSH side ends sessions with the flag, so it's checked along with the
+        // token.  Once the tasks are all done, there's nothing left to the session.
+        let mut check = tokio::time::interval(Duration::from_millis(100));
+        while self.running.load(Ordering::SeqCst) && !self.tasks.is_empty() {
+            tokio::select! {
+                _ = self.cancel.cancelled() => break,
+                _ = check.tick() => {}
+                Some(result) = self.tasks.join_next_with_id() => finished(&mut self.names, result),
+            }
+        }
+        self.cancel.cancel();
+        self.running.store(false, Ordering::SeqCst);
+
+        let (tasks, names) = (&mut self.tasks, &mut self.names);
+        let stopped = async {
+            while let Some(result) = tasks.join_next_with_id().await {
+                finished(names, result);
+            }
+        };
+        let _ = tokio::time::timeout(SUPERVISOR_STOP_TIMEOUT, stopped).await;
+        // Whatever's still running is stuck, and killing its processes unsticks it
+        self.processes.kill_all();
+        while let Some(result) = self.tasks.join_next_with_id().await {
+            finished(&mut self.names, result);
+        }
+    }
+}
+
+// Says if a task panicked, now it's done
+fn finished(names: &mut HashMap<Id, String>, result: Result<(Id, ()), JoinError>) {
+    let id = match &result {
+        Ok((id, ())) => *id,
+        Err(e) => e.id(),
+    };
+    let name = names.remove(&id).unwrap_or_default();
+    if result.is_err_and(|e| e.is_panic()) {
+        error!("{} panicked", name);
+    }
+}
@@ -0,0 +1,95 @@
+// Stand-ins for the display, for trying the pipeline out where there's no X
+// server, ffmpeg or game: a capture of a moving test pattern, and an input sink
+// that keeps what it's sent instead of running xdotool.  `bench` and the tests
+// use them.
+
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::io::AsyncWriteExt;
+
+use crate::config::{AppConfig, InputSettings, RenderSettings, TerminalSize};
+use crate::session::{GameLog, SessionState};
+use crate::render::{Capture, CaptureBackend, CaptureError};
+use crate::session_log::SessionLogs;
+use crate::xdo::{InputError, InputSink};
+
+// A diagonal rainbow that shifts a little each frame, so nothing stays the same.
+// The same frame number always gives the same frame.
+pub fn test_frame(width: usize, height: usize, frame: usize) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let shade = (x + y + frame) as u32;
+            pixels.extend_from_slice(&[(shade * 3) as u8, (shade * 5 + 85) as u8, (shade * 7 + 170) as u8]);
+        }
+    }
+    pixels
+}
+
+// Captures test_frame's frames, numbered from 0, at the rate asked for
+#[derive(Clone, Copy, Default)]
+pub struct TestPattern;
+
+impl CaptureBackend for TestPattern {
+    fn start(
+        &self,
+        width: usize,
+        height: usize,
+        fps: u32,
+        _logs: &SessionLogs,
+        _settings: &AppConfig,
+    ) -> Result<Capture, CaptureError> {
+        let (mut writer, reader) = tokio::io::duplex(width * height * 3);
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(Duration::from_secs(1) / fps.max(1));
+            for frame in 0.. {
+                ticks.tick().await;
+                // Until whoever's reading stops
+                if writer.write_all(&test_frame(width, height, frame)).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Capture {
+            frames: Box::new(reader),
+            process: None,
+        })
+    }
+}
+
+// Keeps the input it's sent, as the xdotool commands it would have run
+#[derive(Clone, Default)]
+pub struct Loopback(Arc<Mutex<VecDeque<String>>>);
+
+impl Loopback {
+    // Everything sent since the last time, like "keydown w"
+    pub fn take(&self) -> Vec<String> {
+        self.0.lock().unwrap().drain(..).collect()
+    }
+}
+
+impl InputSink for Loopback {
+    fn send(&self, args: &[&str]) -> Result<(), InputError> {
+        self.0.lock().unwrap().push_back(args.join(" "));
+        Ok(())
+    }
+}
+
+// A session's state as it would be at the start, for a terminal of the given size
+pub fn session_state(terminal_size: TerminalSize) -> SessionState {
+    SessionState {
+        running: Arc::new(AtomicBool::new(true)),
+        terminal_size: Arc::new(Mutex::new(terminal_size)),
+        input_settings: Arc::new(Mutex::new(InputSettings::default())),
+        render_settings: Arc::new(Mutex::new(RenderSettings::default())),
+        overlay: Default::default(),
+        last_input: Arc::new(Mutex::new(Instant::now())),
+        game_log: Arc::new(Mutex::new(GameLog::default())),
+        dead: Arc::new(AtomicBool::new(false)),
+        logs: SessionLogs::default(),
+        session_info: Default::default(),
+    }
+}
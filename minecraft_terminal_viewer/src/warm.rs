@@ -0,0 +1,240 @@
+// Games launched ahead of time, waiting at the title screen on displays nobody is
+// using, so players don't sit through the 30-60s it takes Minecraft to start.
+// The username is fixed when the game launches, so these only suit sessions that
+// don't join a server.
+//
+// ANSICRAFT_WARM_INSTANCES is how many to keep going, or "auto" to go by demand:
+// as many as sessions started in the busiest minute of the last
+// WARM_DEMAND_WINDOW, between ANSICRAFT_WARM_MIN and ANSICRAFT_WARM_MAX.  Ones
+// that aren't called for any more are stopped, since they sit on memory and CPU.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::process::Child;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::thread;
+#[cfg(feature = "server")]
+use std::time::Duration;
+use std::time::Instant;
+
+use tracing::{error, info};
+
+#[cfg(feature = "server")]
+use crate::config::{WarmTarget, WARM_ADJUST_INTERVAL, WARM_DEMAND_WINDOW};
+use crate::config::{AppConfig, WARM_LAUNCH_DELAY, WARM_USERNAME};
+use crate::launcher::LaunchOptions;
+use crate::{audio, game_env, limits, minecraft, sandbox};
+
+#[derive(Default)]
+struct WarmInstances {
+    // How many to keep going, which changes with demand if it's up to that
+    #[cfg(feature = "server")]
+    policy: WarmTarget,
+    target: usize,
+    // When sessions started, going back WARM_DEMAND_WINDOW
+    starts: VecDeque<Instant>,
+    // Displays that can have one, in the order to fill them
+    displays: Vec<String>,
+    // Displays a session is using
+    busy: HashSet<String>,
+    instances: HashMap<String, Child>,
+    // Each display's screen size, for watching one
+    #[cfg(feature = "server")]
+    resolutions: HashMap<String, (u16, u16)>,
+    jvm_args: Vec<String>,
+    // What they're launched with, as it was at startup
+    settings: Arc<AppConfig>,
+}
+
+static WARM: LazyLock<Mutex<WarmInstances>> = LazyLock::new(Default::default);
+
+#[cfg(feature = "server")]
+pub fn init(displays: Vec<(String, (u16, u16))>, policy: WarmTarget, jvm_args: Vec<String>, settings: Arc<AppConfig>) {
+    // Only Minecraft has a title screen to wait at
+    if matches!(policy, WarmTarget::Fixed(0) | WarmTarget::Demand { max: 0, .. }) || !settings.app.is_minecraft() {
+        return;
+    }
+    let mut warm = WARM.lock().unwrap();
+    warm.policy = policy;
+    warm.displays = displays.iter().map(|(display, _)| display.clone()).collect();
+    match policy {
+        WarmTarget::Fixed(target) => {
+            warm.target = target;
+            info!("Keeping {} warm instances on displays {:?}", target, warm.displays);
+        }
+        WarmTarget::Demand { min, max } => {
+            warm.target = min;
+            info!(
+                "Keeping {} to {} warm instances, going by demand, on displays {:?}",
+                min,
+                max.min(warm.displays.len()),
+                warm.displays
+            );
+            thread::spawn(|| loop {
+                thread::sleep(WARM_ADJUST_INTERVAL);
+                WARM.lock().unwrap().adjust();
+            });
+        }
+    }
+    warm.resolutions = displays.into_iter().collect();
+    warm.jvm_args = jvm_args;
+    warm.settings = settings;
+    warm.top_up();
+}
+
+pub fn is_warm(display: &str) -> bool {
+    WARM.lock().unwrap().instances.contains_key(display)
+}
+
+// A display with a warm instance nobody's using, and its size.  Its title screen
+// pans around a world, which makes for something to watch.
+#[cfg(feature = "server")]
+pub fn showcase() -> Option<(String, (u16, u16))> {
+    let warm = WARM.lock().unwrap();
+    let mut displays: Vec<&String> = warm.instances.keys().filter(|display| !warm.busy.contains(*display)).collect();
+    displays.sort();
+    let display = displays.first()?;
+    Some(((*display).clone(), warm.resolutions.get(*display).copied()?))
+}
+
+// The version warm instances are launched with, the one players get by default
+fn version(settings: &AppConfig) -> String {
+    settings.game_versions().into_iter().next().unwrap_or_default()
+}
+
+// Takes the warm instance on a display, if it's still running and the right version
+pub fn take(display: &str, version: &str) -> Option<Child> {
+    let mut warm = WARM.lock().unwrap();
+    if version != self::version(&warm.settings) {
+        return None;
+    }
+    let mut child = warm.instances.remove(display)?;
+    match child.try_wait() {
+        Ok(None) => Some(child),
+        _ => None,
+    }
+}
+
+// A session has the display, so warm up another one in its place
+pub fn mark_busy(display: &str) {
+    let mut warm = WARM.lock().unwrap();
+    warm.busy.insert(display.to_owned());
+    warm.starts.push_back(Instant::now());
+    warm.top_up();
+}
+
+pub fn mark_idle(display: &str) {
+    WARM.lock().unwrap().busy.remove(display);
+
+    // Give the last game on the display time to exit first
+    thread::spawn(|| {
+        thread::sleep(WARM_LAUNCH_DELAY);
+        WARM.lock().unwrap().top_up();
+    });
+}
+
+impl WarmInstances {
+    // The most sessions started within a minute of each other lately, which is
+    // about how many could want a warm instance before new ones are ready
+    #[cfg(feature = "server")]
+    fn recent_demand(&mut self) -> usize {
+        while self.starts.front().is_some_and(|start| start.elapsed() > WARM_DEMAND_WINDOW) {
+            self.starts.pop_front();
+        }
+        let starts: Vec<Instant> = self.starts.iter().copied().collect();
+        (0..starts.len())
+            .map(|i| {
+                let minute_later = starts[i] + Duration::from_secs(60);
+                starts[i..].iter().take_while(|start| **start < minute_later).count()
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Sets the target from demand, stopping any instances beyond it
+    #[cfg(feature = "server")]
+    fn adjust(&mut self) {
+        let WarmTarget::Demand { min, max } = self.policy else {
+            return;
+        };
+        let target = self.recent_demand().clamp(min, max.max(min));
+        if target != self.target {
+            info!("Keeping {} warm instances for demand, instead of {}", target, self.target);
+            self.target = target;
+        }
+        // The last displays to be filled go first
+        let mut surplus: Vec<String> =
+            self.displays.iter().filter(|display| self.instances.contains_key(*display)).cloned().collect();
+        while surplus.len() > self.target {
+            let display_name = surplus.pop().unwrap_or_default();
+            if let Some(mut child) = self.instances.remove(&display_name) {
+                info!("Stopping the warm instance on display {}, which isn't needed", display_name);
+                let _ = child.kill();
+                let _ = child.wait();
+                limits::release(&display_name, &self.settings.limits);
+            }
+        }
+        self.top_up();
+    }
+
+    fn top_up(&mut self) {
+        // Forget any that have exited on their own
+        self.instances.retain(|display_name, child| {
+            let running = matches!(child.try_wait(), Ok(None));
+            if !running {
+                info!("Warm instance on display {} exited", display_name);
+            }
+            running
+        });
+
+        let idle: Vec<String> = self
+            .displays
+            .iter()
+            .filter(|display| !self.busy.contains(*display) && !self.instances.contains_key(*display))
+            .cloned()
+            .collect();
+        let settings = self.settings.clone();
+        let shared_env = game_env::shared(&settings);
+        let version = version(&settings);
+        for display_name in idle {
+            if self.instances.len() >= self.target {
+                break;
+            }
+            let options = LaunchOptions {
+                username: WARM_USERNAME,
+                server: "",
+                display: &display_name,
+                version: &version,
+                game_dir: None,
+                world: None,
+                jvm_args: &self.jvm_args,
+                account: None,
+                env: &shared_env,
+                settings: &settings,
+            };
+            let mut cmd = match minecraft::launch_command(&options) {
+                Ok(cmd) => cmd,
+                Err(e) => {
+                    error!("Error launching a warm instance on display {}: {}", display_name, e);
+                    break;
+                }
+            };
+            cmd.envs(shared_env.iter().cloned());
+            if settings.sandboxed("") {
+                cmd = sandbox::wrap(cmd, &display_name, None, &settings);
+            }
+            limits::apply(&mut cmd, &settings.limits);
+            audio::apply(&mut cmd, &display_name, &settings);
+            match cmd.spawn() {
+                Ok(child) => {
+                    limits::confine(child.id(), &display_name, &settings.limits);
+                    info!("Launched a warm instance on display {} (PID: {})", display_name, child.id());
+                    self.instances.insert(display_name, child);
+                }
+                Err(e) => {
+                    error!("Error launching a warm instance on display {}: {}", display_name, e);
+                    break;
+                }
+            }
+        }
+    }
+}
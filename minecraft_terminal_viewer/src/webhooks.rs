@@ -0,0 +1,88 @@
+// Tells the outside world what the queue is doing, by POSTing a little JSON to
+// every URL in ANSICRAFT_WEBHOOKS (comma separated).  Each event has a "content"
+// line as well, so a Discord webhook URL works as is.
+
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use tracing::error;
+
+pub enum QueueEvent<'a> {
+    // Someone started waiting
+    Joined { pool: &'a str, waiting: usize },
+    // Someone gave up waiting, or was given up on
+    Abandoned { pool: &'a str, waited: Duration, timed_out: bool },
+    // A slot was handed out
+    Granted { pool: &'a str, resource: u32, waited: Duration },
+}
+
+impl QueueEvent<'_> {
+    fn to_json(&self) -> String {
+        let (name, pool, content, fields) = match self {
+            QueueEvent::Joined { pool, waiting } => (
+                "queue-joined",
+                pool,
+                format!("Someone joined the queue for {} ({} waiting)", pool, waiting),
+                format!("\"waiting\":{}", waiting),
+            ),
+            QueueEvent::Abandoned { pool, waited, timed_out } => (
+                "queue-abandoned",
+                pool,
+                format!("Someone left the queue for {} after {}s", pool, waited.as_secs()),
+                format!("\"waited_secs\":{},\"timed_out\":{}", waited.as_secs(), timed_out),
+            ),
+            QueueEvent::Granted { pool, resource, waited } => (
+                "slot-granted",
+                pool,
+                format!("A slot just opened up on {} and went to someone who waited {}s", pool, waited.as_secs()),
+                format!("\"resource\":{},\"waited_secs\":{}", resource, waited.as_secs()),
+            ),
+        };
+        format!(
+            "{{\"event\":\"{}\",\"pool\":\"{}\",{},\"content\":\"{}\"}}",
+            name,
+            escape(pool),
+            fields,
+            escape(&content)
+        )
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Sends the event in the background, since this is called from the queue manager
+pub fn publish(urls: &[String], event: QueueEvent) {
+    if urls.is_empty() {
+        return;
+    }
+    let body = event.to_json();
+    for url in urls {
+        let (url, body) = (url.clone(), body.clone());
+        thread::spawn(move || {
+            let status = Command::new("curl")
+                .args(["-sS", "--max-time", "10", "-X", "POST", "-H", "Content-Type: application/json"])
+                .args(["--data", &body, &url])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+            match status {
+                Ok(status) if status.success() => {}
+                // Webhook URLs are often secrets, so they stay out of the logs
+                Ok(status) => error!("A webhook failed: curl exited with {}", status),
+                Err(e) => error!("Error running curl for a webhook: {}", e),
+            }
+        });
+    }
+}
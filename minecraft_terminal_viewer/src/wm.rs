@@ -0,0 +1,95 @@
+// Without a window manager, a game's window can open off to the side of the part of
+// the screen that's captured, and clicks then land in the wrong place.  So a window
+// manager (from ANSICRAFT_WINDOW_MANAGER, e.g. "matchbox-window-manager -use_titlebar no")
+// is kept running on each display, and restarted if it dies.  Either way, once the
+// game's window is up it's moved to the corner and made to fill the screen.
+
+use std::collections::HashMap;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing::{error, warn};
+
+use crate::config::WM_RESTART_DELAY;
+
+// Displays with a window manager being kept going, and the flag that stops it
+static SUPERVISED: LazyLock<Mutex<HashMap<String, Arc<AtomicBool>>>> = LazyLock::new(Default::default);
+
+// Keeps the window manager running on the display until `stop` is called for it
+pub fn supervise(display: &str, command: Option<&str>) {
+    let Some(command) = command.map(str::to_owned) else {
+        return;
+    };
+    let running = Arc::new(AtomicBool::new(true));
+    if let Some(previous) = SUPERVISED.lock().unwrap().insert(display.to_owned(), running.clone()) {
+        previous.store(false, Ordering::SeqCst);
+    }
+
+    let display_name = display.to_owned();
+    thread::spawn(move || {
+        let mut args = command.split_whitespace();
+        let program = args.next().unwrap_or_default();
+        let args: Vec<&str> = args.collect();
+        while running.load(Ordering::SeqCst) {
+            let mut child = match Command::new(program)
+                .args(&args)
+                .env("DISPLAY", &display_name)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+            {
+                Ok(child) => child,
+                Err(e) => {
+                    error!("Error starting window manager {} on display {}: {}", program, display_name, e);
+                    return;
+                }
+            };
+            let started = Instant::now();
+            let status = loop {
+                if !running.load(Ordering::SeqCst) {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return;
+                }
+                match child.try_wait() {
+                    Ok(None) => thread::sleep(Duration::from_millis(500)),
+                    Ok(Some(status)) => break status.to_string(),
+                    Err(e) => break e.to_string(),
+                }
+            };
+            warn!("Window manager on display {} exited ({}), restarting it", display_name, status);
+            // Don't spin on one that can't start, e.g. because the X server is gone
+            if started.elapsed() < WM_RESTART_DELAY {
+                thread::sleep(WM_RESTART_DELAY);
+            }
+        }
+    });
+}
+
+pub fn stop(display: &str) {
+    if let Some(running) = SUPERVISED.lock().unwrap().remove(display) {
+        running.store(false, Ordering::SeqCst);
+    }
+}
+
+// Moves the window with this name (a regex) to the top left and sizes it to the
+// screen, so it lines up with what's captured
+#[cfg(unix)]
+pub fn fit_window(display_name: &str, name: &str, (width, height): (u16, u16)) {
+    let status = Command::new("xdotool")
+        .args(["search", "--onlyvisible", "--name", name])
+        .args(["windowmove", "%@", "0", "0"])
+        .args(["windowsize", "%@", &width.to_string(), &height.to_string()])
+        .env("DISPLAY", display_name)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => error!("Couldn't fit the window on display {}: xdotool exited with {}", display_name, status),
+        Err(e) => error!("Error running xdotool: {}", e),
+    }
+}
@@ -1,46 +1,104 @@
+use std::cell::Cell;
 use std::io;
-use std::os::unix::process::ExitStatusExt;
 use std::process::Command;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex, mpsc};
-use std::time::Duration;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
 
 use termwiz::input::{InputEvent, InputParser, KeyCode, Modifiers, MouseButtons};
+use tokio_util::sync::CancellationToken;
+use tracing::error;
 
-use crate::config::{GAME_HEIGHT, GAME_WIDTH, TerminalSize};
+use crate::config::{
+    CYCLE_RENDER_MODE_KEY, DOUBLE_ESCAPE_WINDOW, GAMEPAD_LOOK_DEAD_ZONE,
+    GAMEPAD_MOVE_THRESHOLD, InputSettings, KEY_HOLD_TIMEOUT, KEY_REPEAT_WINDOW, Keymap, LOG_VIEWER_KEY, RenderMode,
+    RenderSettings, TerminalSize,
+};
+use crate::gamepad::{self, ControlDecoder, ControlMessage, GamepadState};
+use crate::plugins;
+use crate::session::SessionState;
+use crate::session_log::SessionLogs;
 
-// Captures keyboard and mouse input using termwiz
-pub fn capture_input<Reader: io::Read + Send + 'static>(
-    input_channel: Arc<Mutex<Reader>>,
-    input_tx: mpsc::Sender<InputEvent>,
-    running: Arc<AtomicBool>,
-) -> io::Result<()> {
+// Why input stopped getting to the game
+#[derive(Debug, thiserror::Error)]
+pub enum InputError {
+    #[error("Couldn't run xdotool: {0}")]
+    Xdotool(#[source] io::Error),
+}
 
-    let mut reader = input_channel.lock().expect("Failed to lock mutex");
-    let mut parser: InputParser = InputParser::new();
-    while running.load(Ordering::SeqCst) {
-        let mut buf = [0u8; 64];
-        match reader.read(&mut buf) {
-            Ok(0) => break,
-            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
-                std::thread::sleep(Duration::from_millis(1));
-            }
-            Err(e) => {
-                eprintln!("Error reading input: {}", e);
-                break;
-            }
-            Ok(n) => {
-                parser.parse(
-                    &buf[0..n],
-                    |event| {
-                        if let Err(e) = input_tx.send(event) {
-                            eprintln!("Error sending event: {}", e);
-                        }
-                    },
-                    false,
-                );
+// Where the game's input goes
+pub trait InputSink {
+    // Sends what an xdotool command would, like ["keydown", "w"]
+    fn send(&self, args: &[&str]) -> Result<(), InputError>;
+}
+
+// Sends input to a display with xdotool
+pub struct Xdotool {
+    pub display: String,
+    pub logs: SessionLogs,
+}
+
+impl InputSink for Xdotool {
+    fn send(&self, args: &[&str]) -> Result<(), InputError> {
+        let status = Command::new("xdotool")
+            .args(args)
+            .env("DISPLAY", &self.display)
+            .stderr(self.logs.stdio("input"))
+            .status();
+        match status {
+            Ok(status) if !status.success() => {
+                self.logs.line("input", &format!("xdotool {} exited with {}", args.join(" "), status));
             }
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(InputError::Xdotool(e)),
+            Err(e) => error!("Error running xdotool: {}", e),
         }
+        Ok(())
+    }
+}
+
+// Everything the input forwarder can receive from the client
+pub enum ClientInput {
+    Terminal(InputEvent),
+    Control(ControlMessage),
+}
+
+// What the client types, as it arrives.  It's shared, since the SSH side reads it
+// too outside of games.
+pub type InputSource = Arc<tokio::sync::Mutex<tokio::sync::mpsc::UnboundedReceiver<Vec<u8>>>>;
+
+// Captures keyboard and mouse input using termwiz
+pub async fn capture_input(
+    input: InputSource,
+    input_tx: mpsc::Sender<ClientInput>,
+    cancel: CancellationToken,
+) -> Result<(), InputError> {
+    let mut input = input.lock().await;
+    let mut parser: InputParser = InputParser::new();
+    let mut control_decoder = ControlDecoder::new();
+    loop {
+        let data = tokio::select! {
+            _ = cancel.cancelled() => break,
+            data = input.recv() => match data {
+                Some(data) => data,
+                None => break,
+            },
+        };
+        let terminal_input = control_decoder.feed(&data, |message| {
+            if let Err(e) = input_tx.send(ClientInput::Control(message)) {
+                error!("Error sending event: {}", e);
+            }
+        });
+        parser.parse(
+            &terminal_input,
+            |event| {
+                if let Err(e) = input_tx.send(ClientInput::Terminal(event)) {
+                    error!("Error sending event: {}", e);
+                }
+            },
+            false,
+        );
     }
 
     Ok(())
@@ -48,27 +106,42 @@ pub fn capture_input<Reader: io::Read + Send + 'static>(
 
 // Forwards captured input to the Minecraft instance
 pub fn forward_input_to_minecraft(
-    input_rx: mpsc::Receiver<InputEvent>,
-    term_size: Arc<Mutex<TerminalSize>>,
-    running: Arc<AtomicBool>,
-    display: String,
+    input_rx: mpsc::Receiver<ClientInput>,
+    session_state: SessionState,
+    sink: impl InputSink,
+    game_size: (u16, u16),
     absolute_mouse_mode_default: bool,
-) -> io::Result<()> {
+) -> Result<(), InputError> {
+    let SessionState {
+        running,
+        terminal_size: term_size,
+        input_settings,
+        render_settings,
+        overlay,
+        last_input,
+        game_log,
+        dead,
+        logs: _,
+        session_info,
+    } = session_state;
+    #[cfg(feature = "scripting")]
+    let sink = crate::scripting::Scripted {
+        sink,
+        session: session_info.clone(),
+    };
+
+    // Without xdotool at all, there's no point going on
+    let missing_xdotool = Cell::new(None);
     let run_xdotool = |args: &[&str]| {
-        Command::new("xdotool")
-            .args(args)
-            .env("DISPLAY", &display)
-            .status()
-            .unwrap_or_else(|e| {
-                eprintln!("Error running xdotool: {}", e);
-                std::process::ExitStatus::from_raw(1)
-            });
+        if let Err(e) = sink.send(args) {
+            missing_xdotool.set(Some(e));
+        }
     };
 
-    fn scale_mouse_coords(x: u16, y: u16, term_size: &TerminalSize) -> (u16, u16) {
-        let scaled_x = (x as f32 / term_size.target_width as f32 * GAME_WIDTH as f32) as u16;
+    fn scale_mouse_coords(x: u16, y: u16, term_size: &TerminalSize, (game_width, game_height): (u16, u16)) -> (u16, u16) {
+        let scaled_x = (x as f32 / term_size.target_width as f32 * game_width as f32) as u16;
         let actual_height_in_pixels = term_size.target_height / 2;
-        let scaled_y = (y as f32 / actual_height_in_pixels as f32 * GAME_HEIGHT as f32) as u16;
+        let scaled_y = (y as f32 / actual_height_in_pixels as f32 * game_height as f32) as u16;
         (scaled_x, scaled_y)
     }
 
@@ -77,153 +150,336 @@ pub fn forward_input_to_minecraft(
         current_y: u16,
         last_x: u16,
         last_y: u16,
+        settings: &InputSettings,
     ) -> (i32, i32) {
         let dx = current_x as i32 - last_x as i32;
         let dy = current_y as i32 - last_y as i32;
-        (dx * 10, dy * 10)
+        (
+            (dx as f32 * settings.look_scale_x) as i32,
+            (dy as f32 * settings.look_scale_y) as i32,
+        )
     }
 
-    #[derive(Clone)]
-    struct KeyState {
-        pressed: bool,
-        release_time: std::time::Instant,
+    // Movement within the dead zone (in terminal cells) of the anchor is treated
+    // as jitter.  Once the cursor leaves it, the anchor follows the cursor.
+    fn outside_dead_zone(current: u16, anchor: u16, dead_zone: u16) -> bool {
+        current.abs_diff(anchor) > dead_zone
     }
 
-    let mut wasd_release_time = std::collections::HashMap::new();
-    wasd_release_time.insert(
-        'w',
-        KeyState {
-            pressed: false,
-            release_time: std::time::Instant::now(),
-        },
-    );
-    wasd_release_time.insert(
-        'a',
-        KeyState {
-            pressed: false,
-            release_time: std::time::Instant::now(),
-        },
-    );
-    wasd_release_time.insert(
-        's',
-        KeyState {
-            pressed: false,
-            release_time: std::time::Instant::now(),
-        },
-    );
-    wasd_release_time.insert(
-        'd',
-        KeyState {
-            pressed: false,
-            release_time: std::time::Instant::now(),
-        },
-    );
-
-    let mut inventory_open = absolute_mouse_mode_default;
+    let mut held_keys = HeldKeys::default();
+    let mut inventory_open = input_settings
+        .lock()
+        .unwrap()
+        .absolute_mouse
+        .unwrap_or(absolute_mouse_mode_default);
     let mut last_mouse_x = 0u16;
     let mut last_mouse_y = 0u16;
+    let mut anchor_cell_x = 0u16;
+    let mut anchor_cell_y = 0u16;
+    let mut gamepad_state = GamepadState::default();
+    // Best guess at whether the chat box has focus, so hotkeys don't eat typed text
+    let mut chat_open = false;
+    // Left button held down by the mining latch key
+    let mut mining_latched = false;
+    // Escape waiting to see whether it's the first half of a double-Escape
+    let mut pending_escape: Option<Instant> = None;
+    let mut menu_open = false;
+    // Lines scrolled up from the bottom of the log viewer, while it's open
+    let mut log_scroll: Option<usize> = None;
+    let mut log_shown = 0;
+
+    // Menus need clicks, not a held button
+    fn release_mining_latch(mining_latched: &mut bool, run_xdotool: &dyn Fn(&[&str])) {
+        if *mining_latched {
+            *mining_latched = false;
+            run_xdotool(&["mouseup", "1"]);
+        }
+    }
+
+    // A lone Escape closes whatever's open in the game
+    fn send_escape(
+        inventory_open: &mut bool,
+        chat_open: &mut bool,
+        mining_latched: &mut bool,
+        run_xdotool: &dyn Fn(&[&str]),
+    ) {
+        *inventory_open = false;
+        *chat_open = false;
+        release_mining_latch(mining_latched, run_xdotool);
+        run_xdotool(&["key", "Escape"]);
+    }
+
+    fn is_fps_key(input_settings: &Mutex<InputSettings>, c: char) -> bool {
+        let settings = input_settings.lock().unwrap();
+        c == settings.fps_down_key || c == settings.fps_up_key
+    }
 
     while running.load(Ordering::SeqCst) {
-        match input_rx.recv_timeout(Duration::from_millis(50)) {
-            Ok(event) => match event {
+        let received = input_rx.recv_timeout(Duration::from_millis(50));
+        if received.as_ref().is_ok_and(|input| !plugins::input(&session_info, input)) {
+            continue;
+        }
+        if matches!(
+            received,
+            Ok(ClientInput::Terminal(_) | ClientInput::Control(ControlMessage::Pad(_)))
+        ) {
+            *last_input.lock().unwrap() = Instant::now();
+        }
+        // Clicking the death screen's buttons leaves it as well as pressing R
+        if let Ok(ClientInput::Terminal(InputEvent::Mouse(mouse_event))) = &received
+            && mouse_event.mouse_buttons.contains(MouseButtons::LEFT)
+            && dead.swap(false, Ordering::SeqCst)
+        {
+            overlay.lock().unwrap().hide_death();
+        }
+        // Anything but a second Escape means the first was a lone one, which has to
+        // reach the game before whatever came after it
+        let second_escape = matches!(
+            &received,
+            Ok(ClientInput::Terminal(InputEvent::Key(key_event))) if key_event.key == KeyCode::Escape
+        );
+        let other_input = matches!(
+            received,
+            Ok(ClientInput::Terminal(_) | ClientInput::Control(ControlMessage::Pad(_)))
+        );
+        if pending_escape.is_some() && other_input && !second_escape {
+            pending_escape = None;
+            send_escape(&mut inventory_open, &mut chat_open, &mut mining_latched, &run_xdotool);
+        }
+        match received {
+            Ok(ClientInput::Control(ControlMessage::Resize { cols, .. })) => {
+                let mut size = term_size.lock().unwrap();
+                size.target_width = cols as usize;
+                size.target_height = crate::render::get_height_from_width(cols as usize);
+            }
+            Ok(ClientInput::Control(ControlMessage::Pad(state))) => {
+                apply_gamepad_state(
+                    &gamepad_state,
+                    &state,
+                    &run_xdotool,
+                    &input_settings.lock().unwrap(),
+                    &mut inventory_open,
+                );
+                gamepad_state = state;
+            }
+            Ok(ClientInput::Terminal(event)) => match event {
+                // So does the log viewer
+                InputEvent::Key(key_event) if log_scroll.is_some() => {
+                    let page = log_page_size(&term_size.lock().unwrap());
+                    let scroll = log_scroll.unwrap_or(0);
+                    log_scroll = match key_event.key {
+                        KeyCode::Escape | KeyCode::Char('q') | KeyCode::Function(LOG_VIEWER_KEY) => None,
+                        KeyCode::UpArrow => Some(scroll + 1),
+                        KeyCode::DownArrow => Some(scroll.saturating_sub(1)),
+                        KeyCode::PageUp => Some(scroll + page),
+                        KeyCode::PageDown => Some(scroll.saturating_sub(page)),
+                        KeyCode::Home => Some(usize::MAX),
+                        KeyCode::End => Some(0),
+                        _ => Some(scroll),
+                    };
+                    match log_scroll {
+                        Some(scroll) => {
+                            let log = game_log.lock().unwrap();
+                            let scroll = scroll.min(log.lines.len().saturating_sub(page));
+                            log_scroll = Some(scroll);
+                            overlay.lock().unwrap().show_panel(log_lines(&log.lines, scroll, page));
+                        }
+                        None => overlay.lock().unwrap().hide_panel(),
+                    }
+                }
+                InputEvent::Mouse(_) if log_scroll.is_some() => {}
+                // The local menu swallows all input while it's open
+                InputEvent::Key(key_event) if menu_open => match key_event.key {
+                    KeyCode::Escape | KeyCode::Char('r') => {
+                        menu_open = false;
+                        overlay.lock().unwrap().hide_panel();
+                    }
+                    KeyCode::Char('q') => {
+                        running.store(false, Ordering::SeqCst);
+                        break;
+                    }
+                    KeyCode::Char('l') => {
+                        menu_open = false;
+                        log_scroll = Some(0);
+                        // Drawn with the next refresh
+                        log_shown = usize::MAX;
+                    }
+                    KeyCode::Char('c') if key_event.modifiers.contains(Modifiers::CTRL) => {
+                        running.store(false, Ordering::SeqCst);
+                        break;
+                    }
+                    key => {
+                        match key {
+                            KeyCode::Char('+') | KeyCode::Char('=') => {
+                                scale_look_sensitivity(&mut input_settings.lock().unwrap(), 1.25)
+                            }
+                            KeyCode::Char('-') => {
+                                scale_look_sensitivity(&mut input_settings.lock().unwrap(), 0.8)
+                            }
+                            KeyCode::Char('j') => {
+                                let mut settings = input_settings.lock().unwrap();
+                                settings.jump_latch = !settings.jump_latch;
+                            }
+                            KeyCode::Char('k') => {
+                                let mut settings = input_settings.lock().unwrap();
+                                settings.keymap = settings.keymap.next();
+                            }
+                            KeyCode::Char('m') => {
+                                inventory_open = !inventory_open;
+                                input_settings.lock().unwrap().absolute_mouse = Some(inventory_open);
+                            }
+                            KeyCode::Char('v') | KeyCode::Function(CYCLE_RENDER_MODE_KEY) => {
+                                let mut settings = render_settings.lock().unwrap();
+                                settings.mode = settings.mode.next();
+                            }
+                            KeyCode::Char(c) if is_fps_key(&input_settings, c) => {
+                                let up = c == input_settings.lock().unwrap().fps_up_key;
+                                render_settings.lock().unwrap().step_fps(up);
+                            }
+                            _ => {}
+                        }
+                        overlay.lock().unwrap().show_panel(menu_lines(
+                            &input_settings.lock().unwrap(),
+                            &render_settings.lock().unwrap(),
+                            inventory_open,
+                        ));
+                    }
+                },
+                InputEvent::Mouse(_) if menu_open => {}
+                InputEvent::Key(key_event)
+                    if matches!(key_event.key, KeyCode::Char('r' | 'R')) && dead.swap(false, Ordering::SeqCst) =>
+                {
+                    // Respawn is the death screen's first button
+                    overlay.lock().unwrap().hide_death();
+                    run_xdotool(&["key", "Tab", "Return"]);
+                }
                 InputEvent::Key(key_event) => match key_event.key {
+                    // Hotkeys are typed normally while the chat is open
+                    KeyCode::Char(c) if !chat_open && is_fps_key(&input_settings, c) => {
+                        let up = c == input_settings.lock().unwrap().fps_up_key;
+                        render_settings.lock().unwrap().step_fps(up);
+                    }
+                    KeyCode::Char(c) if !chat_open && c == input_settings.lock().unwrap().mining_latch_key => {
+                        mining_latched = !mining_latched;
+                        run_xdotool(&[if mining_latched { "mousedown" } else { "mouseup" }, "1"]);
+                    }
                     KeyCode::Char(c) => match c {
-                        ' ' => run_xdotool(&["key", "space"]),
-                        ';' => run_xdotool(&["key", "semicolon"]),
-                        '?' => run_xdotool(&["key", "question"]),
-                        '!' => run_xdotool(&["key", "exclam"]),
-                        ':' => run_xdotool(&["key", "colon"]),
-                        '"' => run_xdotool(&["key", "quotedbl"]),
-                        '\'' => run_xdotool(&["key", "apostrophe"]),
-                        '>' => run_xdotool(&["key", "greater"]),
-                        '<' => run_xdotool(&["key", "less"]),
-                        '|' => run_xdotool(&["key", "bar"]),
-                        '\\' => run_xdotool(&["key", "backslash"]),
-                        '/' => run_xdotool(&["key", "slash"]),
-                        '[' => run_xdotool(&["key", "bracketleft"]),
-                        ']' => run_xdotool(&["key", "bracketright"]),
-                        '{' => run_xdotool(&["key", "braceleft"]),
-                        '}' => run_xdotool(&["key", "braceright"]),
-                        '(' => run_xdotool(&["key", "parenleft"]),
-                        ')' => run_xdotool(&["key", "parenright"]),
-                        '+' => run_xdotool(&["key", "plus"]),
-                        '-' => run_xdotool(&["key", "minus"]),
-                        '=' => run_xdotool(&["key", "equal"]),
-                        '_' => run_xdotool(&["key", "underscore"]),
-                        ',' => run_xdotool(&["key", "comma"]),
-                        '.' => run_xdotool(&["key", "period"]),
-                        '^' => run_xdotool(&["key", "asciicircum"]),
-                        '~' => run_xdotool(&["key", "asciitilde"]),
-                        '@' => run_xdotool(&["key", "at"]),
-                        '#' => run_xdotool(&["key", "numbersign"]),
-                        '$' => run_xdotool(&["key", "dollar"]),
-                        '%' => run_xdotool(&["key", "percent"]),
-                        '&' => run_xdotool(&["key", "ampersand"]),
-                        '*' => run_xdotool(&["key", "asterisk"]),
-
                         '`' => {
                             inventory_open = !inventory_open;
+                            // Remember the mode the player picked for next time
+                            input_settings.lock().unwrap().absolute_mouse = Some(inventory_open);
                         }
 
-                        'e' => {
+                        'e' if !chat_open => {
+                            release_mining_latch(&mut mining_latched, &run_xdotool);
                             inventory_open = !inventory_open;
                             run_xdotool(&["key", "e"]);
                         }
 
-                        'c' => {
-                            // Check for Ctrl+C
-                            if key_event.key == KeyCode::Char('c')
-                                && key_event.modifiers.contains(Modifiers::CTRL)
-                            {
-                                running.store(false, Ordering::SeqCst);
-                                break;
-                            }
-                            run_xdotool(&["key", &c.to_string()])
+                        't' | '/' if !chat_open && !inventory_open => {
+                            chat_open = true;
+                            held_keys.press(&char_keysym(c), &run_xdotool);
                         }
 
-                        'w' | 'a' | 's' | 'd' => {
-                            if let Some(state) = wasd_release_time.get_mut(&c) {
-                                if !state.pressed {
-                                    run_xdotool(&["keydown", &c.to_string()]);
-                                    state.pressed = true;
-                                }
-                                state.release_time =
-                                    std::time::Instant::now() + Duration::from_millis(100);
-                            }
+                        'c' if key_event.modifiers.contains(Modifiers::CTRL) => {
+                            running.store(false, Ordering::SeqCst);
+                            break;
                         }
 
-                        _ => run_xdotool(&["key", &c.to_string()]),
+                        'w' | 'a' | 's' | 'd' => held_keys.hold(&c.to_string(), &run_xdotool),
+
+                        ' ' if !chat_open && input_settings.lock().unwrap().jump_latch => {
+                            held_keys.hold("space", &run_xdotool)
+                        }
+
+                        _ => held_keys.press(&char_keysym(c), &run_xdotool),
                     },
-                    KeyCode::Enter => run_xdotool(&["key", "Return"]),
-                    KeyCode::UpArrow => run_xdotool(&["key", "Up"]),
-                    KeyCode::DownArrow => run_xdotool(&["key", "Down"]),
-                    KeyCode::RightArrow => run_xdotool(&["key", "Right"]),
-                    KeyCode::LeftArrow => run_xdotool(&["key", "Left"]),
-                    KeyCode::Backspace => run_xdotool(&["key", "BackSpace"]),
+                    KeyCode::Enter => {
+                        chat_open = false;
+                        held_keys.press("Return", &run_xdotool);
+                    }
+                    // The arrow keys walk like WASD, except in the chat where they
+                    // move the cursor
+                    KeyCode::UpArrow | KeyCode::DownArrow | KeyCode::RightArrow | KeyCode::LeftArrow
+                        if !chat_open && input_settings.lock().unwrap().keymap == Keymap::Arrows =>
+                    {
+                        let key = match key_event.key {
+                            KeyCode::UpArrow => "w",
+                            KeyCode::LeftArrow => "a",
+                            KeyCode::DownArrow => "s",
+                            _ => "d",
+                        };
+                        held_keys.hold(key, &run_xdotool)
+                    }
+                    KeyCode::UpArrow => held_keys.press("Up", &run_xdotool),
+                    KeyCode::DownArrow => held_keys.press("Down", &run_xdotool),
+                    KeyCode::RightArrow => held_keys.press("Right", &run_xdotool),
+                    KeyCode::LeftArrow => held_keys.press("Left", &run_xdotool),
+                    KeyCode::Backspace => held_keys.press("BackSpace", &run_xdotool),
                     KeyCode::Escape => {
-                        if inventory_open {
-                            inventory_open = false;
+                        // Two escapes in one read arrive as Alt+Escape
+                        let double_escape = pending_escape.take().is_some()
+                            || key_event.modifiers.contains(Modifiers::ALT);
+                        if double_escape {
+                            release_mining_latch(&mut mining_latched, &run_xdotool);
+                            menu_open = true;
+                            overlay.lock().unwrap().show_panel(menu_lines(
+                                &input_settings.lock().unwrap(),
+                                &render_settings.lock().unwrap(),
+                                inventory_open,
+                            ));
+                        } else {
+                            pending_escape = Some(Instant::now());
                         }
-                        run_xdotool(&["key", "Escape"]);
                     }
-                    KeyCode::Tab => run_xdotool(&["key", "Tab"]),
-                    KeyCode::Delete => run_xdotool(&["key", "Delete"]),
-                    KeyCode::Home => run_xdotool(&["key", "Home"]),
-                    KeyCode::End => run_xdotool(&["key", "End"]),
-                    KeyCode::PageUp => run_xdotool(&["key", "Page_Up"]),
-                    KeyCode::PageDown => run_xdotool(&["key", "Page_Down"]),
+                    KeyCode::Tab => held_keys.press("Tab", &run_xdotool),
+                    KeyCode::Delete => held_keys.press("Delete", &run_xdotool),
+                    KeyCode::Home => held_keys.press("Home", &run_xdotool),
+                    KeyCode::End => held_keys.press("End", &run_xdotool),
+                    KeyCode::PageUp => held_keys.press("Page_Up", &run_xdotool),
+                    KeyCode::PageDown => held_keys.press("Page_Down", &run_xdotool),
+                    KeyCode::Function(CYCLE_RENDER_MODE_KEY) => {
+                        let mut settings = render_settings.lock().unwrap();
+                        settings.mode = settings.mode.next();
+                    }
+                    KeyCode::Function(LOG_VIEWER_KEY) => {
+                        release_mining_latch(&mut mining_latched, &run_xdotool);
+                        log_scroll = Some(0);
+                        // Drawn with the next refresh
+                        log_shown = usize::MAX;
+                    }
                     _ => {}
                 },
                 InputEvent::Mouse(mouse_event) => {
                     let term_size_value = term_size.lock().unwrap().clone();
                     let (game_x, game_y) =
-                        scale_mouse_coords(mouse_event.x, mouse_event.y, &term_size_value);
+                        scale_mouse_coords(mouse_event.x, mouse_event.y, &term_size_value, game_size);
 
                     if inventory_open {
                         run_xdotool(&["mousemove", &game_x.to_string(), &game_y.to_string()]);
+                        last_mouse_x = game_x;
+                        last_mouse_y = game_y;
+                        anchor_cell_x = mouse_event.x;
+                        anchor_cell_y = mouse_event.y;
                     } else if last_mouse_x > 0 && last_mouse_y > 0 {
-                        let (dx, dy) =
-                            calculate_relative_movement(game_x, game_y, last_mouse_x, last_mouse_y);
+                        let settings = input_settings.lock().unwrap().clone();
+                        let dead_zone = settings.look_dead_zone;
+                        let (mut target_x, mut target_y) = (last_mouse_x, last_mouse_y);
+                        if outside_dead_zone(mouse_event.x, anchor_cell_x, dead_zone) {
+                            target_x = game_x;
+                            anchor_cell_x = mouse_event.x;
+                        }
+                        if outside_dead_zone(mouse_event.y, anchor_cell_y, dead_zone) {
+                            target_y = game_y;
+                            anchor_cell_y = mouse_event.y;
+                        }
+                        let (dx, dy) = calculate_relative_movement(
+                            target_x,
+                            target_y,
+                            last_mouse_x,
+                            last_mouse_y,
+                            &settings,
+                        );
                         if dx != 0 || dy != 0 {
                             run_xdotool(&[
                                 "mousemove_relative",
@@ -232,15 +488,19 @@ pub fn forward_input_to_minecraft(
                                 &dy.to_string(),
                             ]);
                         }
+                        last_mouse_x = target_x;
+                        last_mouse_y = target_y;
+                    } else {
+                        last_mouse_x = game_x;
+                        last_mouse_y = game_y;
+                        anchor_cell_x = mouse_event.x;
+                        anchor_cell_y = mouse_event.y;
                     }
 
-                    last_mouse_x = game_x;
-                    last_mouse_y = game_y;
-
                     let buttons = mouse_event.mouse_buttons;
                     if buttons.contains(MouseButtons::LEFT) {
                         run_xdotool(&["mousedown", "1"]);
-                    } else {
+                    } else if !mining_latched {
                         run_xdotool(&["mouseup", "1"]);
                     }
                     if buttons.contains(MouseButtons::RIGHT) {
@@ -260,16 +520,272 @@ pub fn forward_input_to_minecraft(
                 }
                 _ => {}
             },
-            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            // Fall through so held keys still get released while no input arrives
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
             Err(mpsc::RecvTimeoutError::Disconnected) => break,
         }
 
-        for (key, state) in wasd_release_time.iter_mut() {
-            if state.pressed && std::time::Instant::now() >= state.release_time {
-                run_xdotool(&["keyup", &key.to_string()]);
-                state.pressed = false;
+        // Keep the log viewer up to date with whatever the game says next
+        if let Some(scroll) = log_scroll {
+            let log = game_log.lock().unwrap();
+            if log.received != log_shown {
+                log_shown = log.received;
+                let page = log_page_size(&term_size.lock().unwrap());
+                overlay.lock().unwrap().show_panel(log_lines(&log.lines, scroll, page));
             }
         }
+
+        // A lone Escape goes to the game once it's clear no second one is coming
+        if pending_escape.is_some_and(|pressed_at| pressed_at.elapsed() > DOUBLE_ESCAPE_WINDOW) {
+            pending_escape = None;
+            send_escape(&mut inventory_open, &mut chat_open, &mut mining_latched, &run_xdotool);
+        }
+
+        held_keys.release_expired(&run_xdotool);
+        if let Some(e) = missing_xdotool.take() {
+            return Err(e);
+        }
     }
     Ok(())
 }
+
+// Contents of the local menu opened by double-Escape
+fn menu_lines(input: &InputSettings, render: &RenderSettings, absolute_mouse: bool) -> Vec<String> {
+    let render_mode = match render.mode {
+        RenderMode::TrueColor => "truecolor",
+        RenderMode::Ansi256 => "256 colors",
+        RenderMode::Ansi16 => "16 colors",
+        RenderMode::Braille => "braille",
+        RenderMode::Ascii => "ASCII",
+    };
+    vec![
+        "ansicraft".to_owned(),
+        String::new(),
+        "[Esc/r] Resume".to_owned(),
+        format!("[+/-]   Look sensitivity: {:.1}", input.look_scale_x),
+        format!("[m]     Mouse mode: {}", if absolute_mouse { "absolute" } else { "relative" }),
+        format!("[j]     Jump latch: {}", if input.jump_latch { "on" } else { "off" }),
+        format!(
+            "[k]     Walk with: {}",
+            match input.keymap {
+                Keymap::Wasd => "WASD",
+                Keymap::Arrows => "WASD or arrows",
+            }
+        ),
+        format!("[v]     Render mode: {}", render_mode),
+        format!("[{} {}]   FPS cap: {}", input.fps_down_key, input.fps_up_key, render.fps),
+        "[l]     Game log".to_owned(),
+        "[q]     Disconnect".to_owned(),
+    ]
+}
+
+// How many lines of the log fit on screen, leaving room for the panel's border
+// and title
+fn log_page_size(size: &TerminalSize) -> usize {
+    (size.target_height / 2).saturating_sub(4).max(1)
+}
+
+// Contents of the log viewer, ending `scroll` lines up from the latest
+fn log_lines(lines: &VecDeque<String>, scroll: usize, page: usize) -> Vec<String> {
+    let end = lines.len().saturating_sub(scroll);
+    let start = end.saturating_sub(page);
+    if lines.is_empty() {
+        return vec!["Game log (F9 to close)".to_owned(), String::new(), "Nothing from the game yet".to_owned()];
+    }
+    let mut panel = vec![
+        format!("Game log ({}-{} of {}, arrows/PgUp/PgDn to scroll, F9 to close)", start + 1, end, lines.len()),
+        String::new(),
+    ];
+    panel.extend(lines.range(start..end).cloned());
+    panel
+}
+
+fn scale_look_sensitivity(settings: &mut InputSettings, factor: f32) {
+    settings.look_scale_x = (settings.look_scale_x * factor).clamp(1.0, 100.0);
+    settings.look_scale_y = (settings.look_scale_y * factor).clamp(1.0, 100.0);
+}
+
+// X's names for the characters xdotool won't take as themselves
+pub(crate) const KEYSYM_NAMES: [(char, &str); 32] = [
+    (' ', "space"),
+    (';', "semicolon"),
+    ('?', "question"),
+    ('!', "exclam"),
+    (':', "colon"),
+    ('"', "quotedbl"),
+    ('\'', "apostrophe"),
+    ('>', "greater"),
+    ('<', "less"),
+    ('|', "bar"),
+    ('\\', "backslash"),
+    ('/', "slash"),
+    ('[', "bracketleft"),
+    (']', "bracketright"),
+    ('{', "braceleft"),
+    ('}', "braceright"),
+    ('(', "parenleft"),
+    (')', "parenright"),
+    ('+', "plus"),
+    ('-', "minus"),
+    ('=', "equal"),
+    ('_', "underscore"),
+    (',', "comma"),
+    ('.', "period"),
+    ('^', "asciicircum"),
+    ('~', "asciitilde"),
+    ('@', "at"),
+    ('#', "numbersign"),
+    ('$', "dollar"),
+    ('%', "percent"),
+    ('&', "ampersand"),
+    ('*', "asterisk"),
+];
+
+// xdotool keysym for a typed character
+fn char_keysym(c: char) -> String {
+    KEYSYM_NAMES
+        .iter()
+        .find(|(named, _)| *named == c)
+        .map_or_else(|| c.to_string(), |(_, name)| (*name).to_owned())
+}
+
+// Terminals only report key presses, never releases, and a held key arrives as a
+// stream of auto-repeated presses.  Keys are kept down in the game while those
+// presses keep coming, and released once they stop for a moment.
+#[derive(Default)]
+struct HeldKeys {
+    release_times: HashMap<String, Instant>,
+    last_tap: Option<(String, Instant)>,
+}
+
+impl HeldKeys {
+    // Holds a key down straight away (movement keys)
+    fn hold(&mut self, keysym: &str, run_xdotool: &dyn Fn(&[&str])) {
+        if !self.release_times.contains_key(keysym) {
+            run_xdotool(&["keydown", keysym]);
+        }
+        self.release_times
+            .insert(keysym.to_owned(), Instant::now() + KEY_HOLD_TIMEOUT);
+    }
+
+    // Taps a key, unless the terminal is auto-repeating it, in which case it's held
+    // down until the repeats stop instead of being pressed and released each time.
+    fn press(&mut self, keysym: &str, run_xdotool: &dyn Fn(&[&str])) {
+        let now = Instant::now();
+        if let Some(release_time) = self.release_times.get_mut(keysym) {
+            *release_time = now + KEY_HOLD_TIMEOUT;
+            return;
+        }
+
+        let repeating = matches!(
+            &self.last_tap,
+            Some((last_keysym, tapped_at)) if last_keysym == keysym && now - *tapped_at <= KEY_REPEAT_WINDOW
+        );
+        if repeating {
+            self.last_tap = None;
+            self.hold(keysym, run_xdotool);
+        } else {
+            run_xdotool(&["key", keysym]);
+            self.last_tap = Some((keysym.to_owned(), now));
+        }
+    }
+
+    fn release_expired(&mut self, run_xdotool: &dyn Fn(&[&str])) {
+        let now = Instant::now();
+        self.release_times.retain(|keysym, release_time| {
+            if now >= *release_time {
+                run_xdotool(&["keyup", keysym]);
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+// Translates a gamepad state change into game input.  The left stick drives WASD,
+// the right stick turns the camera and the buttons follow a console-style layout.
+fn apply_gamepad_state(
+    previous: &GamepadState,
+    current: &GamepadState,
+    run_xdotool: &dyn Fn(&[&str]),
+    settings: &InputSettings,
+    inventory_open: &mut bool,
+) {
+    let stick_keys = |state: &GamepadState| {
+        [
+            ("w", state.left_y < -GAMEPAD_MOVE_THRESHOLD),
+            ("s", state.left_y > GAMEPAD_MOVE_THRESHOLD),
+            ("a", state.left_x < -GAMEPAD_MOVE_THRESHOLD),
+            ("d", state.left_x > GAMEPAD_MOVE_THRESHOLD),
+        ]
+    };
+    for ((key, was_held), (_, held)) in stick_keys(previous).into_iter().zip(stick_keys(current)) {
+        if held && !was_held {
+            run_xdotool(&["keydown", key]);
+        } else if was_held && !held {
+            run_xdotool(&["keyup", key]);
+        }
+    }
+
+    let look_axis = |value: i16, scale: f32| {
+        if value.unsigned_abs() <= GAMEPAD_LOOK_DEAD_ZONE {
+            0
+        } else {
+            (value as f32 / i16::MAX as f32 * settings.gamepad_look_speed * scale / 10.0) as i32
+        }
+    };
+    let dx = look_axis(current.right_x, settings.look_scale_x);
+    let dy = look_axis(current.right_y, settings.look_scale_y);
+    if dx != 0 || dy != 0 {
+        run_xdotool(&["mousemove_relative", "--", &dx.to_string(), &dy.to_string()]);
+    }
+
+    let pressed = |button| current.pressed(button) && !previous.pressed(button);
+    let released = |button| !current.pressed(button) && previous.pressed(button);
+
+    // Buttons that are held down for as long as the button is
+    let held_keys = [
+        (gamepad::BUTTON_A, "space"),
+        (gamepad::BUTTON_B, "Shift_L"),
+        (gamepad::BUTTON_LSTICK, "Control_L"),
+        (gamepad::BUTTON_BACK, "Tab"),
+    ];
+    for (button, key) in held_keys {
+        if pressed(button) {
+            run_xdotool(&["keydown", key]);
+        } else if released(button) {
+            run_xdotool(&["keyup", key]);
+        }
+    }
+    let held_mouse_buttons = [(gamepad::BUTTON_RT, "1"), (gamepad::BUTTON_LT, "3")];
+    for (button, mouse_button) in held_mouse_buttons {
+        if pressed(button) {
+            run_xdotool(&["mousedown", mouse_button]);
+        } else if released(button) {
+            run_xdotool(&["mouseup", mouse_button]);
+        }
+    }
+
+    // Buttons that act once per press
+    if pressed(gamepad::BUTTON_X) {
+        *inventory_open = !*inventory_open;
+        run_xdotool(&["key", "e"]);
+    }
+    if pressed(gamepad::BUTTON_Y) {
+        run_xdotool(&["key", "q"]);
+    }
+    if pressed(gamepad::BUTTON_START) {
+        *inventory_open = false;
+        run_xdotool(&["key", "Escape"]);
+    }
+    if pressed(gamepad::BUTTON_RSTICK) {
+        run_xdotool(&["key", "F5"]);
+    }
+    if pressed(gamepad::BUTTON_LB) {
+        run_xdotool(&["click", "4"]);
+    }
+    if pressed(gamepad::BUTTON_RB) {
+        run_xdotool(&["click", "5"]);
+    }
+}
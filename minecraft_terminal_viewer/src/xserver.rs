@@ -0,0 +1,105 @@
+// X servers started for one session and stopped when it ends, so there's no limit
+// on displays besides the machine, and nothing left over from the last player.
+
+use std::io;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing::info;
+
+use crate::config::{XVFB_BINARY, XVFB_START_TIMEOUT};
+use crate::wm;
+
+pub struct XServer {
+    display: String,
+    process: Child,
+}
+
+fn socket_path(display: &str) -> String {
+    let number = display.trim_start_matches(':').split('.').next().unwrap_or("");
+    format!("/tmp/.X11-unix/X{}", number)
+}
+
+impl XServer {
+    // Starts Xvfb on the display and waits for it to take connections, with the
+    // window manager running on it if there's one
+    pub fn start(display: &str, (width, height): (u16, u16), window_manager: Option<&str>) -> io::Result<Self> {
+        let socket = socket_path(display);
+        // Left over from an X server that didn't shut down cleanly
+        let _ = std::fs::remove_file(&socket);
+        let _ = std::fs::remove_file(format!("/tmp/.X{}-lock", display.trim_start_matches(':')));
+
+        let process = Command::new(XVFB_BINARY)
+            .arg(display)
+            .args(["-screen", "0", &format!("{}x{}x24", width, height)])
+            .args(["-nolisten", "tcp", "-noreset"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        // Dropping this stops the server if it doesn't come up
+        let mut server = Self {
+            display: display.to_owned(),
+            process,
+        };
+
+        let started = Instant::now();
+        while !Path::new(&socket).exists() {
+            if let Some(status) = server.process.try_wait()? {
+                return Err(io::Error::other(format!("X server on {} exited with {}", display, status)));
+            }
+            if started.elapsed() > XVFB_START_TIMEOUT {
+                return Err(io::Error::other(format!("X server on {} didn't start in time", display)));
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        info!("Started X server on display {} (PID: {})", server.display, server.process.id());
+
+        // Same invisible cursor as the displays entry-point.sh starts
+        let _ = Command::new("xsetroot")
+            .args(["-cursor", "/root/blank_cursor.xbm", "/root/blank_cursor.xbm"])
+            .env("DISPLAY", display)
+            .stderr(Stdio::null())
+            .status();
+        wm::supervise(display, window_manager);
+
+        Ok(server)
+    }
+}
+
+// Sets the size of a display's screen, which can't be bigger than the server
+// allows (a dummy Xorg's Virtual size)
+pub fn resize(display: &str, (width, height): (u16, u16)) -> io::Result<()> {
+    let output = Command::new("xrandr")
+        .args(["--fb", &format!("{}x{}", width, height)])
+        .env("DISPLAY", display)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()?;
+    if !output.status.success() {
+        let reason = String::from_utf8_lossy(&output.stderr).lines().next().unwrap_or_default().to_owned();
+        return Err(io::Error::other(format!("xrandr exited with {}: {}", output.status, reason)));
+    }
+    Ok(())
+}
+
+// Whether the display's X server is taking connections
+pub fn answers(display: &str) -> bool {
+    Command::new("xdotool")
+        .arg("getdisplaygeometry")
+        .env("DISPLAY", display)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+impl Drop for XServer {
+    fn drop(&mut self) {
+        info!("Stopping X server on display {}", self.display);
+        wm::stop(&self.display);
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
@@ -0,0 +1,27 @@
+// Backups of players' worlds, taken with the tar on this machine.
+
+#![cfg(unix)]
+
+use std::fs;
+use std::process::Command;
+
+use minecraft_terminal_viewer::backups;
+
+#[test]
+fn worlds_named_like_options_are_still_backed_up() {
+    let dir = std::env::temp_dir().join(format!("ansicraft-backups-{}", std::process::id()));
+    let world = "--checkpoint-action=exec=touch pwned";
+    let saves = dir.join("game").join("saves").join(world);
+    fs::create_dir_all(&saves).unwrap();
+    fs::write(saves.join("level.dat"), b"level").unwrap();
+
+    let result = backups::snapshot(&dir.join("game"), &dir.join("backups"), 3);
+    let backups: Vec<_> = fs::read_dir(dir.join("backups")).unwrap().map(|entry| entry.unwrap().path()).collect();
+    let listed = backups.first().map(|backup| Command::new("tar").arg("--zstd").arg("-tf").arg(backup).output().unwrap());
+    fs::remove_dir_all(&dir).unwrap();
+
+    result.unwrap();
+    assert_eq!(backups.len(), 1);
+    let listed = String::from_utf8_lossy(&listed.unwrap().stdout).into_owned();
+    assert!(listed.lines().any(|line| line == format!("{}/level.dat", world)), "{}", listed);
+}
@@ -0,0 +1,69 @@
+// What the test binaries share: the input pipeline with a loopback sink standing
+// in for the display, and a screen that hands frames back to the test.  Each
+// binary only uses some of it.
+#![allow(dead_code)]
+
+use std::io;
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use minecraft_terminal_viewer::config::TerminalSize;
+use minecraft_terminal_viewer::render::Screen;
+use minecraft_terminal_viewer::synthetic::{self, Loopback};
+use minecraft_terminal_viewer::xdo::{self, InputSource};
+use tokio_util::sync::CancellationToken;
+
+// Sends the bytes through the same pipeline an SSH session's input goes through,
+// until the input forwarder stops, and gives back what reached the game
+pub async fn play(sent: &[&[u8]], wait: Duration) -> Vec<String> {
+    let (client_tx, client_rx) = tokio::sync::mpsc::unbounded_channel();
+    let input: InputSource = Arc::new(tokio::sync::Mutex::new(client_rx));
+    let (events_tx, events_rx) = mpsc::channel();
+    let session_state = synthetic::session_state(TerminalSize {
+        target_width: 80,
+        target_height: 50,
+    });
+    let running = session_state.running.clone();
+    let sink = Loopback::default();
+    let cancel = CancellationToken::new();
+
+    let capturing = tokio::spawn(xdo::capture_input(input, events_tx, cancel.clone()));
+    let forward_sink = sink.clone();
+    let forwarding = tokio::task::spawn_blocking(move || {
+        xdo::forward_input_to_minecraft(events_rx, session_state, forward_sink, (854, 480), false)
+    });
+
+    for bytes in sent {
+        client_tx.send(bytes.to_vec()).unwrap();
+    }
+    tokio::time::sleep(wait).await;
+    running.store(false, Ordering::SeqCst);
+    cancel.cancel();
+    capturing.await.unwrap().unwrap();
+    forwarding.await.unwrap().unwrap();
+    sink.take()
+}
+
+// Frames as they'd reach the player
+pub struct Frames(pub tokio::sync::mpsc::UnboundedSender<Vec<u8>>);
+
+impl Screen for Frames {
+    async fn show(&mut self, bytes: Vec<u8>) -> io::Result<()> {
+        let _ = self.0.send(bytes);
+        Ok(())
+    }
+}
+
+// The first of them with the text in it, within 5s
+pub async fn shown(frames: &mut tokio::sync::mpsc::UnboundedReceiver<Vec<u8>>, text: &str) -> Vec<u8> {
+    let shown = async {
+        loop {
+            let bytes = frames.recv().await.expect("stopped showing frames");
+            if String::from_utf8_lossy(&bytes).contains(text) {
+                break bytes;
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(5), shown).await.expect("no frame within 5s")
+}
@@ -0,0 +1,47 @@
+// Reading the server's settings from a TOML file.  The file can only be picked
+// once, so these are in a test binary of their own.
+
+use minecraft_terminal_viewer::config::{self, AppConfig, RenderMode};
+
+const CONFIG: &str = r#"
+port = 2200
+host_key = "keys/host"
+color = "braille"
+fair_queue = true
+pools = "survival=1-6,creative=7-9"
+workers = ["survival=10.0.0.2:2223/4", "10.0.0.3:2223"]
+launcher = "/opt/launch.py"
+launcher_args = "--name {username}"
+ffmpeg = "/opt/ffmpeg/bin/ffmpeg"
+
+[scale]
+up_command = "start-worker {pool}"
+up_queue = 3
+
+[MINECRAFT_SERVER_ADDRESS]
+creative = "creative.example.com"
+"#;
+
+#[test]
+fn settings_come_from_a_toml_file() {
+    let path = std::env::temp_dir().join(format!("ansicraft-test-{}.toml", std::process::id()));
+    std::fs::write(&path, CONFIG).unwrap();
+    config::use_file(&path);
+    let config = AppConfig::load().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(config.port, 2200);
+    assert_eq!(config.host_key, std::path::PathBuf::from("keys/host"));
+    assert_eq!(config.render.mode, RenderMode::Braille);
+    assert!(!config.detect_color);
+    assert!(config.fair_queue);
+    assert_eq!(config.workers, "survival=10.0.0.2:2223/4,10.0.0.3:2223");
+    assert_eq!(config.scale_up_command, "start-worker {pool}");
+    assert_eq!(config.scale_up_queue, 3);
+    assert_eq!(config.server_address("survival"), "");
+    assert_eq!(config.server_address("creative"), "creative.example.com");
+    assert_eq!(config.pools.as_deref(), Some("survival=1-6,creative=7-9"));
+    assert_eq!(config.launcher.launcher, "/opt/launch.py");
+    assert_eq!(config.launcher.args, "--name {username}");
+    assert_eq!(config.ffmpeg, "/opt/ffmpeg/bin/ffmpeg");
+}
@@ -0,0 +1,57 @@
+// The input side of a session, from the bytes a client sends to the xdotool
+// commands they turn into, with a loopback sink standing in for the display.
+
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use common::play;
+use minecraft_terminal_viewer::config::{TerminalSize, KEY_HOLD_TIMEOUT};
+use minecraft_terminal_viewer::synthetic::{self, Loopback};
+use minecraft_terminal_viewer::xdo::{self, InputSource};
+use tokio_util::sync::CancellationToken;
+
+mod common;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn typing_taps_keys() {
+    let sent = play(&[b"x"], Duration::from_millis(200)).await;
+    assert_eq!(sent, ["key x"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn movement_keys_are_held_until_they_stop_repeating() {
+    let sent = play(&[b"w", b"w", b"w"], KEY_HOLD_TIMEOUT * 3).await;
+    assert_eq!(sent, ["keydown w", "keyup w"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn keys_after_a_lone_escape_reach_the_game_after_it() {
+    let sent = play(&[b"\x1b", b"x"], Duration::from_millis(500)).await;
+    assert_eq!(sent, ["key Escape", "key x"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ctrl_c_ends_the_session() {
+    let (client_tx, client_rx) = tokio::sync::mpsc::unbounded_channel();
+    let input: InputSource = Arc::new(tokio::sync::Mutex::new(client_rx));
+    let (events_tx, events_rx) = mpsc::channel();
+    let session_state = synthetic::session_state(TerminalSize {
+        target_width: 80,
+        target_height: 50,
+    });
+    let running = session_state.running.clone();
+    let cancel = CancellationToken::new();
+
+    let capturing = tokio::spawn(xdo::capture_input(input, events_tx, cancel.clone()));
+    let forwarding = tokio::task::spawn_blocking(move || {
+        xdo::forward_input_to_minecraft(events_rx, session_state, Loopback::default(), (854, 480), false)
+    });
+    client_tx.send(vec![0x03]).unwrap();
+
+    let stopped = tokio::time::timeout(Duration::from_secs(5), forwarding).await.expect("Ctrl+C didn't stop it");
+    assert!(stopped.unwrap().is_ok());
+    assert!(!running.load(Ordering::SeqCst));
+    cancel.cancel();
+    capturing.await.unwrap().unwrap();
+}
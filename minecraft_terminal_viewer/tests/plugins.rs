@@ -0,0 +1,85 @@
+// Plugins' hooks, on the same pipeline sessions use.  Plugins are registered for
+// the whole process, so these are in a test binary of their own.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::{play, shown, Frames};
+use minecraft_terminal_viewer::config::TerminalSize;
+use minecraft_terminal_viewer::plugins::{self, Plugin, SessionInfo};
+use minecraft_terminal_viewer::render;
+use minecraft_terminal_viewer::xdo::ClientInput;
+use termwiz::input::{InputEvent, KeyCode};
+use tokio_util::sync::CancellationToken;
+
+mod common;
+
+// Keeps anyone from typing b, and counts what it sees
+#[derive(Clone, Default)]
+struct NoB {
+    seen: Arc<AtomicUsize>,
+}
+
+impl Plugin for NoB {
+    fn on_frame(&self, _session: &SessionInfo, frame: &mut Vec<u8>) {
+        frame.extend_from_slice(b"[watermark]");
+    }
+
+    fn on_input(&self, _session: &SessionInfo, input: &ClientInput) -> bool {
+        self.seen.fetch_add(1, Ordering::SeqCst);
+        !matches!(input, ClientInput::Terminal(InputEvent::Key(key)) if key.key == KeyCode::Char('b'))
+    }
+}
+
+fn register() -> NoB {
+    static REGISTERED: Mutex<Option<NoB>> = Mutex::new(None);
+    REGISTERED
+        .lock()
+        .unwrap()
+        .get_or_insert_with(|| {
+            let plugin = NoB::default();
+            plugins::register(plugin.clone());
+            plugin
+        })
+        .clone()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn plugins_can_keep_input_from_the_game() {
+    let plugin = register();
+    let sent = play(&[b"x", b"b"], Duration::from_millis(200)).await;
+
+    assert_eq!(sent, ["key x"]);
+    assert!(plugin.seen.load(Ordering::SeqCst) >= 2);
+}
+
+#[tokio::test]
+async fn plugins_can_add_to_frames() {
+    register();
+    let (frames_tx, frames_rx) = tokio::sync::mpsc::channel(1);
+    let (screen_tx, mut screen_rx) = tokio::sync::mpsc::unbounded_channel();
+    let cancel = CancellationToken::new();
+    let showing = tokio::spawn(render::show_frames(
+        frames_rx,
+        Frames(screen_tx),
+        Arc::new(Mutex::new(TerminalSize {
+            target_width: 16,
+            target_height: 10,
+        })),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+        cancel.clone(),
+    ));
+    frames_tx.send("frame".to_owned()).await.unwrap();
+
+    let shown = String::from_utf8_lossy(&shown(&mut screen_rx, "frame").await).into_owned();
+    // Drawn after the frame, so over it
+    let frame_at = shown.find("frame").unwrap();
+    assert!(shown[frame_at..].contains("[watermark]"));
+
+    cancel.cancel();
+    showing.await.unwrap().unwrap();
+}
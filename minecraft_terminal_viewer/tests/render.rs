@@ -0,0 +1,247 @@
+// The capture and encoding pipeline, fed by the synthetic test pattern so it runs
+// without X, ffmpeg or the game.
+
+use std::io;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::{shown, Frames};
+use minecraft_terminal_viewer::config::{AppConfig, LiveConfig, RenderMode, RenderSettings, TerminalSize};
+use minecraft_terminal_viewer::render::{self, CaptureBackend, Screen};
+use minecraft_terminal_viewer::session_log::SessionLogs;
+use minecraft_terminal_viewer::supervisor::Processes;
+use minecraft_terminal_viewer::synthetic::{self, TestPattern};
+use tokio::io::AsyncReadExt;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+mod common;
+
+fn size(width: usize) -> TerminalSize {
+    TerminalSize {
+        target_width: width,
+        target_height: render::get_height_from_width(width),
+    }
+}
+
+// Waits for the next encoded frame, failing rather than hanging if none comes
+async fn next_frame(frames: &mut mpsc::Receiver<String>) -> String {
+    tokio::time::timeout(Duration::from_secs(5), frames.recv())
+        .await
+        .expect("no frame within 5s")
+        .expect("rendering stopped")
+}
+
+#[test]
+fn every_mode_encodes_the_same_frame_the_same_way() {
+    let frame = synthetic::test_frame(16, 10, 3);
+    let mut mode = RenderMode::default();
+    loop {
+        let encoded = render::encode_frame(mode, &frame, 10, 16, 0, 0);
+        assert_eq!(encoded, render::encode_frame(mode, &frame, 10, 16, 0, 0), "{:?}", mode);
+        assert!(encoded.starts_with("\x1b[1;1H"), "{:?} doesn't start at the top left", mode);
+        assert!(encoded.ends_with("\x1b[m"), "{:?} doesn't reset its colors", mode);
+        mode = mode.next();
+        if mode == RenderMode::default() {
+            break;
+        }
+    }
+}
+
+#[test]
+fn truecolor_draws_two_pixels_a_cell() {
+    let frame = synthetic::test_frame(4, 6, 0);
+    let encoded = render::encode_frame(RenderMode::TrueColor, &frame, 6, 4, 0, 0);
+    assert_eq!(encoded.matches('▄').count(), 4 * 3);
+    // Each row of cells goes back to the start of the next
+    assert_eq!(encoded.matches("\x1b[B\x1b[4D").count(), 3);
+    // The top left pixel is the background, the one under it the foreground
+    assert!(encoded.contains(&format!("\x1b[48;2;{};{};{}m", frame[0], frame[1], frame[2])));
+    assert!(encoded.contains(&format!("\x1b[38;2;{};{};{}m", frame[12], frame[13], frame[14])));
+}
+
+#[test]
+fn sixteen_colors_uses_only_the_basic_sgr_codes() {
+    // Black on top of white
+    let frame = vec![0, 0, 0, 250, 250, 250];
+    let encoded = render::encode_frame(RenderMode::Ansi16, &frame, 2, 1, 0, 0);
+    assert!(encoded.contains("\x1b[40;97m▄"), "{:?}", encoded);
+    assert!(!encoded.contains("5;") && !encoded.contains("2;"));
+}
+
+#[test]
+fn color_mode_goes_by_the_terminal() {
+    assert_eq!(RenderMode::detect("xterm-256color", "truecolor"), Some(RenderMode::TrueColor));
+    assert_eq!(RenderMode::detect("xterm-kitty", ""), Some(RenderMode::TrueColor));
+    assert_eq!(RenderMode::detect("xterm-256color", ""), Some(RenderMode::Ansi256));
+    assert_eq!(RenderMode::detect("linux", ""), Some(RenderMode::Ansi16));
+    assert_eq!(RenderMode::detect("dumb", ""), Some(RenderMode::Ascii));
+    assert_eq!(RenderMode::detect("", ""), None);
+}
+
+#[test]
+fn frames_after_the_first_only_redraw_what_changed() {
+    let mut differ = render::FrameDiffer::default();
+    let mut frame = synthetic::test_frame(8, 6, 0);
+    let full = differ.encode(RenderMode::TrueColor, &frame, 6, 8);
+    assert_eq!(full, render::encode_frame(RenderMode::TrueColor, &frame, 6, 8, 0, 0));
+
+    // The pixel under the third cell of the second row
+    let pixel = (3 * 8 + 2) * 3;
+    frame[pixel] = frame[pixel].wrapping_add(100);
+    let delta = differ.encode(RenderMode::TrueColor, &frame, 6, 8);
+    assert!(delta.starts_with("\x1b[2;3H"), "{:?}", delta);
+    assert_eq!(delta.matches('▄').count(), 1);
+    // Leaving the cursor under the frame, like a whole one does
+    assert!(delta.ends_with("\x1b[4;1H\x1b[m"), "{:?}", delta);
+
+    let unchanged = differ.encode(RenderMode::TrueColor, &frame, 6, 8);
+    assert_eq!(unchanged.matches('▄').count(), 0);
+    // A new mode needs everything drawn again
+    let ascii = differ.encode(RenderMode::Ascii, &frame, 6, 8);
+    assert_eq!(ascii, render::encode_frame(RenderMode::Ascii, &frame, 6, 8, 0, 0));
+}
+
+#[test]
+fn offsets_move_the_frame() {
+    let frame = synthetic::test_frame(4, 4, 0);
+    let encoded = render::encode_frame(RenderMode::Ascii, &frame, 4, 4, 7, 2);
+    assert!(encoded.starts_with("\x1b[3;8H"));
+}
+
+#[tokio::test]
+async fn the_test_pattern_is_the_same_every_time() {
+    let logs = SessionLogs::default();
+    let mut first = vec![0; 8 * 6 * 3 * 2];
+    let mut second = first.clone();
+    let mut capture = TestPattern.start(8, 6, 1000, &logs, &AppConfig::default()).unwrap();
+    capture.frames.read_exact(&mut first).await.unwrap();
+    let mut capture = TestPattern.start(8, 6, 1000, &logs, &AppConfig::default()).unwrap();
+    capture.frames.read_exact(&mut second).await.unwrap();
+    assert!(capture.process.is_none());
+    assert_eq!(first, second);
+    assert_eq!(first[..8 * 6 * 3], synthetic::test_frame(8, 6, 0));
+    assert_eq!(first[8 * 6 * 3..], synthetic::test_frame(8, 6, 1));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rendering_follows_the_terminal_size() {
+    let terminal_size = Arc::new(Mutex::new(size(12)));
+    let render_settings = Arc::new(Mutex::new(RenderSettings::default()));
+    let config = LiveConfig::load(|config| config.max_fps = None).unwrap();
+    let cancel = CancellationToken::new();
+    let (frames_tx, mut frames) = mpsc::channel(1);
+    let rendering = tokio::spawn(render::render_video(
+        frames_tx,
+        None,
+        Default::default(),
+        terminal_size.clone(),
+        render_settings.clone(),
+        config,
+        TestPattern,
+        cancel.clone(),
+        Processes::default(),
+        SessionLogs::default(),
+    ));
+
+    assert!(next_frame(&mut frames).await.contains("\x1b[B\x1b[12D"));
+
+    *terminal_size.lock().unwrap() = size(20);
+    // Frames of the old size might still be on their way
+    let resized = async {
+        loop {
+            let frame = next_frame(&mut frames).await;
+            if frame.contains("\x1b[B\x1b[20D") {
+                break frame;
+            }
+            assert!(frame.contains("\x1b[B\x1b[12D"), "a frame of neither size");
+        }
+    };
+    let frame = tokio::time::timeout(Duration::from_secs(5), resized).await.expect("never resized");
+    assert_eq!(frame.matches("\x1b[B\x1b[20D").count(), render::get_height_from_width(20) / 2);
+    assert!(!next_frame(&mut frames).await.contains("\x1b[B\x1b[12D"));
+
+    // Switching modes doesn't need a new capture
+    render_settings.lock().unwrap().mode = RenderMode::Ascii;
+    let ascii = async {
+        loop {
+            if !next_frame(&mut frames).await.contains("\x1b[48;2;") {
+                break;
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(5), ascii).await.expect("never switched to ASCII");
+
+    cancel.cancel();
+    let result = tokio::time::timeout(Duration::from_secs(5), rendering).await.expect("didn't stop");
+    assert!(result.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn what_is_shown_is_counted() {
+    let (frames_tx, frames_rx) = mpsc::channel(1);
+    let (screen_tx, mut screen_rx) = mpsc::unbounded_channel();
+    let streamed = Arc::new(render::Streamed::default());
+    let cancel = CancellationToken::new();
+    let showing = tokio::spawn(render::show_frames(
+        frames_rx,
+        Frames(screen_tx),
+        Arc::new(Mutex::new(size(16))),
+        Default::default(),
+        Default::default(),
+        streamed.clone(),
+        Default::default(),
+        cancel.clone(),
+    ));
+    let mut sent = 0;
+    for frame in ["one", "two"] {
+        frames_tx.send(frame.to_owned()).await.unwrap();
+        sent += shown(&mut screen_rx, frame).await.len();
+    }
+    assert_eq!(streamed.frames.load(Ordering::Relaxed), 2);
+    assert_eq!(streamed.bytes.load(Ordering::Relaxed), sent as u64);
+
+    cancel.cancel();
+    showing.await.unwrap().unwrap();
+}
+
+// A link that takes 20ms for every frame, however small
+struct SlowLink;
+
+impl Screen for SlowLink {
+    async fn show(&mut self, _bytes: Vec<u8>) -> io::Result<()> {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn a_slow_link_turns_the_frame_rate_down() {
+    let (frames_tx, frames_rx) = mpsc::channel(1);
+    let streamed = Arc::new(render::Streamed::default());
+    let cancel = CancellationToken::new();
+    let showing = tokio::spawn(render::show_frames(
+        frames_rx,
+        SlowLink,
+        Arc::new(Mutex::new(size(16))),
+        Default::default(),
+        Default::default(),
+        streamed.clone(),
+        Default::default(),
+        cancel.clone(),
+    ));
+    // Sent as fast as they're taken, about 50 a second
+    let sending = tokio::spawn(async move { while frames_tx.send("frame".to_owned()).await.is_ok() {} });
+    let throttled = async {
+        while streamed.throttle.limit().is_none() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(10), throttled).await.expect("never throttled");
+    assert_eq!(streamed.throttle.limit(), Some(30));
+
+    cancel.cancel();
+    showing.await.unwrap().unwrap();
+    sending.abort();
+}
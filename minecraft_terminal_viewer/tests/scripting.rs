@@ -0,0 +1,84 @@
+// An operator's script remapping input and drawing an overlay.  Only the first
+// script loaded is used, so these are in a test binary of their own.
+#![cfg(feature = "scripting")]
+
+use std::sync::{Arc, Mutex, Once};
+use std::time::Duration;
+
+use common::{play, shown, Frames};
+use minecraft_terminal_viewer::config::TerminalSize;
+use minecraft_terminal_viewer::plugins::SessionInfo;
+use minecraft_terminal_viewer::render;
+use minecraft_terminal_viewer::scripting;
+use tokio_util::sync::CancellationToken;
+
+mod common;
+
+const SCRIPT: &str = r#"
+fn on_input(session, command) {
+    if command == ["key", "q"] {
+        return [];
+    }
+    if command == ["key", "x"] {
+        return ["key slash", ["key", "h"]];
+    }
+}
+
+fn overlay(session) {
+    "Hi " + session.username + "\nRules: be nice"
+}
+"#;
+
+fn load() {
+    static LOADED: Once = Once::new();
+    LOADED.call_once(|| {
+        let path = std::env::temp_dir().join(format!("ansicraft-test-{}.rhai", std::process::id()));
+        std::fs::write(&path, SCRIPT).unwrap();
+        scripting::load(&path);
+    });
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scripts_can_drop_input() {
+    load();
+    assert_eq!(play(&[b"q", b"c"], Duration::from_millis(200)).await, ["key c"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scripts_can_send_sequences() {
+    load();
+    assert_eq!(play(&[b"x"], Duration::from_millis(200)).await, ["key slash", "key h"]);
+}
+
+#[tokio::test]
+async fn scripts_can_draw_overlays() {
+    load();
+    let (frames_tx, frames_rx) = tokio::sync::mpsc::channel(1);
+    let (screen_tx, mut screen_rx) = tokio::sync::mpsc::unbounded_channel();
+    let cancel = CancellationToken::new();
+    let session_info = SessionInfo {
+        username: "steve".to_owned(),
+        ..Default::default()
+    };
+    let showing = tokio::spawn(render::show_frames(
+        frames_rx,
+        Frames(screen_tx),
+        Arc::new(Mutex::new(TerminalSize {
+            target_width: 16,
+            target_height: 10,
+        })),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+        Arc::new(session_info),
+        cancel.clone(),
+    ));
+    frames_tx.send("frame".to_owned()).await.unwrap();
+
+    let shown = String::from_utf8_lossy(&shown(&mut screen_rx, "frame").await).into_owned();
+    assert!(shown.contains("\x1b[1;1H\x1b[0;97;40mHi steve\x1b[m"));
+    assert!(shown.contains("\x1b[2;1H\x1b[0;97;40mRules: be nice\x1b[m"));
+
+    cancel.cancel();
+    showing.await.unwrap().unwrap();
+}